@@ -77,11 +77,12 @@ impl TreeNode {
 impl Executor {
     fn extract_header_dependency(&self, header: &Path) -> Result<TreeNode> {
         let header_path = self.deopt.get_library_build_header_path()?;
-        let output = Command::new("clang++")
+        let output = Command::new(crate::config::get_cxx())
             .current_dir(&header_path)
             .arg("-fsyntax-only")
             .arg("-H")
             .arg("-I.")
+            .args(crate::config::get_extra_cxxflags())
             .arg(header)
             .stdout(Stdio::null())
             .stderr(Stdio::piped())
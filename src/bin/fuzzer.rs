@@ -6,11 +6,18 @@ use prompt_fuzz::{
 };
 
 fn main() -> Result<()> {
-    dotenv::dotenv().ok();
-    config::init_openai_env();
     config::parse_config()?;
+    config::load_env_file(get_config().env_file.as_deref())?;
+    config::init_openai_env();
     //config::Config::init_test("cJSON");
     init_logger()?;
+    if !get_config().entry_points.is_empty() {
+        return prompt_fuzz::fuzzer::fuzz_entry_points();
+    }
+    if get_config().preflight {
+        let deopt = prompt_fuzz::deopt::Deopt::new(config::get_library_name())?;
+        prompt_fuzz::execution::preflight::preflight_library(&deopt)?;
+    }
     let mut fuzzer = Fuzzer::new()?;
     fuzzer.fuzz_loop()?;
     if get_config().fuzzer_run {
@@ -1,8 +1,15 @@
 use clap::{Parser, Subcommand};
 use eyre::{Result};
+use prompt_fuzz::config::{GenerationModeP, LibConfig};
+use prompt_fuzz::fuzzer::Fuzzer;
+use prompt_fuzz::request::Handler;
 use prompt_fuzz::deopt::{self, Deopt};
+use prompt_fuzz::execution::logger::ProgramError;
 use prompt_fuzz::execution::Executor;
-use prompt_fuzz::cntg_program::CNTGProgram;
+use prompt_fuzz::feedback::clang_coverage::CoverageReport;
+use prompt_fuzz::minimize::CoverageSet;
+use prompt_fuzz::program::Program;
+use prompt_fuzz::cntg_program::{attribution, CNTGProgram};
 use prompt_fuzz::cntg_program::seed_metas::SeedMetas;
 use std::path::{Path, PathBuf};
 use std::process::{Command, ExitCode, Stdio};
@@ -15,6 +22,27 @@ use std::io::Write;
 pub struct Config {
     /// Target project to proceed
     project: String,
+    /// Show the progress bars of synthesis and compilation
+    #[arg(long, global = true)]
+    progress: bool,
+    /// The binary name of each fused core
+    #[arg(long, global = true)]
+    core_binary_name: Option<String>,
+    /// The output dir of the library, instead of `output/<project>`
+    #[arg(long, global = true)]
+    work_dir: Option<PathBuf>,
+    /// The seed dir of the library, see `--seed-dir` of the fuzzer
+    #[arg(long = "seed-dir", value_name = "SEED_DIR", global = true)]
+    library_seed_dir: Option<PathBuf>,
+    /// Work on the outputs of this entry point of the library, see `--entry-points` of the fuzzer
+    #[arg(long, global = true)]
+    entry_point: Option<String>,
+    /// The C++ compiler, instead of clang++
+    #[arg(long, global = true)]
+    cxx: Option<PathBuf>,
+    /// Extra flags appended to each compilation
+    #[arg(long, global = true, allow_hyphen_values = true)]
+    extra_cxxflags: Option<String>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -28,11 +56,94 @@ enum Commands {
         /// The batch size of files to be fused together
         #[clap(short, long)]
         batch_size: Option<usize>,
+        /// Keep the old CNTG and driver dirs, retain the object files and log the clang command lines per core.
+        #[clap(long)]
+        keep_artifacts: bool,
+        /// Fuse the seeds in the order sampled by their quality scores, so the better seeds are fused first.
+        #[clap(long)]
+        by_quality: bool,
+        /// Fuse the byte-identical seeds as well, instead of dropping the duplicates.
+        #[clap(long)]
+        no_dedup: bool,
+        /// Only fuse the K seeds of the highest coverage, for a quick coverage estimate. It requires the per-seed
+        /// coverage, i.e., the profiles kept by `--keep-profiles` of the fuzzer, or the seed metas.
+        #[clap(long)]
+        top_k: Option<usize>,
+    },
+    /// Recompile and rerun each seed, and report the seeds that fail now.
+    ValidateSeeds {
+        /// the path of seeds to validate
+        seed_dir: Option<PathBuf>,
+        /// Move the failing seeds out of the corpus, into the error seed dir
+        #[clap(long)]
+        prune: bool,
+    },
+    /// Report the seeds that can be removed without reducing the coverage of the corpus.
+    FindRedundant {
+        /// the path of seeds to check
+        seed_dir: Option<PathBuf>,
+        /// Move the redundant seeds out of the corpus, into the redundant seed dir
+        #[clap(long)]
+        prune: bool,
+    },
+    /// Check that a trivial driver compiles, links and runs against the library.
+    Preflight,
+    /// Print every API gadget extracted from the library with its signature, and whether it can be put in prompts
+    ListGadgets {
+        /// Print a JSON array of `{name, signature, selectable}` objects instead
+        #[clap(long)]
+        json: bool,
+        /// Preview the APIs selectable with these `--include-only-apis` of the fuzzer
+        #[clap(long, value_delimiter = ',')]
+        include_only_apis: Option<Vec<String>>,
+        /// Preview the APIs selectable with these `--exclude-apis` of the fuzzer
+        #[clap(long, value_delimiter = ',')]
+        exclude_apis: Vec<String>,
     },
     /// Collect coverage for CNTG fused programs
     CollectCoverage,
     /// Report coverage for CNTG fused programs
-    ReportCoverage,
+    ReportCoverage {
+        /// Exit with failure if the branch coverage (in percent) of all the cores is below this gate
+        #[clap(long)]
+        coverage_gate: Option<f32>,
+    },
+    /// Collect coverage for CNTG fused programs, and print the total and covered lines, functions, branches and
+    /// regions as a compact JSON object
+    CoverageJson {
+        /// Exit with failure if the branch coverage (in percent) of all the cores is below this gate
+        #[clap(long)]
+        coverage_gate: Option<f32>,
+    },
+    /// Run each seed of the fused cores alone, and write the branches it covers and contributes uniquely to a CSV
+    AttributeCoverage {
+        /// The CSV to write. Default: <cntg_dir>/coverage_attribution.csv
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Run a fused core or a compiled seed on a crashing input under the sanitizer environment, and print the report
+    Replay {
+        /// The fuzzer binary to run
+        #[clap(long)]
+        binary: PathBuf,
+        /// The crashing input
+        #[clap(long)]
+        input: PathBuf,
+    },
+    /// Compare the API pairs of two runs: observer snapshots or JSON arrays of `[caller, callee]` pairs
+    PairsDiff {
+        file_a: PathBuf,
+        file_b: PathBuf,
+    },
+    /// Render the discovered API pairs as a Graphviz DOT graph, whose edges are weighted by the discovered times
+    PairsGraph {
+        /// An observer snapshot or a JSON array of `[caller, callee]` pairs. Default: the observer snapshot of the library
+        #[clap(long)]
+        input: Option<PathBuf>,
+        /// The DOT file to write
+        #[clap(short, long)]
+        output: PathBuf,
+    },
     /// Record coverage based on the seed meta file to the same file
     RecordCoverage {
         /// The number of seeds that are batched together for coverage
@@ -40,33 +151,311 @@ enum Commands {
         #[clap(short, long, default_value_t = 100)]
         batch_size: usize,
     },
+    /// Register a new target library: scaffold its `data` and `output/build` layout and write its config.yaml.
+    RegisterTarget {
+        /// The directory (or a single file) of the public headers.
+        #[clap(long)]
+        headers: PathBuf,
+        /// The directory of the built libraries.
+        #[clap(long)]
+        lib: PathBuf,
+        /// The name of the static linked library. Default: lib<project>.a
+        #[clap(long)]
+        static_lib_name: Option<String>,
+        /// The name of the dynamic linked library. Default: lib<project>.so
+        #[clap(long)]
+        dyn_lib_name: Option<String>,
+        /// A config.yaml used as the template of this library's config.
+        #[clap(long)]
+        config: Option<PathBuf>,
+    },
+    /// Run the fuzzer in the FuzzDriver and the ApiCombination mode under an equal budget, and compare their
+    /// coverage, unique API pairs and seed counts. Pass more fuzzer arguments after the command.
+    CompareModes {
+        /// The wall-clock budget of the seed generation of each mode, in seconds. It is rounded up to minutes,
+        /// the unit of `--seed-gen-timeout` of the fuzzer.
+        #[clap(long)]
+        budget: u64,
+        #[clap(raw = true)]
+        fuzzer_args: Vec<String>,
+    },
     /// Create seeds, fuse them, and report coverage. Pass fuzzer arguments after the command.
     All {
+        /// Read more fuzzer arguments from this file, one per line, with `#` comments. They are appended to
+        /// the arguments after `--`.
+        #[clap(long)]
+        fuzzer_args_file: Option<PathBuf>,
         #[clap(raw = true)]
         fuzzer_args: Vec<String>,
     },
 }
 
+/// Returns whether the crash is reproduced.
+fn replay(project: String, binary: &Path, input: &Path) -> Result<bool> {
+    let deopt = Deopt::new(project)?;
+    let executor = Executor::new(&deopt)?;
+    let replay = executor.replay(binary, input)?;
+    println!("{}", replay.report);
+    match replay.status {
+        Some(status) => println!("exit status: {status}"),
+        None => println!("exit status: timed out after {}s", prompt_fuzz::config::EXECUTION_TIMEOUT),
+    }
+    println!("signature: {}", replay.signature);
+    Ok(replay.is_crashed())
+}
+
+fn pairs_diff(file_a: &Path, file_b: &Path) -> Result<()> {
+    use prompt_fuzz::feedback::observer::{diff_pair_sets, load_pair_set};
+    let (only_a, only_b, common) = diff_pair_sets(&load_pair_set(file_a)?, &load_pair_set(file_b)?);
+    for (title, pairs) in [
+        (format!("Only in {file_a:?}"), &only_a),
+        (format!("Only in {file_b:?}"), &only_b),
+        ("Common".to_string(), &common),
+    ] {
+        println!("{title}: {}", pairs.len());
+        for (caller, callee) in pairs {
+            println!("\t{caller} -> {callee}");
+        }
+    }
+    Ok(())
+}
+
+fn list_gadgets(json: bool) -> Result<()> {
+    use prompt_fuzz::program::gadget::{get_func_gadgets, get_selectable_func_gadgets};
+    let selectable: std::collections::HashSet<&str> = get_selectable_func_gadgets()
+        .iter()
+        .map(|gadget| gadget.get_func_name())
+        .collect();
+    let gadgets = get_func_gadgets();
+    if json {
+        let entries: Vec<serde_json::Value> = gadgets
+            .iter()
+            .map(|gadget| {
+                serde_json::json!({
+                    "name": gadget.get_func_name(),
+                    "signature": gadget.gen_signature(),
+                    "selectable": selectable.contains(gadget.get_func_name()),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+    for gadget in gadgets {
+        let mark = if selectable.contains(gadget.get_func_name()) {
+            ""
+        } else {
+            "  (excluded)"
+        };
+        println!("{}: {}{mark}", gadget.get_func_name(), gadget.gen_signature());
+    }
+    log::info!(
+        "{} gadgets are extracted, {} of them are selectable.",
+        gadgets.len(),
+        selectable.len()
+    );
+    Ok(())
+}
+
+fn pairs_graph(project: String, input: &Option<PathBuf>, output: &Path) -> Result<()> {
+    use prompt_fuzz::feedback::observer::{load_pair_counts, pairs_to_dot};
+    let input = match input {
+        Some(input) => input.clone(),
+        None => Deopt::new(project)?.get_library_observer_snapshot_path()?,
+    };
+    let counts = load_pair_counts(&input)?;
+    std::fs::write(output, pairs_to_dot(&counts))?;
+    log::info!("Write the graph of {} API pairs to {output:?}", counts.len());
+    Ok(())
+}
+
 fn fuse_seeds(
     project: String,
     seed_dir: &Option<PathBuf>,
     batch_size: Option<usize>,
+    by_quality: bool,
+    dedup: bool,
+    top_k: Option<usize>,
 ) -> Result<()> {
-    let deopt = Deopt::new(project)?;
+    let mut deopt = Deopt::new(project)?;
     let test_dir: PathBuf = if let Some(seed_dir) = seed_dir {
         seed_dir.clone()
     } else {
         deopt.get_library_seed_dir()?
     };
-    let programs = crate::deopt::utils::read_sort_dir(&test_dir)?;
+    let mut programs = crate::deopt::utils::read_sort_dir(&test_dir)?;
+    if by_quality {
+        programs = order_seeds_by_quality(&mut deopt, programs)?;
+    }
+    if let Some(k) = top_k {
+        programs = select_top_k_seeds(&deopt, programs, k)?;
+    }
 
     let batch_size = batch_size.unwrap_or(100);
 
     let mut cntg_program = CNTGProgram::new(programs, batch_size, &deopt);
+    if dedup {
+        cntg_program.dedup()?;
+    }
     cntg_program.reset()?;
-    cntg_program.chdir(&deopt.get_library_driver_dir().unwrap())?;
-    cntg_program.synthesis(&deopt.get_library_cntg_dir().unwrap())?;
-    cntg_program.compile(&deopt.get_library_cntg_dir().unwrap())?;
+    cntg_program.build(&deopt.get_library_driver_dir()?, &deopt.get_library_cntg_dir()?)?;
+    Ok(())
+}
+
+/// The `k` seeds of the highest coverage. Each seed is scored by the branches covered in its profile kept by
+/// `--keep-profiles` of the fuzzer if any, otherwise by its gain of the cumulative coverage in the seed metas.
+fn select_top_k_seeds(deopt: &Deopt, seeds: Vec<PathBuf>, k: usize) -> Result<Vec<PathBuf>> {
+    if k == 0 {
+        eyre::bail!("--top-k should be positive");
+    }
+    let mut scores = std::collections::HashMap::new();
+    let profiles: Vec<PathBuf> = crate::deopt::utils::read_sort_dir(&deopt.get_library_profiles_dir()?)?
+        .into_iter()
+        .filter(|path| path.extension().is_some_and(|ext| ext == "profdata"))
+        .collect();
+    if !profiles.is_empty() {
+        let executor = Executor::new(deopt)?;
+        for profile in profiles {
+            let coverage = executor.obtain_cov_summary_from_profdata(&profile)?;
+            let covered = coverage.get_total_summary().count_covered_branches();
+            let stem = profile.file_stem().unwrap().to_string_lossy().to_string();
+            scores.insert(stem, covered as f32);
+        }
+        log::info!("Score the seeds by the branches covered in {} kept profiles.", scores.len());
+    } else {
+        let meta_path = [deopt.get_work_seed_meta_path()?, deopt.get_seed_meta_path()?]
+            .into_iter()
+            .find(|path| path.exists())
+            .ok_or_else(|| eyre::eyre!("--top-k requires the per-seed coverage: run the fuzzer with --keep-profiles, or record the seed metas by `record-coverage`."))?;
+        let seed_metas = SeedMetas::try_from(meta_path.as_path())?;
+        scores.extend(seed_metas.coverage_gains());
+        log::info!("Score the seeds by their coverage gains in {meta_path:?}.");
+    }
+    let selected = prompt_fuzz::cntg_program::select_top_k_seeds(seeds, &scores, k);
+    if selected.is_empty() {
+        eyre::bail!("None of the seeds has coverage data to select the top {k} from.");
+    }
+    for (seed, score) in &selected {
+        log::info!("Select seed {seed:?} of score {score:.2}");
+    }
+    log::info!("Fuse the top {} seeds.", selected.len());
+    Ok(selected.into_iter().map(|(seed, _)| seed).collect())
+}
+
+/// Order the seeds by sampling them by quality. The seeds cannot be loaded as programs are appended at last.
+fn order_seeds_by_quality(deopt: &mut Deopt, seeds: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+    let mut paths = std::collections::HashMap::new();
+    let mut rest = Vec::new();
+    deopt.seed_queue.clear();
+    for seed in seeds {
+        match Program::load_from_path(&seed) {
+            Ok(program) if !paths.contains_key(&program.id) => {
+                paths.insert(program.id, seed);
+                deopt.seed_queue.push_back(program);
+            }
+            _ => rest.push(seed),
+        }
+    }
+    let mut ordered: Vec<PathBuf> = deopt
+        .sample_seeds_by_quality(deopt.seed_queue.len())
+        .iter()
+        .filter_map(|program| paths.remove(&program.id))
+        .collect();
+    deopt.seed_queue.clear();
+    ordered.extend(rest);
+    Ok(ordered)
+}
+
+fn validate_seeds(project: String, seed_dir: &Option<PathBuf>, prune: bool) -> Result<()> {
+    let mut deopt = Deopt::new(project)?;
+    let seed_dir: PathBuf = if let Some(seed_dir) = seed_dir {
+        seed_dir.clone()
+    } else {
+        deopt.get_library_seed_dir()?
+    };
+    let executor = Executor::new(&deopt)?;
+
+    let mut drivers = Vec::new();
+    let mut sequences = Vec::new();
+    for seed in crate::deopt::utils::read_sort_dir(&seed_dir)? {
+        if !seed.is_file() {
+            continue;
+        }
+        match Program::load_from_path(&seed) {
+            // fuzz drivers are checked by fuzzing, api sequences are checked by their main method.
+            Ok(program) if program.statements.contains("LLVMFuzzerTestOneInput") => {
+                drivers.push((seed, program))
+            }
+            Ok(program) => sequences.push((seed, program)),
+            Err(err) => log::warn!("Skip the seed cannot be loaded: {seed:?}, {err}"),
+        }
+    }
+
+    let mut results: Vec<(PathBuf, Program, Option<ProgramError>)> = Vec::new();
+    if !drivers.is_empty() {
+        let programs: Vec<Program> = drivers.iter().map(|(_, program)| program.clone()).collect();
+        let errs = executor.check_programs_are_correct(&programs, &deopt)?;
+        for ((seed, program), err) in drivers.into_iter().zip(errs) {
+            results.push((seed, program, err));
+        }
+    }
+    for (seed, program) in sequences {
+        let err = executor.validate_api_sequence(&program, &deopt)?;
+        results.push((seed, program, err));
+    }
+
+    let total = results.len();
+    let mut failed = 0;
+    for (seed, program, err) in results {
+        let Some(err) = err else {
+            continue;
+        };
+        failed += 1;
+        log::warn!("Seed fails now: {seed:?}{}", err.get_err_msg());
+        if prune {
+            let saved = deopt.save_err_program(&program, &err)?;
+            std::fs::remove_file(&seed)?;
+            log::info!("Pruned {seed:?} to {saved:?}");
+        }
+    }
+    log::info!("{failed} of {total} seeds fail the validation.");
+    Ok(())
+}
+
+fn find_redundant(project: String, seed_dir: &Option<PathBuf>, prune: bool) -> Result<()> {
+    let deopt = Deopt::new(project)?;
+    let seed_dir: PathBuf = if let Some(seed_dir) = seed_dir {
+        seed_dir.clone()
+    } else {
+        deopt.get_library_seed_dir()?
+    };
+    let mut seeds = Vec::new();
+    let mut sets = Vec::new();
+    for seed in crate::deopt::utils::read_sort_dir(&seed_dir)? {
+        if !seed.is_file() {
+            continue;
+        }
+        match CoverageSet::from_seed(&seed, &deopt) {
+            Ok(set) => {
+                seeds.push(seed);
+                sets.push(set);
+            }
+            // the seeds without coverage are kept, as their contributions are unknown.
+            Err(err) => log::warn!("Skip the seed without coverage: {seed:?}, {err}"),
+        }
+    }
+
+    let redundant = prompt_fuzz::minimize::find_redundant(&sets);
+    for &i in &redundant {
+        let seed = &seeds[i];
+        println!("{}", seed.display());
+        if prune {
+            let to = deopt.get_library_redundant_seed_dir()?.join(seed.file_name().unwrap());
+            std::fs::rename(seed, &to)?;
+            log::info!("Pruned {seed:?} to {to:?}");
+        }
+    }
+    log::info!("{} of {} seeds are redundant.", redundant.len(), seeds.len());
     Ok(())
 }
 
@@ -85,23 +474,49 @@ fn collect_coverage(project: String) -> Result<()> {
     Ok(())
 }
 
-fn report_coverage(project: String) -> Result<()> {
-    let deopt = Deopt::new(project)?;
+/// Collect the coverage of the fused cores by `executor`, and return the merged profdata.
+fn collect_cntg_profdata(deopt: &Deopt, executor: &Executor) -> Result<PathBuf> {
     let cntg_dir = deopt.get_library_cntg_dir()?;
     if !cntg_dir.exists() {
         eyre::bail!("CNTG directory not found: {cntg_dir:?}. Please run 'fuse-seeds' first.");
     }
 
-    // 1. Collect coverage
-    let executor = Executor::new(&deopt)?;
     executor.collect_cntg_cov_all_cores(&cntg_dir)?;
     log::info!("CNTG coverage collection completed successfully");
 
-    // 2. Report coverage
     let profdata_path: PathBuf = [cntg_dir.clone(), "default.profdata".into()].iter().collect();
     if !profdata_path.exists() {
         eyre::bail!("default.profdata not found in {cntg_dir:?}.");
     }
+    Ok(profdata_path)
+}
+
+/// The coverage summary of the merged profdata of all the cores.
+fn get_coverage_report(executor: &Executor, profdata: &Path) -> Result<CoverageReport> {
+    let coverage = executor.obtain_cov_summary_from_profdata(profdata)?;
+    Ok(coverage.get_total_summary().to_report())
+}
+
+fn coverage_json(project: String, coverage_gate: Option<f32>) -> Result<()> {
+    let deopt = Deopt::new(project)?;
+    let executor = Executor::new(&deopt)?;
+    let profdata_path = collect_cntg_profdata(&deopt, &executor)?;
+    let report = get_coverage_report(&executor, &profdata_path)?;
+    println!("{}", serde_json::to_string(&report)?);
+    if let Some(gate) = coverage_gate {
+        report.check_branch_gate(gate)?;
+    }
+    Ok(())
+}
+
+fn report_coverage(project: String, coverage_gate: Option<f32>) -> Result<()> {
+    let deopt = Deopt::new(project)?;
+
+    // 1. Collect coverage
+    let executor = Executor::new(&deopt)?;
+    let profdata_path = collect_cntg_profdata(&deopt, &executor)?;
+
+    // 2. Report coverage
 
     let cov_lib = crate::deopt::utils::get_cov_lib_path(&deopt, true);
 
@@ -122,6 +537,29 @@ fn report_coverage(project: String) -> Result<()> {
     std::fs::write(cov_report_path, &output.stdout)?;
     std::io::stdout().write_all(&output.stdout)?;
 
+    if let Some(gate) = coverage_gate {
+        get_coverage_report(&executor, &profdata_path)?.check_branch_gate(gate)?;
+    }
+    Ok(())
+}
+
+fn attribute_coverage(project: String, output: &Option<PathBuf>) -> Result<()> {
+    let deopt = Deopt::new(project)?;
+    let cntg_dir = deopt.get_library_cntg_dir()?;
+    if !cntg_dir.exists() {
+        eyre::bail!("CNTG directory not found: {cntg_dir:?}. Please run 'fuse-seeds' first.");
+    }
+    let executor = Executor::new(&deopt)?;
+    let contributions = attribution::attribute_cntg_coverage(&executor, &cntg_dir)?;
+    let output = output
+        .clone()
+        .unwrap_or_else(|| cntg_dir.join("coverage_attribution.csv"));
+    attribution::write_contributions(&contributions, &output)?;
+    let valuable = contributions.iter().filter(|c| c.unique_branches > 0).count();
+    log::info!(
+        "{valuable}/{} seeds contribute unique branches, written to {output:?}",
+        contributions.len()
+    );
     Ok(())
 }
 
@@ -136,6 +574,30 @@ fn record_coverage(project: String, batch_size: usize) -> Result<()> {
     Ok(())
 }
 
+fn register_target(
+    project: String,
+    headers: &Path,
+    lib: &Path,
+    static_lib_name: &Option<String>,
+    dyn_lib_name: &Option<String>,
+    config: &Option<PathBuf>,
+) -> Result<()> {
+    let mut lib_config: LibConfig = if let Some(config) = config {
+        serde_yaml::from_str(&std::fs::read_to_string(config)?)?
+    } else {
+        LibConfig::default()
+    };
+    lib_config.static_lib_name = static_lib_name
+        .clone()
+        .unwrap_or_else(|| format!("lib{project}.a"));
+    lib_config.dyn_lib_name = dyn_lib_name
+        .clone()
+        .unwrap_or_else(|| format!("lib{project}.so"));
+    lib_config.project_name = project;
+    Deopt::register_library(lib_config, headers, lib)?;
+    Ok(())
+}
+
 fn create_seeds(project: &str, fuzzer_args: &[String]) -> Result<()> {
     let mut cmd = Command::new("cargo");
     cmd.arg("run")
@@ -144,6 +606,26 @@ fn create_seeds(project: &str, fuzzer_args: &[String]) -> Result<()> {
         .arg("--")
         .arg(project)
         .args(fuzzer_args);
+    if prompt_fuzz::config::show_progress() && !fuzzer_args.iter().any(|arg| arg == "--progress") {
+        cmd.arg("--progress");
+    }
+    let (work_dir, seed_dir) = prompt_fuzz::config::get_dir_overrides();
+    if let Some(work_dir) = work_dir {
+        if !fuzzer_args.iter().any(|arg| arg == "--work-dir") {
+            cmd.arg("--work-dir").arg(work_dir);
+        }
+    }
+    if let Some(seed_dir) = seed_dir {
+        if !fuzzer_args.iter().any(|arg| arg == "--seed-dir") {
+            cmd.arg("--seed-dir").arg(seed_dir);
+        }
+    }
+
+    if let Some(entry_point) = prompt_fuzz::config::get_entry_point() {
+        if !fuzzer_args.iter().any(|arg| arg.starts_with("--entry-point")) {
+            cmd.arg("--entry-point").arg(entry_point);
+        }
+    }
 
     let status = cmd.status()?;
     if !status.success() {
@@ -152,31 +634,316 @@ fn create_seeds(project: &str, fuzzer_args: &[String]) -> Result<()> {
     Ok(())
 }
 
-fn all(project: String, fuzzer_args: &[String]) -> Result<()> {
+/// The outcome of a mode in `compare-modes`, a row of its CSV.
+#[derive(serde::Serialize)]
+struct ModeOutcome {
+    mode: String,
+    budget_secs: u64,
+    /// What the branch coverage is measured on, which differs between the modes.
+    coverage_of: String,
+    branch_covered: usize,
+    branch_total: usize,
+    branch_percent: f32,
+    unique_pairs: usize,
+    seeds: usize,
+}
+
+/// The options that `compare-modes` sets for each mode.
+const COMPARE_MODES_OPTIONS: [&str; 3] = ["--gen-mode", "--work-dir", "--seed-gen-timeout"];
+
+fn check_compare_args(fuzzer_args: &[String]) -> Result<()> {
+    let is_set = |arg: &String, option: &str| {
+        arg == option || arg.strip_prefix(option).is_some_and(|rest| rest.starts_with('='))
+    };
+    if fuzzer_args
+        .iter()
+        .any(|arg| COMPARE_MODES_OPTIONS.iter().any(|option| is_set(arg, option)))
+    {
+        eyre::bail!("`--gen-mode`, `--work-dir` and `--seed-gen-timeout` are set by compare-modes for each mode.");
+    }
+    Ok(())
+}
+
+/// The fuzzer config of `mode` in `compare-modes`: the fuzzer arguments with the mode, its work dir and its budget.
+/// The harness-wide options of `base` apply unless the fuzzer arguments set them.
+fn mode_config(
+    project: &str,
+    mode: &GenerationModeP,
+    work_dir: &Path,
+    minutes: u64,
+    fuzzer_args: &[String],
+    base: &prompt_fuzz::config::Config,
+) -> Result<prompt_fuzz::config::Config> {
+    let args = ["fuzzer".to_string(), project.to_string()]
+        .into_iter()
+        .chain(fuzzer_args.iter().cloned());
+    let mut config = prompt_fuzz::config::Config::try_parse_from(args)?;
+    if !config.entry_points.is_empty() {
+        eyre::bail!("compare-modes runs a single entry point, pass `--entry-point` instead of `--entry-points`.");
+    }
+    config.generation_mode = mode.clone();
+    config.work_dir = Some(work_dir.to_path_buf());
+    config.seed_gen_timeout = Some(minutes);
+    config.progress |= base.progress;
+    if config.entry_point.is_none() {
+        config.entry_point = base.entry_point.clone();
+    }
+    if config.cxx.is_none() {
+        config.cxx = base.cxx.clone();
+    }
+    if config.extra_cxxflags.is_none() {
+        config.extra_cxxflags = base.extra_cxxflags.clone();
+    }
+    prompt_fuzz::config::check_config(&config)?;
+    Ok(config)
+}
+
+/// Load the LLM service variables of the fuzzer arguments, as the fuzzer does on start.
+fn init_llm_env(project: &str, fuzzer_args: &[String]) -> Result<()> {
+    let args = ["fuzzer".to_string(), project.to_string()]
+        .into_iter()
+        .chain(fuzzer_args.iter().cloned());
+    let config = prompt_fuzz::config::Config::try_parse_from(args)?;
+    prompt_fuzz::config::load_env_file(config.env_file.as_deref())?;
+    prompt_fuzz::config::init_openai_env();
+    Ok(())
+}
+
+/// Generate the seeds of the installed config, whose programs are requested from `handler`.
+fn run_mode(handler: Box<dyn Handler>) -> Result<()> {
+    let mut fuzzer = Fuzzer::with_handler(handler)?;
+    fuzzer.fuzz_loop()
+}
+
+/// The coverage of a finished run in `work_dir`: the observed coverage of the fuzz drivers in FuzzDriver mode,
+/// and the coverage of the fused cores in ApiCombination mode, whose observer collects no coverage.
+/// The `coverage_of` column tells them apart.
+fn measure_mode(project: &str, mode: &GenerationModeP, work_dir: &Path, budget: u64) -> Result<ModeOutcome> {
+    let deopt = Deopt::new(project.to_string())?;
+    let (branch_covered, branch_total) = match mode {
+        GenerationModeP::FuzzDriver => match prompt_fuzz::feedback::observer::Observer::load_snapshot(&deopt)? {
+            Some(observer) => observer.get_branch_coverage(),
+            None => eyre::bail!("No observer snapshot is saved in {work_dir:?}"),
+        },
+        GenerationModeP::ApiCombination => {
+            fuse_seeds(project.to_string(), &None, None, false, true, None)?;
+            let executor = Executor::new(&deopt)?;
+            let profdata_path = collect_cntg_profdata(&deopt, &executor)?;
+            let report = get_coverage_report(&executor, &profdata_path)?;
+            (report.branches.covered, report.branches.total)
+        }
+    };
+    let seeds: Vec<Program> = crate::deopt::utils::read_sort_dir(&deopt.get_library_seed_dir()?)?
+        .iter()
+        .filter(|seed| seed.is_file())
+        .filter_map(|seed| Program::load_from_path(seed).ok())
+        .collect();
+    Ok(ModeOutcome {
+        mode: format!("{mode:?}"),
+        budget_secs: budget,
+        coverage_of: match mode {
+            GenerationModeP::FuzzDriver => "drivers",
+            GenerationModeP::ApiCombination => "fused cores",
+        }
+        .to_string(),
+        branch_covered,
+        branch_total,
+        branch_percent: if branch_total == 0 {
+            0_f32
+        } else {
+            100_f32 * branch_covered as f32 / branch_total as f32
+        },
+        unique_pairs: prompt_fuzz::minimize::unique_api_pairs(&seeds).len(),
+        seeds: seeds.len(),
+    })
+}
+
+/// Run the fuzzer in each mode in turn with the programs requested from `handler`, and compare their outcomes.
+fn compare_modes(
+    project: String,
+    budget: u64,
+    fuzzer_args: &[String],
+    handler: impl Fn() -> Box<dyn Handler>,
+) -> Result<()> {
+    if budget == 0 {
+        eyre::bail!("--budget should be positive");
+    }
+    check_compare_args(fuzzer_args)?;
+    let compare_dir = Deopt::new(project.clone())?.get_library_output_dir()?.join("compare");
+    let minutes = budget.div_ceil(60);
+    if minutes * 60 != budget {
+        log::warn!("The budget {budget}s is rounded up to {minutes} minutes.");
+    }
+    let mut outcomes = Vec::new();
+    for (mode, name) in [
+        (GenerationModeP::FuzzDriver, "fuzz-driver"),
+        (GenerationModeP::ApiCombination, "api-combination"),
+    ] {
+        let work_dir = compare_dir.join(name);
+        let base = prompt_fuzz::config::get_config();
+        let config = mode_config(&project, &mode, &work_dir, minutes, fuzzer_args, &base)?;
+        drop(base);
+        log::info!("Run the {mode:?} mode for {minutes} minutes in {work_dir:?}");
+        let instance = prompt_fuzz::config::CONFIG_INSTANCE.get().unwrap();
+        let base = std::mem::replace(&mut *instance.write().unwrap(), config);
+        let outcome = run_mode(handler()).and_then(|_| measure_mode(&project, &mode, &work_dir, budget));
+        *instance.write().unwrap() = base;
+        outcomes.push(outcome?);
+    }
+
+    let csv_path = compare_dir.join("compare_modes.csv");
+    let mut writer = csv::Writer::from_path(&csv_path)?;
+    println!(
+        "{:<16} {:>12} {:>16} {:>10} {:>14} {:>8}",
+        "mode", "coverage of", "branches", "percent", "unique pairs", "seeds"
+    );
+    for outcome in &outcomes {
+        println!(
+            "{:<16} {:>12} {:>16} {:>9.2}% {:>14} {:>8}",
+            outcome.mode,
+            outcome.coverage_of,
+            format!("{}/{}", outcome.branch_covered, outcome.branch_total),
+            outcome.branch_percent,
+            outcome.unique_pairs,
+            outcome.seeds
+        );
+        writer.serialize(outcome)?;
+    }
+    writer.flush()?;
+    log::info!("Write the comparison to {csv_path:?}");
+    Ok(())
+}
+
+fn all(project: String, fuzzer_args: &[String], fuzzer_args_file: &Option<PathBuf>) -> Result<()> {
+    let mut fuzzer_args = fuzzer_args.to_vec();
+    if let Some(path) = fuzzer_args_file {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| eyre::eyre!("Failed to read the fuzzer args file {path:?}: {err}"))?;
+        fuzzer_args.extend(prompt_fuzz::config::parse_fuzzer_args_file(&content));
+    }
     // 1. Create seeds
-    create_seeds(&project, fuzzer_args)?;
+    create_seeds(&project, &fuzzer_args)?;
 
     // 2. Fuse seeds
-    fuse_seeds(project.clone(), &None, None)?;
+    fuse_seeds(project.clone(), &None, None, false, true, None)?;
 
     // 3. Report coverage
-    report_coverage(project)
+    report_coverage(project, None)
 }
 
 fn main() -> ExitCode {
     let config = Config::parse();
     prompt_fuzz::config::Config::init_test(&config.project);
+    if config.progress {
+        let instance = prompt_fuzz::config::CONFIG_INSTANCE.get().unwrap();
+        instance.write().unwrap().progress = true;
+    }
+    if let Some(name) = &config.core_binary_name {
+        let instance = prompt_fuzz::config::CONFIG_INSTANCE.get().unwrap();
+        instance.write().unwrap().core_binary_name = name.clone();
+    }
+    if let Some(work_dir) = &config.work_dir {
+        let instance = prompt_fuzz::config::CONFIG_INSTANCE.get().unwrap();
+        instance.write().unwrap().work_dir = Some(work_dir.clone());
+    }
+    if let Some(seed_dir) = &config.library_seed_dir {
+        let instance = prompt_fuzz::config::CONFIG_INSTANCE.get().unwrap();
+        instance.write().unwrap().seed_dir = Some(seed_dir.clone());
+    }
+    if let Some(entry_point) = &config.entry_point {
+        let instance = prompt_fuzz::config::CONFIG_INSTANCE.get().unwrap();
+        instance.write().unwrap().entry_point = Some(entry_point.clone());
+    }
+    if config.cxx.is_some() || config.extra_cxxflags.is_some() {
+        let instance = prompt_fuzz::config::CONFIG_INSTANCE.get().unwrap();
+        let mut instance = instance.write().unwrap();
+        instance.cxx = config.cxx.clone();
+        instance.extra_cxxflags = config.extra_cxxflags.clone();
+    }
     let project = config.project.clone();
     match &config.command {
         Commands::FuseSeeds {
             seed_dir,
             batch_size,
+            keep_artifacts,
+            by_quality,
+            no_dedup,
+            top_k,
         } => {
-            if let Err(err) = fuse_seeds(project, seed_dir, *batch_size) {
+            if *keep_artifacts {
+                let config = prompt_fuzz::config::CONFIG_INSTANCE.get().unwrap();
+                config.write().unwrap().keep_artifacts = true;
+            }
+            if let Err(err) = fuse_seeds(project, seed_dir, *batch_size, *by_quality, !*no_dedup, *top_k) {
                 log::error!("Failed to fuse seeds: {}", err);
                 return ExitCode::FAILURE;
             }
         }
+        Commands::ValidateSeeds { seed_dir, prune } => {
+            if let Err(err) = validate_seeds(project, seed_dir, *prune) {
+                log::error!("Failed to validate seeds: {}", err);
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        }
+        Commands::FindRedundant { seed_dir, prune } => {
+            if let Err(err) = find_redundant(project, seed_dir, *prune) {
+                log::error!("Failed to find redundant seeds: {}", err);
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        }
+        Commands::Preflight => {
+            let result = Deopt::new(project)
+                .and_then(|deopt| prompt_fuzz::execution::preflight::preflight_library(&deopt));
+            if let Err(err) = result {
+                log::error!("Preflight failed: {}", err);
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        }
+        Commands::Replay { binary, input } => match replay(project, binary, input) {
+            Ok(true) => return ExitCode::FAILURE,
+            Ok(false) => {
+                log::info!("The crash is not reproduced.");
+                return ExitCode::SUCCESS;
+            }
+            Err(err) => {
+                log::error!("Failed to replay {input:?}: {}", err);
+                return ExitCode::from(2);
+            }
+        },
+        Commands::PairsDiff { file_a, file_b } => {
+            if let Err(err) = pairs_diff(file_a, file_b) {
+                log::error!("Failed to diff the API pairs: {}", err);
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        }
+        Commands::ListGadgets {
+            json,
+            include_only_apis,
+            exclude_apis,
+        } => {
+            {
+                let instance = prompt_fuzz::config::CONFIG_INSTANCE.get().unwrap();
+                let mut instance = instance.write().unwrap();
+                instance.include_only_apis = include_only_apis.clone();
+                instance.exclude_apis = exclude_apis.clone();
+            }
+            if let Err(err) = list_gadgets(*json) {
+                log::error!("Failed to list the gadgets: {}", err);
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        }
+        Commands::PairsGraph { input, output } => {
+            if let Err(err) = pairs_graph(project, input, output) {
+                log::error!("Failed to render the API pairs: {}", err);
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        }
         Commands::CollectCoverage => {
             if let Err(err) = collect_coverage(project) {
                 log::error!("Failed to collect coverage: {}", err);
@@ -184,13 +951,27 @@ fn main() -> ExitCode {
             }
             return ExitCode::SUCCESS;
         }
-        Commands::ReportCoverage => {
-            if let Err(err) = report_coverage(project) {
+        Commands::ReportCoverage { coverage_gate } => {
+            if let Err(err) = report_coverage(project, *coverage_gate) {
+                log::error!("Failed to report coverage: {}", err);
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        }
+        Commands::CoverageJson { coverage_gate } => {
+            if let Err(err) = coverage_json(project, *coverage_gate) {
                 log::error!("Failed to report coverage: {}", err);
                 return ExitCode::FAILURE;
             }
             return ExitCode::SUCCESS;
         }
+        Commands::AttributeCoverage { output } => {
+            if let Err(err) = attribute_coverage(project, output) {
+                log::error!("Failed to attribute coverage: {}", err);
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        }
         Commands::RecordCoverage {
             batch_size
         } => {
@@ -200,8 +981,36 @@ fn main() -> ExitCode {
             }
             return ExitCode::SUCCESS;
         }
-        Commands::All { fuzzer_args } => {
-            if let Err(err) = all(project, fuzzer_args) {
+        Commands::RegisterTarget {
+            headers,
+            lib,
+            static_lib_name,
+            dyn_lib_name,
+            config,
+        } => {
+            if let Err(err) = register_target(project, headers, lib, static_lib_name, dyn_lib_name, config) {
+                log::error!("Failed to register target: {}", err);
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        }
+        Commands::CompareModes {
+            budget,
+            fuzzer_args,
+        } => {
+            let result = init_llm_env(&project, fuzzer_args)
+                .and_then(|_| compare_modes(project, *budget, fuzzer_args, Fuzzer::default_handler));
+            if let Err(err) = result {
+                log::error!("Failed to compare the modes: {}", err);
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        }
+        Commands::All {
+            fuzzer_args,
+            fuzzer_args_file,
+        } => {
+            if let Err(err) = all(project, fuzzer_args, fuzzer_args_file) {
                 log::error!("Failed to run all: {}", err);
                 return ExitCode::FAILURE;
             }
@@ -210,3 +1019,43 @@ fn main() -> ExitCode {
     };
     ExitCode::SUCCESS
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_check_compare_args() {
+        assert!(check_compare_args(&args(&["--n-sample", "8", "--work-dirs"])).is_ok());
+        for arg in ["--gen-mode", "--gen-mode=fuzz-driver", "--work-dir=/tmp/out", "--seed-gen-timeout=5"] {
+            assert!(check_compare_args(&args(&[arg])).is_err(), "{arg} is not rejected");
+        }
+    }
+
+    #[test]
+    fn test_mode_config() -> Result<()> {
+        let base = prompt_fuzz::config::Config::parse_from(["fuzzer", "cJSON", "--progress", "--entry-point", "parse"]);
+        let work_dir = Path::new("/tmp/compare/fuzz-driver");
+        let fuzzer_args = args(&["--n-sample", "8", "--entry-point", "print"]);
+        let config = mode_config("cJSON", &GenerationModeP::FuzzDriver, work_dir, 2, &fuzzer_args, &base)?;
+        assert_eq!(config.generation_mode, GenerationModeP::FuzzDriver);
+        assert_eq!(config.work_dir.as_deref(), Some(work_dir));
+        assert_eq!(config.seed_gen_timeout, Some(2));
+        assert_eq!(config.n_sample, 8);
+        assert!(config.progress);
+        // the fuzzer arguments take precedence over the harness-wide options.
+        assert_eq!(config.entry_point.as_deref(), Some("print"));
+
+        let config = mode_config("cJSON", &GenerationModeP::ApiCombination, work_dir, 2, &[], &base)?;
+        assert_eq!(config.generation_mode, GenerationModeP::ApiCombination);
+        assert_eq!(config.entry_point.as_deref(), Some("parse"));
+
+        let fuzzer_args = args(&["--entry-points", "parse,print"]);
+        assert!(mode_config("cJSON", &GenerationModeP::FuzzDriver, work_dir, 2, &fuzzer_args, &base).is_err());
+        Ok(())
+    }
+}
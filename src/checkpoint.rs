@@ -0,0 +1,121 @@
+//! The periodic checkpoint of `--save-every`, written to `misc/checkpoint.json` of the library.
+//!
+//! The observer snapshot and the prompt/exec counters are persisted on their own, so a checkpoint
+//! only completes them with the states that are otherwise kept in memory or recovered from the log:
+//! the schedule energies and the logger counters.
+
+use std::path::Path;
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::execution::logger::ProgramLogger;
+use crate::feedback::schedule::Schedule;
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The loop of this execution at which the checkpoint is written.
+    pub loop_cnt: usize,
+    pub seed_id: usize,
+    pub quiet_round: usize,
+    pub schedule_loop_count: u32,
+    /// The energy and the sampling weight of each API.
+    pub energies: Vec<(String, f32, f32)>,
+    /// The state lines of the logger, see `ProgramLogger::state_lines`.
+    pub logger: Vec<String>,
+    /// The log file name of the execution that writes the checkpoint.
+    #[serde(default)]
+    pub run: Option<String>,
+}
+
+impl Checkpoint {
+    pub fn collect(
+        loop_cnt: usize,
+        seed_id: usize,
+        quiet_round: usize,
+        schedule: &Schedule,
+        logger: &ProgramLogger,
+    ) -> Self {
+        Self {
+            loop_cnt,
+            seed_id,
+            quiet_round,
+            schedule_loop_count: schedule.loop_count,
+            energies: schedule.get_energies(),
+            logger: logger.state_lines(),
+            run: None,
+        }
+    }
+
+    /// Whether the checkpoint is written by the execution of the `log` file.
+    pub fn is_of_run(&self, log: &Path) -> bool {
+        self.run.is_some() && self.run.as_deref() == log.file_name().and_then(|name| name.to_str())
+    }
+
+    pub fn restore_logger(&self) -> Result<ProgramLogger> {
+        ProgramLogger::snyc_from_str(&self.logger.join("\n"))
+    }
+
+    /// Write to a temporary file first, so that a kill during the write leaves the last checkpoint intact.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Load the checkpoint, None if there is none.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+}
+
+/// Whether to write a checkpoint after `loop_cnt` loops, given `--save-every`.
+pub fn should_checkpoint(loop_cnt: usize, save_every: Option<usize>) -> bool {
+    save_every.is_some_and(|every| every > 0 && loop_cnt > 0 && loop_cnt.is_multiple_of(every))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_checkpoint() {
+        assert!(!should_checkpoint(3, None));
+        assert!(!should_checkpoint(0, Some(2)));
+        assert!(!should_checkpoint(3, Some(2)));
+        assert!(should_checkpoint(4, Some(2)));
+        assert!(should_checkpoint(1, Some(1)));
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() -> Result<()> {
+        let mut logger = ProgramLogger::default();
+        logger.log_succ();
+        let mut checkpoint = Checkpoint::collect(4, 7, 2, &Schedule::new(), &logger);
+        checkpoint.energies = vec![("compress".to_string(), 0.5, 0.25)];
+        let path = std::env::temp_dir().join("prompt_fuzz_checkpoint.json");
+        checkpoint.save(&path)?;
+        let loaded = Checkpoint::load(&path)?.unwrap();
+        assert_eq!(loaded, checkpoint);
+        assert_eq!(loaded.restore_logger()?.get_gc_succ(), 1);
+        std::fs::remove_file(&path)?;
+        assert!(Checkpoint::load(&path)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint_of_run() {
+        let log = Path::new("/output/cJSON/fuzzer_2024-01-02_10-00-00.log");
+        let mut checkpoint = Checkpoint::default();
+        assert!(!checkpoint.is_of_run(log));
+        checkpoint.run = Some("fuzzer_2024-01-01_10-00-00.log".to_string());
+        assert!(!checkpoint.is_of_run(log));
+        checkpoint.run = Some("fuzzer_2024-01-02_10-00-00.log".to_string());
+        assert!(checkpoint.is_of_run(log));
+    }
+}
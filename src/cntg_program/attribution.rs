@@ -0,0 +1,117 @@
+/// Attribute the coverage of fused CNTG cores to the seeds fused in them.
+///
+/// The drivers in a core share the library, so the per-function data of the
+/// `test_<lib>_api_sequence_<id>` functions cannot tell which library branches
+/// each driver reaches. Instead, each driver is run alone by `core <driver_id>`
+/// and its library branches are compared with those of all other drivers.
+use crate::cntg_program::{get_core_path, CoreManifest};
+use crate::execution::Executor;
+use crate::feedback::branches::Branch;
+use eyre::Result;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// The coverage contributed by a seed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SeedContribution {
+    pub core: String,
+    pub driver_id: usize,
+    pub seed: PathBuf,
+    /// The library branches covered by this seed.
+    pub covered_branches: usize,
+    /// The library branches covered by this seed only.
+    pub unique_branches: usize,
+}
+
+/// For each branch set, count the branches not in any other set.
+pub fn count_unique_branches(covers: &[HashSet<Branch>]) -> Vec<usize> {
+    let mut hits: HashMap<&Branch, usize> = HashMap::new();
+    for cover in covers {
+        for branch in cover {
+            *hits.entry(branch).or_default() += 1;
+        }
+    }
+    covers
+        .iter()
+        .map(|cover| cover.iter().filter(|branch| hits[branch] == 1).count())
+        .collect()
+}
+
+/// Run each driver of the cores in `cntg_dir` alone, and attribute the covered branches to its seed.
+/// The cores without a manifest are skipped.
+pub fn attribute_cntg_coverage(executor: &Executor, cntg_dir: &Path) -> Result<Vec<SeedContribution>> {
+    let mut core_dirs: Vec<PathBuf> = std::fs::read_dir(cntg_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    core_dirs.sort();
+
+    let mut contributions = Vec::new();
+    let mut covers = Vec::new();
+    for core_dir in core_dirs {
+        let Ok(manifest) = CoreManifest::load(&core_dir) else {
+            log::warn!("No manifest in {core_dir:?}, its coverage is not attributed.");
+            continue;
+        };
+        let core_binary = get_core_path(&core_dir);
+        let core = core_dir.file_name().unwrap().to_string_lossy().to_string();
+        for driver in manifest.drivers {
+            let profdata = core_dir.join(format!("driver_{}.profdata", driver.driver_id));
+            let cover: HashSet<Branch> =
+                match executor.execute_cov_cntg_driver(&core_binary, driver.driver_id, &profdata) {
+                    Ok(true) => executor
+                        .obtain_cov_from_profdata(&profdata)?
+                        .get_covered_branch()
+                        .into_iter()
+                        .collect(),
+                    Ok(false) => {
+                        log::warn!("driver {} of {core} timed out.", driver.driver_id);
+                        HashSet::new()
+                    }
+                    Err(err) => {
+                        log::warn!("driver {} of {core} failed: {err}", driver.driver_id);
+                        HashSet::new()
+                    }
+                };
+            contributions.push(SeedContribution {
+                core: core.clone(),
+                driver_id: driver.driver_id,
+                seed: driver.seed,
+                covered_branches: cover.len(),
+                unique_branches: 0,
+            });
+            covers.push(cover);
+        }
+    }
+    for (contribution, unique) in contributions.iter_mut().zip(count_unique_branches(&covers)) {
+        contribution.unique_branches = unique;
+    }
+    Ok(contributions)
+}
+
+pub fn write_contributions(contributions: &[SeedContribution], path: &Path) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for contribution in contributions {
+        writer.serialize(contribution)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_unique_branches() {
+        let branch = |line: usize| -> Branch { [line, 0, 0, 0, 0, 0, 0, 0] };
+        let covers = vec![
+            HashSet::from([branch(1), branch(2)]),
+            HashSet::from([branch(2), branch(3), branch(4)]),
+            HashSet::from([branch(2)]),
+            HashSet::new(),
+        ];
+        assert_eq!(count_unique_branches(&covers), vec![1, 2, 0, 0]);
+    }
+}
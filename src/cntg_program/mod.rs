@@ -3,12 +3,65 @@
 ///
 /// CNTG programs generated via ApiCombination mode differs from fuzzers in that
 /// CNTG programs require a main method.
+///
+/// This is the only CNTGProgram of the crate: the harness (`fuse-seeds`) and the
+/// coverage of seed metas both use it. All steps take the directory they work in,
+/// and `build` runs the whole clone -> synthesis -> compile pipeline.
 
+pub mod attribution;
 pub mod seed_metas;
 
 use crate::deopt::Deopt;
+use crate::progress::ProgressBar;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
 use eyre::{Context, Result, eyre};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// The driver function named like `test_<lib>_api_sequence`, tolerating the deviations of LLMs.
+static DRIVER_FUNC: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\b(test_\w*_api_sequence\w*)\s*\(").unwrap());
+
+/// The file name of the manifest in each core dir.
+pub const CORE_MANIFEST_NAME: &str = "manifest.json";
+
+/// The manifest of a fused core, which maps the coverage of the core back to its seeds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CoreManifest {
+    pub core_id: usize,
+    /// The binary name within the core dir.
+    pub binary: String,
+    pub drivers: Vec<CoreDriver>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CoreDriver {
+    /// The id in the renamed `test_<lib>_api_sequence_<driver_id>` function.
+    pub driver_id: usize,
+    /// The source file within the core dir.
+    pub file: String,
+    /// The originating seed.
+    pub seed: PathBuf,
+}
+
+impl CoreManifest {
+    pub fn load(core_dir: &Path) -> Result<Self> {
+        let path = core_dir.join(CORE_MANIFEST_NAME);
+        let content =
+            std::fs::read_to_string(&path).context(format!("Unable to read {path:?}"))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, core_dir: &Path) -> Result<()> {
+        let path = core_dir.join(CORE_MANIFEST_NAME);
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .context(format!("Unable to write {path:?}"))
+    }
+}
 
 /// CNTGProgram represents a single executable created from multiple API combination programs.
 /// Unlike LibFuzzer, this keeps the original main() functions and fuses them into one binary.
@@ -16,6 +69,8 @@ use eyre::{Context, Result, eyre};
 pub struct CNTGProgram {
     /// programs to fuse into a single executable
     programs: Vec<PathBuf>,
+    /// the originating seeds of `programs`, which are kept by `chdir`
+    seeds: Vec<PathBuf>,
     /// number of programs coalesced to a huge executable
     batch: usize,
     /// Deopt
@@ -29,6 +84,7 @@ impl CNTGProgram {
         deopt: &Deopt,
     ) -> Self {
         Self {
+            seeds: programs.clone(),
             programs,
             batch: batch_size,
             deopt: deopt.clone(),
@@ -37,6 +93,10 @@ impl CNTGProgram {
 
     /// Initialize the default directories by clearing old data
     pub fn reset(&self) -> Result<()> {
+        if crate::config::keep_artifacts() {
+            log::info!("--keep-artifacts is set, the old CNTG and driver dirs are kept.");
+            return Ok(());
+        }
         let cntg_dir = self.deopt.get_library_cntg_dir()?;
         if cntg_dir.exists() {
             std::fs::remove_dir_all(cntg_dir)?;
@@ -48,31 +108,67 @@ impl CNTGProgram {
         Ok(())
     }
 
+    /// Drop the programs that are byte-identical to an earlier one, and return the number of dropped programs.
+    pub fn dedup(&mut self) -> Result<usize> {
+        let mut hashes = HashSet::new();
+        let mut unique = Vec::new();
+        let mut unique_seeds = Vec::new();
+        for (program, seed) in self.programs.iter().zip(self.seeds.iter()) {
+            let content =
+                std::fs::read(program).context(format!("Unable to read {program:?}"))?;
+            let mut hasher = DefaultHasher::new();
+            content.hash(&mut hasher);
+            if hashes.insert(hasher.finish()) {
+                unique.push(program.clone());
+                unique_seeds.push(seed.clone());
+            }
+        }
+        let removed = self.programs.len() - unique.len();
+        log::info!("Removed {removed} duplicate programs, {} programs remain.", unique.len());
+        self.programs = unique;
+        self.seeds = unique_seeds;
+        Ok(removed)
+    }
+
     /// Clone the programs to dst_dir and work on the cloned programs from now
     /// on.
     ///
     /// Returns cloned program paths
     pub fn chdir(&mut self, dst_dir: &Path) -> Result<Vec<PathBuf>> {
         let mut new_programs = Vec::new();
+        let bar = ProgressBar::new("Clone programs", self.programs.len());
         for (id, program) in self.programs.iter().enumerate() {
             let dst_path = dst_dir.join(format!("id_{number:>0width$}.cc", number = id, width = 6));
             std::fs::copy(program, &dst_path)
                 .context(format!("Unable to copy {program:?} to {dst_path:?}"))?;
             new_programs.push(dst_path);
+            bar.inc(1);
         }
         self.programs = new_programs.clone();
         Ok(new_programs)
     }
 
+    /// Clone the programs to `driver_dir`, then synthesize and compile the cores in `core_dir`.
+    pub fn build(&mut self, driver_dir: &Path, core_dir: &Path) -> Result<()> {
+        self.chdir(driver_dir)?;
+        self.synthesis(core_dir)?;
+        self.compile(core_dir)
+    }
+
     /// Synthesize the separate CNTG drivers/seeds into a large programs.
     ///
     /// Each program contains `self.batch` number of seeds and a large core that calls functions in each seed sequentially.
     pub fn synthesis(&mut self, outdir: &Path) -> Result<()> {
         log::debug!("synthesis huge CNTG cores!");
+        self.drop_unmatched_drivers()?;
+        if self.programs.is_empty() {
+            eyre::bail!("No driver to synthesize.");
+        }
 
         let mut batch = Vec::new();
         let mut batch_id = Vec::new();
         let mut core_id = 0;
+        let bar = ProgressBar::new("Synthesize cores", self.programs.len().div_ceil(self.batch));
 
         for (i, driver) in self.programs.clone().iter().enumerate() {
             batch.push(driver.clone());
@@ -83,11 +179,38 @@ impl CNTGProgram {
                 batch.clear();
                 batch_id.clear();
                 core_id += 1;
+                bar.inc(1);
             }
         }
         Ok(())
     }
 
+    /// Drop the drivers without a recognizable driver function, which would break the link of their cores.
+    fn drop_unmatched_drivers(&mut self) -> Result<()> {
+        let mut programs = Vec::new();
+        let mut seeds = Vec::new();
+        let mut unmatched = Vec::new();
+        for (program, seed) in self.programs.iter().zip(self.seeds.iter()) {
+            let content = std::fs::read_to_string(program)
+                .context(format!("Unable to read {program:?}"))?;
+            if find_driver_function(&content).is_some() {
+                programs.push(program.clone());
+                seeds.push(seed.clone());
+            } else {
+                unmatched.push(seed.clone());
+            }
+        }
+        if !unmatched.is_empty() {
+            log::warn!(
+                "{} seeds have no `test_<lib>_api_sequence` function and are not fused: {unmatched:?}",
+                unmatched.len()
+            );
+        }
+        self.programs = programs;
+        self.seeds = seeds;
+        Ok(())
+    }
+
     /// Write 
     fn synthesis_batch(&mut self, batch_id: &Vec<usize>) -> Result<String> {
         let mut stmts = String::new();
@@ -104,13 +227,16 @@ impl CNTGProgram {
         }
         stmts.push_str("\n\n");
 
+        // `core <driver_id>` runs only that driver, which attributes the coverage to seeds.
         stmts.push_str(
             "int main(int argc, char* argv[])\n{\n",
         );
+        stmts.push_str("\tint only = argc > 1 ? atoi(argv[1]) : -1;\n");
         for (i, id) in batch_id.iter().enumerate() {
-            stmts.push_str(&format!("\tstd::cout << \"Running program {i}...\" << std::endl;\n"));
+            stmts.push_str(&format!("\tif (only < 0 || only == {id}) {{\n"));
+            stmts.push_str(&format!("\t\tstd::cout << \"Running program {i}...\" << std::endl;\n"));
             stmts.push_str(&format!(
-                "\ttest_{}_api_sequence_{id}();\n",
+                "\t\ttest_{}_api_sequence_{id}();\n\t}}\n",
                 lib
             ));
         }
@@ -121,6 +247,7 @@ impl CNTGProgram {
 
 
     /// Write the single core with multiple drivers' source files, renaming driver functions to link with core.
+    /// The manifest of the core records the drivers and the binary name to compile to.
     fn fuse_core(
         &self,
         outdir: &Path,
@@ -133,17 +260,26 @@ impl CNTGProgram {
         crate::deopt::utils::create_dir_if_nonexist(&core_dir)?;
         // write the condensed core
         let core_path: PathBuf = [core_dir.clone(), "core.cc".into()].iter().collect();
-        let new_core_content=format!("#include <cstddef>\n{}",core_content);
+        let new_core_content=format!("#include <cstddef>\n#include <cstdlib>\n{}",core_content);
         std::fs::write(core_path, new_core_content)?;
 
+        let mut manifest = CoreManifest {
+            core_id,
+            binary: crate::config::get_core_binary_name(),
+            drivers: Vec::new(),
+        };
         for (id, driver) in drivers.iter().enumerate() {
             // write each unit driver with new driver id.
-            let dst_driver: PathBuf = [core_dir.clone(), driver.file_name().unwrap().into()]
-                .iter()
-                .collect();
+            let file_name = driver.file_name().unwrap();
+            let dst_driver: PathBuf = [core_dir.clone(), file_name.into()].iter().collect();
             self.change_driver_id(driver, &dst_driver, driver_id[id])?;
+            manifest.drivers.push(CoreDriver {
+                driver_id: driver_id[id],
+                file: file_name.to_string_lossy().to_string(),
+                seed: self.seeds.get(driver_id[id]).unwrap_or(driver).clone(),
+            });
         }
-        Ok(())
+        manifest.save(&core_dir)
     }
 
     fn get_core_dir(&self, outdir: &Path, core_id: usize) -> Result<PathBuf> {
@@ -163,12 +299,8 @@ impl CNTGProgram {
         driver_id: usize,
     ) -> Result<()> {
         let buf = std::fs::read_to_string(src_driver)?;
-        let library_name = self.deopt.project_name.clone();
-        let function_name = format!("test_{}_api_sequence", library_name);
-        let buf = buf.replace(
-            &function_name,
-            &format!("{}_{}", function_name, driver_id),
-        );
+        let buf = rename_driver_function(&buf, &self.deopt.project_name, driver_id)
+            .ok_or_else(|| eyre!("No driver function is found in {src_driver:?}"))?;
         std::fs::write(dst_driver, buf)?;
         Ok(())
     }
@@ -176,32 +308,35 @@ impl CNTGProgram {
     /// Compile all cores in cores_dir
     ///
     /// Each core should be in a folder generated by synthesis:
-    /// <cores_dir>/Core_<id>/core.cc
+    /// <cores_dir>/Core_<id>/core.cc, and is compiled to the binary named in its manifest.
     pub fn compile(&self, cores_dir: &Path) -> Result<()> {
         let executor = crate::execution::Executor::new(&self.deopt)?;
+        let core_dirs = list_core_dirs(cores_dir)?;
+        let bar = ProgressBar::new("Compile cores", core_dirs.len());
         std::thread::scope(|s| {
-            let mut handles = Vec::<std::thread::ScopedJoinHandle::<()>>::new();
-            for dir in std::fs::read_dir(cores_dir).unwrap() {
+            let mut handles = Vec::<std::thread::ScopedJoinHandle::<Result<()>>>::new();
+            let executor = &executor;
+            let bar = &bar;
+            for core_dir in &core_dirs {
                 handles.push(
-                    s.spawn(|| {
-                        let core_dir = dir.unwrap().path();
-                        if core_dir.is_dir() {
-                            log::debug!("Compile to Core: {core_dir:?}");
-                            let core_binary = get_core_path(&core_dir);
-                            executor.compile_lib_fuzzers(
-                                &core_dir,
-                                &core_binary,
-                                crate::execution::Compile::CoverageNoFuzz,
-                            ).unwrap();
-                            self.deopt.copy_library_init_file(&core_dir).unwrap();
-                        }
+                    s.spawn(move || {
+                        log::debug!("Compile to Core: {core_dir:?}");
+                        let core_binary = get_core_path(core_dir);
+                        executor.compile_core_fuzzers(
+                            core_dir,
+                            &core_binary,
+                            crate::execution::Compile::CoverageNoFuzz,
+                        )?;
+                        self.deopt.copy_library_init_file(core_dir)?;
+                        bar.inc(1);
+                        Ok(())
                     })
                 );
             }
             for handle in handles {
-                let result = handle.join();
-                if result.is_err() {
-                    return Err(eyre!(""));
+                match handle.join() {
+                    Ok(result) => result?,
+                    Err(_) => return Err(eyre!("A thread compiling the cores panicked")),
                 }
             }
             return Ok(());
@@ -209,6 +344,169 @@ impl CNTGProgram {
     }
 }
 
+/// The `k` seeds of the highest scores, keyed by their file stems, in the descending order of the scores. The seeds
+/// without a score are skipped, and the ties keep the order of `seeds`.
+pub fn select_top_k_seeds(
+    seeds: Vec<PathBuf>,
+    scores: &std::collections::HashMap<String, f32>,
+    k: usize,
+) -> Vec<(PathBuf, f32)> {
+    let mut scored: Vec<(PathBuf, f32)> = seeds
+        .into_iter()
+        .filter_map(|seed| {
+            let score = *scores.get(seed.file_stem()?.to_str()?)?;
+            Some((seed, score))
+        })
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(k);
+    scored
+}
+
+/// The `Core_XXX` dirs in `cores_dir`, sorted by their core ids to be independent of the readdir order.
+pub fn list_core_dirs(cores_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut core_dirs = crate::deopt::utils::read_sort_dir(cores_dir)?;
+    core_dirs.retain(|dir| dir.is_dir());
+    Ok(core_dirs)
+}
+
+/// The name of the driver function in `content`, if any.
+pub fn find_driver_function(content: &str) -> Option<&str> {
+    DRIVER_FUNC
+        .captures(content)
+        .map(|caps| caps.get(1).unwrap().as_str())
+}
+
+/// Rename the driver function in `content` to `test_<lib>_api_sequence_<driver_id>`.
+pub fn rename_driver_function(content: &str, lib: &str, driver_id: usize) -> Option<String> {
+    let name = find_driver_function(content)?;
+    let pattern = Regex::new(&format!(r"\b{}\b", regex::escape(name))).unwrap();
+    let new_name = format!("test_{lib}_api_sequence_{driver_id}");
+    Some(pattern.replace_all(content, new_name.as_str()).to_string())
+}
+
+/// The binary of the core, named by its manifest if any, otherwise by `--core-binary-name`.
 pub fn get_core_path(core_dir: &Path) -> PathBuf {
-    [core_dir.to_path_buf(), "core".into()].iter().collect()
+    let binary = CoreManifest::load(core_dir)
+        .map(|manifest| manifest.binary)
+        .unwrap_or_else(|_| crate::config::get_core_binary_name());
+    core_dir.join(binary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_top_k_seeds() {
+        let seeds: Vec<PathBuf> = ["id_000001.cc", "id_000002.cc", "id_000003.cc", "id_000004.cc"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let scores = std::collections::HashMap::from([
+            ("id_000001".to_string(), 3.0),
+            ("id_000002".to_string(), 8.0),
+            ("id_000004".to_string(), 3.0),
+        ]);
+        let selected = select_top_k_seeds(seeds.clone(), &scores, 2);
+        assert_eq!(
+            selected,
+            vec![(seeds[1].clone(), 8.0), (seeds[0].clone(), 3.0)]
+        );
+        assert_eq!(select_top_k_seeds(seeds, &scores, 10).len(), 3);
+    }
+
+    #[test]
+    fn test_dedup_programs() -> Result<()> {
+        let dir = std::env::temp_dir().join("prompt_fuzz_dedup_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        let mut programs = Vec::new();
+        for (name, content) in [("a.cc", "int a;"), ("b.cc", "int b;"), ("c.cc", "int a;")] {
+            let path = dir.join(name);
+            std::fs::write(&path, content)?;
+            programs.push(path);
+        }
+        let mut cntg_program = CNTGProgram::new(programs, 10, &Deopt::default());
+        assert_eq!(cntg_program.dedup()?, 1);
+        assert_eq!(cntg_program.programs, vec![dir.join("a.cc"), dir.join("b.cc")]);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_core_dirs() -> Result<()> {
+        let dir = std::env::temp_dir().join("prompt_fuzz_list_core_dirs_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        for core in ["Core_010", "Core_002", "Core_100", "Core_000"] {
+            std::fs::create_dir_all(dir.join(core))?;
+        }
+        std::fs::write(dir.join("Core_001"), "not a core")?;
+        let cores: Vec<String> = list_core_dirs(&dir)?
+            .iter()
+            .map(|core| core.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(cores, vec!["Core_000", "Core_002", "Core_010", "Core_100"]);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_driver_function() {
+        let rename = |content: &str| rename_driver_function(content, "zlib", 3);
+        assert_eq!(
+            rename("int test_zlib_api_sequence() { return 0; }").unwrap(),
+            "int test_zlib_api_sequence_3() { return 0; }"
+        );
+        // capitalization and spacing
+        assert_eq!(
+            rename("int Test_Zlib_API_Sequence (void) {\n  return 0;\n}").unwrap(),
+            "int test_zlib_api_sequence_3 (void) {\n  return 0;\n}"
+        );
+        // another library name, with a forward declaration
+        assert_eq!(
+            rename("int test_libz_api_sequence();\nint test_libz_api_sequence() { return 0; }")
+                .unwrap(),
+            "int test_zlib_api_sequence_3();\nint test_zlib_api_sequence_3() { return 0; }"
+        );
+        // similar identifiers are kept
+        assert_eq!(
+            rename("int test_zlib_api_sequence_helper = 0;\nint test_zlib_api_sequence_v2() {}")
+                .unwrap(),
+            "int test_zlib_api_sequence_helper = 0;\nint test_zlib_api_sequence_3() {}"
+        );
+        assert_eq!(rename("int main() { return 0; }"), None);
+        assert_eq!(rename("int test_zlib_sequence() { return 0; }"), None);
+    }
+
+    #[test]
+    fn test_core_manifest() -> Result<()> {
+        let dir = std::env::temp_dir().join("prompt_fuzz_core_manifest_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let seed_dir = dir.join("seeds");
+        let driver_dir = dir.join("drivers");
+        std::fs::create_dir_all(&seed_dir)?;
+        std::fs::create_dir_all(&driver_dir)?;
+        let mut programs = Vec::new();
+        for name in ["a.cc", "b.cc"] {
+            let path = seed_dir.join(name);
+            std::fs::write(&path, "int test_lib_api_sequence() { return 0; }")?;
+            programs.push(path);
+        }
+        let mut cntg_program = CNTGProgram::new(programs.clone(), 10, &Deopt::default());
+        cntg_program.chdir(&driver_dir)?;
+        cntg_program.fuse_core(&dir, String::new(), 0, &cntg_program.programs.clone(), &[0, 1])?;
+
+        let core_dir = dir.join("Core_000");
+        let manifest = CoreManifest::load(&core_dir)?;
+        assert_eq!(manifest.core_id, 0);
+        assert_eq!(manifest.binary, "core");
+        let files: Vec<_> = manifest.drivers.iter().map(|driver| driver.file.as_str()).collect();
+        assert_eq!(files, vec!["id_000000.cc", "id_000001.cc"]);
+        let seeds: Vec<_> = manifest.drivers.iter().map(|driver| driver.seed.clone()).collect();
+        assert_eq!(seeds, programs);
+        assert_eq!(get_core_path(&core_dir), core_dir.join("core"));
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 }
@@ -76,6 +76,23 @@ impl SeedMetas {
         self.seed_metas.len()
     }
 
+    /// The gain of each seed on the cumulative branch coverage over the seeds generated before it, keyed by the
+    /// file stem of the seed. The seeds without coverage are omitted.
+    pub fn coverage_gains(&self) -> Vec<(String, f32)> {
+        let mut seed_metas: Vec<&SeedMeta> = self.seed_metas.iter().collect();
+        seed_metas.sort_by_key(|m| m.duration_since_start);
+        let mut cumulative = 0_f32;
+        let mut gains = Vec::new();
+        for seed_meta in seed_metas {
+            let (Some(coverage), Some(stem)) = (seed_meta.cumulative_branch_coverage, seed_meta.seed_path.file_stem()) else {
+                continue;
+            };
+            gains.push((stem.to_string_lossy().to_string(), (coverage - cumulative).max(0_f32)));
+            cumulative = cumulative.max(coverage);
+        }
+        gains
+    }
+
     /// Write seed metadata to path
     pub fn write_to(&self, path: &Path) -> Result<()> {
         let mut writer =  Writer::from_path(path)?;
@@ -117,9 +134,7 @@ impl SeedMetas {
 
             // Compile program
             let executor = Executor::new(&deopt)?;
-            program.chdir(&seed_dir)?;
-            program.synthesis(&seed_dir)?;
-            program.compile(&seed_dir)?;
+            program.build(&seed_dir, &seed_dir)?;
 
             // Execute program
             let (tx, rx) = std::sync::mpsc::channel();
@@ -181,3 +196,44 @@ impl TryFrom<&Path> for SeedMetas {
         Ok(SeedMetas { start_time: None, seed_metas })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed_metas_roundtrip() -> Result<()> {
+        let path = std::env::temp_dir().join("prompt_fuzz_seed_meta.csv");
+        let mut seed_metas = SeedMetas::new(&Instant::now());
+        seed_metas.add(Path::new("seeds/id_000001.cc"), Instant::now(), Some(12.5))?;
+        seed_metas.add(Path::new("seeds/id_000002.cc"), Instant::now(), None)?;
+        seed_metas.write_to(&path)?;
+
+        let loaded = SeedMetas::try_from(path.as_path())?;
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.seed_metas[0].seed_path, PathBuf::from("seeds/id_000001.cc"));
+        assert_eq!(loaded.seed_metas[0].cumulative_branch_coverage, Some(12.5));
+        assert_eq!(loaded.seed_metas[1].cumulative_branch_coverage, None);
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_coverage_gains() -> Result<()> {
+        let start = Instant::now();
+        let mut seed_metas = SeedMetas::new(&start);
+        seed_metas.add(Path::new("seeds/id_000002.cc"), start + Duration::from_secs(2), Some(15.0))?;
+        seed_metas.add(Path::new("seeds/id_000001.cc"), start + Duration::from_secs(1), Some(10.0))?;
+        seed_metas.add(Path::new("seeds/id_000003.cc"), start + Duration::from_secs(3), None)?;
+        seed_metas.add(Path::new("seeds/id_000004.cc"), start + Duration::from_secs(4), Some(15.0))?;
+        assert_eq!(
+            seed_metas.coverage_gains(),
+            vec![
+                ("id_000001".to_string(), 10.0),
+                ("id_000002".to_string(), 5.0),
+                ("id_000004".to_string(), 0.0)
+            ]
+        );
+        Ok(())
+    }
+}
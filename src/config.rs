@@ -1,9 +1,16 @@
-use std::sync::{RwLock, RwLockReadGuard};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{RwLock, RwLockReadGuard},
+};
 
 use once_cell::sync::OnceCell;
 
 pub const CONNECT_TIMEOUT: u64 = 1;
 
+/// Default timeout in seconds of a whole LLM request.
+pub const REQUEST_TIMEOUT: u64 = 180;
+
 // LLM Service Interface configure options
 pub static OPENAI_MODEL_NAME: OnceCell<String> = OnceCell::new();
 
@@ -16,15 +23,25 @@ pub const MUTATE_LINE: usize = 3;
 
 pub const MAX_TOKENS: u16 = 2048_u16;
 
+/// The default bounds of `--n-sample` adjusted by `--auto-n-sample`.
+pub const DEFAULT_N_SAMPLE_MIN: u8 = 2;
+pub const DEFAULT_N_SAMPLE_MAX: u8 = 32;
+/// The default upper bound that `max_tokens` is raised to when completions are truncated.
+pub const DEFAULT_MAX_TOKENS_CAP: u32 = 16384_u32;
+
 pub const MAX_INST_TOKENS: u16 = 256_u16;
 
 pub const MUTATE_SEED_ROUND: u8 = 0;
 
 pub const RETRY_N: u8 = 5;
 
+// The consecutive generations without any program before the prompt is shuffled.
+pub const MAX_EMPTY_GENERATIONS: usize = 3;
+
 pub const MAX_SAMPLE_LEN: usize = 20;
 
 pub const DEFAULT_COMB_LEN: usize = 5;
+pub const DEFAULT_COMB_LEN_MIN: usize = 4;
 
 pub static CONFIG_INSTANCE: OnceCell<RwLock<Config>> = OnceCell::new();
 
@@ -88,7 +105,96 @@ pub enum GenerationModeP {
     ApiCombination,
 }
 
+/// Linear decay of the sampling temperature from `start` to `end`, parsed from `<start>:<end>`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TemperatureSchedule {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl TemperatureSchedule {
+    /// The temperature at `progress` percent of the fuzz loop, `progress` is clamped into [0, 1].
+    pub fn temperature_at(&self, progress: f32) -> f32 {
+        let progress = progress.clamp(0_f32, 1_f32);
+        self.start + (self.end - self.start) * progress
+    }
+}
+
+impl std::str::FromStr for TemperatureSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expect `<start>:<end>`, got `{s}`"))?;
+        let parse = |x: &str| {
+            x.trim()
+                .parse::<f32>()
+                .map_err(|e| format!("invalid temperature `{x}`: {e}"))
+        };
+        Ok(Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
 pub const ASAN_OPTIONS: [&str; 2] = ["exitcode=168", "alloc_dealloc_mismatch=0"];
+pub const UBSAN_OPTIONS: [&str; 0] = [];
+
+/// Merge the `key=value` sanitizer options of `layers`, each separated by `:`.
+/// The later keys override the earlier ones, keeping the position of the first occurrence.
+pub fn merge_sanitizer_options(layers: &[&str]) -> String {
+    let mut options: Vec<(&str, &str)> = Vec::new();
+    for option in layers.iter().flat_map(|layer| layer.split(':')) {
+        let option = option.trim();
+        if option.is_empty() {
+            continue;
+        }
+        let (key, value) = option.split_once('=').unwrap_or((option, ""));
+        match options.iter_mut().find(|(k, _)| *k == key) {
+            Some(entry) => entry.1 = value,
+            None => options.push((key, value)),
+        }
+    }
+    options
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn check_sanitizer_options(flag: &str, options: &str) -> eyre::Result<()> {
+    for option in options.split(':').filter(|option| !option.trim().is_empty()) {
+        if !option.contains('=') {
+            eyre::bail!("{flag} only accepts options like `key=value`, got `{option}`");
+        }
+    }
+    Ok(())
+}
+
+/// The range of `--comb-len-min` and `--comb-len-max`, the defaults if the config is not initialized.
+pub fn get_comb_len_range() -> (usize, usize) {
+    CONFIG_INSTANCE.get().map_or((DEFAULT_COMB_LEN_MIN, DEFAULT_COMB_LEN), |config| {
+        let config = config.read().unwrap();
+        (config.comb_len_min, config.comb_len_max)
+    })
+}
+
+/// The `--detect-leaks`, None if it is not set or the config is not initialized.
+pub fn get_detect_leaks() -> Option<bool> {
+    CONFIG_INSTANCE
+        .get()
+        .and_then(|config| config.read().unwrap().detect_leaks)
+}
+
+/// The `--asan-options` and `--ubsan-options`, None if the config is not initialized.
+pub fn get_cli_sanitizer_options() -> (Option<String>, Option<String>) {
+    CONFIG_INSTANCE.get().map_or((None, None), |config| {
+        let config = config.read().unwrap();
+        (config.asan_options.clone(), config.ubsan_options.clone())
+    })
+}
 
 pub fn get_openai_model_name() -> String {
     OPENAI_MODEL_NAME.get().unwrap().to_string()
@@ -102,6 +208,19 @@ pub fn get_openai_proxy() -> &'static Option<String> {
     OPENAI_PROXY_BASE.get().unwrap()
 }
 
+/// Load the variables of `path`, or of `.env` in the current dir or its parents if None, into the process env.
+/// The variables already set are not overridden. A missing `.env` is ignored, but a missing `path` is an error.
+pub fn load_env_file(path: Option<&Path>) -> eyre::Result<()> {
+    match path {
+        Some(path) => dotenv::from_path(path)
+            .map_err(|err| eyre::eyre!("Failed to load the env file {path:?}: {err}")),
+        None => {
+            dotenv::dotenv().ok();
+            Ok(())
+        }
+    }
+}
+
 pub fn init_openai_env() {
     let model =
         std::env::var("OPENAI_MODEL_NAME").unwrap_or_else(|_| panic!("OPENAI_MODEL_NAME not set"));
@@ -134,6 +253,144 @@ pub fn get_sample_num() -> u8 {
     config.n_sample
 }
 
+/// The `[min, max]` band of `--auto-n-sample`.
+pub fn get_n_sample_range() -> (u8, u8) {
+    let config = CONFIG_INSTANCE.get().unwrap().read().unwrap();
+    (config.n_sample_min, config.n_sample_max)
+}
+
+/// The marker between the programs of a completion, see `--program-separator`. None if the config is not initialized.
+pub fn get_program_separator() -> Option<String> {
+    CONFIG_INSTANCE
+        .get()
+        .and_then(|config| config.read().unwrap().program_separator.clone())
+}
+
+/// The timeout of a whole LLM request, fall back to `REQUEST_TIMEOUT` if the config is not initialized.
+pub fn get_request_timeout() -> std::time::Duration {
+    let secs = CONFIG_INSTANCE
+        .get()
+        .map_or(REQUEST_TIMEOUT, |config| config.read().unwrap().request_timeout);
+    std::time::Duration::from_secs(secs)
+}
+
+/// Whether the build artifacts should be kept, false if the config is not initialized.
+pub fn keep_artifacts() -> bool {
+    CONFIG_INSTANCE
+        .get()
+        .is_some_and(|config| config.read().unwrap().keep_artifacts)
+}
+
+/// Whether the error programs are saved gzip compressed, false if the config is not initialized.
+pub fn compress_errors() -> bool {
+    CONFIG_INSTANCE
+        .get()
+        .is_some_and(|config| config.read().unwrap().compress_errors)
+}
+
+/// Whether the programs are syntax checked before the sanitizer build, false if the config is not initialized.
+pub fn quick_validate() -> bool {
+    CONFIG_INSTANCE
+        .get()
+        .is_some_and(|config| config.read().unwrap().quick_validate)
+}
+
+/// The APIs of `--include-only-apis`, None if not given or the config is not initialized.
+pub fn get_include_only_apis() -> Option<Vec<String>> {
+    CONFIG_INSTANCE
+        .get()
+        .and_then(|config| config.read().unwrap().include_only_apis.clone())
+}
+
+/// The APIs of `--exclude-apis`, empty if the config is not initialized.
+pub fn get_exclude_apis() -> Vec<String> {
+    CONFIG_INSTANCE
+        .get()
+        .map_or_else(Vec::new, |config| config.read().unwrap().exclude_apis.clone())
+}
+
+/// The `--min-new-branches` of a seed, 1 if the config is not initialized.
+pub fn get_min_new_branches() -> usize {
+    CONFIG_INSTANCE
+        .get()
+        .map_or(1, |config| config.read().unwrap().min_new_branches)
+}
+
+/// The cap of the seed queue, None if unlimited or the config is not initialized.
+pub fn get_seed_count_limit() -> Option<usize> {
+    CONFIG_INSTANCE
+        .get()
+        .and_then(|config| config.read().unwrap().seed_count_limit)
+}
+
+/// Whether the raw LLM completions are recorded, false if the config is not initialized.
+pub fn verbose_generation() -> bool {
+    CONFIG_INSTANCE
+        .get()
+        .is_some_and(|config| config.read().unwrap().verbose_generation)
+}
+
+/// The initial and the upper bound of the max tokens, None if `--max-tokens` is not given or the config is not initialized.
+pub fn get_max_tokens_range() -> Option<(u32, u32)> {
+    CONFIG_INSTANCE.get().and_then(|config| {
+        let config = config.read().unwrap();
+        config.max_tokens.map(|max_tokens| (max_tokens, config.max_tokens_cap))
+    })
+}
+
+/// Whether the progress bars should be shown, false if the config is not initialized.
+pub fn show_progress() -> bool {
+    CONFIG_INSTANCE
+        .get()
+        .is_some_and(|config| config.read().unwrap().progress)
+}
+
+/// The overrides of the library output dir and seed dir.
+pub fn get_dir_overrides() -> (Option<PathBuf>, Option<PathBuf>) {
+    CONFIG_INSTANCE.get().map_or((None, None), |config| {
+        let config = config.read().unwrap();
+        (config.work_dir.clone(), config.seed_dir.clone())
+    })
+}
+
+/// The entry point of this execution, None for the whole library or if the config is not initialized.
+pub fn get_entry_point() -> Option<String> {
+    CONFIG_INSTANCE
+        .get()
+        .and_then(|config| config.read().unwrap().entry_point.clone())
+}
+
+/// The binary name of the fused CNTG cores, "core" if the config is not initialized.
+pub fn get_core_binary_name() -> String {
+    CONFIG_INSTANCE.get().map_or_else(
+        || "core".to_string(),
+        |config| config.read().unwrap().core_binary_name.clone(),
+    )
+}
+
+/// The `--minimize-epsilon`, 0 if the config is not initialized.
+pub fn get_minimize_epsilon() -> f32 {
+    CONFIG_INSTANCE
+        .get()
+        .map_or(0.0, |config| config.read().unwrap().minimize_epsilon)
+}
+
+/// The `--null-term` override, `auto` if the config is not initialized.
+pub fn get_null_term() -> NullTerm {
+    CONFIG_INSTANCE
+        .get()
+        .map_or(NullTerm::Auto, |config| config.read().unwrap().null_term)
+}
+
+/// The `--comb-strategy`, which is `random` with `--disable-power-schedule`.
+pub fn get_comb_strategy() -> CombStrategy {
+    let config = CONFIG_INSTANCE.get().unwrap().read().unwrap();
+    if config.disable_power_schedule {
+        return CombStrategy::Random;
+    }
+    config.comb_strategy
+}
+
 pub fn get_handler_type() -> HandlerType {
     let config = CONFIG_INSTANCE.get().unwrap().read().unwrap();
     config.handler_type.clone()
@@ -143,25 +400,278 @@ pub fn get_minimize_compile_flag() -> &'static str {
     static MIN_FLAG: OnceCell<String> = OnceCell::new();
     MIN_FLAG.get_or_init(|| {
         let mut minimize_flag: String = "-fsanitize-coverage-ignorelist=".into();
-        let bl_file = Deopt::get_coverage_bl_file_name().unwrap();
+        // the override must be read here, as the flag is cached after the first call.
+        let custom_bl = CONFIG_INSTANCE
+            .get()
+            .and_then(|config| config.read().unwrap().coverage_ignorelist.clone());
+        let bl_file = match custom_bl {
+            Some(path) => path.to_string_lossy().to_string(),
+            None => Deopt::get_coverage_bl_file_name().unwrap(),
+        };
         minimize_flag.push_str(&bl_file);
         minimize_flag
     })
 }
+
+/// The C++ compiler given by `--cxx`, clang++ if unset or the config is not initialized.
+pub fn get_cxx() -> PathBuf {
+    CONFIG_INSTANCE
+        .get()
+        .and_then(|config| config.read().unwrap().cxx.clone())
+        .unwrap_or_else(|| PathBuf::from("clang++"))
+}
+
+/// The compile flags of the library's `extra_c_flags` followed by `--extra-cxxflags`.
+pub fn merge_cxxflags(lib_flags: Option<&[String]>, extra: Option<&str>) -> Vec<String> {
+    let mut flags: Vec<String> = lib_flags.unwrap_or_default().to_vec();
+    flags.extend(extra.unwrap_or_default().split_whitespace().map(str::to_string));
+    flags
+}
+
+/// The flags of `--extra-cxxflags`, empty if the config is not initialized.
+pub fn get_extra_cxxflags() -> Vec<String> {
+    CONFIG_INSTANCE.get().map_or_else(Vec::new, |config| {
+        merge_cxxflags(None, config.read().unwrap().extra_cxxflags.as_deref())
+    })
+}
+
+/// Split `--libfuzzer-args` into flags. Only flags like `-name=value` are accepted, and each flag can be given once.
+pub fn parse_libfuzzer_args(args: &str) -> eyre::Result<Vec<String>> {
+    let mut flags: Vec<String> = Vec::new();
+    for arg in args.split_whitespace() {
+        let Some((name, _)) = arg.split_once('=') else {
+            eyre::bail!("--libfuzzer-args only accepts flags like `-name=value`, got `{arg}`");
+        };
+        if !name.starts_with('-') {
+            eyre::bail!("--libfuzzer-args only accepts flags like `-name=value`, got `{arg}`");
+        }
+        if flags.iter().any(|flag| flag.starts_with(&format!("{name}="))) {
+            eyre::bail!("--libfuzzer-args sets `{name}` more than once");
+        }
+        flags.push(arg.to_string());
+    }
+    Ok(flags)
+}
+
+/// Resolve the fuzz time window `(min, max)` in seconds: the run options override the library's, which override
+/// MIN_FUZZ_TIME and MAX_FUZZ_TIME.
+pub fn resolve_fuzz_time_window(
+    min: Option<u64>,
+    max: Option<u64>,
+    lib: &LibConfig,
+) -> eyre::Result<(u64, u64)> {
+    let min = min.or(lib.min_fuzz_time).unwrap_or(MIN_FUZZ_TIME);
+    let max = max.or(lib.max_fuzz_time).unwrap_or(MAX_FUZZ_TIME);
+    if min == 0 || min > max {
+        eyre::bail!("The min fuzz time ({min}s) should be positive and no larger than the max fuzz time ({max}s)");
+    }
+    Ok((min, max))
+}
+
+/// The fuzz time window of the library, see `resolve_fuzz_time_window`.
+pub fn get_fuzz_time_window(lib: &LibConfig) -> eyre::Result<(u64, u64)> {
+    let (min, max) = CONFIG_INSTANCE.get().map_or((None, None), |config| {
+        let config = config.read().unwrap();
+        (config.min_fuzz_time, config.max_fuzz_time)
+    });
+    resolve_fuzz_time_window(min, max, lib)
+}
+
+/// The time limit of the fuzzer run phase, if the max fuzz time is set by the run options or the library.
+pub fn get_fuzzer_run_time_limit(lib: &LibConfig) -> Option<u64> {
+    CONFIG_INSTANCE
+        .get()
+        .and_then(|config| config.read().unwrap().max_fuzz_time)
+        .or(lib.max_fuzz_time)
+}
+
+/// The flags of `--libfuzzer-args`, which are validated in `parse_config`.
+pub fn get_libfuzzer_args() -> Vec<String> {
+    let config = CONFIG_INSTANCE.get().unwrap().read().unwrap();
+    match &config.libfuzzer_args {
+        Some(args) => parse_libfuzzer_args(args).unwrap_or_default(),
+        None => Vec::new(),
+    }
+}
+
+/// Parse the content of `--fuzzer-args-file`: one arg per line. The lines are trimmed, and blank lines and
+/// `#` comments are skipped.
+pub fn parse_fuzzer_args_file(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Parse the lines of `--headers-include`. Bare header names are taken as `#include <name>`.
+/// Empty lines and `//` comments are skipped.
+pub fn parse_include_lines(content: &str) -> eyre::Result<Vec<String>> {
+    let mut includes = Vec::new();
+    for line in content.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+        let include = if line.starts_with('#') {
+            let header = line.strip_prefix('#').unwrap().trim_start();
+            let Some(header) = header.strip_prefix("include") else {
+                eyre::bail!("--headers-include only accepts `#include` lines, got `{line}`");
+            };
+            let header = header.trim();
+            let quoted = header.starts_with('"') && header.ends_with('"');
+            let angled = header.starts_with('<') && header.ends_with('>');
+            if header.len() < 3 || !(quoted || angled) {
+                eyre::bail!("Malformed include line in --headers-include: `{line}`");
+            }
+            format!("#include {header}")
+        } else {
+            format!("#include <{line}>")
+        };
+        if !includes.contains(&include) {
+            includes.push(include);
+        }
+    }
+    Ok(includes)
+}
+
+/// The extra include lines of `--headers-include`, which are validated in `parse_config`.
+pub fn get_extra_include_lines() -> &'static Vec<String> {
+    static INCLUDES: OnceCell<Vec<String>> = OnceCell::new();
+    INCLUDES.get_or_init(|| {
+        let Some(path) = get_config().headers_include.clone() else {
+            return Vec::new();
+        };
+        std::fs::read_to_string(&path)
+            .map_err(eyre::Report::new)
+            .and_then(|content| parse_include_lines(&content))
+            .unwrap_or_else(|err| panic!("Fail to load --headers-include {path:?}: {err}"))
+    })
+}
+
+/// Check the values of the arguments that clap cannot, before the config is installed.
+pub fn check_config(config: &Config) -> eyre::Result<()> {
+    // the window combined with the library's is checked when the fuzzer is created.
+    if let (Some(min), Some(max)) = (config.min_fuzz_time, config.max_fuzz_time) {
+        resolve_fuzz_time_window(Some(min), Some(max), &LibConfig::default())?;
+    }
+    if let Some(args) = &config.libfuzzer_args {
+        parse_libfuzzer_args(args)?;
+    }
+    if let Some(fraction) = config.target_pair_fraction {
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            eyre::bail!("--target-pair-fraction should be in (0, 1], got {fraction}");
+        }
+    }
+    if config.comb_len_min == 0 || config.comb_len_min > config.comb_len_max {
+        eyre::bail!(
+            "--comb-len-min ({}) should be positive and no larger than --comb-len-max ({})",
+            config.comb_len_min,
+            config.comb_len_max
+        );
+    }
+    if config.auto_n_sample
+        && (config.n_sample_min == 0
+            || config.n_sample_min > config.n_sample
+            || config.n_sample > config.n_sample_max)
+    {
+        eyre::bail!(
+            "--auto-n-sample requires 0 < --n-sample-min ({}) <= --n-sample ({}) <= --n-sample-max ({})",
+            config.n_sample_min,
+            config.n_sample,
+            config.n_sample_max
+        );
+    }
+    if !(0.0..=1.0).contains(&config.minimize_epsilon) {
+        eyre::bail!("--minimize-epsilon should be in [0, 1]");
+    }
+    if config.save_every == Some(0) {
+        eyre::bail!("--save-every should be positive");
+    }
+    if config.min_new_branches == 0 {
+        eyre::bail!("--min-new-branches should be positive");
+    }
+    if config.success_weight < 0_f32 {
+        eyre::bail!("--success-weight should not be negative");
+    }
+    if config.infra_retry_cap == 0 {
+        eyre::bail!("--infra-retry-cap should be positive");
+    }
+    if let Some(threshold) = config.judge_threshold {
+        if !(0.0..=1.0).contains(&threshold) {
+            eyre::bail!("--judge-threshold should be in [0, 1]");
+        }
+    }
+    if let Some(separator) = &config.program_separator {
+        if separator.trim().is_empty() || separator.contains('\n') {
+            eyre::bail!("--program-separator should be a non-empty single line");
+        }
+        if config.programs_per_call < 2 {
+            eyre::bail!("--program-separator requires --programs-per-call larger than 1");
+        }
+        if config.stream {
+            eyre::bail!("--program-separator cannot be used with --stream, whose completions are taken as single programs");
+        }
+    }
+    if config.stream && config.generation_mode == GenerationModeP::FuzzDriver {
+        eyre::bail!("--stream is only supported in ApiCombination mode");
+    }
+    if config.seed_count_limit == Some(0) {
+        eyre::bail!("--seed-count-limit should be positive");
+    }
+    if config.prompts_per_round == 0 {
+        eyre::bail!("--prompts-per-round should be positive");
+    }
+    if let Some(max_tokens) = config.max_tokens {
+        if max_tokens == 0 || max_tokens > config.max_tokens_cap {
+            eyre::bail!(
+                "--max-tokens ({max_tokens}) should be positive and no larger than --max-tokens-cap ({})",
+                config.max_tokens_cap
+            );
+        }
+    }
+    if let Some(options) = &config.asan_options {
+        check_sanitizer_options("--asan-options", options)?;
+    }
+    if let Some(options) = &config.ubsan_options {
+        check_sanitizer_options("--ubsan-options", options)?;
+    }
+    let name = &config.core_binary_name;
+    if name.is_empty() || name.contains(std::path::is_separator) {
+        eyre::bail!("--core-binary-name should be a plain file name, got {name:?}");
+    }
+    if let Some(path) = &config.headers_include {
+        let content = std::fs::read_to_string(path).map_err(|err| {
+            eyre::eyre!("Cannot read the file {path:?} passed to `--headers-include`: {err}")
+        })?;
+        parse_include_lines(&content)?;
+    }
+    Ok(())
+}
+
 pub fn parse_config() -> eyre::Result<()> {
     let config = Config::parse();
+    check_config(&config)?;
     CONFIG_INSTANCE.set(RwLock::new(config)).unwrap();
     let deopt = Deopt::new(get_library_name())?;
-    let data = deopt.get_library_data_dir()?;
-    if !data.exists() {
-        eyre::bail!(
-            "Cannot find the entry {} in `data` dir, please prepare it in anvance.",
-            deopt.config.project_name
-        );
+    deopt.check_library_paths()?;
+    let config = get_config();
+    for entry_point in config.entry_points.iter().chain(config.entry_point.iter()) {
+        if deopt.config.get_entry_point_suffix(entry_point).is_none() {
+            eyre::bail!(
+                "The entry point `{entry_point}` is not declared in the `entry_points` of the config of {}",
+                deopt.project_name
+            );
+        }
     }
-    let lib = deopt.get_library_build_lib_path()?;
-    if !lib.exists() {
-        eyre::bail!("Cannot find the build library {} in `output/build` dir, please build it by build.sh in anvance.", deopt.config.project_name);
+    drop(config);
+    if let Some(bl_file) = &get_config().coverage_ignorelist {
+        if !bl_file.is_file() {
+            eyre::bail!(
+                "Cannot find the coverage ignorelist {}, please check the path passed to `--coverage-ignorelist`.",
+                bl_file.display()
+            );
+        }
     }
     Ok(())
 }
@@ -170,6 +680,40 @@ use clap::{Parser, ValueEnum};
 
 use crate::Deopt;
 
+/// How the next prompt combination is chosen in FuzzDriver mode.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq)]
+pub enum CombStrategy {
+    /// A random combination each round.
+    Random,
+    /// Mutate the combination by the power schedule.
+    Energy,
+    /// Enumerate the combinations of `--comb-len-max` gadgets in index order, without randomness.
+    Enumerate,
+    /// Like `energy`, but once a seed API is chosen, prefer the APIs semantically near to it by `--api-embeddings`.
+    /// Falls back to `energy` if the embeddings are missing.
+    Semantic,
+}
+
+/// Whether the fuzzer input is null-terminated before it is passed to the driver.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq)]
+pub enum NullTerm {
+    /// Follow `null_term` of the library config.
+    Auto,
+    On,
+    Off,
+}
+
+impl NullTerm {
+    /// Resolve the override against the library's `null_term`.
+    pub fn resolve(self, lib_null_term: bool) -> bool {
+        match self {
+            NullTerm::Auto => lib_null_term,
+            NullTerm::On => true,
+            NullTerm::Off => false,
+        }
+    }
+}
+
 /// Handler类型选择
 #[derive(Debug, Clone, ValueEnum, PartialEq)]
 pub enum HandlerType {
@@ -178,6 +722,16 @@ pub enum HandlerType {
     /// 使用HTTP客户端
     Http,
 }
+
+impl HandlerType {
+    /// The sampling temperature of this handler if `--temperature` is not given.
+    pub fn default_temperature(&self) -> f32 {
+        match self {
+            HandlerType::Openai => 1_f32,
+            HandlerType::Http => 0.7,
+        }
+    }
+}
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
 #[command(author="Anonymous", name = "LLMFuzzer", version, about="A LLM based Fuzer", long_about = None)]
@@ -187,12 +741,61 @@ pub struct Config {
     pub generation_mode: GenerationModeP,
     /// The target project you decide to fuzz. Available: ["cJSON", "c-ares", "libvpx", "libaom", "libpng", "cre2", "curl", "lcms", "libjpeg-turbo", "libmagic", "libtiff", "sqlite3", "zlib", "libpcap"]
     pub target: String,
-    /// Sample N outputs per LLM's request, max: 128
+    /// Sample N programs from LLM per round, max: 128
     #[arg(short, long, default_value = "10")]
     pub n_sample: u8,
+    /// Adjust `--n-sample` by the success ratio of the rounds: lower it when almost every program succeeds, and raise it when almost none does, within `[--n-sample-min, --n-sample-max]`.
+    #[arg(long, default_value = "false")]
+    pub auto_n_sample: bool,
+    /// The lower bound of `--auto-n-sample`.
+    #[arg(long, default_value_t = DEFAULT_N_SAMPLE_MIN)]
+    pub n_sample_min: u8,
+    /// The upper bound of `--auto-n-sample`.
+    #[arg(long, default_value_t = DEFAULT_N_SAMPLE_MAX)]
+    pub n_sample_max: u8,
+    /// Number of completions (`n`) asked in each LLM call. The handlers keep calling until `--n-sample` programs are received.
+    #[arg(long, default_value = "1")]
+    pub programs_per_call: u8,
+    /// Ask the model to write the `--programs-per-call` programs in a single completion, separated by lines of this marker, e.g., `// ----- NEXT PROGRAM -----`. The native `n` is not sent, which suits the backends without it.
+    #[arg(long)]
+    pub program_separator: Option<String>,
+    /// Number of distinct API combinations whose generations are requested concurrently in each generation step. The programs of all combinations are pooled. Not applied to streaming, repair and CoT requests.
+    #[arg(long, default_value = "1")]
+    pub prompts_per_round: usize,
+    /// The initial max tokens of each completion. Not sent by default, leaving the limit to the backend, as some backends reject `max_completion_tokens`.
+    #[arg(long)]
+    pub max_tokens: Option<u32>,
+    /// The upper bound of the max tokens, used with --max-tokens. When a completion is truncated by the token limit, the truncated completions are requested again with doubled max tokens up to this bound. Keep it within the context of the model.
+    #[arg(long, default_value_t = DEFAULT_MAX_TOKENS_CAP)]
+    pub max_tokens_cap: u32,
     /// Sampling temperature. Higher values means the model will take more risks. Try 1 for more creative applications, and 0 (argmax sampling) for ones with a well-defined answer.
-    #[arg(short, long, default_value = "1")]
-    pub temperature: f32,
+    /// Default: 1 for the openai handler, 0.7 for the http handler.
+    #[arg(short, long)]
+    pub temperature: Option<f32>,
+    /// The sampling temperature of the FuzzDriver mode, which overrides `--temperature` in that mode.
+    #[arg(long)]
+    pub driver_temperature: Option<f32>,
+    /// The sampling temperature of the ApiCombination mode, which overrides `--temperature` in that mode.
+    #[arg(long)]
+    pub api_temperature: Option<f32>,
+    /// Decay the sampling temperature linearly from start to end across the fuzz loop, e.g., `1.2:0.4`. Overrides `--temperature`.
+    /// The progress is measured by `--seed-gen-timeout` if set, otherwise by the quiet rounds.
+    #[arg(long)]
+    pub temperature_schedule: Option<TemperatureSchedule>,
+    /// How the next prompt combination is chosen in FuzzDriver mode.
+    #[arg(long, default_value = "energy")]
+    pub comb_strategy: CombStrategy,
+    /// Log the schedule state each round: the coverage, exec count, prompt count and energy of each API,
+    /// sorted by the energy.
+    #[arg(long, default_value = "false")]
+    pub dump_schedule: bool,
+    /// Append the prompt (system+user messages) and the completion of every successful program to this JSONL file,
+    /// with its combination and coverage as the metadata, e.g., to build a fine-tuning dataset.
+    #[arg(long)]
+    pub save_transcripts: Option<PathBuf>,
+    /// The embeddings file of `--comb-strategy semantic`. Defaults to `data/<library>/api_embeddings.json`.
+    #[arg(long)]
+    pub api_embeddings: Option<PathBuf>,
     /// whether use the power schedule to mutate prompt. true for purly random mutation of prompt.
     #[arg(short, long, default_value = "false")]
     pub disable_power_schedule: bool,
@@ -219,29 +822,221 @@ pub struct Config {
     /// Select the handler type for LLM requests
     #[arg(long = "handler", default_value = "openai")]
     pub handler_type: HandlerType,
+    /// Load the LLM service variables, e.g., `OPENAI_MODEL_NAME` and `HTTP_LLM_BASE`, from this file instead of `.env`.
+    /// The variables set in the process env take precedence over the file.
+    #[arg(long)]
+    pub env_file: Option<PathBuf>,
     /// Timeout in minutes for the seed generation phase
     #[arg(long)]
     pub seed_gen_timeout: Option<u64>,
     /// Number of rounds over which the convergence condition is calculated for
-    /// ApiCombination mode
+    /// ApiCombination mode: the loop converges after this many consecutive rounds
+    /// with fewer than `--num-new-pairs` new pairs, so a single barren round does not end it.
     #[arg(long, default_value = "3")]
     pub quiet_round: usize,
     /// Minimum number of newly discovered pairs that indicate that the
     /// generator has not converged in ApiCombination mode
     #[arg(long, default_value = "3")]
     pub num_new_pairs: usize,
+    /// Stop ApiCombination mode once this number of distinct API pairs is discovered.
+    #[arg(long)]
+    pub target_pairs: Option<usize>,
+    /// Stop ApiCombination mode once this fraction (0, 1] of all possible API pairs is discovered.
+    #[arg(long)]
+    pub target_pair_fraction: Option<f32>,
+    /// Only count the API pairs whose first API's outputs can feed the second API's inputs as the possible pairs.
+    #[arg(long, default_value = "false")]
+    pub type_compatible_pairs: bool,
+    /// Drop the discovered API triples of ApiCombination mode whose calls are not fed by the former ones.
+    #[arg(long, default_value = "false")]
+    pub type_compatible_triples: bool,
     /// Enable Chain of Thought (CoT) mode for API combination generation. In CoT mode, LLM first generates an execution plan in natural language, then generates code based on that plan. This can improve correctness for complex libraries.
     #[arg(long = "cot", default_value = "false")]
     pub enable_cot: bool,
+    /// Number of consecutive rounds without any successful program before the prompt is fully reset with a random combination.
+    #[arg(long)]
+    pub stuck_threshold: Option<usize>,
+    /// Also hard reset the prompt once the diversity (mean Jaccard distance from the recent combinations) stays below this value for `--stuck-threshold` rounds.
+    #[arg(long)]
+    pub diversity_threshold: Option<f32>,
+    /// Reject the generated programs that have more non-empty lines than this limit. Unlimited by default.
+    #[arg(long)]
+    pub max_program_len: Option<usize>,
+    /// Stream the LLM responses in ApiCombination mode, and cancel the remaining generations once `--fr` programs are validated.
+    /// Not supported in FuzzDriver mode.
+    #[arg(long, default_value = "false")]
+    pub stream: bool,
+    /// Request the OpenAI models to respond with a JSON object whose `code` field holds the program. Non-conforming responses are still parsed as plain text.
+    #[arg(long, default_value = "false")]
+    pub structured_output: bool,
+    /// Timeout in seconds of a LLM request. Timed out requests are retried, and the round is counted as failed once retries are exhausted.
+    #[arg(long, default_value_t = REQUEST_TIMEOUT)]
+    pub request_timeout: u64,
+    /// Custom ignorelist passed to `-fsanitize-coverage-ignorelist=` when minimizing. Default: ban_all.txt in the crate dir.
+    #[arg(long)]
+    pub coverage_ignorelist: Option<PathBuf>,
+    /// Keep the fuzzer/CNTG and driver directories across runs, retain the object files of the fused cores, and log the clang command lines per core.
+    #[arg(long, default_value = "false")]
+    pub keep_artifacts: bool,
+    /// Extra flags forwarded verbatim to libfuzzer in the fuzzer run phase, e.g., "-max_len=4096 -timeout=25".
+    /// A flag set internally (e.g., -timeout, -rss_limit_mb) is overridden with a warning.
+    #[arg(long, allow_hyphen_values = true)]
+    pub libfuzzer_args: Option<String>,
+    /// The seconds a fuzzer runs before it is stopped for no coverage gained, overriding `min_fuzz_time` of the library and MIN_FUZZ_TIME.
+    #[arg(long)]
+    pub min_fuzz_time: Option<u64>,
+    /// The C++ compiler used to check and compile the programs and the fused cores. It should be a clang, as the coverage and fuzzer flags are clang's. Default: clang++.
+    #[arg(long)]
+    pub cxx: Option<PathBuf>,
+    /// Extra flags appended to each compilation after the `extra_c_flags` of the library, e.g., "--target=aarch64-linux-gnu".
+    #[arg(long, allow_hyphen_values = true)]
+    pub extra_cxxflags: Option<String>,
+    /// The maximum seconds a fuzzer runs, overriding `max_fuzz_time` of the library and MAX_FUZZ_TIME. It also bounds the fuzzer run phase if set.
+    #[arg(long)]
+    pub max_fuzz_time: Option<u64>,
+    /// A file listing extra `#include` lines (one per line) for the auxiliary headers that the header detection misses.
+    /// They are appended to the headers in prompts and included in the synthesized drivers.
+    #[arg(long)]
+    pub headers_include: Option<PathBuf>,
+    /// Show the progress bars of generation, validation and compilation on a terminal.
+    #[arg(long, default_value = "false")]
+    pub progress: bool,
+    /// Resume from the most recent prior execution: restore the seed id, observer coverage, schedule counters and quiet rounds. The observer coverage is restored from `misc/observer_snapshot.json` if present, otherwise from the seeds.
+    #[arg(long, default_value = "false")]
+    pub resume: bool,
+    /// Start the generation from this prompt, saved by a previous execution, e.g., its `misc/prompt_checkpoint.json`. Otherwise, `--resume` starts from the checkpointed prompt.
+    #[arg(long)]
+    pub load_prompt: Option<PathBuf>,
+    /// Skip the generation loop: load the existing seeds and observer state, and only run the minimizer of the generation mode.
+    #[arg(long, default_value = "false")]
+    pub only_minimize: bool,
+    /// Exit right after the generation loop, without the final minimization of the corpus.
+    #[arg(long, default_value = "false", conflicts_with = "only_minimize")]
+    pub no_minimize: bool,
+    /// The fraction of the covered branches the minimized corpus may lose, checked after the minimization.
+    /// In ApiCombination mode, it is only checked if the coverage of the seeds was collected.
+    #[arg(long, default_value = "0")]
+    pub minimize_epsilon: f32,
+    /// Only select these APIs, e.g., `--include-only-apis a,b,c`, into the combinations, energies and prompts, to fuzz a subsystem of the library. `--exclude-apis` is applied after it.
+    #[arg(long, value_delimiter = ',')]
+    pub include_only_apis: Option<Vec<String>>,
+    /// Exclude these APIs, e.g., `--exclude-apis a,b,c`, from the combinations, energies and prompts, in addition to the `ban` list of the library.
+    #[arg(long, value_delimiter = ',')]
+    pub exclude_apis: Vec<String>,
+    /// Write a checkpoint every this many loops: the schedule energies and the logger counters, alongside the observer snapshot and the counters saved every round. `--resume` restores from the checkpoint, so that at most this many loops are lost on an abrupt kill.
+    #[arg(long)]
+    pub save_every: Option<usize>,
+    /// Keep the coverage profile of each seed as `work/profiles/id_XXXXXX.profdata`, e.g., to recompute the aggregate
+    /// or differential coverage offline. Off by default as the profiles are large.
+    #[arg(long, default_value = "false")]
+    pub keep_profiles: bool,
+    /// Only keep a program as a seed if it triggers at least this many unique branches. The programs below it are
+    /// still merged into the coverage, but are saved to the succ seeds instead of the seed queue.
+    #[arg(long, default_value = "1")]
+    pub min_new_branches: usize,
+    /// Abort the run after this many consecutive checked batches in which every program failed for an
+    /// infrastructure error, e.g., a missing compiler or build library, instead of burning more LLM calls.
+    #[arg(long, default_value = "2")]
+    pub infra_retry_cap: usize,
+    /// Rate each generated API sequence by a heuristic judge (0-1) before the validation, and skip the programs
+    /// scored below this threshold. The judge is off if unset.
+    #[arg(long)]
+    pub judge_threshold: Option<f32>,
+    /// Cap the seed queue at this many seeds. Once exceeded, the seeds of the lowest quality score are evicted to the succ seeds.
+    #[arg(long)]
+    pub seed_count_limit: Option<usize>,
+    /// The minimum number of APIs in a random prompt combination.
+    #[arg(long, default_value_t = DEFAULT_COMB_LEN_MIN)]
+    pub comb_len_min: usize,
+    /// The maximum number of APIs in a random prompt combination.
+    #[arg(long, default_value_t = DEFAULT_COMB_LEN)]
+    pub comb_len_max: usize,
+    /// Extra ASAN_OPTIONS like `detect_leaks=0:max_allocation_size_mb=2048`, which override the library's `asan_option`.
+    #[arg(long)]
+    pub asan_options: Option<String>,
+    /// Set `detect_leaks` of ASAN_OPTIONS. If true, the leak reports are saved in `crashes/leaks` apart from other crashes.
+    #[arg(long)]
+    pub detect_leaks: Option<bool>,
+    /// Override `null_term` of the library: whether the synthesized driver appends a '\0' to the fuzzer input
+    /// before passing it on, e.g., to rule out the spurious crashes of a wrong null termination assumption.
+    #[arg(long, default_value = "auto", value_enum)]
+    pub null_term: NullTerm,
+    /// Fail at startup if the versions of clang, `llvm-cov` and `llvm-profdata` mismatch, or the clang version is unknown, instead of warning.
+    #[arg(long, default_value = "false")]
+    pub strict_toolchain: bool,
+    /// Extra UBSAN_OPTIONS, which override the library's `ubsan_option`.
+    #[arg(long)]
+    pub ubsan_options: Option<String>,
+    /// Save the error programs and their messages gzip compressed, as `id_XXXXXX.cc.gz`.
+    #[arg(long, default_value = "false")]
+    pub compress_errors: bool,
+    /// Write each raw LLM completion, before deserialization, to `work/raw_generations/round_XXXXX.txt`, tagged with the prompt combination.
+    #[arg(long, default_value = "false")]
+    pub verbose_generation: bool,
+    /// Reject the programs failing a syntax-only check (`clang++ -fsyntax-only` with the normal flags) before the expensive sanitizer build.
+    #[arg(long, default_value = "false")]
+    pub quick_validate: bool,
+    /// Compile and run a trivial driver against the library at startup, to catch link errors before any LLM call.
+    #[arg(long, default_value = "false")]
+    pub preflight: bool,
+    /// The binary name of each fused CNTG core within its `Core_XXX` dir.
+    #[arg(long, default_value = "core")]
+    pub core_binary_name: String,
+    /// Put the outputs of the library under this dir instead of `output/<library>`, so that parallel experiments don't collide.
+    #[arg(long)]
+    pub work_dir: Option<PathBuf>,
+    /// Save and load the seeds in this dir instead of `seeds` under the output dir of the library. The seeds of an entry point are under `entry_points/<name>` of it.
+    #[arg(long)]
+    pub seed_dir: Option<PathBuf>,
+    /// Synthesize the drivers of each of these entry points in turn, e.g., `--entry-points decode,encode`. They are declared in the `entry_points` of the library config, and each one gets its own prompt suffix, and its own seeds, coverage and minimization under `entry_points/<name>` of the output dir.
+    #[arg(long, value_delimiter = ',', conflicts_with = "entry_point")]
+    pub entry_points: Vec<String>,
+    /// Synthesize the drivers of this entry point only, see `--entry-points`.
+    #[arg(long)]
+    pub entry_point: Option<String>,
+    /// Bonus added to the sampling weight of the APIs never put in a prompt in ApiCombination mode, until they are selected once. 0, the default, disables it.
+    #[arg(long, default_value = "0")]
+    pub exploration_bonus: f32,
+    /// The exponent of the per-API success rate (the validated over all the generated programs calling it) in the energy, which down-weights the APIs the model keeps using wrongly in the FuzzDriver mode. 0 disables it.
+    #[arg(long, default_value = "0")]
+    pub success_weight: f32,
 }
 
 impl Config {
+    /// The sampling temperature of `mode` and the option it comes from: the override of the mode
+    /// if set, otherwise `--temperature`.
+    pub fn mode_temperature(&self, mode: &GenerationModeP) -> (f32, &'static str) {
+        let (temperature, option) = match mode {
+            GenerationModeP::FuzzDriver => (self.driver_temperature, "--driver-temperature"),
+            GenerationModeP::ApiCombination => (self.api_temperature, "--api-temperature"),
+        };
+        match temperature {
+            Some(temperature) => (temperature, option),
+            None => (
+                self.temperature
+                    .unwrap_or_else(|| self.handler_type.default_temperature()),
+                "--temperature",
+            ),
+        }
+    }
+
     pub fn init_test(target: &str) {
         let config = Config {
             generation_mode: GenerationModeP::FuzzDriver,
             target: target.to_string(),
             n_sample: 10,
-            temperature: 0.6,
+            auto_n_sample: false,
+            n_sample_min: DEFAULT_N_SAMPLE_MIN,
+            n_sample_max: DEFAULT_N_SAMPLE_MAX,
+            programs_per_call: 1,
+            program_separator: None,
+            prompts_per_round: 1,
+            max_tokens: None,
+            max_tokens_cap: DEFAULT_MAX_TOKENS_CAP,
+            temperature: Some(0.6),
+            driver_temperature: None,
+            api_temperature: None,
+            temperature_schedule: None,
             cores: 10,
             max_cores: 0,
             fuzz_round_succ: 1,
@@ -249,12 +1044,67 @@ impl Config {
             exponent_branch: false,
             recheck: false,
             fuzzer_run: false,
+            comb_strategy: CombStrategy::Energy,
+            dump_schedule: false,
+            api_embeddings: None,
             disable_power_schedule: false,
             handler_type: HandlerType::Openai,
+            env_file: None,
             seed_gen_timeout: None,
             quiet_round: 3,
             num_new_pairs: 3,
+            target_pairs: None,
+            target_pair_fraction: None,
+            type_compatible_pairs: false,
+            type_compatible_triples: false,
             enable_cot: false,
+            stuck_threshold: None,
+            diversity_threshold: None,
+            max_program_len: None,
+            stream: false,
+            structured_output: false,
+            request_timeout: REQUEST_TIMEOUT,
+            coverage_ignorelist: None,
+            keep_artifacts: false,
+            libfuzzer_args: None,
+            min_fuzz_time: None,
+            cxx: None,
+            extra_cxxflags: None,
+            max_fuzz_time: None,
+            headers_include: None,
+            progress: false,
+            resume: false,
+            load_prompt: None,
+            only_minimize: false,
+            no_minimize: false,
+            minimize_epsilon: 0.0,
+            seed_count_limit: None,
+            min_new_branches: 1,
+            infra_retry_cap: 2,
+            judge_threshold: None,
+            keep_profiles: false,
+            save_every: None,
+            include_only_apis: None,
+            exclude_apis: Vec::new(),
+            exploration_bonus: 0_f32,
+            success_weight: 0_f32,
+            comb_len_min: DEFAULT_COMB_LEN_MIN,
+            comb_len_max: DEFAULT_COMB_LEN,
+            asan_options: None,
+            detect_leaks: None,
+            null_term: NullTerm::Auto,
+            strict_toolchain: false,
+            ubsan_options: None,
+            compress_errors: false,
+            verbose_generation: false,
+            quick_validate: false,
+            preflight: false,
+            core_binary_name: "core".to_string(),
+            work_dir: None,
+            entry_points: Vec::new(),
+            entry_point: None,
+            save_transcripts: None,
+            seed_dir: None,
         };
         let _ = CONFIG_INSTANCE.set(RwLock::new(config));
         crate::init_debug_logger().unwrap();
@@ -278,6 +1128,8 @@ pub struct LibConfig {
     pub extra_c_flags: Option<Vec<String>>,
     /// The landmark corpus prompt as example of input.
     pub landmark: Option<bool>,
+    /// The file used as the landmark input, which takes precedence over `landmark`. Relative paths are resolved from the library's data dir.
+    pub landmark_file: Option<String>,
     /// The types need to be forced added into prompt
     pub force_types: Option<Vec<String>>,
     /// Whether this library should be fuzzed in fork mode.
@@ -290,10 +1142,19 @@ pub struct LibConfig {
     pub init_file: Option<String>,
     /// The extra ASAN options used for libraries.
     pub asan_option: Option<String>,
+    /// The extra UBSAN options used for libraries.
+    pub ubsan_option: Option<String>,
     /// Whether disable fmemopen.
     pub disable_fmemopen: Option<bool>,
     /// Memory limit passed to libfuzzer
     pub rss_limit_mb: Option<usize>,
+    /// The seconds a fuzzer runs before it is stopped for no coverage gained.
+    pub min_fuzz_time: Option<u64>,
+    /// The maximum seconds a fuzzer runs.
+    pub max_fuzz_time: Option<u64>,
+    /// The independent entry points of the library, e.g., `decode` and `encode`, each with the instruction appended
+    /// to the prompts of its drivers. They are selected by `--entry-points`.
+    pub entry_points: Option<BTreeMap<String, String>>,
 }
 
 impl LibConfig {
@@ -303,6 +1164,14 @@ impl LibConfig {
         }
         false
     }
+
+    /// The prompt instruction of an entry point, None if the library does not declare it.
+    pub fn get_entry_point_suffix(&self, entry_point: &str) -> Option<&str> {
+        self.entry_points
+            .as_ref()
+            .and_then(|entry_points| entry_points.get(entry_point))
+            .map(String::as_str)
+    }
 }
 
 /// Template of generative prompt in system role. 
@@ -330,6 +1199,10 @@ Here are the custom types declared in {project}. Ensure that the variables you u
 {context}
 ----------------------
 ";
+pub const PROGRAM_SEPARATOR_TEMPLATE: &str = "
+Write {n} different programs in your response. Put a line containing only `{separator}` between each two of them.
+";
+
 pub const ERROR_REPAIR_TEMPLATE: &str =
 "The previous attempt to generate code failed with the following error:
 
@@ -362,6 +1235,7 @@ Here are the custom types declared in {project}. Ensure that the variables you u
 
 Use the following APIs in your function:
 {combinations}
+{avoid_pairs}
 Here are some successful examples:
 {successful_examples}
 Function Requirements:
@@ -688,7 +1562,11 @@ pub fn get_user_chat_template() -> String {
     let library_name = get_library_name();
     let deopt = Deopt::new(library_name).unwrap();
     let mut template = get_user_gen_template().to_string();
-    if let Some(landmark) = deopt.get_library_landmark_corpus() {
+    let landmark = deopt.get_library_landmark_corpus().unwrap_or_else(|err| {
+        log::warn!("Skip the landmark in the prompt: {err}");
+        None
+    });
+    if let Some(landmark) = landmark {
         template.insert_str(0, &format!("The input data is: {landmark}\n\n\n."));
     }
     if let Some(init) = &deopt.config.spec {
@@ -704,3 +1582,153 @@ pub fn get_user_chat_template() -> String {
     }
     template
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_sanitizer_options() {
+        assert_eq!(
+            merge_sanitizer_options(&[
+                "exitcode=168:alloc_dealloc_mismatch=0",
+                "detect_leaks=1:exitcode=1",
+                "",
+                "detect_leaks=0:max_allocation_size_mb=2048:",
+            ]),
+            "exitcode=1:alloc_dealloc_mismatch=0:detect_leaks=0:max_allocation_size_mb=2048"
+        );
+        assert_eq!(merge_sanitizer_options(&["", ""]), "");
+        assert!(check_sanitizer_options("--asan-options", "detect_leaks=0:").is_ok());
+        assert!(check_sanitizer_options("--asan-options", "detect_leaks").is_err());
+    }
+
+    #[test]
+    fn test_parse_include_lines() {
+        let content = "
+// auxiliary headers
+#include <zlib/zconf.h>
+#  include \"extra.h\"
+curl/easy.h
+#include <zlib/zconf.h>
+";
+        assert_eq!(
+            parse_include_lines(content).unwrap(),
+            vec!["#include <zlib/zconf.h>", "#include \"extra.h\"", "#include <curl/easy.h>"]
+        );
+        assert!(parse_include_lines("#define A 1").is_err());
+        assert!(parse_include_lines("#include zconf.h").is_err());
+    }
+
+    #[test]
+    fn test_resolve_fuzz_time_window() {
+        let lib = LibConfig {
+            min_fuzz_time: Some(30),
+            max_fuzz_time: Some(120),
+            ..Default::default()
+        };
+        assert_eq!(
+            resolve_fuzz_time_window(None, None, &LibConfig::default()).unwrap(),
+            (MIN_FUZZ_TIME, MAX_FUZZ_TIME)
+        );
+        assert_eq!(resolve_fuzz_time_window(None, None, &lib).unwrap(), (30, 120));
+        assert_eq!(resolve_fuzz_time_window(Some(10), None, &lib).unwrap(), (10, 120));
+        assert!(resolve_fuzz_time_window(Some(200), None, &lib).is_err());
+        assert!(resolve_fuzz_time_window(Some(0), Some(10), &lib).is_err());
+    }
+
+    #[test]
+    fn test_merge_cxxflags() {
+        let lib_flags = vec!["-lz".to_string(), "-DNDEBUG".to_string()];
+        assert_eq!(
+            merge_cxxflags(Some(&lib_flags), Some(" --target=aarch64-linux-gnu  -O1 ")),
+            vec!["-lz", "-DNDEBUG", "--target=aarch64-linux-gnu", "-O1"]
+        );
+        assert_eq!(merge_cxxflags(None, Some("-O1")), vec!["-O1"]);
+        assert!(merge_cxxflags(None, None).is_empty());
+    }
+
+    #[test]
+    fn test_parse_libfuzzer_args() {
+        let flags = parse_libfuzzer_args(" -max_len=4096  -timeout=25 ").unwrap();
+        assert_eq!(flags, vec!["-max_len=4096", "-timeout=25"]);
+        assert!(parse_libfuzzer_args("").unwrap().is_empty());
+        assert!(parse_libfuzzer_args("corpus_dir").is_err());
+        assert!(parse_libfuzzer_args("-max_len").is_err());
+        assert!(parse_libfuzzer_args("-timeout=5 -timeout=10").is_err());
+    }
+
+    #[test]
+    fn test_parse_fuzzer_args_file() {
+        let content = "# the sampling\n--n-sample\n  8  \n\n--comb-strategy\nsemantic\n  # --stream\n";
+        assert_eq!(
+            parse_fuzzer_args_file(content),
+            ["--n-sample", "8", "--comb-strategy", "semantic"]
+        );
+        assert!(parse_fuzzer_args_file("\n# nothing\n").is_empty());
+    }
+
+    #[test]
+    fn test_load_env_file() -> eyre::Result<()> {
+        let path = std::env::temp_dir().join("prompt_fuzz_test.env");
+        std::fs::write(
+            &path,
+            "# the model\nPROMPT_FUZZ_TEST_ENV_MODEL=model-from-file\nPROMPT_FUZZ_TEST_ENV_BASE=http://file\n",
+        )?;
+        std::env::set_var("PROMPT_FUZZ_TEST_ENV_BASE", "http://process");
+        load_env_file(Some(&path))?;
+        assert_eq!(std::env::var("PROMPT_FUZZ_TEST_ENV_MODEL")?, "model-from-file");
+        assert_eq!(std::env::var("PROMPT_FUZZ_TEST_ENV_BASE")?, "http://process");
+        std::fs::remove_file(&path)?;
+        assert!(load_env_file(Some(&path)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_null_term_override() {
+        assert!(NullTerm::Auto.resolve(true));
+        assert!(!NullTerm::Auto.resolve(false));
+        assert!(NullTerm::On.resolve(false));
+        assert!(!NullTerm::Off.resolve(true));
+    }
+
+    #[test]
+    fn test_mode_temperature() {
+        let mut config = Config::parse_from(["fuzzer", "zlib", "-t", "0.8", "--api-temperature", "1.3"]);
+        assert_eq!(
+            config.mode_temperature(&GenerationModeP::ApiCombination),
+            (1.3, "--api-temperature")
+        );
+        assert_eq!(
+            config.mode_temperature(&GenerationModeP::FuzzDriver),
+            (0.8, "--temperature")
+        );
+        config.driver_temperature = Some(0.2);
+        assert_eq!(
+            config.mode_temperature(&GenerationModeP::FuzzDriver),
+            (0.2, "--driver-temperature")
+        );
+        let config = Config::parse_from(["fuzzer", "zlib"]);
+        assert_eq!(config.mode_temperature(&GenerationModeP::FuzzDriver), (1_f32, "--temperature"));
+        let config = Config::parse_from(["fuzzer", "zlib", "--handler", "http"]);
+        assert_eq!(config.mode_temperature(&GenerationModeP::FuzzDriver), (0.7, "--temperature"));
+    }
+
+    #[test]
+    fn test_temperature_schedule() {
+        let schedule: TemperatureSchedule = "1.2:0.4".parse().unwrap();
+        assert_eq!(schedule.temperature_at(0_f32), 1.2);
+        assert!((schedule.temperature_at(2_f32) - 0.4).abs() < 1e-6);
+        assert!((schedule.temperature_at(0.5) - 0.8).abs() < 1e-6);
+        assert!("1.2".parse::<TemperatureSchedule>().is_err());
+        assert!("a:0.4".parse::<TemperatureSchedule>().is_err());
+    }
+
+    #[test]
+    fn test_check_stream_mode() {
+        let config = Config::parse_from(["fuzzer", "zlib", "--stream"]);
+        assert!(check_config(&config).is_ok());
+        let config = Config::parse_from(["fuzzer", "zlib", "--stream", "--gen-mode", "fuzz-driver"]);
+        assert!(check_config(&config).is_err());
+    }
+}
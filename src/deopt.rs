@@ -4,7 +4,7 @@ use crate::{
     deopt::utils::get_file_dirname,
     execution::{ast::remove_duplicate_definition, logger::ProgramError, Executor},
     feedback::{clang_coverage::CodeCoverage, observer::Observer},
-    program::{serde::Serialize, Program},
+    program::{gadget::ApiSignature, serde::Serialize, Program},
 };
 use eyre::{Context, Result};
 use once_cell::sync::OnceCell;
@@ -22,6 +22,12 @@ pub struct Deopt {
     pub seed_queue: VecDeque<Program>,
     pub seed_id: usize,
     pub config: LibConfig,
+    /// Overrides `output/<library>` if set.
+    pub work_dir: Option<PathBuf>,
+    /// Overrides the `seeds` dir under the output dir if set.
+    pub seed_dir: Option<PathBuf>,
+    /// Puts the outputs in `entry_points/<name>` of the output dir if set, see `--entry-points`.
+    pub entry_point: Option<String>,
 }
 
 impl Deopt {
@@ -73,6 +79,18 @@ impl Deopt {
         program
     }
 
+    /// Sample at most `n` distinct seeds from the seed queue, weighted by their quality scores.
+    pub fn sample_seeds_by_quality(&self, n: usize) -> Vec<Program> {
+        let mut candidates: Vec<&Program> = self.seed_queue.iter().collect();
+        let mut sampled = Vec::new();
+        while sampled.len() < n && !candidates.is_empty() {
+            let weights: Vec<f32> = candidates.iter().map(|seed| seed.get_weight()).collect();
+            let choose = crate::program::rand::weighted_choose(weights);
+            sampled.push(candidates.swap_remove(choose).clone());
+        }
+        sampled
+    }
+
     pub fn update_seed_queue(
         &mut self,
         program: Program,
@@ -83,12 +101,45 @@ impl Deopt {
             self.update_seed_unique_branches(coverage);
             self.save_program(&program)?;
             self.seed_queue.push_back(program);
+            if let Some(limit) = config::get_seed_count_limit() {
+                for seed in self.evict_seeds_over_limit(limit) {
+                    log::info!(
+                        "Evict seed {} of quality {:.4}, the seed queue exceeds --seed-count-limit {limit}.",
+                        seed.id,
+                        seed.get_weight()
+                    );
+                    // the evicted seeds stay in the succ seeds, and are not loaded into the queue again.
+                    self.save_succ_program(&seed)?;
+                    std::fs::remove_file(self.get_seed_path_by_id(seed.id)?)?;
+                }
+            }
         } else {
             self.save_succ_program(&program)?;
         }
         Ok(())
     }
 
+    /// Remove the seeds of the lowest quality score from the seed queue until at most `limit` seeds remain,
+    /// and return them. Among the seeds of the same score, the older one is evicted first.
+    pub fn evict_seeds_over_limit(&mut self, limit: usize) -> Vec<Program> {
+        let mut evicted = Vec::new();
+        while self.seed_queue.len() > limit {
+            let lowest = self
+                .seed_queue
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    a.get_weight()
+                        .total_cmp(&b.get_weight())
+                        .then(a.id.cmp(&b.id))
+                })
+                .map(|(idx, _)| idx)
+                .unwrap();
+            evicted.extend(self.seed_queue.remove(lowest));
+        }
+        evicted
+    }
+
     // Update the quality (especially the unique branches) of seeds in prompt queue according the incoming new code coverage.
     pub fn update_seed_unique_branches(&mut self, coverage: &CodeCoverage) {
         log::debug!("Update prompt queue according the incoming code coverage");
@@ -139,6 +190,92 @@ impl Deopt {
         let mut deopt = Deopt::default();
         deopt.project_name = project_name;
         deopt.config = deopt.parse_lib_config()?;
+        let (work_dir, seed_dir) = config::get_dir_overrides();
+        Ok(deopt
+            .with_dir_overrides(work_dir, seed_dir)
+            .with_entry_point(config::get_entry_point()))
+    }
+
+    /// Separate the outputs of an entry point from the other ones. `None` keeps the outputs of the whole library.
+    pub fn with_entry_point(mut self, entry_point: Option<String>) -> Self {
+        self.entry_point = entry_point;
+        self
+    }
+
+    /// The prompt instruction of the entry point, empty for the whole library.
+    pub fn get_entry_point_suffix(&self) -> &str {
+        self.entry_point
+            .as_deref()
+            .and_then(|entry_point| self.config.get_entry_point_suffix(entry_point))
+            .unwrap_or_default()
+    }
+
+    /// Relocate the outputs of the library to `work_dir` and the seeds to `seed_dir`.
+    /// `None` keeps the built-in layout.
+    pub fn with_dir_overrides(mut self, work_dir: Option<PathBuf>, seed_dir: Option<PathBuf>) -> Self {
+        self.work_dir = work_dir;
+        self.seed_dir = seed_dir;
+        self
+    }
+
+    /// Register a library that is not in the built-in list: scaffold its `data` and `output/build` layout and write the `LibConfig`.
+    /// `headers` is the directory (or a single file) of the public headers, and `lib` is the directory of the built libraries.
+    pub fn register_library(config: LibConfig, headers: &Path, lib: &Path) -> Result<Self> {
+        for (lib_name, suffix) in [(&config.static_lib_name, ".a"), (&config.dyn_lib_name, ".so")] {
+            if !lib_name.ends_with(suffix) {
+                eyre::bail!("The library name `{lib_name}` should end with `{suffix}`.");
+            }
+            let lib_path: PathBuf = [lib, Path::new(lib_name)].iter().collect();
+            if !lib_path.is_file() {
+                eyre::bail!("Cannot resolve the library `{lib_name}` in {lib:?}.");
+            }
+        }
+        if !headers.exists() {
+            eyre::bail!("Cannot find the headers: {headers:?}.");
+        }
+
+        let deopt = Deopt {
+            project_name: config.project_name.clone(),
+            config,
+            ..Default::default()
+        };
+        let data_dir = deopt.get_library_data_dir()?;
+        let config_path: PathBuf = [data_dir.clone(), "config.yaml".into()].iter().collect();
+        if config_path.exists() {
+            eyre::bail!(
+                "The library {} has been registered in {config_path:?}.",
+                deopt.project_name
+            );
+        }
+        std::fs::create_dir_all(&data_dir)?;
+
+        // copy the headers with their relative layout.
+        let header_dir = deopt.get_library_build_header_path()?;
+        if headers.is_file() {
+            create_dir_if_nonexist(&header_dir)?;
+            std::fs::copy(headers, header_dir.join(headers.file_name().unwrap()))?;
+        } else {
+            for header in utils::read_all_files_in_dir(headers)? {
+                let to_file = header_dir.join(header.strip_prefix(headers)?);
+                std::fs::create_dir_all(get_file_dirname(&to_file))?;
+                std::fs::copy(&header, to_file)?;
+            }
+        }
+        // copy the built libraries, including their instrumented variants.
+        let lib_dir = deopt.get_library_build_lib_path()?;
+        create_dir_if_nonexist(&lib_dir)?;
+        for file in utils::read_sort_dir(lib)? {
+            if file.is_file() {
+                std::fs::copy(&file, lib_dir.join(file.file_name().unwrap()))?;
+            }
+        }
+
+        let content = serde_yaml::to_string(&deopt.config)?;
+        std::fs::write(&config_path, content)?;
+        log::info!(
+            "Registered library {} at {data_dir:?}.",
+            deopt.project_name
+        );
         Ok(deopt)
     }
 
@@ -193,6 +330,15 @@ impl Deopt {
         Ok(gadget_path)
     }
 
+    /// The exported APIs of the library and their signatures, derived from `get_func_gadgets`.
+    /// The gadgets are of the configured library, so call it on the `Deopt` of that library.
+    pub fn exported_apis(&self) -> Vec<ApiSignature> {
+        crate::program::gadget::get_func_gadgets()
+            .iter()
+            .map(ApiSignature::from)
+            .collect()
+    }
+
     pub fn get_library_func_gadget_path(&self) -> Result<PathBuf> {
         let gadget_path: PathBuf = [self.get_library_misc_dir()?, "func_gadgets.txt".into()]
             .iter()
@@ -207,6 +353,39 @@ impl Deopt {
         Ok(save_path)
     }
 
+    pub fn get_library_observer_snapshot_path(&self) -> Result<PathBuf> {
+        let save_path: PathBuf = [self.get_library_misc_dir()?, "observer_snapshot.json".into()]
+            .iter()
+            .collect();
+        Ok(save_path)
+    }
+
+    /// The clang version recorded on the first run of the library, see `execution::toolchain`.
+    pub fn get_library_toolchain_path(&self) -> Result<PathBuf> {
+        Ok(self.get_library_misc_dir()?.join("clang_version.txt"))
+    }
+
+    pub fn get_library_checkpoint_path(&self) -> Result<PathBuf> {
+        Ok(self.get_library_misc_dir()?.join("checkpoint.json"))
+    }
+
+    /// The prompt checkpointed for `--resume`, see `Prompt::save`.
+    pub fn get_library_prompt_checkpoint_path(&self) -> Result<PathBuf> {
+        Ok(self.get_library_misc_dir()?.join("prompt_checkpoint.json"))
+    }
+
+    pub fn get_library_comb_enumerator_path(&self) -> Result<PathBuf> {
+        Ok(self.get_library_misc_dir()?.join("comb_enumerator.json"))
+    }
+
+    /// The embeddings file of `--comb-strategy semantic`.
+    pub fn get_library_api_embeddings_path(&self) -> Result<PathBuf> {
+        if let Some(path) = &config::get_config().api_embeddings {
+            return Ok(path.clone());
+        }
+        Ok(self.get_library_data_dir()?.join("api_embeddings.json"))
+    }
+
     pub fn get_library_api_cov_dump_path(&self) -> Result<PathBuf> {
         let save_path: PathBuf = [self.get_library_misc_dir()?, "api_coverages.json".into()]
             .iter()
@@ -216,8 +395,21 @@ impl Deopt {
 
     /// get the output directory of the library under test.
     pub fn get_library_output_dir(&self) -> Result<PathBuf> {
-        let mut p_out_dir: PathBuf = Self::get_crate_output_dir()?;
-        p_out_dir.push(&self.project_name);
+        let mut p_out_dir: PathBuf = match &self.work_dir {
+            // the parents of a user given dir may not exist.
+            Some(work_dir) => {
+                std::fs::create_dir_all(work_dir)?;
+                work_dir.clone()
+            }
+            None => [Self::get_crate_output_dir()?, self.project_name.clone().into()]
+                .iter()
+                .collect(),
+        };
+        if let Some(entry_point) = &self.entry_point {
+            p_out_dir.push("entry_points");
+            p_out_dir.push(entry_point);
+            std::fs::create_dir_all(&p_out_dir)?;
+        }
         utils::create_dir_if_nonexist(&p_out_dir)?;
         Ok(p_out_dir)
     }
@@ -236,9 +428,26 @@ impl Deopt {
         Ok(outs_dir)
     }
 
+    /// The dir the redundant seeds pruned by `find-redundant` are moved to.
+    pub fn get_library_redundant_seed_dir(&self) -> Result<PathBuf> {
+        let outs_dir = self.get_library_output_dir()?.join("redundant_seeds");
+        utils::create_dir_if_nonexist(&outs_dir)?;
+        Ok(outs_dir)
+    }
+
     pub fn get_library_seed_dir(&self) -> Result<PathBuf> {
-        let mut outs_dir = self.get_library_output_dir()?;
-        outs_dir.push("seeds");
+        let outs_dir = match &self.seed_dir {
+            // the entry points share `--seed-dir`, so each gets its own bucket as under the output dir.
+            Some(seed_dir) => {
+                let seed_dir = match &self.entry_point {
+                    Some(entry_point) => seed_dir.join("entry_points").join(entry_point),
+                    None => seed_dir.clone(),
+                };
+                std::fs::create_dir_all(&seed_dir)?;
+                seed_dir
+            }
+            None => self.get_library_output_dir()?.join("seeds"),
+        };
         utils::create_dir_if_nonexist(&outs_dir)?;
         Ok(outs_dir)
     }
@@ -282,6 +491,62 @@ impl Deopt {
         Ok(misc_dir)
     }
 
+    /// The directory that preserves the crash-triggering inputs and their sanitizer reports.
+    pub fn get_library_crashes_dir(&self) -> Result<PathBuf> {
+        let mut crashes_dir = self.get_library_output_dir()?;
+        crashes_dir.push("crashes");
+        utils::create_dir_if_nonexist(&crashes_dir)?;
+        Ok(crashes_dir)
+    }
+
+    /// The number of crash reports in the crashes dir, including the leaks dir.
+    pub fn count_crash_artifacts(&self) -> Result<usize> {
+        let crashes_dir = self.get_library_crashes_dir()?;
+        let mut count = 0;
+        for dir in [crashes_dir.clone(), crashes_dir.join("leaks")] {
+            for path in utils::read_sort_dir(&dir)? {
+                if path.extension().is_some_and(|ext| ext == "stderr") {
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// The directory of leak reproducers with `--detect-leaks true`.
+    pub fn get_library_leaks_dir(&self) -> Result<PathBuf> {
+        let leaks_dir = self.get_library_crashes_dir()?.join("leaks");
+        utils::create_dir_if_nonexist(&leaks_dir)?;
+        Ok(leaks_dir)
+    }
+
+    /// Save a crash to the crashes dir as `<source>-<kind>-<hash>.input` and `<source>-<kind>-<hash>.stderr`.
+    ///
+    /// The `.input` file is only written if the crash has a triggering input. Returns the path without extension.
+    /// Leaks are saved in the leaks dir instead if `--detect-leaks true` is set.
+    pub fn save_crash_artifact(
+        &self,
+        source: &str,
+        input: Option<&[u8]>,
+        report: &str,
+    ) -> Result<PathBuf> {
+        let name = utils::get_crash_artifact_name(source, input, report);
+        let crash_dir = if crate::config::get_detect_leaks() == Some(true)
+            && utils::get_crash_kind(report) == "leak"
+        {
+            self.get_library_leaks_dir()?
+        } else {
+            self.get_library_crashes_dir()?
+        };
+        let crash_path = crash_dir.join(name);
+        if let Some(input) = input {
+            std::fs::write(crash_path.with_extension("input"), input)?;
+        }
+        std::fs::write(crash_path.with_extension("stderr"), report)?;
+        log::info!("Crash artifact saved: {crash_path:?}");
+        Ok(crash_path)
+    }
+
     pub fn get_library_coverage_path(&self) -> Result<PathBuf> {
         let path: PathBuf = [self.get_library_misc_dir()?, "code_coverage.csv".into()]
             .iter()
@@ -314,42 +579,106 @@ impl Deopt {
         Ok(corpus_dir)
     }
 
-    pub fn get_library_landmark_corpus(&self) -> Option<String> {
-        if let Some(landmark) = &self.config.landmark {
-            if landmark == &true {
-                let corpus_files =
-                    utils::read_sort_dir(&self.get_library_build_corpus_dir().unwrap()).unwrap();
-                let choose = crate::program::rand::random_select(&corpus_files);
-                let data = std::fs::read_to_string(choose).unwrap();
-                return Some(data);
-            }
+    pub fn get_library_landmark_corpus(&self) -> Result<Option<String>> {
+        if let Some(landmark_file) = &self.config.landmark_file {
+            let landmark_path = self.get_library_data_dir()?.join(landmark_file);
+            let data = std::fs::read(&landmark_path).with_context(|| {
+                format!("Cannot read the landmark file {landmark_path:?}, please check `landmark_file` in the config.yaml of {}.", self.project_name)
+            })?;
+            return Ok(Some(utils::encode_landmark(&data)));
         }
-        None
+        if self.config.landmark == Some(true) {
+            let corpus_files = self.get_library_landmark_corpus_files()?;
+            let choose = crate::program::rand::random_select(&corpus_files);
+            let data = std::fs::read(choose)
+                .with_context(|| format!("Cannot read the landmark corpus {choose:?}"))?;
+            return Ok(Some(utils::encode_landmark(&data)));
+        }
+        Ok(None)
     }
 
-    pub fn get_asan_options(&self) -> String {
-        let mut options = crate::config::ASAN_OPTIONS.join(":");
-        if let Some(extra_option) = &self.config.asan_option {
-            options.push(':');
-            options.push_str(extra_option);
+    /// The corpus files the landmark is chosen from, which must be built in advance.
+    fn get_library_landmark_corpus_files(&self) -> Result<Vec<PathBuf>> {
+        let corpus_dir = self.get_library_build_corpus_dir()?;
+        let corpus_files = utils::read_sort_dir(&corpus_dir).with_context(|| {
+            format!("Cannot find the corpus dir {corpus_dir:?} for `landmark`, please build {} by its build.sh in advance.", self.project_name)
+        })?;
+        if corpus_files.is_empty() {
+            eyre::bail!("The corpus dir {corpus_dir:?} for `landmark` is empty, please check the corpus built by the build.sh of {}.", self.project_name);
         }
-        options
+        Ok(corpus_files)
+    }
+
+    /// The initialize file of the library in its build dir, if it requires one.
+    fn get_library_init_file_path(&self) -> Result<Option<PathBuf>> {
+        let Some(init_file) = &self.config.init_file else {
+            return Ok(None);
+        };
+        let file_path: PathBuf = [
+            self.get_library_build_dir()?,
+            "work".into(),
+            init_file.into(),
+        ]
+        .iter()
+        .collect();
+        if !file_path.exists() {
+            return Err(crate::FuzzerError::Infra(format!("Cannot find the library initialize file {file_path:?}, please build {} by its build.sh in advance.", self.project_name)).into());
+        }
+        Ok(Some(file_path))
+    }
+
+    /// Check the files the library under test requires at startup, so that a missing one fails with the
+    /// path expected and how to create it, instead of deep in a run.
+    pub fn check_library_paths(&self) -> Result<()> {
+        let data = self.get_library_data_dir()?;
+        if !data.exists() {
+            eyre::bail!(
+                "Cannot find the entry {} in `data` dir ({data:?}), please prepare it in advance, e.g., by `harness register-target`.",
+                self.project_name
+            );
+        }
+        let lib = self.get_library_build_lib_path()?;
+        if !lib.exists() {
+            return Err(crate::FuzzerError::Infra(format!("Cannot find the build library {} in `output/build` dir ({lib:?}), please build it by build.sh in advance.", self.project_name)).into());
+        }
+        self.get_library_landmark_corpus()?;
+        self.get_library_init_file_path()?;
+        Ok(())
+    }
+
+    /// The ASAN_OPTIONS, in which `--asan-options` overrides `--detect-leaks`,
+    /// which overrides the library's, which overrides the defaults.
+    pub fn get_asan_options(&self) -> String {
+        let defaults = crate::config::ASAN_OPTIONS.join(":");
+        let (cli, _) = crate::config::get_cli_sanitizer_options();
+        let detect_leaks = crate::config::get_detect_leaks()
+            .map(|detect| format!("detect_leaks={}", detect as u8))
+            .unwrap_or_default();
+        crate::config::merge_sanitizer_options(&[
+            &defaults,
+            self.config.asan_option.as_deref().unwrap_or_default(),
+            &detect_leaks,
+            cli.as_deref().unwrap_or_default(),
+        ])
+    }
+
+    /// The UBSAN_OPTIONS, merged like `get_asan_options`.
+    pub fn get_ubsan_options(&self) -> String {
+        let defaults = crate::config::UBSAN_OPTIONS.join(":");
+        let (_, cli) = crate::config::get_cli_sanitizer_options();
+        crate::config::merge_sanitizer_options(&[
+            &defaults,
+            self.config.ubsan_option.as_deref().unwrap_or_default(),
+            cli.as_deref().unwrap_or_default(),
+        ])
     }
 
     pub fn copy_library_init_file(&self, dir: &Path) -> Result<()> {
-        if let Some(init_file) = &self.config.init_file {
-            let file_path: PathBuf = [
-                self.get_library_build_dir()?,
-                "work".into(),
-                init_file.into(),
-            ]
-            .iter()
-            .collect();
-            if !file_path.exists() {
-                eyre::bail!("Cannot find the library initialize file {init_file:?}")
-            }
-            let cur_file: PathBuf = [PathBuf::from(dir), init_file.into()].iter().collect();
-            std::fs::copy(file_path, cur_file)?;
+        if let Some(file_path) = self.get_library_init_file_path()? {
+            let cur_file = dir.join(file_path.file_name().unwrap());
+            std::fs::copy(&file_path, &cur_file).map_err(|err| {
+                crate::FuzzerError::Infra(format!("Cannot copy the library initialize file to {cur_file:?}: {err}"))
+            })?;
         }
         Ok(())
     }
@@ -486,6 +815,11 @@ impl Deopt {
         Ok(meta_path)
     }
 
+    /// Returns the path where the seed metadata of FuzzDriver mode are saved, under the work dir.
+    pub fn get_work_seed_meta_path(&self) -> Result<PathBuf> {
+        Ok(self.get_library_work_dir()?.join("seed_meta.csv"))
+    }
+
     pub fn get_seed_work_dir_by_id(&self, id: usize) -> Result<PathBuf> {
         let work_dir = self.get_library_work_dir()?;
         let seed_work_dir: PathBuf = [
@@ -520,6 +854,25 @@ impl Deopt {
         Ok(Self::get_coverage_file_by_dir(&seed_work_dir))
     }
 
+    /// The dir of the coverage profiles kept by `--keep-profiles`.
+    pub fn get_library_profiles_dir(&self) -> Result<PathBuf> {
+        let path = self.get_library_work_dir()?.join("profiles");
+        utils::create_dir_if_nonexist(&path)?;
+        Ok(path)
+    }
+
+    /// Copy the coverage profile of a seed to `work/profiles/id_XXXXXX.profdata`, where it is not overwritten
+    /// by the rechecks.
+    pub fn keep_seed_profile(&self, seed_id: usize) -> Result<PathBuf> {
+        let profdata = self.get_seed_coverage_file(seed_id)?;
+        let kept: PathBuf = self
+            .get_library_profiles_dir()?
+            .join(format!("id_{number:>0width$}.profdata", number = seed_id, width = 6));
+        std::fs::copy(&profdata, &kept)
+            .with_context(|| format!("Failed to keep the coverage profile {profdata:?}"))?;
+        Ok(kept)
+    }
+
     pub fn get_seed_coverage(&self, seed_id: usize) -> Result<CodeCoverage> {
         let profdata = self.get_seed_coverage_file(seed_id)?;
         let executor = Executor::new(self)?;
@@ -566,6 +919,9 @@ impl Deopt {
             ProgramError::Fuzzer(_) => [save_dir.clone(), "fuzzer".into()].iter().collect(),
             ProgramError::Coverage(_) => [save_dir.clone(), "coverage".into()].iter().collect(),
             ProgramError::Hang(_) => [save_dir.clone(), "hang".into()].iter().collect(),
+            ProgramError::TooLong(_) => [save_dir.clone(), "too_long".into()].iter().collect(),
+            ProgramError::Infra(_) => [save_dir.clone(), "infra".into()].iter().collect(),
+            ProgramError::Judged(_) => [save_dir.clone(), "judged".into()].iter().collect(),
         };
         utils::create_dir_if_nonexist(&save_dir)?;
         let seed_path: PathBuf = [
@@ -580,6 +936,11 @@ impl Deopt {
             err_msg.get_err_msg(),
         ]
         .concat();
+        if crate::config::compress_errors() {
+            let seed_path = utils::append_gz_extension(&seed_path);
+            utils::write_gz(&seed_path, content.as_bytes())?;
+            return Ok(seed_path);
+        }
         std::fs::write(&seed_path, content)?;
         Ok(seed_path)
     }
@@ -631,10 +992,40 @@ impl Deopt {
 }
 
 pub mod utils {
+    use base64::Engine;
     use std::collections::HashSet;
 
     use super::*;
 
+    /// `id_000001.cc` -> `id_000001.cc.gz`
+    pub fn append_gz_extension(path: &Path) -> PathBuf {
+        let mut path = path.as_os_str().to_owned();
+        path.push(".gz");
+        PathBuf::from(path)
+    }
+
+    /// Write `content` gzip compressed.
+    pub fn write_gz(path: &Path, content: &[u8]) -> Result<()> {
+        use std::io::Write;
+        let file = std::fs::File::create(path)?;
+        let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        encoder.write_all(content)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    /// Read the file to string, which is decompressed if it ends with `.gz`.
+    pub fn read_to_string_maybe_gz(path: &Path) -> Result<String> {
+        use std::io::Read;
+        if path.extension().is_some_and(|ext| ext == "gz") {
+            let file = std::fs::File::open(path)?;
+            let mut content = String::new();
+            flate2::read::GzDecoder::new(file).read_to_string(&mut content)?;
+            return Ok(content);
+        }
+        Ok(std::fs::read_to_string(path)?)
+    }
+
     /// create the directory if it does not exist
     pub fn create_dir_if_nonexist(path: &Path) -> Result<()> {
         if !path.exists() {
@@ -643,7 +1034,29 @@ pub mod utils {
         Ok(())
     }
 
-    /// read the directory and sort the entries by the alphabet oder
+    /// The sort key of a path: the file name is split at its first number, e.g., `id_000010.cc` => ("id_", 10, ".cc").
+    fn embedded_id_key(path: &Path) -> (PathBuf, String, Option<u128>, String) {
+        let parent = path.parent().map(PathBuf::from).unwrap_or_default();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let Some(start) = name.find(|c: char| c.is_ascii_digit()) else {
+            return (parent, name, None, String::new());
+        };
+        let end = name[start..]
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(name.len(), |len| start + len);
+        let id = name[start..end].parse().ok();
+        (parent, name[..start].to_string(), id, name[end..].to_string())
+    }
+
+    /// sort the paths by the number embedded in their file names, e.g., `id_000009.cc` < `id_000010.cc` < `id_1000000.cc`.
+    pub fn sort_by_embedded_id(paths: &mut [PathBuf]) {
+        paths.sort_by_cached_key(|path| (embedded_id_key(path), path.clone()));
+    }
+
+    /// read the directory and sort the entries by the ids embedded in their names
     pub fn read_sort_dir(dir: &Path) -> Result<Vec<PathBuf>> {
         let mut entries = Vec::new();
         if !dir.exists() {
@@ -656,7 +1069,7 @@ pub mod utils {
             let path = entry?.path();
             entries.push(path);
         }
-        entries.sort();
+        sort_by_embedded_id(&mut entries);
         Ok(entries)
     }
 
@@ -668,6 +1081,10 @@ pub mod utils {
             for header in get_library_headers(deopt).unwrap() {
                 content.push_str(&format!("#include <{header}>\n"));
             }
+            for include in crate::config::get_extra_include_lines() {
+                content.push_str(include);
+                content.push('\n');
+            }
             content
         })
     }
@@ -687,13 +1104,13 @@ pub mod utils {
     }
 
     /// Get the paths of all corpus files in the corpus directory.
-    pub fn get_library_corpus_files(deopt: &Deopt) -> &'static Vec<PathBuf> {
+    pub fn get_library_corpus_files(deopt: &Deopt) -> Result<&'static Vec<PathBuf>> {
         static CORPUS_FILES: OnceCell<Vec<PathBuf>> = OnceCell::new();
-        CORPUS_FILES.get_or_init(|| {
-            let corpus_dir: PathBuf = deopt
-                .get_library_build_corpus_dir()
-                .expect("This library has not built with corpus. Please build it first.");
-            read_all_files_in_dir(&corpus_dir).unwrap()
+        CORPUS_FILES.get_or_try_init(|| {
+            let corpus_dir: PathBuf = deopt.get_library_build_corpus_dir()?;
+            read_all_files_in_dir(&corpus_dir).with_context(|| {
+                format!("This library has not built with corpus {corpus_dir:?}. Please build it by its build.sh first.")
+            })
         })
     }
 
@@ -807,6 +1224,51 @@ pub mod utils {
         })
     }
 
+    /// Format the landmark input for prompt. The binary inputs are base64-encoded, as they would be garbled as text.
+    pub fn encode_landmark(data: &[u8]) -> String {
+        if let Ok(text) = std::str::from_utf8(data) {
+            if !text
+                .chars()
+                .any(|c| c.is_control() && !c.is_ascii_whitespace())
+            {
+                return text.to_string();
+            }
+        }
+        let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+        format!("(base64-encoded) {encoded}")
+    }
+
+    /// Parse the kind of crash from the sanitizer or libFuzzer report, e.g., heap-buffer-overflow, timeout.
+    pub fn get_crash_kind(report: &str) -> String {
+        // the leaks are told first, as LeakSanitizer may report in the SUMMARY of AddressSanitizer.
+        if report.contains("detected memory leaks") {
+            return "leak".to_string();
+        }
+        let re = regex::Regex::new(r"SUMMARY: \w+Sanitizer: ([a-zA-Z][\w-]*)").unwrap();
+        if let Some(captures) = re.captures(report) {
+            return captures[1].to_string();
+        }
+        if report.contains("ERROR: libFuzzer: timeout") {
+            return "timeout".to_string();
+        }
+        if report.contains("ERROR: libFuzzer: out-of-memory") {
+            return "oom".to_string();
+        }
+        "crash".to_string()
+    }
+
+    /// The file name of crash artifact: `<source>-<kind>-<hash>`. The hash is taken from the input if any, otherwise from the report.
+    pub fn get_crash_artifact_name(source: &str, input: Option<&[u8]>, report: &str) -> String {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        match input {
+            Some(input) => input.hash(&mut hasher),
+            None => report.hash(&mut hasher),
+        }
+        let kind = get_crash_kind(report);
+        format!("{source}-{kind}-{:016x}", hasher.finish())
+    }
+
     pub fn get_file_dirname(path: &Path) -> PathBuf {
         if path.is_dir() {
             return PathBuf::from(path);
@@ -850,6 +1312,50 @@ pub mod utils {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_evict_seeds_over_limit() {
+        let seed = |id: usize, density: usize| {
+            let mut program = Program::new("");
+            program.id = id;
+            program.set_quality(crate::program::Quality::new(density, vec![], vec![]));
+            program
+        };
+        let mut deopt = Deopt {
+            seed_queue: [seed(1, 2), seed(2, 1), seed(3, 5), seed(4, 1)].into(),
+            ..Default::default()
+        };
+        assert!(deopt.evict_seeds_over_limit(4).is_empty());
+
+        let evicted: Vec<usize> = deopt.evict_seeds_over_limit(2).iter().map(|s| s.id).collect();
+        // seed 2 and 4 tie on the lowest score, the older one goes first.
+        assert_eq!(evicted, vec![2, 4]);
+        let kept: Vec<usize> = deopt.seed_queue.iter().map(|s| s.id).collect();
+        assert_eq!(kept, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_crash_artifact_name() {
+        let asan = "==1==ERROR: AddressSanitizer: heap-buffer-overflow on address\n\
+                    SUMMARY: AddressSanitizer: heap-buffer-overflow foo.c:3 in bar";
+        assert_eq!(utils::get_crash_kind(asan), "heap-buffer-overflow");
+        let leak = "ERROR: LeakSanitizer: detected memory leaks\n\
+                    SUMMARY: AddressSanitizer: 24 byte(s) leaked in 1 allocation(s).";
+        assert_eq!(utils::get_crash_kind(leak), "leak");
+        let leak = "ERROR: LeakSanitizer: detected memory leaks\n\
+                    SUMMARY: LeakSanitizer: leaked 24 byte(s) in 1 allocation(s).";
+        assert_eq!(utils::get_crash_kind(leak), "leak");
+        let timeout = "==1== ERROR: libFuzzer: timeout after 3 seconds";
+        assert_eq!(utils::get_crash_kind(timeout), "timeout");
+        assert_eq!(utils::get_crash_kind("Segmentation fault"), "crash");
+
+        let name = utils::get_crash_artifact_name("fuzzer0", Some(b"AAAA"), asan);
+        assert!(name.starts_with("fuzzer0-heap-buffer-overflow-"));
+        // the same input is named the same, regardless of the noisy report.
+        let noisy = format!("{asan}\n");
+        assert_eq!(name, utils::get_crash_artifact_name("fuzzer0", Some(b"AAAA"), &noisy));
+        assert_ne!(name, utils::get_crash_artifact_name("fuzzer0", Some(b"AAAB"), asan));
+    }
+
     #[test]
     fn test_update_prompt_queue() -> Result<()> {
         config::Config::init_test("cJSON");
@@ -874,4 +1380,185 @@ mod tests {
         deopt.update_seed_unique_branches(&coverage);
         Ok(())
     }
+
+    #[test]
+    fn test_sort_by_embedded_id() {
+        use rand::{seq::SliceRandom, Rng, SeedableRng};
+
+        let dir = PathBuf::from("seeds");
+        // the seed names of millions of seeds, sampled sparsely: the ids around each power of ten,
+        // where the digits grow and pass the 6-digit padding at 1000000, and random ids up to 1e7.
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut ids: Vec<usize> = (1..=7)
+            .flat_map(|exp| {
+                let boundary = 10usize.pow(exp);
+                boundary.saturating_sub(10)..boundary + 10
+            })
+            .collect();
+        ids.extend((0..10_000).map(|_| rng.gen_range(0..10_000_000)));
+        ids.sort_unstable();
+        ids.dedup();
+        let seed_name = |id: usize| format!("id_{number:>0width$}.cc", number = id, width = 6);
+        let mut paths: Vec<PathBuf> = ids.iter().map(|id| dir.join(seed_name(*id))).collect();
+        paths.shuffle(&mut rng);
+        utils::sort_by_embedded_id(&mut paths);
+        let expected: Vec<PathBuf> = ids.iter().map(|id| dir.join(seed_name(*id))).collect();
+        assert_eq!(paths, expected);
+        assert!(expected.contains(&dir.join("id_999999.cc")) && expected.contains(&dir.join("id_1000000.cc")));
+
+        let mut cores: Vec<PathBuf> = ["Core_10", "Core_9", "core.profraw", "Core_010", "Core_1"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        utils::sort_by_embedded_id(&mut cores);
+        let cores: Vec<&str> = cores.iter().map(|x| x.to_str().unwrap()).collect();
+        assert_eq!(cores, vec!["Core_1", "Core_9", "Core_010", "Core_10", "core.profraw"]);
+    }
+
+    #[test]
+    fn test_sample_seeds_by_quality() {
+        let mut deopt = Deopt::default();
+        for (id, density) in [(0, 1), (1, 9)] {
+            let mut seed = Program::new("");
+            seed.id = id;
+            seed.set_quality(crate::program::Quality {
+                density,
+                ..Default::default()
+            });
+            deopt.seed_queue.push_back(seed);
+        }
+        let mut draws = [0; 2];
+        for _ in 0..2000 {
+            let sampled = deopt.sample_seeds_by_quality(1);
+            draws[sampled[0].id] += 1;
+        }
+        assert!(draws[1] > draws[0] * 3, "{draws:?}");
+
+        // without replacement
+        let mut ids: Vec<usize> = deopt.sample_seeds_by_quality(5).iter().map(|x| x.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_register_library_with_unresolved_lib() {
+        let lib_dir = std::env::temp_dir().join("prompt_fuzz_register_empty_lib");
+        std::fs::create_dir_all(&lib_dir).unwrap();
+        let config = LibConfig {
+            project_name: "__unregistered_test_lib__".to_string(),
+            static_lib_name: "libunregistered.a".to_string(),
+            dyn_lib_name: "libunregistered.so".to_string(),
+            ..Default::default()
+        };
+        let res = Deopt::register_library(config.clone(), &lib_dir, &lib_dir);
+        assert!(res.is_err());
+        let deopt = Deopt {
+            project_name: config.project_name,
+            ..Default::default()
+        };
+        assert!(!deopt.get_library_data_dir().unwrap().exists());
+    }
+
+    #[test]
+    fn test_check_missing_library_paths() {
+        let deopt = Deopt {
+            project_name: "__missing_test_lib__".to_string(),
+            config: LibConfig {
+                landmark_file: Some("landmark.bin".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let err = deopt.check_library_paths().unwrap_err().to_string();
+        assert!(err.contains("__missing_test_lib__") && err.contains("register-target"), "{err}");
+        let err = format!("{:#}", deopt.get_library_landmark_corpus().unwrap_err());
+        assert!(err.contains("landmark.bin") && err.contains("landmark_file"), "{err}");
+    }
+
+    #[test]
+    fn test_gz_roundtrip() -> Result<()> {
+        let path = std::env::temp_dir().join("prompt_fuzz_err_program.cc");
+        let gz_path = utils::append_gz_extension(&path);
+        assert_eq!(gz_path, std::env::temp_dir().join("prompt_fuzz_err_program.cc.gz"));
+        let content = "int main() { return 0; }\n// link error\n".repeat(10);
+        utils::write_gz(&gz_path, content.as_bytes())?;
+        assert!(std::fs::metadata(&gz_path)?.len() < content.len() as u64);
+        assert_eq!(utils::read_to_string_maybe_gz(&gz_path)?, content);
+        std::fs::write(&path, &content)?;
+        assert_eq!(utils::read_to_string_maybe_gz(&path)?, content);
+        std::fs::remove_file(&path)?;
+        std::fs::remove_file(&gz_path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_dir_overrides() -> Result<()> {
+        let root = std::env::temp_dir().join("prompt_fuzz_dir_overrides");
+        let _ = std::fs::remove_dir_all(&root);
+        let deopt = Deopt {
+            project_name: "zlib".to_string(),
+            ..Default::default()
+        };
+        assert!(deopt.work_dir.is_none() && deopt.seed_dir.is_none());
+
+        let work_dir = root.join("work");
+        let deopt = deopt.with_dir_overrides(Some(work_dir.clone()), None);
+        assert_eq!(deopt.get_library_output_dir()?, work_dir);
+        assert_eq!(deopt.get_library_seed_dir()?, work_dir.join("seeds"));
+        assert_eq!(deopt.get_library_cntg_dir()?, work_dir.join("cntg"));
+        assert_eq!(deopt.get_library_work_dir()?, work_dir.join("work"));
+
+        let seed_dir = root.join("seeds");
+        let deopt = deopt.with_dir_overrides(Some(work_dir.clone()), Some(seed_dir.clone()));
+        assert_eq!(deopt.get_library_seed_dir()?, seed_dir);
+        assert!(seed_dir.is_dir());
+        assert_eq!(deopt.get_library_cntg_dir()?, work_dir.join("cntg"));
+
+        let deopt = deopt
+            .with_dir_overrides(Some(work_dir.clone()), None)
+            .with_entry_point(Some("decode".to_string()));
+        let bucket = work_dir.join("entry_points").join("decode");
+        assert_eq!(deopt.get_library_output_dir()?, bucket);
+        assert_eq!(deopt.get_library_seed_dir()?, bucket.join("seeds"));
+        assert_eq!(deopt.get_library_misc_dir()?, bucket.join("misc"));
+
+        let deopt = deopt.with_dir_overrides(Some(work_dir.clone()), Some(seed_dir.clone()));
+        assert_eq!(deopt.get_library_seed_dir()?, seed_dir.join("entry_points").join("decode"));
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_keep_seed_profile() -> Result<()> {
+        let root = std::env::temp_dir().join("prompt_fuzz_keep_profiles");
+        let _ = std::fs::remove_dir_all(&root);
+        let deopt = Deopt {
+            project_name: "zlib".to_string(),
+            ..Default::default()
+        }
+        .with_dir_overrides(Some(root.clone()), None);
+        assert!(deopt.keep_seed_profile(7).is_err());
+
+        std::fs::write(deopt.get_seed_coverage_file(7)?, "profdata of seed 7")?;
+        let kept = deopt.keep_seed_profile(7)?;
+        assert_eq!(kept, root.join("work").join("profiles").join("id_000007.profdata"));
+        // a recheck overwrites the profile of the seed, but not the kept one.
+        std::fs::write(deopt.get_seed_coverage_file(7)?, "rechecked")?;
+        assert_eq!(std::fs::read_to_string(&kept)?, "profdata of seed 7");
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_landmark() {
+        assert_eq!(utils::encode_landmark(b"{\"a\": 1}\n"), "{\"a\": 1}\n");
+        assert_eq!(
+            utils::encode_landmark(&[0x89, b'P', b'N', b'G', 0x00]),
+            "(base64-encoded) iVBORwA="
+        );
+        assert_eq!(
+            utils::encode_landmark(b"GIF\x00\x01"),
+            "(base64-encoded) R0lGAAE="
+        );
+    }
 }
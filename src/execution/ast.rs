@@ -31,7 +31,7 @@ impl Executor {
         let include_path =
             "-I".to_owned() + deopt.get_library_build_header_path()?.to_str().unwrap();
 
-        let mut binding = Command::new("clang++");
+        let mut binding = Command::new(crate::config::get_cxx());
         let mut cmd = binding
             .arg("-fsyntax-only")
             .arg("-Xclang")
@@ -39,6 +39,7 @@ impl Executor {
             .arg("-Xclang")
             .arg(ast_filter)
             .arg(include_path)
+            .args(crate::config::get_extra_cxxflags())
             .arg(program);
 
         for pch in pch_list {
@@ -68,7 +69,8 @@ impl Executor {
     }
 
     pub fn extract_program_ast(program: &Path) -> Result<Node> {
-        let mut binding = Command::new("clang++");
+        // the frontend of `-cc1` does not take the driver flags of `--extra-cxxflags`.
+        let mut binding = Command::new(crate::config::get_cxx());
         let output = binding
             .arg("-cc1")
             .arg("-fsyntax-only")
@@ -96,12 +98,13 @@ impl Executor {
             "-I".to_owned() + deopt.get_library_build_header_path()?.to_str().unwrap();
         //println!("include path: {include_path}");
        // println!("header path: {header:?}");
-        let mut binding = Command::new("clang++");
+        let mut binding = Command::new(crate::config::get_cxx());
         let binding = binding
             .arg("-fsyntax-only")
             .arg("-Xclang")
             .arg("-ast-dump=json")
             .arg(include_path)
+            .args(crate::config::get_extra_cxxflags())
             .arg(header);
         let output = binding
             .stdout(Stdio::piped())
@@ -214,7 +217,7 @@ fn test_ast_dump_filter() -> Result<()> {
     let ast_filter = format!("-ast-dump-filter={func}");
     let include_path = "-I".to_owned() + deopt.get_library_build_header_path()?.to_str().unwrap();
 
-    let mut binding = Command::new("clang++");
+    let mut binding = Command::new(crate::config::get_cxx());
     let output = binding
         .arg("-fsyntax-only")
         .arg("-Xclang")
@@ -0,0 +1,105 @@
+//! The status lines libFuzzer prints on stderr, e.g.,
+//! `#1024 NEW    cov: 567 ft: 890 corp: 12/345b lim: 4 exec/s: 0 rss: 32Mb L: 3/3 MS: 1 ChangeBit-`,
+//! or `#5120: cov: 567 ft: 890 corp: 12 exec/s 512 oom/timeout/crash: 0/0/0 time: 10s job: 2` in the fork mode.
+//! They are a cheap and live signal of the coverage growth during `run_libfuzzer`.
+
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The bytes read from the end of a fuzz log to find its last status line.
+const LOG_TAIL_BYTES: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibfuzzerStat {
+    /// The number of executions.
+    pub execs: u64,
+    /// The event of this line, e.g., `NEW`, `REDUCE` and `pulse`. Empty in the fork mode.
+    pub event: String,
+    /// The covered edges.
+    pub cov: u64,
+    /// The features, i.e., the edges with their hit counts.
+    pub ft: u64,
+}
+
+/// Parse a status line of libFuzzer, None if it is not one.
+pub fn parse_libfuzzer_stats(line: &str) -> Option<LibfuzzerStat> {
+    let mut tokens = line.split_whitespace();
+    let execs = tokens.next()?.strip_prefix('#')?.trim_end_matches(':');
+    let execs = execs.parse().ok()?;
+    let tokens: Vec<&str> = tokens.collect();
+    let value_of = |key: &str| {
+        let pos = tokens.iter().position(|token| *token == key)?;
+        tokens.get(pos + 1)?.parse::<u64>().ok()
+    };
+    let cov = value_of("cov:")?;
+    let ft = value_of("ft:").unwrap_or(0);
+    let event = match tokens.first() {
+        Some(token) if token.chars().all(|c| c.is_ascii_alphabetic()) => token.to_string(),
+        _ => String::new(),
+    };
+    Some(LibfuzzerStat {
+        execs,
+        event,
+        cov,
+        ft,
+    })
+}
+
+/// The last status line in the tail of the fuzz log of a libFuzzer run.
+pub fn read_last_stat(log: &Path) -> Option<LibfuzzerStat> {
+    let mut file = std::fs::File::open(log).ok()?;
+    let len = file.metadata().ok()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(LOG_TAIL_BYTES)))
+        .ok()?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).ok()?;
+    String::from_utf8_lossy(&buf)
+        .lines()
+        .rev()
+        .find_map(parse_libfuzzer_stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_libfuzzer_stats() {
+        let stat = parse_libfuzzer_stats(
+            "#1024\tNEW    cov: 567 ft: 890 corp: 12/345b lim: 4 exec/s: 0 rss: 32Mb L: 3/3 MS: 1 ChangeBit-",
+        )
+        .unwrap();
+        assert_eq!(
+            stat,
+            LibfuzzerStat {
+                execs: 1024,
+                event: "NEW".to_string(),
+                cov: 567,
+                ft: 890
+            }
+        );
+        let stat = parse_libfuzzer_stats(
+            "#5120: cov: 600 ft: 901 corp: 12 exec/s 512 oom/timeout/crash: 0/0/0 time: 10s job: 2",
+        )
+        .unwrap();
+        assert_eq!((stat.execs, stat.event.as_str(), stat.cov, stat.ft), (5120, "", 600, 901));
+        assert_eq!(parse_libfuzzer_stats("#2\tINITED cov: 3 ft: 4 corp: 1/1b").unwrap().event, "INITED");
+
+        assert!(parse_libfuzzer_stats("INFO: Seed: 1234").is_none());
+        assert!(parse_libfuzzer_stats("#0\tREAD units: 1").is_none());
+        assert!(parse_libfuzzer_stats("").is_none());
+    }
+
+    #[test]
+    fn test_read_last_stat() -> std::io::Result<()> {
+        let log = std::env::temp_dir().join("prompt_fuzz_libfuzzer_stats.log");
+        std::fs::write(
+            &log,
+            "INFO: Seed: 1234\n#2\tINITED cov: 3 ft: 4 corp: 1/1b\n#16\tNEW    cov: 9 ft: 12 corp: 2/3b\nINFO: done\n",
+        )?;
+        assert_eq!(read_last_stat(&log).map(|stat| stat.cov), Some(9));
+        std::fs::remove_file(&log)?;
+        assert!(read_last_stat(&log).is_none());
+        Ok(())
+    }
+}
@@ -16,8 +16,22 @@ pub enum ProgramError {
     Fuzzer(String),
     Coverage(String),
     Hang(String),
+    TooLong(String),
+    /// A failure of our own setup instead of the program, e.g., a missing compiler or build library.
+    Infra(String),
+    /// Rejected by the judge before the validation, see `--judge-threshold`.
+    Judged(String),
 }
 
+/// The clang and linker messages of the failures that no program could fix: an input of our own is missing,
+/// or the symbols of our own instrumentation cannot be linked. Our own failures are `FuzzerError::Infra` instead.
+const INFRA_ERROR_PATTERNS: [&str; 4] = [
+    "clang: error: no such file or directory",
+    "clang++: error: no such file or directory",
+    "error: unable to execute command",
+    "undefined reference to `__llvm_profile",
+];
+
 impl ProgramError {
     pub fn get_err_msg(&self) -> String {
         match self {
@@ -27,9 +41,58 @@ impl ProgramError {
             ProgramError::Fuzzer(msg) => format!("\nFuzzer Error: \n{msg}"),
             ProgramError::Coverage(msg) => format!("\nCoverage Error: \n{msg}"),
             ProgramError::Hang(msg) => format!("\nExecuted Hang!: \n{msg}"),
+            ProgramError::TooLong(msg) => format!("\nToo Long: \n{msg}"),
+            ProgramError::Infra(msg) => format!("\nInfrastructure Error: \n{msg}"),
+            ProgramError::Judged(msg) => format!("\nJudged Implausible: \n{msg}"),
+        }
+    }
+
+    pub fn is_infra(&self) -> bool {
+        matches!(self, ProgramError::Infra(_))
+    }
+
+    /// Whether the program itself failed to compile or validate, i.e., the APIs it calls were used wrongly.
+    pub fn is_validation_failure(&self) -> bool {
+        !matches!(
+            self,
+            ProgramError::Infra(_) | ProgramError::Judged(_) | ProgramError::TooLong(_)
+        )
+    }
+
+    /// The `Infra` error of `err` if it is a failure of our own setup, i.e., a `FuzzerError::Infra` in its chain.
+    pub fn from_infra_report(err: &eyre::Report) -> Option<Self> {
+        err.chain().find_map(|cause| match cause.downcast_ref::<crate::FuzzerError>() {
+            Some(crate::FuzzerError::Infra(msg)) => Some(ProgramError::Infra(msg.clone())),
+            _ => None,
+        })
+    }
+
+    /// Reclassify the error as `Infra` if the clang or linker output in its message shows a failure of our own setup.
+    pub fn classify_infra(self) -> Self {
+        let msg = match &self {
+            ProgramError::Infra(_) | ProgramError::TooLong(_) | ProgramError::Judged(_) => return self,
+            ProgramError::Syntax(msg)
+            | ProgramError::Link(msg)
+            | ProgramError::Execute(msg)
+            | ProgramError::Fuzzer(msg)
+            | ProgramError::Coverage(msg)
+            | ProgramError::Hang(msg) => msg,
+        };
+        if INFRA_ERROR_PATTERNS.iter().any(|pattern| msg.contains(pattern)) {
+            return ProgramError::Infra(msg.clone());
         }
+        self
     }
 }
+
+/// Whether every program of a checked batch failed for an infrastructure error, which retrying the generation
+/// cannot fix.
+pub fn is_infra_failed_batch(results: &[Option<ProgramError>]) -> bool {
+    !results.is_empty()
+        && results
+            .iter()
+            .all(|res| res.as_ref().is_some_and(|err| err.is_infra()))
+}
 impl fmt::Display for ProgramError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -37,6 +100,9 @@ impl fmt::Display for ProgramError {
             ProgramError::Link(e) => write!(f, "Link Error: {}", e),
             ProgramError::Execute(e) => write!(f, "Execution Error: {}", e),
             ProgramError::Hang(e) => write!(f, "Hang Error: {}", e),
+            ProgramError::TooLong(e) => write!(f, "Too Long Error: {}", e),
+            ProgramError::Infra(e) => write!(f, "Infrastructure Error: {}", e),
+            ProgramError::Judged(e) => write!(f, "Judged Implausible: {}", e),
             _ => write!(f, "Other Error!"),
         }
     }
@@ -95,6 +161,9 @@ struct Counter {
     hang: usize,
     fuzzer: usize,
     coverage: usize,
+    too_long: usize,
+    infra: usize,
+    judged: usize,
 }
 
 impl Counter {
@@ -107,6 +176,9 @@ impl Counter {
         self.hang = 0;
         self.fuzzer = 0;
         self.coverage = 0;
+        self.too_long = 0;
+        self.infra = 0;
+        self.judged = 0;
     }
 
     fn from_capture(captures: Captures) -> Result<Self> {
@@ -119,6 +191,19 @@ impl Counter {
             hang: captures.get(6).unwrap().as_str().parse()?,
             fuzzer: captures.get(7).unwrap().as_str().parse()?,
             coverage: captures.get(8).unwrap().as_str().parse()?,
+            // absent in the logs of old versions.
+            too_long: match captures.get(9) {
+                Some(too_long) => too_long.as_str().parse()?,
+                None => 0,
+            },
+            infra: match captures.get(10) {
+                Some(infra) => infra.as_str().parse()?,
+                None => 0,
+            },
+            judged: match captures.get(11) {
+                Some(judged) => judged.as_str().parse()?,
+                None => 0,
+            },
         })
     }
 }
@@ -154,6 +239,53 @@ impl AsanCounter {
     }
 }
 
+/// The upper bounds (in seconds) of the buckets of the LLM request latency histogram, the last bucket is unbounded.
+const LATENCY_BUCKETS: [u64; 5] = [5, 15, 30, 60, 120];
+
+#[derive(Default, Debug, PartialEq)]
+struct TimingCounter {
+    requests: usize,
+    request_ms: u64,
+    rounds: usize,
+    round_ms: u64,
+    histogram: [usize; LATENCY_BUCKETS.len() + 1],
+}
+
+impl TimingCounter {
+    fn from_capture(captures: Captures) -> Result<Self> {
+        let mut histogram = [0; LATENCY_BUCKETS.len() + 1];
+        let buckets: Vec<&str> = captures.get(5).unwrap().as_str().split('/').collect();
+        if buckets.len() != histogram.len() {
+            eyre::bail!("the latency histogram should have {} buckets", histogram.len());
+        }
+        for (count, bucket) in histogram.iter_mut().zip(buckets) {
+            *count = bucket.parse()?;
+        }
+        Ok(Self {
+            requests: captures.get(1).unwrap().as_str().parse()?,
+            request_ms: captures.get(2).unwrap().as_str().parse()?,
+            rounds: captures.get(3).unwrap().as_str().parse()?,
+            round_ms: captures.get(4).unwrap().as_str().parse()?,
+            histogram,
+        })
+    }
+
+    fn avg_latency_secs(&self) -> f64 {
+        if self.requests == 0 {
+            return 0.0;
+        }
+        self.request_ms as f64 / self.requests as f64 / 1000.0
+    }
+
+    /// The successful programs per second of the rounds.
+    fn throughput(&self, succ: usize) -> f64 {
+        if self.round_ms == 0 {
+            return 0.0;
+        }
+        succ as f64 * 1000.0 / self.round_ms as f64
+    }
+}
+
 #[derive(Default)]
 pub struct ProgramLogger {
     // round counter
@@ -162,6 +294,10 @@ pub struct ProgramLogger {
     gc: Counter,
     // asan err statistic
     sc: AsanCounter,
+    // cumulative timing of requests and rounds
+    tc: TimingCounter,
+    // raw completions that failed to parse into a program, counted with `--verbose-generation`
+    unparsable: usize,
 }
 
 impl ProgramLogger {
@@ -177,6 +313,25 @@ impl ProgramLogger {
         self.gc.total
     }
 
+    pub fn get_gc_succ(&self) -> usize {
+        self.gc.succ
+    }
+
+    /// The global number of error programs of each kind.
+    pub fn get_gc_errors(&self) -> Vec<(&'static str, usize)> {
+        vec![
+            ("syntax", self.gc.syntax),
+            ("link", self.gc.link),
+            ("execute", self.gc.execute),
+            ("hang", self.gc.hang),
+            ("fuzzer", self.gc.fuzzer),
+            ("coverage", self.gc.coverage),
+            ("too_long", self.gc.too_long),
+            ("infra", self.gc.infra),
+            ("judged", self.gc.judged),
+        ]
+    }
+
     pub fn log_err(&mut self, err_msg: &ProgramError) {
         self.rc.total += 1;
         self.gc.total += 1;
@@ -209,6 +364,18 @@ impl ProgramLogger {
                 self.rc.coverage += 1;
                 self.gc.coverage += 1;
             }
+            ProgramError::TooLong(_) => {
+                self.rc.too_long += 1;
+                self.gc.too_long += 1;
+            }
+            ProgramError::Infra(_) => {
+                self.rc.infra += 1;
+                self.gc.infra += 1;
+            }
+            ProgramError::Judged(_) => {
+                self.rc.judged += 1;
+                self.gc.judged += 1;
+            }
         }
     }
 
@@ -241,6 +408,36 @@ impl ProgramLogger {
         self.rc.clear();
     }
 
+    /// Record the latency of an LLM request.
+    pub fn log_request_latency(&mut self, latency: time::Duration) {
+        self.tc.requests += 1;
+        self.tc.request_ms += latency.as_millis() as u64;
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| latency.as_secs() < *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.tc.histogram[bucket] += 1;
+    }
+
+    /// Record the duration of a fuzz round, including the generation and the validation.
+    pub fn log_round_duration(&mut self, duration: time::Duration) {
+        self.tc.rounds += 1;
+        self.tc.round_ms += duration.as_millis() as u64;
+    }
+
+    /// The number of finished rounds.
+    pub fn get_round_num(&self) -> usize {
+        self.tc.rounds
+    }
+
+    pub fn log_unparsable_completions(&mut self, num: usize) {
+        self.unparsable += num;
+    }
+
+    pub fn get_unparsable_num(&self) -> usize {
+        self.unparsable
+    }
+
     fn sync_round(&mut self, round_counter: Counter) {
         self.rc = round_counter
     }
@@ -248,10 +445,10 @@ impl ProgramLogger {
     pub fn snyc_from_str(log_str: &str) -> Result<Self> {
         let mut logger = Self::default();
         let round_re = Regex::new(
-            r"\[round\] total: ([0-9]+), succ: ([0-9]+), syntax: ([0-9]+), link: ([0-9]+), execute: ([0-9]+), hang: ([0-9]+), fuzzer: ([0-9]+), coverage: ([0-9]+)",
+            r"\[round\] total: ([0-9]+), succ: ([0-9]+), syntax: ([0-9]+), link: ([0-9]+), execute: ([0-9]+), hang: ([0-9]+), fuzzer: ([0-9]+), coverage: ([0-9]+)(?:, too_long: ([0-9]+))?(?:, infra: ([0-9]+))?(?:, judged: ([0-9]+))?",
         )?;
         let global_re = Regex::new(
-            r"\[global\] total: ([0-9]+), succ: ([0-9]+), syntax: ([0-9]+), link: ([0-9]+), execute: ([0-9]+), hang: ([0-9]+), fuzzer: ([0-9]+), coverage: ([0-9]+)",
+            r"\[global\] total: ([0-9]+), succ: ([0-9]+), syntax: ([0-9]+), link: ([0-9]+), execute: ([0-9]+), hang: ([0-9]+), fuzzer: ([0-9]+), coverage: ([0-9]+)(?:, too_long: ([0-9]+))?(?:, infra: ([0-9]+))?(?:, judged: ([0-9]+))?",
         )?;
         let san_re = Regex::new(
             r"\[Asan\] df: ([0-9]+), uaf: ([0-9]+), ho: ([0-9]+), so: ([0-9]+), leak: ([0-9]+), segv: ([0-9]+), undefined: ([0-9]+), FDSan: ([0-9]+), others: ([0-9]+)",
@@ -268,6 +465,13 @@ impl ProgramLogger {
             let asan_counter = AsanCounter::from_capture(captures)?;
             logger.sync_san(asan_counter);
         }
+        let timing_re = Regex::new(
+            r"\[timing\] requests: ([0-9]+), request_ms: ([0-9]+), rounds: ([0-9]+), round_ms: ([0-9]+), latency histogram: ([0-9/]+)",
+        )?;
+        // absent in the logs of old versions.
+        if let Some(captures) = timing_re.captures_iter(log_str).last() {
+            logger.tc = TimingCounter::from_capture(captures)?;
+        }
         log::info!("Restore the ProgramLogger succesfully!");
         logger.print_succ_round();
         Ok(logger)
@@ -281,41 +485,67 @@ impl ProgramLogger {
         self.sc = sc;
     }
 
+    /// The `[round]`, `[global]`, `[Asan]` and `[timing]` lines of the states, which `snyc_from_str` restores from.
+    pub fn state_lines(&self) -> Vec<String> {
+        let histogram: Vec<String> = self.tc.histogram.iter().map(|x| x.to_string()).collect();
+        vec![
+            format!(
+                "[round] total: {}, succ: {}, syntax: {}, link: {}, execute: {}, hang: {}, fuzzer: {}, coverage: {}, too_long: {}, infra: {}, judged: {}",
+                self.rc.total,
+                self.rc.succ,
+                self.rc.syntax,
+                self.rc.link,
+                self.rc.execute,
+                self.rc.hang,
+                self.rc.fuzzer,
+                self.rc.coverage,
+                self.rc.too_long,
+                self.rc.infra,
+                self.rc.judged
+            ),
+            format!(
+                "[global] total: {}, succ: {}, syntax: {}, link: {}, execute: {}, hang: {}, fuzzer: {}, coverage: {}, too_long: {}, infra: {}, judged: {}",
+                self.gc.total,
+                self.gc.succ,
+                self.gc.syntax,
+                self.gc.link,
+                self.gc.execute,
+                self.gc.hang,
+                self.gc.fuzzer,
+                self.gc.coverage,
+                self.gc.too_long,
+                self.gc.infra,
+                self.gc.judged
+            ),
+            format!(
+                "[Asan] df: {}, uaf: {}, ho: {}, so: {}, leak: {}, segv: {}, undefined: {}, FDSan: {}, others: {}",
+                self.sc.df,
+                self.sc.uaf,
+                self.sc.h_overflow,
+                self.sc.s_overflow,
+                self.sc.leak,
+                self.sc.segv,
+                self.sc.undefined,
+                self.sc.fdsan,
+                self.sc.other
+            ),
+            format!(
+                "[timing] requests: {}, request_ms: {}, rounds: {}, round_ms: {}, latency histogram: {}, avg latency: {:.2}s, throughput: {:.3} programs/s",
+                self.tc.requests,
+                self.tc.request_ms,
+                self.tc.rounds,
+                self.tc.round_ms,
+                histogram.join("/"),
+                self.tc.avg_latency_secs(),
+                self.tc.throughput(self.gc.succ)
+            ),
+        ]
+    }
+
     pub fn print_succ_round(&self) {
-        log::debug!(
-            "[round] total: {}, succ: {}, syntax: {}, link: {}, execute: {}, hang: {}, fuzzer: {}, coverage: {}",
-            self.rc.total,
-            self.rc.succ,
-            self.rc.syntax,
-            self.rc.link,
-            self.rc.execute,
-            self.rc.hang,
-            self.rc.fuzzer,
-            self.rc.coverage
-        );
-        log::debug!(
-            "[global] total: {}, succ: {}, syntax: {}, link: {}, execute: {}, hang: {}, fuzzer: {}, coverage: {}",
-            self.gc.total,
-            self.gc.succ,
-            self.gc.syntax,
-            self.gc.link,
-            self.gc.execute,
-            self.gc.hang,
-            self.gc.fuzzer,
-            self.gc.coverage
-        );
-        log::debug!(
-            "[Asan] df: {}, uaf: {}, ho: {}, so: {}, leak: {}, segv: {}, undefined: {}, FDSan:{}, others: {}",
-            self.sc.df,
-            self.sc.uaf,
-            self.sc.h_overflow,
-            self.sc.s_overflow,
-            self.sc.leak,
-            self.sc.segv,
-            self.sc.undefined,
-            self.sc.fdsan,
-            self.sc.other
-        );
+        for line in self.state_lines() {
+            log::debug!("{line}");
+        }
     }
 }
 
@@ -419,3 +649,74 @@ pub fn get_gtl() -> RwLockReadGuard<'static, GlobalTimeLogger> {
 pub fn get_gtl_mut() -> RwLockWriteGuard<'static, GlobalTimeLogger> {
     GTL.get().expect("GTL should not be None").write().unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_restore_timing() -> Result<()> {
+        let mut logger = ProgramLogger::default();
+        logger.log_request_latency(Duration::from_millis(1500));
+        logger.log_request_latency(Duration::from_secs(20));
+        logger.log_request_latency(Duration::from_secs(300));
+        logger.log_round_duration(Duration::from_secs(40));
+        logger.log_succ();
+        logger.log_succ();
+        assert_eq!(logger.tc.histogram, [1, 0, 1, 0, 0, 1]);
+        assert!((logger.tc.avg_latency_secs() - 107.166).abs() < 1e-2);
+        assert!((logger.tc.throughput(logger.gc.succ) - 0.05).abs() < 1e-6);
+
+        let log_str = "[timing] requests: 3, request_ms: 321500, rounds: 1, round_ms: 40000, \
+                       latency histogram: 1/0/1/0/0/1, avg latency: 107.17s, throughput: 0.050 programs/s";
+        let restored = ProgramLogger::snyc_from_str(log_str)?;
+        assert_eq!(restored.tc, logger.tc);
+        // the logs of old versions have no timing.
+        let restored = ProgramLogger::snyc_from_str("")?;
+        assert_eq!(restored.tc, TimingCounter::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_state_lines() -> Result<()> {
+        let mut logger = ProgramLogger::default();
+        logger.log_succ();
+        logger.log_err(&ProgramError::Syntax("error".to_string()));
+        logger.log_request_latency(Duration::from_secs(2));
+        let restored = ProgramLogger::snyc_from_str(&logger.state_lines().join("\n"))?;
+        assert_eq!(restored.state_lines(), logger.state_lines());
+        assert_eq!(restored.get_gc_total(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_classify_infra_error() {
+        let err: eyre::Report = crate::FuzzerError::Infra("Cannot find the build library".to_string()).into();
+        assert!(ProgramError::from_infra_report(&err).is_some_and(|err| err.is_infra()));
+        let err = err.wrap_err("Failed to check the program");
+        assert!(ProgramError::from_infra_report(&err).is_some());
+        assert!(ProgramError::from_infra_report(&eyre::eyre!("fail to compile")).is_none());
+        // our own messages are not matched by their text.
+        let err = ProgramError::Fuzzer("Cannot find the build library \"libz_fuzzer.a\", please build zlib".to_string());
+        assert!(!err.classify_infra().is_infra());
+        let err = ProgramError::Link("clang: error: no such file or directory: 'libz_san.a'".to_string());
+        assert!(err.classify_infra().is_infra());
+        let err = ProgramError::Link("undefined reference to `inflateFoo'".to_string());
+        assert!(!err.classify_infra().is_infra());
+        // a driver without the fuzz entry is the fault of the program.
+        let err = ProgramError::Link("undefined reference to `LLVMFuzzerTestOneInput'".to_string());
+        assert!(!err.classify_infra().is_infra());
+
+        let infra = Some(ProgramError::Infra("missing clang".to_string()));
+        assert!(is_infra_failed_batch(&[infra.clone(), infra.clone()]));
+        assert!(!is_infra_failed_batch(&[infra.clone(), None]));
+        assert!(!is_infra_failed_batch(&[infra, Some(ProgramError::Syntax("e".to_string()))]));
+        assert!(!is_infra_failed_batch(&[]));
+
+        let mut logger = ProgramLogger::default();
+        logger.log_err(&ProgramError::Infra("missing clang".to_string()));
+        let restored = ProgramLogger::snyc_from_str(&logger.state_lines().join("\n")).unwrap();
+        assert_eq!(restored.gc.infra, 1);
+    }
+}
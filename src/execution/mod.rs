@@ -1,7 +1,12 @@
 pub mod ast;
+pub mod libfuzzer_stats;
 pub mod logger;
+pub mod preflight;
+pub mod replay;
 pub mod sanitize;
+pub mod toolchain;
 
+pub use self::libfuzzer_stats::{parse_libfuzzer_stats, LibfuzzerStat};
 use self::logger::ProgramError;
 use crate::config::{get_config, get_minimize_compile_flag};
 use crate::program::libfuzzer::respawn_libfuzzer_process;
@@ -23,6 +28,7 @@ use std::sync::{
     Arc,
 };
 use std::{
+    collections::HashMap,
     ffi::OsStr,
     fmt::Debug,
     io::Read,
@@ -43,6 +49,9 @@ pub enum Compile {
     Normal,
 }
 
+/// The log of clang command lines written into each core dir with `--keep-artifacts`.
+pub const COMPILE_LOG_NAME: &str = "compile_cmd.log";
+
 #[derive(Default, Clone)]
 pub struct Executor {
     pub header_cmd: String,
@@ -101,17 +110,23 @@ impl Executor {
     }
 
     /// compile programs into binary.
-    pub fn compile(&self, programs: Vec<&Path>, out: &Path, kind: Compile) -> Result<()> {
+    fn build_compile_cmd(&self, programs: &[&Path], out: &Path, kind: Compile) -> Result<Command> {
         let (cflags, lib) = self.get_compile_flags(kind);
+        if !lib.exists() {
+            return Err(crate::FuzzerError::Infra(format!(
+                "Cannot find the build library {lib:?}, please build {} by its build.sh in advance.",
+                self.deopt.project_name
+            ))
+            .into());
+        }
 
-        let mut cmd = Command::new("clang++");
-        for program in &programs {
+        let mut cmd = Command::new(config::get_cxx());
+        for program in programs {
             cmd.arg(*program);
         }
         let include_fdp = "-I".to_owned() + Deopt::get_fdp_path()?.to_str().unwrap();
 
-        let cmd = cmd
-            .stdin(Stdio::null())
+        cmd.stdin(Stdio::null())
             .stdout(Stdio::null())
             .stderr(Stdio::piped())
             .args(cflags.as_slice())
@@ -121,21 +136,48 @@ impl Executor {
             .arg("-o")
             .arg(out)
             .arg(lib);
-        self.deopt.add_extra_c_flags(cmd)?;
-
-        let output = cmd
-            .output()
-            .expect("failed to execute the syntax check process");
-        let success = output.status.success();
-        if !success {
-            eyre::bail!(
-                "fail to compile {programs:?}\n, {}",
-                String::from_utf8_lossy(&output.stderr)
-            );
+        self.deopt.add_extra_c_flags(&mut cmd)?;
+        cmd.args(config::get_extra_cxxflags());
+        Ok(cmd)
+    }
+
+    pub fn compile(&self, programs: Vec<&Path>, out: &Path, kind: Compile) -> Result<()> {
+        let timeout = Duration::from_secs(crate::config::SANITIZATION_TIMEOUT);
+        self.compile_with_timeout(programs, out, kind, timeout)
+    }
+
+    /// Compile like `compile`, but kill the compiler with all its subprocesses if it outlives `timeout`,
+    /// which fails with `FuzzerError::CompileTimeout`.
+    pub fn compile_with_timeout(
+        &self,
+        programs: Vec<&Path>,
+        out: &Path,
+        kind: Compile,
+        timeout: Duration,
+    ) -> Result<()> {
+        let mut cmd = self.build_compile_cmd(&programs, out, kind)?;
+        let res = run_compiler_with_timeout(&mut cmd, timeout).map_err(|err| {
+            crate::FuzzerError::Infra(format!("failed to execute the compiler {:?}: {err}", config::get_cxx()))
+        })?;
+        let Some((status, err_msg)) = res else {
+            return Err(crate::FuzzerError::CompileTimeout(format!("{programs:?}"), timeout.as_secs()).into());
+        };
+        if !status.success() {
+            eyre::bail!("fail to compile {programs:?}\n, {err_msg}");
         }
         Ok(())
     }
 
+    /// The ASAN_OPTIONS and UBSAN_OPTIONS to execute programs with, UBSAN_OPTIONS is omitted if empty.
+    pub fn get_sanitizer_envs(&self) -> Vec<(&'static str, String)> {
+        let mut envs = vec![("ASAN_OPTIONS", self.deopt.get_asan_options())];
+        let ubsan_options = self.deopt.get_ubsan_options();
+        if !ubsan_options.is_empty() {
+            envs.push(("UBSAN_OPTIONS", ubsan_options));
+        }
+        envs
+    }
+
     pub fn spawn<S: AsRef<OsStr> + Debug>(
         &self,
         binary: &Path,
@@ -150,15 +192,22 @@ impl Executor {
             exec.arg(arg);
         }
 
+        // the envs in `extra_envs` take precedence over the sanitizer options.
+        exec.envs(self.get_sanitizer_envs());
         for (key, val) in &extra_envs {
             exec.env(key, val);
         }
 
-        let asan_options = self.deopt.get_asan_options();
         let rss_limit = format!(
             "-rss_limit_mb={}",
             self.deopt.config.rss_limit_mb.unwrap_or_default()
         );
+        // the flags in `extra_args` take precedence over the default ones.
+        let has_flag = |name: &str| {
+            extra_args.iter().any(|arg| {
+                libfuzzer_flag_name(&arg.as_ref().to_string_lossy()) == name
+            })
+        };
         let current_dir = current_dir.unwrap_or(get_file_dirname(binary));
         let stderr = stderr.unwrap_or(Stdio::piped());
         let timeout = if enough_timeout {
@@ -180,11 +229,10 @@ impl Executor {
 
         let child = exec
             .current_dir(current_dir)
-            .env("ASAN_OPTIONS", asan_options)
             .env("LD_LIBRARY_PATH", lib_path)
-            .arg(rss_limit)
-            .arg(format!("-timeout={}", timeout))
-            .arg("-close_fd_mask=3")
+            .args((!has_flag("-rss_limit_mb")).then_some(rss_limit))
+            .args((!has_flag("-timeout")).then(|| format!("-timeout={}", timeout)))
+            .args((!has_flag("-close_fd_mask")).then_some("-close_fd_mask=3"))
             .stdin(Stdio::null())
             .stdout(Stdio::null())
             .stderr(stderr)
@@ -311,15 +359,16 @@ impl Executor {
             false,
         );
 
+        let (min_fuzz_time, max_fuzz_time) = config::get_fuzz_time_window(&self.deopt.config)?;
         let mut cost_time: u64 = 0;
         let mut previous_cov = None;
         let mut should_break = false;
         loop {
-            if cost_time >= config::MAX_FUZZ_TIME {
+            if cost_time >= max_fuzz_time {
                 should_break = true;
             }
-            let wait_time = config::MIN_FUZZ_TIME;
-            // no coverage gained during config::MIN_FUZZ_TIME, break
+            let wait_time = min_fuzz_time;
+            // no coverage gained during the min fuzz time, break
             if cost_time >= wait_time && cost_time % wait_time == 0 {
                 let cov = parse_cov_from_log(&log_file)?;
                 if let Some(cov) = cov {
@@ -379,13 +428,42 @@ impl Executor {
         Ok(())
     }
 
+    /// Run the CNTG core and convert its profile to `profdata`.
+    ///
+    /// Returns false if the core hung longer than `EXECUTION_TIMEOUT` and was killed, its profile is omitted then.
     pub fn execute_cov_cntg_core(
         &self,
         core_binary: &Path,
         profdata: &Path,
-    ) -> Result<()> {
+    ) -> Result<bool> {
+        self.execute_cov_cntg_core_with_args(core_binary, &[], "core.profraw", profdata)
+    }
+
+    /// Run only the driver `driver_id` of the CNTG core, and convert its profile to `profdata`.
+    pub fn execute_cov_cntg_driver(
+        &self,
+        core_binary: &Path,
+        driver_id: usize,
+        profdata: &Path,
+    ) -> Result<bool> {
+        let profraw_name = format!("driver_{driver_id}.profraw");
+        self.execute_cov_cntg_core_with_args(
+            core_binary,
+            &[driver_id.to_string()],
+            &profraw_name,
+            profdata,
+        )
+    }
+
+    fn execute_cov_cntg_core_with_args(
+        &self,
+        core_binary: &Path,
+        args: &[String],
+        profraw_name: &str,
+        profdata: &Path,
+    ) -> Result<bool> {
         let core_dir = get_file_dirname(core_binary);
-        let profraw_file: PathBuf = [core_dir.clone(), "core.profraw".into()].iter().collect();
+        let profraw_file: PathBuf = core_dir.join(profraw_name);
         
         // Remove existing profraw file if it exists
         if profraw_file.exists() {
@@ -393,14 +471,24 @@ impl Executor {
         }
         
         // Run the CNTG core with coverage
-        let output = Command::new(core_binary)
-            .env("LLVM_PROFILE_FILE", &profraw_file)
-            .output()?;
+        let mut cmd = Command::new(core_binary);
+        cmd.args(args).env("LLVM_PROFILE_FILE", &profraw_file);
+        let timeout = Duration::from_secs(crate::config::EXECUTION_TIMEOUT);
+        let Some((status, err_msg)) = run_with_timeout(&mut cmd, timeout)? else {
+            // the profile of a killed core is incomplete.
+            if profraw_file.exists() {
+                std::fs::remove_file(&profraw_file)?;
+            }
+            return Ok(false);
+        };
         
-        if !output.status.success() {
+        if !status.success() {
             log::warn!("execute CNTG core failed! {core_binary:?}");
-            let err_msg = String::from_utf8_lossy(&output.stderr);
             log::error!("Error: {err_msg}");
+            // CNTG cores take no input, the report records which core crashed.
+            let core_name = core_dir.file_name().unwrap().to_string_lossy().to_string();
+            let report = format!("core: {core_binary:?}\n{err_msg}");
+            self.deopt.save_crash_artifact(&core_name, None, &report)?;
         }
         
         // Convert profraw to profdata
@@ -411,7 +499,7 @@ impl Executor {
         if profdata.exists() {
             std::fs::remove_file(&profraws[0])?;
         }
-        Ok(())
+        Ok(true)
     }
 
     pub fn execute_cov_fuzzer_pool(
@@ -597,12 +685,7 @@ impl Executor {
         Ok(())
     }
 
-    pub fn compile_lib_fuzzers(
-        &self,
-        fuzzer_dir: &Path,
-        fuzzer_binary: &Path,
-        kind: Compile,
-    ) -> Result<()> {
+    fn get_fuzzer_drivers(fuzzer_dir: &Path) -> Result<Vec<PathBuf>> {
         let mut drivers = Vec::new();
         for entry in std::fs::read_dir(fuzzer_dir)? {
             let path = entry?.path();
@@ -613,11 +696,63 @@ impl Executor {
                 drivers.push(path);
             }
         }
+        Ok(drivers)
+    }
+
+    pub fn compile_lib_fuzzers(
+        &self,
+        fuzzer_dir: &Path,
+        fuzzer_binary: &Path,
+        kind: Compile,
+    ) -> Result<()> {
+        let drivers = Self::get_fuzzer_drivers(fuzzer_dir)?;
         // compile all the fuzzers into one binary.
         let programs: Vec<&Path> = drivers.iter().map(|x| x.as_path()).collect();
         self.compile(programs, fuzzer_binary, kind)
     }
 
+    /// Compile a fused core. With `--keep-artifacts`, the object files and the command line are retained in the core dir.
+    pub fn compile_core_fuzzers(
+        &self,
+        fuzzer_dir: &Path,
+        fuzzer_binary: &Path,
+        kind: Compile,
+    ) -> Result<()> {
+        if crate::config::keep_artifacts() {
+            let log_file = fuzzer_dir.join(COMPILE_LOG_NAME);
+            return self.compile_lib_fuzzers_with_log(fuzzer_dir, fuzzer_binary, kind, &log_file);
+        }
+        self.compile_lib_fuzzers(fuzzer_dir, fuzzer_binary, kind)
+    }
+
+    /// Compile the fuzzers like `compile_lib_fuzzers`, but retain the object files beside the binary,
+    /// and write the exact clang command line and its diagnostics into `log_file`.
+    pub fn compile_lib_fuzzers_with_log(
+        &self,
+        fuzzer_dir: &Path,
+        fuzzer_binary: &Path,
+        kind: Compile,
+        log_file: &Path,
+    ) -> Result<()> {
+        let drivers = Self::get_fuzzer_drivers(fuzzer_dir)?;
+        let programs: Vec<&Path> = drivers.iter().map(|x| x.as_path()).collect();
+        let mut cmd = self.build_compile_cmd(&programs, fuzzer_binary, kind)?;
+        cmd.arg("-save-temps=obj");
+        let mut log = format!("{cmd:?}\n");
+        let timeout = Duration::from_secs(crate::config::SANITIZATION_TIMEOUT);
+        let Some((status, err_msg)) = run_compiler_with_timeout(&mut cmd, timeout)? else {
+            log.push_str(&format!("timed out after {}s", timeout.as_secs()));
+            std::fs::write(log_file, log)?;
+            return Err(crate::FuzzerError::CompileTimeout(format!("{programs:?}"), timeout.as_secs()).into());
+        };
+        log.push_str(&err_msg);
+        std::fs::write(log_file, log)?;
+        if !status.success() {
+            eyre::bail!("fail to compile {programs:?}, see the command line in {log_file:?}\n, {err_msg}");
+        }
+        Ok(())
+    }
+
     pub fn spawn_libfuzzer(&self, fuzzer_binary: &Path, corpus: &Path) -> Result<Child> {
         let fuzzer_dir = crate::deopt::utils::get_file_dirname(fuzzer_binary);
         if !fuzzer_binary.exists() {
@@ -647,6 +782,7 @@ impl Executor {
                 extra_args.push(OsString::from("-fork=1"));
             }
         }
+        let extra_args = merge_libfuzzer_args(extra_args, &config::get_libfuzzer_args());
 
         let child = self.spawn(
             fuzzer_binary,
@@ -711,6 +847,7 @@ impl Executor {
             childs.push((child, path));
         }
         let start = std::time::Instant::now();
+        let mut last_cov: HashMap<PathBuf, u64> = HashMap::new();
 
         loop {
             for (child, path) in childs.iter_mut() {
@@ -726,6 +863,20 @@ impl Executor {
                 }
             }
             std::thread::sleep(std::time::Duration::from_secs(10));
+            // the live coverage of each fuzzer, to spot the stalled ones early.
+            for (_, path) in childs.iter() {
+                let Some(stat) = libfuzzer_stats::read_last_stat(&path.join("fuzz.log")) else {
+                    continue;
+                };
+                let growth = stat.cov.saturating_sub(last_cov.insert(path.clone(), stat.cov).unwrap_or(0));
+                log::info!(
+                    "{:?}: #{} cov: {} (+{growth}) ft: {}",
+                    path.file_name().unwrap_or_default(),
+                    stat.execs,
+                    stat.cov,
+                    stat.ft
+                );
+            }
             let elapsed = std::time::Instant::elapsed(&start);
             log::info!("fuzzing time: {}s\n\n", elapsed.as_secs());
             // reach time limit, kill all the running process and exit.
@@ -792,16 +943,46 @@ impl Executor {
 }
 
 fn check_clang() -> Result<()> {
-    let output = Command::new("clang++")
+    let cxx = config::get_cxx();
+    let output = Command::new(&cxx)
         .arg("-v")
         .output()
-        .expect("failed to execute the clang check process.");
+        .map_err(|err| eyre::eyre!("The compiler {cxx:?} cannot be executed: {err}"))?;
     if !output.status.success() {
-        eyre::bail!("clang does not exist in your environment!");
+        eyre::bail!("The compiler {cxx:?} does not work in your environment!");
     }
     Ok(())
 }
 
+/// The flags that `Executor::spawn` passes to every libfuzzer invocation.
+const SPAWN_LIBFUZZER_FLAGS: [&str; 3] = ["-rss_limit_mb", "-timeout", "-close_fd_mask"];
+
+/// `-timeout=25` => `-timeout`
+fn libfuzzer_flag_name(arg: &str) -> &str {
+    arg.split('=').next().unwrap_or(arg)
+}
+
+/// Append the user's `--libfuzzer-args` to the internal flags. The user's flag overrides the internal one with a warning.
+fn merge_libfuzzer_args(internal: Vec<OsString>, user: &[String]) -> Vec<OsString> {
+    let mut merged = Vec::new();
+    for arg in internal {
+        let arg_str = arg.to_string_lossy().to_string();
+        let name = libfuzzer_flag_name(&arg_str);
+        if let Some(flag) = user.iter().find(|flag| libfuzzer_flag_name(flag) == name) {
+            log::warn!("--libfuzzer-args `{flag}` overrides the internal flag `{arg_str}`");
+            continue;
+        }
+        merged.push(arg);
+    }
+    for flag in user {
+        if SPAWN_LIBFUZZER_FLAGS.contains(&libfuzzer_flag_name(flag)) {
+            log::warn!("--libfuzzer-args `{flag}` overrides the internal default of this flag");
+        }
+        merged.push(OsString::from(flag));
+    }
+    merged
+}
+
 /// if the exit code represent program executed normally.
 fn is_exit_normally(code: Option<i32>) -> bool {
     if let Some(exit_code) = code {
@@ -818,6 +999,65 @@ pub fn get_child_err(mut err: ChildStderr) -> String {
     err_msg
 }
 
+/// Run `cmd` and wait at most `timeout` for it, its stderr is drained concurrently.
+/// Returns None if the process timed out, and it was killed and reaped.
+fn run_with_timeout(
+    cmd: &mut Command,
+    timeout: Duration,
+) -> Result<Option<(std::process::ExitStatus, String)>> {
+    spawn_with_timeout(cmd, timeout, false)
+}
+
+/// Run the compiler like `run_with_timeout`, but in its own process group with its cc1 and linker
+/// subprocesses. The whole group is killed and reaped if the compiler timed out.
+fn run_compiler_with_timeout(
+    cmd: &mut Command,
+    timeout: Duration,
+) -> Result<Option<(std::process::ExitStatus, String)>> {
+    spawn_with_timeout(cmd, timeout, true)
+}
+
+fn spawn_with_timeout(
+    cmd: &mut Command,
+    timeout: Duration,
+    process_group: bool,
+) -> Result<Option<(std::process::ExitStatus, String)>> {
+    use std::os::unix::process::CommandExt;
+    cmd.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::piped());
+    if process_group {
+        cmd.process_group(0);
+    }
+    let mut child = cmd.spawn()?;
+    let stderr = child.stderr.take().unwrap();
+    let reader = std::thread::spawn(move || get_child_err(stderr));
+    match child.wait_timeout(timeout)? {
+        Some(status) => {
+            let err_msg = reader.join().unwrap_or_default();
+            Ok(Some((status, err_msg)))
+        }
+        None => {
+            if process_group {
+                // the group id is the pid of its leader.
+                unsafe { libc::killpg(child.id() as libc::pid_t, libc::SIGKILL) };
+            } else {
+                child.kill()?;
+            }
+            child.wait()?;
+            // the stderr is closed once all the processes holding it are killed.
+            _ = reader.join();
+            Ok(None)
+        }
+    }
+}
+
+/// Whether the error is a compilation killed by the watchdog of `Executor::compile`.
+pub fn is_compile_timeout(err: &eyre::Report) -> bool {
+    matches!(
+        err.downcast_ref::<crate::FuzzerError>(),
+        Some(crate::FuzzerError::CompileTimeout(_, _))
+    )
+}
+
 // mkdir the directory "corpus" under the same directory of fuzzer.
 fn mkdir_fuzzer_corpus(fuzzer_path: &Path) -> PathBuf {
     let mut corpus_dir = PathBuf::from(fuzzer_path);
@@ -841,7 +1081,7 @@ pub fn create_corpus_for_fuzzer(fuzzer_path: &Path, deopt: &Deopt) -> Result<Pat
     if !literal_corpus.exists() {
         eyre::bail!("fuzzer corpus {literal_corpus:?} not existed")
     }
-    let lib_corpus_files = crate::deopt::utils::get_library_corpus_files(deopt);
+    let lib_corpus_files = crate::deopt::utils::get_library_corpus_files(deopt)?;
     // make up new corpus which concatenated with lib corpus and program's literal corpus.
     for file in lib_corpus_files {
         let lib_corpus = std::fs::read(file)?;
@@ -888,6 +1128,70 @@ pub fn max_cpu_count() -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn test_merge_libfuzzer_args() {
+        let internal = vec![OsString::from("-dict=a.dict"), OsString::from("-fork=1")];
+        let user = vec!["-fork=4".to_string(), "-max_len=64".to_string()];
+        let merged = merge_libfuzzer_args(internal, &user);
+        assert_eq!(merged, vec!["-dict=a.dict", "-fork=4", "-max_len=64"]);
+        assert_eq!(libfuzzer_flag_name("-timeout=25"), "-timeout");
+    }
+
+    #[test]
+    fn test_run_with_timeout() -> Result<()> {
+        let mut hang = Command::new("sleep");
+        hang.arg("10");
+        let start = std::time::Instant::now();
+        assert!(run_with_timeout(&mut hang, Duration::from_millis(200))?.is_none());
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let mut quick = Command::new("sh");
+        quick.arg("-c").arg("echo oops >&2; exit 3");
+        let (status, err_msg) = run_with_timeout(&mut quick, Duration::from_secs(5))?.unwrap();
+        assert_eq!(status.code(), Some(3));
+        assert_eq!(err_msg.trim(), "oops");
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs clang++ in PATH, run with `cargo test -- --ignored`"]
+    fn test_compile_timeout_on_template_recursion() -> Result<()> {
+        let cxx = config::get_cxx();
+        let dir = std::env::temp_dir().join("prompt_fuzz_compile_timeout");
+        std::fs::create_dir_all(&dir)?;
+        // 2^40 distinct instantiations, which keep clang busy for good.
+        let recursion = dir.join("recursion.cc");
+        std::fs::write(
+            &recursion,
+            "template <int D, long I> struct T {\n  static constexpr long v = T<D - 1, 2 * I>::v + T<D - 1, 2 * I + 1>::v;\n};\ntemplate <long I> struct T<0, I> {\n  static constexpr long v = I;\n};\nconstexpr long x = T<40, 0>::v;\n",
+        )?;
+        let plain = dir.join("plain.cc");
+        std::fs::write(&plain, "int main() { return 0; }\n")?;
+
+        // the compilation after a timed out one goes on as usual.
+        let mut results = Vec::new();
+        for program in [&recursion, &plain] {
+            let mut cmd = Command::new(&cxx);
+            cmd.arg("-fsyntax-only").arg(program);
+            let start = std::time::Instant::now();
+            results.push(run_compiler_with_timeout(&mut cmd, Duration::from_secs(2))?);
+            assert!(start.elapsed() < Duration::from_secs(10));
+        }
+        assert!(results[0].is_none());
+        assert!(results[1].as_ref().is_some_and(|(status, _)| status.success()));
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_compile_timeout() -> Result<()> {
+        let err: eyre::Report = crate::FuzzerError::CompileTimeout("a.cc".to_string(), 1).into();
+        assert!(is_compile_timeout(&err));
+        assert!(!is_compile_timeout(&eyre::eyre!("fail to compile")));
+        Ok(())
+    }
+
     #[test]
     fn test_concurrent_checking() -> Result<()> {
         crate::config::Config::init_test("cJSON");
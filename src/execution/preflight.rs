@@ -0,0 +1,87 @@
+//! Check that the library links and runs before any LLM call is spent.
+
+use super::{run_with_timeout, Compile, Executor};
+use crate::deopt::Deopt;
+use eyre::Result;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+/// A driver that includes the library headers and takes the address of each API,
+/// so that a missing symbol fails the link.
+pub fn preflight_driver(headers: &str, apis: &[&str]) -> String {
+    let mut driver = format!("{headers}\n#include <cstdio>\n\nint main() {{\n");
+    if apis.is_empty() {
+        driver.push_str("\tvoid* volatile apis[] = {nullptr};\n");
+    } else {
+        driver.push_str("\tvoid* volatile apis[] = {\n");
+        for api in apis {
+            driver.push_str(&format!("\t\t(void*)&{api},\n"));
+        }
+        driver.push_str("\t};\n");
+    }
+    driver.push_str("\tstd::printf(\"%zu APIs linked\\n\", sizeof(apis) / sizeof(apis[0]));\n");
+    driver.push_str("\treturn 0;\n}\n");
+    driver
+}
+
+/// Preflight the library with all its function gadgets.
+pub fn preflight_library(deopt: &Deopt) -> Result<()> {
+    let apis: Vec<&str> = crate::program::gadget::get_func_gadgets()
+        .iter()
+        .map(|gadget| gadget.get_func_name())
+        .collect();
+    Executor::new(deopt)?.preflight(&apis)
+}
+
+impl Executor {
+    /// Compile a trivial driver referencing `apis` against the coverage library, and run it.
+    /// The error reports the exact clang command and its diagnostics.
+    pub fn preflight(&self, apis: &[&str]) -> Result<()> {
+        let dir: PathBuf = self.deopt.get_library_misc_dir()?.join("preflight");
+        crate::deopt::utils::create_dir_if_nonexist(&dir)?;
+        let driver = dir.join("preflight.cc");
+        let binary = dir.join("preflight");
+        let headers = crate::deopt::utils::format_library_header_strings(&self.deopt);
+        std::fs::write(&driver, preflight_driver(headers, apis))?;
+
+        let mut cmd = self.build_compile_cmd(&[driver.as_path()], &binary, Compile::CoverageNoFuzz)?;
+        let output = cmd.output()?;
+        if !output.status.success() {
+            eyre::bail!(
+                "Preflight failed to compile or link the library.\ncmd: {cmd:?}\n{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        let mut run = Command::new(&binary);
+        run.current_dir(&dir)
+            .env("LLVM_PROFILE_FILE", dir.join("preflight.profraw"));
+        let timeout = Duration::from_secs(crate::config::EXECUTION_TIMEOUT);
+        match run_with_timeout(&mut run, timeout)? {
+            Some((status, _)) if status.success() => {
+                log::info!("Preflight passed: {} APIs of the library link and run.", apis.len());
+                Ok(())
+            }
+            Some((status, err_msg)) => {
+                eyre::bail!("Preflight binary {binary:?} failed with {status}.\n{err_msg}")
+            }
+            None => eyre::bail!("Preflight binary {binary:?} timed out."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preflight_driver() {
+        let driver = preflight_driver("#include \"zlib.h\"", &["compress", "uncompress"]);
+        assert!(driver.starts_with("#include \"zlib.h\"\n"));
+        assert!(driver.contains("\t\t(void*)&compress,\n\t\t(void*)&uncompress,\n"));
+        assert!(driver.contains("int main() {"));
+        let driver = preflight_driver("", &[]);
+        assert!(driver.contains("{nullptr}"));
+    }
+}
@@ -0,0 +1,94 @@
+//! Reproduce a crash: run a fuzzer binary (a fused core or a compiled seed) on a single input,
+//! under the same sanitizer options, timeout and library path as the fuzzer.
+
+use super::{get_child_err, Executor};
+use crate::deopt::utils::get_crash_kind;
+use eyre::Result;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::time::Duration;
+use wait_timeout::ChildExt;
+
+#[derive(Debug)]
+pub struct ReplayReport {
+    /// The exit status of the binary, None if it timed out and was killed.
+    pub status: Option<std::process::ExitStatus>,
+    /// The sanitizer or libFuzzer report on stderr.
+    pub report: String,
+    /// The crash signature, see `crash_signature`.
+    pub signature: String,
+}
+
+impl ReplayReport {
+    pub fn is_crashed(&self) -> bool {
+        !self.status.is_some_and(|status| status.success())
+    }
+}
+
+/// The signature of a crash: its kind and the function of the top stack frame, e.g.,
+/// `heap-buffer-overflow in inflate`. The frame is omitted if the report has no stack trace.
+pub fn crash_signature(report: &str) -> String {
+    let kind = get_crash_kind(report);
+    let re = regex::Regex::new(r"(?m)^\s*#0 0x[0-9a-f]+ in (\S+)").unwrap();
+    match re.captures(report) {
+        Some(captures) => format!("{kind} in {}", &captures[1]),
+        None => kind,
+    }
+}
+
+impl Executor {
+    /// Run `binary` on `input` once, and collect its report.
+    pub fn replay(&self, binary: &Path, input: &Path) -> Result<ReplayReport> {
+        if !binary.exists() {
+            eyre::bail!("The binary {binary:?} does not exist!");
+        }
+        // the binary is run in its own dir.
+        let input = input.canonicalize()?;
+        let mut child = self.spawn(
+            binary,
+            vec![input.as_os_str()],
+            Vec::<(&OsStr, &OsStr)>::new(),
+            None,
+            None,
+            false,
+        );
+        let stderr = child.stderr.take().unwrap();
+        let reader = std::thread::spawn(move || get_child_err(stderr));
+        let timeout = Duration::from_secs(crate::config::EXECUTION_TIMEOUT + 3);
+        let status = match child.wait_timeout(timeout)? {
+            Some(status) => Some(status),
+            None => {
+                child.kill()?;
+                child.wait()?;
+                None
+            }
+        };
+        let report = reader.join().unwrap_or_default();
+        let signature = match status {
+            None => "timeout".to_string(),
+            Some(status) if status.success() => "none".to_string(),
+            Some(_) => crash_signature(&report),
+        };
+        Ok(ReplayReport {
+            status,
+            report,
+            signature,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crash_signature() {
+        let report = "==42==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000011\n\
+                      READ of size 1 at 0x602000000011 thread T0\n    \
+                      #0 0x55d4c3 in inflate /src/zlib/inflate.c:1234:5\n    \
+                      #1 0x55d5e1 in LLVMFuzzerTestOneInput /out/fuzzer.cc:20:3\n\
+                      SUMMARY: AddressSanitizer: heap-buffer-overflow /src/zlib/inflate.c:1234:5 in inflate";
+        assert_eq!(crash_signature(report), "heap-buffer-overflow in inflate");
+        assert_eq!(crash_signature("==1== ERROR: libFuzzer: timeout after 3 seconds"), "timeout");
+    }
+}
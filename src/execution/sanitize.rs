@@ -21,7 +21,6 @@ use std::{
     process::{Command, Stdio},
     time::Duration,
     io::Write,
-    sync::mpsc::channel,
 };
 use wait_timeout::ChildExt;
 
@@ -29,13 +28,19 @@ impl Executor {
     /// check whether the c program is syntactically and semantically correct.
     fn is_program_syntax_correct(&self, program_path: &Path) -> Result<Option<ProgramError>> {
         let time_logger = TimeUsage::new(get_file_dirname(program_path));
-        let output: std::process::Output = Command::new("clang++")
+        let output: std::process::Output = Command::new(crate::config::get_cxx())
             .stdout(Stdio::null())
             .arg("-fsyntax-only")
             .arg(&self.header_cmd)
+            .args(crate::config::get_extra_cxxflags())
             .arg(program_path.as_os_str())
             .output()
-            .expect("failed to execute the syntax check process");
+            .map_err(|err| {
+                crate::FuzzerError::Infra(format!(
+                    "failed to execute the compiler {:?}: {err}",
+                    crate::config::get_cxx()
+                ))
+            })?;
         time_logger.log("syntax")?;
         let success = output.status.success();
         if success {
@@ -57,6 +62,12 @@ impl Executor {
 
         if let Err(err) = res {
             let err_msg = err.to_string();
+            if super::is_compile_timeout(&err) {
+                return Ok(Some(ProgramError::Hang(err_msg)));
+            }
+            if let Some(infra) = ProgramError::from_infra_report(&err) {
+                return Ok(Some(infra));
+            }
             return Ok(Some(ProgramError::Link(err_msg)));
         }
         Ok(None)
@@ -138,7 +149,12 @@ impl Executor {
         Ok(Some(ProgramError::Coverage(format!("The program cannot cover the callees along the path that contains maximum callees.\n{err_msg}"))))
     }
 
+    /// The failures of our own setup in any step are `ProgramError::Infra`.
     pub fn check_program_is_correct(&self, seed_path: &Path) -> Result<Option<ProgramError>> {
+        infra_as_program_error(self.check_program_steps(seed_path))
+    }
+
+    fn check_program_steps(&self, seed_path: &Path) -> Result<Option<ProgramError>> {
         if let Some(err) = self.is_program_syntax_correct(seed_path)? {
             return Ok(Some(err));
         }
@@ -156,47 +172,23 @@ impl Executor {
         }
         Ok(None)
     }
+    /// Compile and run the API sequence of `program`, the setup failures are classified as infrastructure errors.
     pub fn validate_api_sequence(
         &self,
         program: &Program,
         deopt: &Deopt,
     ) -> Result<Option<ProgramError>> {
-        // write the program to a temp file.
-        let temp_path = deopt.get_work_seed_by_id(program.id)?;
-        if let Some(parent) = temp_path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-        let mut temp_file = std::fs::File::create(&temp_path)?;
-        write!(temp_file, "#include <cstddef>\n")?;
-        write!(
-            temp_file,
-            "{}",
-            crate::deopt::utils::format_library_header_strings(deopt)
-        )?;
-        
-        write!(temp_file, "#include <iostream>\n")?;
-        write!(temp_file, "#include <stdio.h>\n")?;
-        write!(temp_file, "#include <fcntl.h>\n")?;
-        let project_name = get_library_name();
-        writeln!(temp_file, "{}", program.statements)?;
-        //log::debug!("Program: {}", program.statements);
-        writeln!(temp_file, "int main() {{")?;
-        writeln!(
-            temp_file,
-            "int result = test_{}_api_sequence();",
-            project_name
-        )?;
-        writeln!(temp_file, "if (result != 66) {{")?;
-        writeln!(
-            temp_file,
-            "        std::cerr << \"API sequence test failed with error code: \" << result << std::endl;"
-        )?;
-        writeln!(temp_file, "        exit(100);")?;
-        writeln!(temp_file, "    }}")?;
-        writeln!(temp_file, "    return 0;")?;
-        writeln!(temp_file, "}}")?;
-        drop(temp_file);
-        
+        let res = infra_as_program_error(self.check_api_sequence(program, deopt))?;
+        Ok(res.map(ProgramError::classify_infra))
+    }
+
+    fn check_api_sequence(
+        &self,
+        program: &Program,
+        deopt: &Deopt,
+    ) -> Result<Option<ProgramError>> {
+        let temp_path = self.write_api_sequence(program, deopt)?;
+
         // check the program syntax and link correctness.
         if let Some(err) = self.is_program_syntax_correct(&temp_path)? {
             return Ok(Some(err));
@@ -212,28 +204,27 @@ impl Executor {
         let binary_out = temp_path.with_extension("out");
         log::debug!("Compiling program: {}", temp_path.display());
 
-        // Compile in a separate thread with timeout to prevent hanging
-        let (tx, rx) = channel();
-        let handle = std::thread::spawn({
-            let s = self.clone();
-            let t = temp_path.clone();
-            let b = binary_out.clone();
-            move || {
-                let result = s.compile(vec![&t], &b, crate::execution::Compile::CoverageNoFuzz);
-                tx.send(result).unwrap();
-            }
-        });
+        // the hanging compilation is killed by the watchdog.
         let timeout = Duration::from_secs(10); // 设置10秒超时
-        match rx.recv_timeout(timeout) {
-            Ok(Err(err)) => {
+        let result = self.compile_with_timeout(
+            vec![&temp_path],
+            &binary_out,
+            crate::execution::Compile::CoverageNoFuzz,
+            timeout,
+        );
+        match result {
+            Err(err) if super::is_compile_timeout(&err) => {
+                log::warn!("Compilation timed out: {err}");
+                return Ok(Some(ProgramError::Hang("Compilation timed out".to_owned())));
+            }
+            Err(err) => {
                 log::error!("Compilation failed: {}", err);
+                if let Some(infra) = ProgramError::from_infra_report(&err) {
+                    return Ok(Some(infra));
+                }
                 return Ok(Some(ProgramError::Link(format!("Compilation failed: {}", err))));
-            },
-            Err(_) => {
-                log::warn!("Child compilation thread is hanging and is not terminated");
-                return Ok(Some(ProgramError::Hang("Compilation timed out".to_owned())));
-            },
-            Ok(Ok(_)) => {}
+            }
+            Ok(_) => {}
         }
         
         // 设置 LD_LIBRARY_PATH 以确保使用正确版本的动态库
@@ -243,6 +234,7 @@ impl Executor {
         );
         
         let mut exec_child = Command::new(&binary_out)
+            .envs(self.get_sanitizer_envs())
             .env("LD_LIBRARY_PATH", lib_path)
             .stdout(Stdio::null())
             .stderr(Stdio::piped())
@@ -266,6 +258,46 @@ impl Executor {
         Ok(None)
     }
 
+    /// Write the API sequence of `program` with a main calling it to its work seed file.
+    fn write_api_sequence(&self, program: &Program, deopt: &Deopt) -> Result<PathBuf> {
+        // write the program to a temp file.
+        let temp_path = deopt.get_work_seed_by_id(program.id)?;
+        if let Some(parent) = temp_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut temp_file = std::fs::File::create(&temp_path)?;
+        write!(temp_file, "#include <cstddef>\n")?;
+        write!(
+            temp_file,
+            "{}",
+            crate::deopt::utils::format_library_header_strings(deopt)
+        )?;
+        
+        write!(temp_file, "#include <iostream>\n")?;
+        write!(temp_file, "#include <stdio.h>\n")?;
+        write!(temp_file, "#include <fcntl.h>\n")?;
+        let project_name = get_library_name();
+        writeln!(temp_file, "{}", program.statements)?;
+        //log::debug!("Program: {}", program.statements);
+        writeln!(temp_file, "int main() {{")?;
+        writeln!(
+            temp_file,
+            "int result = test_{}_api_sequence();",
+            project_name
+        )?;
+        writeln!(temp_file, "if (result != 66) {{")?;
+        writeln!(
+            temp_file,
+            "        std::cerr << \"API sequence test failed with error code: \" << result << std::endl;"
+        )?;
+        writeln!(temp_file, "        exit(100);")?;
+        writeln!(temp_file, "    }}")?;
+        writeln!(temp_file, "    return 0;")?;
+        writeln!(temp_file, "}}")?;
+        drop(temp_file);
+        Ok(temp_path)
+    }
+
     /// 专门处理 cre2 库的验证逻辑
     fn validate_cre2_program(&self, program_path: &Path) -> Result<Option<ProgramError>> {
         log::debug!("Validating cre2 program: {}", program_path.display());
@@ -311,6 +343,7 @@ impl Executor {
         // 步骤2: 运行 cre2 程序
         log::debug!("Step 2: Running cre2 program");
         let mut exec_child = Command::new(&binary_out)
+            .envs(self.get_sanitizer_envs())
             .env("LD_LIBRARY_PATH", format!("{}:{}:{}", cre2_lib, re2_lib, std::env::var("LD_LIBRARY_PATH").unwrap_or_default()))
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -339,6 +372,54 @@ impl Executor {
         Ok(None)
     }
 
+    /// The syntax-only check of `--quick-validate`: compile the programs concurrently, `--cores` at a time, with `-fsyntax-only` and the normal flags.
+    /// A check outliving SANITIZATION_TIMEOUT is killed, and its program is rejected as a hang.
+    pub fn quick_check_batch(&self, programs: &[PathBuf]) -> Result<Vec<Option<ProgramError>>> {
+        let timeout = Duration::from_secs(crate::config::SANITIZATION_TIMEOUT);
+        let header_cmd = &self.header_cmd;
+        let core = crate::config::get_config().cores.max(1);
+        let mut has_errs = Vec::new();
+        for chunk in programs.chunks(core) {
+            std::thread::scope(|scope| -> Result<()> {
+                let handles: Vec<_> = chunk
+                    .iter()
+                    .map(|program| {
+                        scope.spawn(move || {
+                            let mut cmd = Command::new(crate::config::get_cxx());
+                            cmd.arg("-fsyntax-only")
+                                .args(crate::config::NORMAL_FLAGS)
+                                .arg(header_cmd)
+                                .args(crate::config::get_extra_cxxflags())
+                                .arg(program);
+                            super::run_compiler_with_timeout(&mut cmd, timeout).map_err(|err| {
+                                format!("failed to execute the compiler {:?}: {err}", crate::config::get_cxx())
+                            })
+                        })
+                    })
+                    .collect();
+                for handle in handles {
+                    let res = handle
+                        .join()
+                        .map_err(|_| eyre::eyre!("the syntax check thread panicked"))?;
+                    has_errs.push(match res {
+                        Err(err) => Some(ProgramError::Infra(err)),
+                        Ok(None) => Some(ProgramError::Hang("Syntax check timed out".to_owned())),
+                        Ok(Some((status, _))) if status.success() => None,
+                        Ok(Some((_, err_msg))) => Some(ProgramError::Syntax(err_msg)),
+                    });
+                }
+                Ok(())
+            })?;
+        }
+        Ok(has_errs)
+    }
+
+    /// The syntax-only check of `--quick-validate` for an API sequence, before `validate_api_sequence` builds it.
+    pub fn quick_check_api_sequence(&self, program: &Program, deopt: &Deopt) -> Result<Option<ProgramError>> {
+        let temp_path = self.write_api_sequence(program, deopt)?;
+        Ok(self.quick_check_batch(&[temp_path])?.pop().flatten())
+    }
+
     pub fn check_programs_are_correct(
         &self,
         programs: &[Program],
@@ -353,7 +434,27 @@ impl Executor {
             std::fs::write(&temp_path, content)?;
             program_paths.push(temp_path);
         }
-        let res = self.concurrent_check_batch(&program_paths)?;
+        let res = if crate::config::quick_validate() {
+            let gate = self.quick_check_batch(&program_paths)?;
+            let passed: Vec<PathBuf> = program_paths
+                .iter()
+                .zip(&gate)
+                .filter(|(_, err)| err.is_none())
+                .map(|(path, _)| path.clone())
+                .collect();
+            log::info!(
+                "Quick validation rejected {} of {} programs.",
+                program_paths.len() - passed.len(),
+                program_paths.len()
+            );
+            merge_gate_results(gate, self.concurrent_check_batch(&passed)?)
+        } else {
+            self.concurrent_check_batch(&program_paths)?
+        };
+        let res: Vec<Option<ProgramError>> = res
+            .into_iter()
+            .map(|has_err| has_err.map(ProgramError::classify_infra))
+            .collect();
         // print the time usage of the sanitization
         utils::print_san_cost(&program_paths)?;
 
@@ -381,6 +482,13 @@ impl Executor {
         &self,
         programs: &[PathBuf],
     ) -> Result<Vec<Option<ProgramError>>> {
+        // the checks run in the harness, which should compile with the same compiler.
+        let mut compiler_args = vec!["--cxx".into(), crate::config::get_cxx().into_os_string()];
+        let extra_cxxflags = crate::config::get_extra_cxxflags();
+        if !extra_cxxflags.is_empty() {
+            compiler_args.push("--extra-cxxflags".into());
+            compiler_args.push(extra_cxxflags.join(" ").into());
+        }
         let mut childs = Vec::new();
         for program in programs {
             let child = Command::new("cargo")
@@ -391,6 +499,7 @@ impl Executor {
                 .arg("harness")
                 .arg("--")
                 .arg(get_library_name())
+                .args(&compiler_args)
                 .arg("check")
                 .arg(program)
                 .stdout(Stdio::null())
@@ -560,11 +669,48 @@ pub mod utils {
     }
 }
 
+/// Take the failure of our own setup as the `ProgramError::Infra` of the checked program, see `FuzzerError::Infra`.
+fn infra_as_program_error(res: Result<Option<ProgramError>>) -> Result<Option<ProgramError>> {
+    match res {
+        Err(err) => match ProgramError::from_infra_report(&err) {
+            Some(infra) => Ok(Some(infra)),
+            None => Err(err),
+        },
+        res => res,
+    }
+}
+
+/// Fill the programs passing the quick gate with their full check results, in order.
+fn merge_gate_results(
+    gate: Vec<Option<ProgramError>>,
+    full: Vec<Option<ProgramError>>,
+) -> Vec<Option<ProgramError>> {
+    let mut full = full.into_iter();
+    gate.into_iter()
+        .map(|err| match err {
+            Some(err) => Some(err),
+            None => full.next().unwrap_or(None),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_merge_gate_results() {
+        let syntax = || Some(ProgramError::Syntax("error".to_string()));
+        let gate = vec![None, syntax(), None, syntax()];
+        let full = vec![Some(ProgramError::Link("link".to_string())), None];
+        let merged = merge_gate_results(gate, full);
+        assert!(matches!(merged[0], Some(ProgramError::Link(_))));
+        assert!(matches!(merged[1], Some(ProgramError::Syntax(_))));
+        assert!(merged[2].is_none());
+        assert!(matches!(merged[3], Some(ProgramError::Syntax(_))));
+    }
+
     #[test]
     fn test_coverage_sanitize() -> Result<()> {
         crate::config::Config::init_test("cJSON");
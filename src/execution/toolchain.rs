@@ -0,0 +1,115 @@
+//! Check that the clang compiling the programs matches the `llvm-cov` and `llvm-profdata` in PATH, and the
+//! clang recorded when the library outputs were created. A version skew among them fails the coverage
+//! collection with cryptic profdata errors.
+
+use std::process::Command;
+
+use eyre::Result;
+
+use crate::deopt::Deopt;
+
+/// The version in the `--version` output of clang or an LLVM tool, e.g., `18.1.3` of
+/// `Ubuntu clang version 18.1.3 (1ubuntu1)` or `LLVM version 18.1.3`.
+pub fn parse_llvm_version(output: &str) -> Option<String> {
+    let re = regex::Regex::new(r"(?:clang|LLVM) version (\d+(?:\.\d+)*)").unwrap();
+    re.captures(output).map(|captures| captures[1].to_string())
+}
+
+fn major_of(version: &str) -> &str {
+    version.split('.').next().unwrap_or(version)
+}
+
+/// The mismatches of the clang version against the LLVM tools and the `recorded` clang. Only the major versions
+/// are compared, as the profile format is stable within them. A tool without a version is a mismatch as well.
+pub fn toolchain_mismatches(
+    clang: &str,
+    llvm_cov: Option<&str>,
+    llvm_profdata: Option<&str>,
+    recorded: Option<&str>,
+) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    for (tool, version) in [("llvm-cov", llvm_cov), ("llvm-profdata", llvm_profdata)] {
+        match version {
+            Some(version) if major_of(version) == major_of(clang) => {}
+            Some(version) => mismatches.push(format!("{tool} {version} does not match clang {clang}")),
+            None => mismatches.push(format!("The version of {tool} in PATH is unknown")),
+        }
+    }
+    if let Some(recorded) = recorded {
+        if major_of(recorded) != major_of(clang) {
+            mismatches.push(format!(
+                "The library outputs were created with clang {recorded}, but clang {clang} is used now"
+            ));
+        }
+    }
+    mismatches
+}
+
+fn detect_version(tool: &std::ffi::OsStr) -> Option<String> {
+    let output = Command::new(tool).arg("--version").output().ok()?;
+    parse_llvm_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Detect the versions of the toolchain and log them. The clang version is recorded in the misc dir of the library
+/// on the first run. The mismatches and an undetectable compiler version are warned about, or fail the check if
+/// `strict`.
+pub fn check_toolchain(deopt: &Deopt, strict: bool) -> Result<()> {
+    let cxx = crate::config::get_cxx();
+    let Some(clang) = detect_version(cxx.as_os_str()) else {
+        if strict {
+            eyre::bail!("Cannot detect the version of the compiler {cxx:?} (--strict-toolchain)");
+        }
+        log::warn!("Cannot detect the version of the compiler {cxx:?}, skip the toolchain check.");
+        return Ok(());
+    };
+    let llvm_cov = detect_version("llvm-cov".as_ref());
+    let llvm_profdata = detect_version("llvm-profdata".as_ref());
+    log::info!(
+        "Detected toolchain: clang {clang}, llvm-cov {}, llvm-profdata {}",
+        llvm_cov.as_deref().unwrap_or("unknown"),
+        llvm_profdata.as_deref().unwrap_or("unknown")
+    );
+
+    let record_path = deopt.get_library_toolchain_path()?;
+    let recorded = std::fs::read_to_string(&record_path)
+        .ok()
+        .map(|version| version.trim().to_string());
+    if recorded.is_none() {
+        std::fs::write(&record_path, &clang)?;
+    }
+    let mismatches =
+        toolchain_mismatches(&clang, llvm_cov.as_deref(), llvm_profdata.as_deref(), recorded.as_deref());
+    if strict && !mismatches.is_empty() {
+        eyre::bail!("Mismatched toolchain (--strict-toolchain): {}", mismatches.join("; "));
+    }
+    for mismatch in mismatches {
+        log::warn!("{mismatch}. The coverage collection may fail.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_llvm_version() {
+        let clang = "Ubuntu clang version 18.1.3 (1ubuntu1)\nTarget: x86_64-pc-linux-gnu\nThread model: posix\n";
+        assert_eq!(parse_llvm_version(clang).as_deref(), Some("18.1.3"));
+        let llvm_cov = "Ubuntu LLVM version 18.1.3\n  Optimized build.\n";
+        assert_eq!(parse_llvm_version(llvm_cov).as_deref(), Some("18.1.3"));
+        assert_eq!(parse_llvm_version("g++ (GCC) 13.2.0").as_deref(), None);
+    }
+
+    #[test]
+    fn test_toolchain_mismatches() {
+        assert!(toolchain_mismatches("18.1.3", Some("18.1.8"), Some("18.1.3"), Some("18.1.3")).is_empty());
+        assert!(toolchain_mismatches("18.1.3", Some("18.1.3"), Some("18.1.3"), None).is_empty());
+
+        let mismatches = toolchain_mismatches("18.1.3", Some("15.0.7"), None, Some("17.0.6"));
+        assert_eq!(mismatches.len(), 3);
+        assert_eq!(mismatches[0], "llvm-cov 15.0.7 does not match clang 18.1.3");
+        assert!(mismatches[1].contains("llvm-profdata"));
+        assert!(mismatches[2].contains("clang 17.0.6"));
+    }
+}
@@ -97,7 +97,7 @@ impl BranchState {
     }
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct FuncBranches {
     branches: Vec<BranchState>,
 }
@@ -128,7 +128,7 @@ impl FuncBranches {
     }
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct GlobalBranches {
     branches: HashMap<String, FuncBranches>,
 }
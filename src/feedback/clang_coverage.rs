@@ -121,6 +121,16 @@ impl CovSummary {
         self.regions.covered
     }
 
+    /// The total, covered and percent of the lines, functions, branches and regions, e.g., for CI gates.
+    pub fn to_report(&self) -> CoverageReport {
+        CoverageReport {
+            lines: CoverageNumbers::from(&self.lines),
+            functions: CoverageNumbers::from(&self.functions),
+            branches: CoverageNumbers::from(&self.branches),
+            regions: CoverageNumbers::from(&self.regions),
+        }
+    }
+
     pub fn has_new_coverage(&self, pre: &Self) -> bool {
         self.count_covered_branches() > pre.count_covered_branches()
             || self.count_covered_functions() > pre.count_covered_functions()
@@ -137,6 +147,54 @@ struct CovData {
     percent: f32,
 }
 
+/// The compact coverage summary printed by `harness <lib> coverage-json`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CoverageReport {
+    pub lines: CoverageNumbers,
+    pub functions: CoverageNumbers,
+    pub branches: CoverageNumbers,
+    pub regions: CoverageNumbers,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CoverageNumbers {
+    pub total: usize,
+    pub covered: usize,
+    pub percent: f32,
+}
+
+impl CoverageReport {
+    /// Fail if the branch coverage is below `gate`, in percent.
+    pub fn check_branch_gate(&self, gate: f32) -> Result<()> {
+        if !(0.0..=100.0).contains(&gate) {
+            eyre::bail!("The coverage gate should be in [0, 100], got {gate}");
+        }
+        if self.branches.percent < gate {
+            eyre::bail!(
+                "Branch coverage {:.2}% ({}/{}) is below the required {gate:.2}%",
+                self.branches.percent,
+                self.branches.covered,
+                self.branches.total
+            );
+        }
+        log::info!(
+            "Branch coverage {:.2}% meets the required {gate:.2}%",
+            self.branches.percent
+        );
+        Ok(())
+    }
+}
+
+impl From<&CovData> for CoverageNumbers {
+    fn from(data: &CovData) -> Self {
+        Self {
+            total: data.count,
+            covered: data.covered,
+            percent: data.percent,
+        }
+    }
+}
+
 // [line_start, col_start, line_end, col_end, exec_count, false_count, fileid, expand_file_id, kind]
 pub type CovBranch = [usize; 9];
 pub trait BranchCount {
@@ -540,37 +598,48 @@ impl Executor {
         Ok(())
     }
 
-    pub fn collect_cntg_cov_per_core(&self, core_dir: &Path) -> Result<()> {
+    /// Returns false if the core timed out, see `execute_cov_cntg_core`.
+    pub fn collect_cntg_cov_per_core(&self, core_dir: &Path) -> Result<bool> {
         log::trace!("collect cov for CNTG core: {core_dir:?}");
         
-        let core_binary: PathBuf = [PathBuf::from(core_dir), "core".into()].iter().collect();
+        let core_binary = crate::cntg_program::get_core_path(core_dir);
         if !core_binary.exists() {
             eyre::bail!("CNTG core binary not found: {core_binary:?}");
         }
 
         // Run the CNTG core and collect coverage
         let profdata: PathBuf = crate::deopt::Deopt::get_coverage_file_by_dir(core_dir);
-        self.execute_cov_cntg_core(&core_binary, &profdata)?;
-        Ok(())
+        self.execute_cov_cntg_core(&core_binary, &profdata)
     }
 
     pub fn collect_cntg_cov_all_cores(&self, cntg_dir: &Path) -> Result<()> {
         let mut cov_data = Vec::new();
+        let mut timeout_cores = Vec::new();
         for entry in std::fs::read_dir(cntg_dir)? {
             let core_dir = entry?.path();
             if !core_dir.is_dir() {
                 continue;
             }
             
-            let res = self.collect_cntg_cov_per_core(&core_dir);
-            if res.is_ok() {
-                let profdata: PathBuf = crate::deopt::Deopt::get_coverage_file_by_dir(&core_dir);
-                log::debug!("collect CNTG core cov finished: {core_dir:?}");
-                cov_data.push(profdata);
-            } else {
-                log::error!("{:?}", res.err().unwrap());
+            match self.collect_cntg_cov_per_core(&core_dir) {
+                Ok(true) => {
+                    let profdata: PathBuf = crate::deopt::Deopt::get_coverage_file_by_dir(&core_dir);
+                    log::debug!("collect CNTG core cov finished: {core_dir:?}");
+                    cov_data.push(profdata);
+                }
+                Ok(false) => {
+                    log::warn!("CNTG core timed out and was skipped: {core_dir:?}");
+                    timeout_cores.push(core_dir);
+                }
+                Err(err) => log::error!("{err:?}"),
             }
         }
+        if !timeout_cores.is_empty() {
+            log::warn!(
+                "{} CNTG cores timed out, their profiles are omitted: {timeout_cores:?}",
+                timeout_cores.len()
+            );
+        }
 
         if cov_data.is_empty() {
             eyre::bail!("No CNTG cores found or no coverage data collected");
@@ -798,6 +867,37 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_coverage_report() -> Result<()> {
+        let export = r#"{"data": [{"files": [], "totals": {
+            "branches": {"count": 200, "covered": 50, "notcovered": 150, "percent": 25.0},
+            "functions": {"count": 20, "covered": 13, "percent": 65.0},
+            "instantiations": {"count": 20, "covered": 13, "percent": 65.0},
+            "lines": {"count": 1000, "covered": 331, "percent": 33.1},
+            "regions": {"count": 800, "covered": 353, "notcovered": 447, "percent": 44.125}
+        }}], "type": "llvm.coverage.json.export", "version": "2.0.1"}"#;
+        let report = CodeCoverage::from_slice(export.as_bytes())?.get_total_summary().to_report();
+        assert_eq!(
+            report.branches,
+            CoverageNumbers {
+                total: 200,
+                covered: 50,
+                percent: 25.0
+            }
+        );
+        assert_eq!((report.functions.total, report.functions.covered), (20, 13));
+        assert_eq!(report.lines.covered, 331);
+        assert_eq!(report.regions.percent, 44.125);
+        let json = serde_json::to_value(&report)?;
+        assert_eq!(json["lines"]["total"], 1000);
+
+        assert!(report.check_branch_gate(25.0).is_ok());
+        let err = report.check_branch_gate(30.0).unwrap_err().to_string();
+        assert!(err.contains("25.00%") && err.contains("30.00%"), "{err}");
+        assert!(report.check_branch_gate(101.0).is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_parse_coverage_summary() -> Result<()> {
         crate::config::Config::init_test("cJSON");
@@ -0,0 +1,94 @@
+//! The precomputed embeddings of the APIs, used by `--comb-strategy semantic`.
+//!
+//! The embeddings file is a JSON object from the API name to its embedding vector, e.g.,
+//! `{"compress": [0.12, -0.03, ...], "uncompress": [0.10, -0.01, ...]}`. All the vectors must be
+//! of the same dimension. The APIs absent from the file are treated as unrelated to any others.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use eyre::Result;
+
+/// How strongly the similarity to the chosen APIs scales the sampling weight: `exp(SEMANTIC_BIAS * sim)`.
+pub const SEMANTIC_BIAS: f32 = 3.0;
+
+#[derive(Debug, Default)]
+pub struct ApiEmbeddings {
+    /// The normalized embedding of each API.
+    vectors: HashMap<String, Vec<f32>>,
+}
+
+impl ApiEmbeddings {
+    pub fn from_map(map: HashMap<String, Vec<f32>>) -> Result<Self> {
+        let mut dim = None;
+        let mut vectors = HashMap::new();
+        for (api, vector) in map {
+            if *dim.get_or_insert(vector.len()) != vector.len() {
+                eyre::bail!("The embedding of {api} is not of dimension {}.", dim.unwrap());
+            }
+            let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if norm == 0.0 || !norm.is_finite() {
+                eyre::bail!("The embedding of {api} cannot be normalized.");
+            }
+            vectors.insert(api, vector.iter().map(|x| x / norm).collect());
+        }
+        Ok(Self { vectors })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let map: HashMap<String, Vec<f32>> = serde_json::from_str(&content)?;
+        let embeddings = Self::from_map(map)?;
+        log::info!("Load the embeddings of {} APIs from {path:?}", embeddings.len());
+        Ok(embeddings)
+    }
+
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty()
+    }
+
+    /// The cosine similarity of two APIs, 0 if either has no embedding.
+    pub fn similarity(&self, lhs: &str, rhs: &str) -> f32 {
+        match (self.vectors.get(lhs), self.vectors.get(rhs)) {
+            (Some(lhs), Some(rhs)) => lhs.iter().zip(rhs).map(|(x, y)| x * y).sum(),
+            _ => 0.0,
+        }
+    }
+
+    /// The factor of the sampling weight of `api`, given the APIs already in the combination.
+    /// It is `exp(SEMANTIC_BIAS * sim)` where `sim` is the mean similarity to the chosen APIs.
+    pub fn bias(&self, api: &str, chosen: &[&str]) -> f32 {
+        if chosen.is_empty() {
+            return 1.0;
+        }
+        let sim: f32 =
+            chosen.iter().map(|other| self.similarity(api, other)).sum::<f32>() / chosen.len() as f32;
+        (SEMANTIC_BIAS * sim).exp()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedding_bias() -> Result<()> {
+        let json = r#"{"compress": [1.0, 0.0], "compress2": [2.0, 0.2], "crc32": [0.0, 1.0]}"#;
+        let map: HashMap<String, Vec<f32>> = serde_json::from_str(json)?;
+        let embeddings = ApiEmbeddings::from_map(map)?;
+        assert_eq!(embeddings.len(), 3);
+        assert!((embeddings.similarity("compress", "compress") - 1.0).abs() < 1e-6);
+        assert_eq!(embeddings.similarity("compress", "missing"), 0.0);
+        assert_eq!(embeddings.bias("crc32", &[]), 1.0);
+        assert!(embeddings.bias("compress2", &["compress"]) > embeddings.bias("crc32", &["compress"]));
+        assert_eq!(embeddings.bias("missing", &["compress"]), 1.0);
+
+        let map = HashMap::from([("a".to_string(), vec![1.0]), ("b".to_string(), vec![1.0, 0.0])]);
+        assert!(ApiEmbeddings::from_map(map).is_err());
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 pub mod branches;
 pub mod clang_coverage;
+pub mod embedding;
 pub mod observer;
 pub mod schedule;
@@ -12,21 +12,121 @@ use crate::{
     config::CONFIG_INSTANCE,
     deopt::utils::read_sort_dir,
     program::{
-        gadget::{get_func_gadgets, FuncGadget},
+        gadget::{count_possible_pairs, ctype, get_func_gadgets, get_selectable_func_gadgets, ApiSignature, FuncGadget},
         Program,
     },
     Deopt,
 };
 use eyre::Result;
+use once_cell::sync::OnceCell;
 use std::collections::HashSet;
 use std::sync::{Arc, RwLock};
 
+/// Bump it once the format of `ObserverSnapshot` is changed.
+pub const OBSERVER_SNAPSHOT_VERSION: u32 = 1;
+
+/// The on-disk snapshot of the observed coverage, which is independent of the log format.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ObserverSnapshot {
+    version: u32,
+    branches: GlobalBranches,
+    api_coverage: HashMap<String, f32>,
+    discovered_api_triples: Vec<(String, String, String)>,
+}
+
+/// The pairs only in the first set, only in the second set, and in both, each sorted.
+pub type PairDiff = (Vec<(String, String)>, Vec<(String, String)>, Vec<(String, String)>);
+
+/// The adjacent call pairs of the API triples.
+pub fn pairs_of_triples<'a>(
+    triples: impl Iterator<Item = &'a (String, String, String)>,
+) -> HashSet<(String, String)> {
+    let mut pairs = HashSet::new();
+    for (a, b, c) in triples {
+        pairs.insert((a.clone(), b.clone()));
+        pairs.insert((b.clone(), c.clone()));
+    }
+    pairs
+}
+
+/// Diff two sets of ordered API pairs.
+pub fn diff_pair_sets(lhs: &HashSet<(String, String)>, rhs: &HashSet<(String, String)>) -> PairDiff {
+    let sorted = |pairs: Vec<&(String, String)>| {
+        let mut pairs: Vec<(String, String)> = pairs.into_iter().cloned().collect();
+        pairs.sort();
+        pairs
+    };
+    (
+        sorted(lhs.difference(rhs).collect()),
+        sorted(rhs.difference(lhs).collect()),
+        sorted(lhs.intersection(rhs).collect()),
+    )
+}
+
+/// Load a persisted pair set: an observer snapshot, whose pairs are those of its discovered API triples,
+/// or a JSON array of `[caller, callee]` pairs.
+pub fn load_pair_set(path: &Path) -> Result<HashSet<(String, String)>> {
+    Ok(load_pair_counts(path)?.into_keys().collect())
+}
+
+/// Load a persisted pair set like `load_pair_set`, with the times each pair is discovered:
+/// the number of the discovered triples it is in, or of its occurrences in the list.
+pub fn load_pair_counts(path: &Path) -> Result<HashMap<(String, String), usize>> {
+    let content = std::fs::read_to_string(path)?;
+    let value: serde_json::Value = serde_json::from_str(&content)?;
+    let mut counts = HashMap::new();
+    if let Some(triples) = value.get("discovered_api_triples") {
+        let triples: Vec<(String, String, String)> = serde_json::from_value(triples.clone())?;
+        for (a, b, c) in triples {
+            *counts.entry((a, b.clone())).or_insert(0) += 1;
+            *counts.entry((b, c)).or_insert(0) += 1;
+        }
+        return Ok(counts);
+    }
+    let pairs: Vec<(String, String)> = serde_json::from_value(value)
+        .map_err(|err| eyre::eyre!("{path:?} is neither an observer snapshot nor a list of pairs: {err}"))?;
+    for pair in pairs {
+        *counts.entry(pair).or_insert(0) += 1;
+    }
+    Ok(counts)
+}
+
+/// Render the pairs as a Graphviz DOT digraph: a node per API, and an edge from the caller to the callee
+/// of each pair, weighted and labeled by its discovered times.
+pub fn pairs_to_dot(counts: &HashMap<(String, String), usize>) -> String {
+    let quote = |name: &str| format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut apis: Vec<&str> = counts
+        .keys()
+        .flat_map(|(caller, callee)| [caller.as_str(), callee.as_str()])
+        .collect();
+    apis.sort();
+    apis.dedup();
+    let mut edges: Vec<(&(String, String), &usize)> = counts.iter().collect();
+    edges.sort();
+
+    let mut dot = String::from("digraph api_pairs {\n");
+    for api in apis {
+        dot.push_str(&format!("    {};\n", quote(api)));
+    }
+    for ((caller, callee), count) in edges {
+        dot.push_str(&format!(
+            "    {} -> {} [weight={count}, label=\"{count}\"];\n",
+            quote(caller),
+            quote(callee)
+        ));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
 pub struct Observer {
     pub adg: ADG,
     pub discovered_api_triples: Arc<RwLock<HashSet<(String, String,String)>>>,
     deopt: Deopt,
     branches: GlobalBranches,
     api_coverage: HashMap<String, f32>,
+    /// Held for writing while the coverage is being rechecked. See `Observer::pause_handle`.
+    pause: Arc<RwLock<()>>,
 }
 
 impl Observer {
@@ -37,8 +137,19 @@ impl Observer {
             branches: GlobalBranches::new(),
             api_coverage: HashMap::new(),
             discovered_api_triples: Arc::new(RwLock::new(HashSet::new())),
+            pause: Arc::new(RwLock::new(())),
         }
     }
+
+    /// The lock of the pause phase around recheck.
+    ///
+    /// The fuzz loop takes it for writing during `recheck_seed`, `recompute_global_coverage` and
+    /// `load_programs_from_seeds`, where the seeds and global coverage are inconsistent.
+    /// Any accessor out of the fuzz loop (e.g., a metrics exporter in another thread) should hold
+    /// it for reading while it reads the observer or seeds.
+    pub fn pause_handle(&self) -> Arc<RwLock<()>> {
+        self.pause.clone()
+    }
     pub fn has_new_api_triples(&self, pairs: &[(String, String,String)]) -> bool {
         if pairs.is_empty() {
             return false;
@@ -63,6 +174,43 @@ impl Observer {
         }
     }
 
+    /// The number of distinct API pairs, i.e., the adjacent calls in the discovered API triples.
+    pub fn get_discovered_pair_num(&self) -> usize {
+        let triples = self.discovered_api_triples.read().unwrap();
+        let mut pairs = HashSet::new();
+        for (a, b, c) in triples.iter() {
+            pairs.insert((a, b));
+            pairs.insert((b, c));
+        }
+        pairs.len()
+    }
+
+    /// The distinct API pairs of the discovered API triples.
+    pub fn get_discovered_pairs(&self) -> HashSet<(String, String)> {
+        pairs_of_triples(self.discovered_api_triples.read().unwrap().iter())
+    }
+
+    /// The pairs discovered only by this observer, only in `other`, and in both.
+    pub fn diff_pairs(&self, other: &HashSet<(String, String)>) -> PairDiff {
+        diff_pair_sets(&self.get_discovered_pairs(), other)
+    }
+
+    /// The number of ordered pairs of the selectable APIs, which is the denominator of the discovered pairs.
+    /// If `type_compatible`, only the pairs that data can flow from the first API to the second are counted, by the
+    /// canonical header types as the triples of `--type-compatible-triples`.
+    pub fn total_possible_pairs(&self, type_compatible: bool) -> usize {
+        static ALL_PAIRS: OnceCell<usize> = OnceCell::new();
+        static COMPATIBLE_PAIRS: OnceCell<usize> = OnceCell::new();
+        let cell = if type_compatible { &COMPATIBLE_PAIRS } else { &ALL_PAIRS };
+        *cell.get_or_init(|| {
+            let signatures: Vec<ApiSignature> = get_selectable_func_gadgets()
+                .iter()
+                .map(|gadget| ApiSignature::from(*gadget).canonicalize(ctype::retrieve_canonical_type))
+                .collect();
+            count_possible_pairs(&signatures, type_compatible)
+        })
+    }
+
     pub fn get_global_branches(&self) -> &GlobalBranches {
         &self.branches
     }
@@ -240,6 +388,20 @@ impl Observer {
         dump_str
     }
 
+    /// The covered and the total branches.
+    pub fn get_branch_coverage(&self) -> (usize, usize) {
+        self.branches.compute_branch_coverage()
+    }
+
+    /// The percentage of the covered branches of the library.
+    pub fn get_branch_cover_percent(&self) -> f32 {
+        let (covered_branch, total_branch) = self.branches.compute_branch_coverage();
+        if total_branch == 0 {
+            return 0_f32;
+        }
+        100_f32 * covered_branch as f32 / total_branch as f32
+    }
+
     pub fn get_covered_branch(&self) -> Vec<Branch> {
         self.branches.get_covered_branch()
     }
@@ -256,6 +418,54 @@ impl Observer {
         Ok(cover_rate)
     }
 
+    /// Save the merged branches, per-API coverage and discovered API triples as a snapshot.
+    pub fn save_snapshot(&self, deopt: &Deopt) -> Result<()> {
+        self.save_snapshot_to(&deopt.get_library_observer_snapshot_path()?)
+    }
+
+    fn save_snapshot_to(&self, path: &Path) -> Result<()> {
+        let snapshot = ObserverSnapshot {
+            version: OBSERVER_SNAPSHOT_VERSION,
+            branches: self.branches.clone(),
+            api_coverage: self.api_coverage.clone(),
+            discovered_api_triples: self
+                .discovered_api_triples
+                .read()
+                .unwrap()
+                .iter()
+                .cloned()
+                .collect(),
+        };
+        std::fs::write(path, serde_json::to_string(&snapshot)?)?;
+        Ok(())
+    }
+
+    /// Load the observer from the snapshot of this library. Returns None if there is no snapshot.
+    pub fn load_snapshot(deopt: &Deopt) -> Result<Option<Self>> {
+        Self::load_snapshot_from(&deopt.get_library_observer_snapshot_path()?, deopt)
+    }
+
+    fn load_snapshot_from(path: &Path, deopt: &Deopt) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(path)?;
+        let version = serde_json::from_str::<serde_json::Value>(&content)?
+            .get("version")
+            .and_then(|v| v.as_u64());
+        if version != Some(OBSERVER_SNAPSHOT_VERSION as u64) {
+            eyre::bail!(
+                "Observer snapshot {path:?} has version {version:?}, expected {OBSERVER_SNAPSHOT_VERSION}."
+            );
+        }
+        let snapshot: ObserverSnapshot = serde_json::from_str(&content)?;
+        let mut observer = Observer::new(deopt);
+        observer.branches = snapshot.branches;
+        observer.api_coverage = snapshot.api_coverage;
+        observer.merge_api_triples(&snapshot.discovered_api_triples.into_iter().collect());
+        Ok(Some(observer))
+    }
+
     pub fn sync_from_previous(deopt: &mut Deopt) -> Result<Self> {
         let mut observer = Observer::new(deopt);
         deopt.load_programs_from_seeds()?;
@@ -302,6 +512,99 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_discovered_pair_num() {
+        let observer = Observer::new(&Deopt::default());
+        let triple = |a: &str, b: &str, c: &str| (a.to_string(), b.to_string(), c.to_string());
+        let triples: HashSet<(String, String, String)> =
+            [triple("a", "b", "c"), triple("b", "c", "d"), triple("a", "b", "d")].into();
+        observer.merge_api_triples(&triples);
+        // (a, b), (b, c), (c, d), (b, d)
+        assert_eq!(observer.get_discovered_pair_num(), 4);
+    }
+
+    #[test]
+    fn test_diff_pairs() -> Result<()> {
+        let observer = Observer::new(&Deopt::default());
+        let triple = |a: &str, b: &str, c: &str| (a.to_string(), b.to_string(), c.to_string());
+        let pair = |a: &str, b: &str| (a.to_string(), b.to_string());
+        observer.merge_api_triples(&[triple("a", "b", "c")].into());
+        // pairs are ordered, so (c, b) differs from (b, c).
+        let other: HashSet<(String, String)> = [pair("b", "c"), pair("c", "b")].into();
+        let (only_self, only_other, common) = observer.diff_pairs(&other);
+        assert_eq!(only_self, vec![pair("a", "b")]);
+        assert_eq!(only_other, vec![pair("c", "b")]);
+        assert_eq!(common, vec![pair("b", "c")]);
+
+        let path = std::env::temp_dir().join("prompt_fuzz_pair_set.json");
+        std::fs::write(&path, r#"[["c", "b"], ["b", "c"]]"#)?;
+        assert_eq!(load_pair_set(&path)?, other);
+        observer.save_snapshot_to(&path)?;
+        assert_eq!(load_pair_set(&path)?, observer.get_discovered_pairs());
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_pairs_to_dot() -> Result<()> {
+        let triple = |a: &str, b: &str, c: &str| (a.to_string(), b.to_string(), c.to_string());
+        let observer = Observer::new(&Deopt::default());
+        observer.merge_api_triples(&[triple("a", "b", "c"), triple("a", "b", "d")].into());
+        let path = std::env::temp_dir().join("prompt_fuzz_pairs_graph.json");
+        observer.save_snapshot_to(&path)?;
+        let counts = load_pair_counts(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(
+            pairs_to_dot(&counts),
+            "digraph api_pairs {\n    \"a\";\n    \"b\";\n    \"c\";\n    \"d\";\n    \
+             \"a\" -> \"b\" [weight=2, label=\"2\"];\n    \
+             \"b\" -> \"c\" [weight=1, label=\"1\"];\n    \
+             \"b\" -> \"d\" [weight=1, label=\"1\"];\n}\n"
+        );
+        let quoted = HashMap::from([(("ns::\"f\"".to_string(), "g".to_string()), 1)]);
+        assert!(pairs_to_dot(&quoted).contains("\"ns::\\\"f\\\"\" -> \"g\""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pause_handle() {
+        let observer = Observer::new(&Deopt::default());
+        let handle = observer.pause_handle();
+        {
+            let paused = observer.pause_handle();
+            let _guard = paused.write().unwrap();
+            assert!(handle.try_read().is_err());
+        }
+        assert!(handle.try_read().is_ok());
+    }
+
+    #[test]
+    fn test_observer_snapshot() -> Result<()> {
+        let deopt = Deopt::default();
+        let mut observer = Observer::new(&deopt);
+        observer.api_coverage.insert("inflate".to_string(), 0.5);
+        let triples: HashSet<(String, String, String)> =
+            [("a".to_string(), "b".to_string(), "c".to_string())].into();
+        observer.merge_api_triples(&triples);
+
+        let path = std::env::temp_dir().join("prompt_fuzz_observer_snapshot.json");
+        let _ = std::fs::remove_file(&path);
+        assert!(Observer::load_snapshot_from(&path, &deopt)?.is_none());
+        observer.save_snapshot_to(&path)?;
+        let loaded = Observer::load_snapshot_from(&path, &deopt)?.unwrap();
+        assert_eq!(loaded.api_coverage, observer.api_coverage);
+        assert_eq!(*loaded.discovered_api_triples.read().unwrap(), triples);
+
+        // a snapshot of another format version is detected.
+        let content = std::fs::read_to_string(&path)?;
+        let mut snapshot: serde_json::Value = serde_json::from_str(&content)?;
+        snapshot["version"] = serde_json::json!(OBSERVER_SNAPSHOT_VERSION + 1);
+        std::fs::write(&path, snapshot.to_string())?;
+        assert!(Observer::load_snapshot_from(&path, &deopt).is_err());
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
     #[test]
     fn test_observe_api_coverage() -> Result<()> {
         crate::config::Config::init_test("zlib");
@@ -1,13 +1,19 @@
-use std::{collections::HashMap, collections::HashSet, f32::consts::E};
+use std::{collections::HashMap, collections::HashSet, collections::VecDeque, f32::consts::E};
+use std::path::Path;
 
 use petgraph::algo;
 
+use super::embedding::ApiEmbeddings;
 use crate::{
     deopt::Deopt, minimize, mutation::mutate_prompt, program::{
-        gadget::{FuncGadget, get_func_gadget, get_func_gadgets},
-        get_exec_counter_value, load_exec_counter,
+        gadget::{
+            get_func_gadget, get_selectable_func_gadgets, is_banned_func,
+            FuncGadget,
+        },
+        get_exec_counter_value, get_failure_counter_value, get_success_counter_value,
+        load_exec_counter, load_failure_counter, load_success_counter,
         rand::{prob_coin, rand_comb_len, weighted_choose},
-        set_exec_counter_value,
+        set_exec_counter_value, set_failure_counter_value, set_success_counter_value,
     }, request::prompt::{
         Prompt, get_prompt_counter_value, load_prompt_counter, set_prompt_counter_value
     }
@@ -18,6 +24,10 @@ pub struct Seed {
     coverage: f32,
     exec_count: u32,
     prompt_count: u32,
+    /// The number of the validated programs calling this API.
+    success_count: u32,
+    /// The number of the failed programs calling this API.
+    failure_count: u32,
     energy: f32,
     sampling_weight: f32,
 }
@@ -35,6 +45,8 @@ impl Seed {
             coverage,
             exec_count,
             prompt_count,
+            success_count: 0,
+            failure_count: 0,
             energy: 0_f32,
             sampling_weight: 0_f32,
         };
@@ -47,6 +59,8 @@ impl Seed {
             coverage: 0.0,   // In API mode, coverage is not used
             exec_count: 0,   // In API mode, exec_count is not used
             prompt_count: 0, // In API mode, prompt_count is not used
+            success_count: 0,
+            failure_count: 0,
             energy: 1.0, // Assign a base energy value to all APIs to ensure they have a chance to be selected
             sampling_weight: 1_f32,
         }
@@ -60,6 +74,20 @@ impl Seed {
         self.energy = energy;
         energy
     }
+
+    /// The smoothed rate of the programs calling this API that passed the validation. It is 0.5 for an API never called.
+    pub fn success_rate(&self) -> f32 {
+        (self.success_count + 1) as f32 / (self.success_count + self.failure_count + 2) as f32
+    }
+
+    /// Down-weight the energy by `success_rate ^ weight`, so that the APIs the model cannot use correctly are
+    /// chosen less. A weight of 0 leaves the energy unchanged.
+    pub fn weight_by_success(&mut self, success_count: u32, failure_count: u32, weight: f32) -> f32 {
+        self.success_count = success_count;
+        self.failure_count = failure_count;
+        self.energy *= self.success_rate().powf(weight);
+        self.energy
+    }
 }
 
 fn sigmoid_function(succ_rate: f32, threshold: f32, steepness: f32) -> f32 {
@@ -79,10 +107,37 @@ fn should_deterministic_mutate(deopt: &Deopt) -> bool {
     coin
 }
 
+/// Number of recent combinations that the diversity is measured against.
+const DIVERSITY_WINDOW: usize = 5;
+
+/// Jaccard distance between two API sets: 0 for the same set, 1 for disjoint sets.
+pub fn jaccard_distance(a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0_f32;
+    }
+    1_f32 - a.intersection(b).count() as f32 / union as f32
+}
+
 pub struct Schedule {
     seeds: HashMap<String, Seed>,
     exponent: u32,
-    pub loop_count:u32
+    pub loop_count:u32,
+    /// the exponent of the success rate in the energy, see `--success-weight`.
+    success_weight: f32,
+    /// APIs that have never been put in a prompt in API mode.
+    unexplored: HashSet<String>,
+    /// bonus added to the sampling weight of the unexplored APIs.
+    exploration_bonus: f32,
+    /// API sets of the last `DIVERSITY_WINDOW` combinations.
+    recent_combinations: VecDeque<HashSet<String>>,
+    /// mean Jaccard distance of the current combination from the recent ones.
+    diversity: f32,
+    diversity_threshold: Option<f32>,
+    /// consecutive rounds whose diversity is below `diversity_threshold`.
+    low_diversity_round: usize,
+    /// the API embeddings of `--comb-strategy semantic`, which bias the combination toward related APIs.
+    embeddings: Option<ApiEmbeddings>,
 }
 
 impl Default for Schedule {
@@ -97,11 +152,147 @@ impl Schedule {
             seeds: HashMap::new(),
             exponent: 1,
             loop_count: 0,
+            success_weight: 0_f32,
+            unexplored: HashSet::new(),
+            exploration_bonus: 0_f32,
+            recent_combinations: VecDeque::new(),
+            diversity: 1_f32,
+            diversity_threshold: None,
+            low_diversity_round: 0,
+            embeddings: None,
         }
     }
+
+    pub fn set_embeddings(&mut self, embeddings: ApiEmbeddings) {
+        self.embeddings = Some(embeddings);
+    }
+
+    pub fn set_diversity_threshold(&mut self, threshold: Option<f32>) {
+        self.diversity_threshold = threshold;
+    }
+
+    /// Measure how different the combination is from the recent ones, then push it to the window.
+    pub fn record_combination<'a>(&mut self, apis: impl IntoIterator<Item = &'a str>) -> f32 {
+        let current: HashSet<String> = apis.into_iter().map(|api| api.to_string()).collect();
+        self.diversity = if self.recent_combinations.is_empty() {
+            1_f32
+        } else {
+            self.recent_combinations
+                .iter()
+                .map(|recent| jaccard_distance(&current, recent))
+                .sum::<f32>()
+                / self.recent_combinations.len() as f32
+        };
+        if self.diversity_threshold.is_some_and(|threshold| self.diversity < threshold) {
+            self.low_diversity_round += 1;
+        } else {
+            self.low_diversity_round = 0;
+        }
+        self.recent_combinations.push_back(current);
+        if self.recent_combinations.len() > DIVERSITY_WINDOW {
+            self.recent_combinations.pop_front();
+        }
+        self.diversity
+    }
+
+    pub fn get_diversity(&self) -> f32 {
+        self.diversity
+    }
+
+    pub fn get_low_diversity_round(&self) -> usize {
+        self.low_diversity_round
+    }
+
+    /// Forget the recent combinations, typically after the prompt is hard reset.
+    pub fn reset_diversity(&mut self) {
+        self.recent_combinations.clear();
+        self.diversity = 1_f32;
+        self.low_diversity_round = 0;
+    }
+
+    pub fn set_success_weight(&mut self, weight: f32) {
+        self.success_weight = weight;
+    }
+
+    pub fn set_exploration_bonus(&mut self, bonus: f32) {
+        self.exploration_bonus = bonus;
+    }
+
+    /// Remove the APIs that have been put in a prompt from the unexplored set.
+    pub fn mark_explored<'a>(&mut self, apis: impl IntoIterator<Item = &'a str>) {
+        for api in apis {
+            self.unexplored.remove(api);
+        }
+    }
+
+    pub fn get_unexplored_num(&self) -> usize {
+        self.unexplored.len()
+    }
     pub fn increment_loop(&mut self) {
         self.loop_count += 1;
     }
+    /// The `n` APIs of the highest energy, in descending order.
+    pub fn top_energy_apis(&self, n: usize) -> Vec<(String, f32)> {
+        let mut apis: Vec<(String, f32)> = self
+            .seeds
+            .values()
+            .map(|seed| (seed.name.clone(), seed.energy))
+            .collect();
+        apis.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        apis.truncate(n);
+        apis
+    }
+
+    /// The energy and the sampling weight of each API, sorted by the API name.
+    pub fn get_energies(&self) -> Vec<(String, f32, f32)> {
+        let mut energies: Vec<(String, f32, f32)> = self
+            .seeds
+            .values()
+            .map(|seed| (seed.name.clone(), seed.energy, seed.sampling_weight))
+            .collect();
+        energies.sort_by(|a, b| a.0.cmp(&b.0));
+        energies
+    }
+
+    /// Restore the energies saved by `get_energies`. The APIs that are not in the schedule are skipped.
+    /// Returns the number of restored APIs.
+    pub fn restore_energies(&mut self, energies: &[(String, f32, f32)]) -> usize {
+        let mut restored = 0;
+        for (name, energy, sampling_weight) in energies {
+            if let Some(seed) = self.seeds.get_mut(name) {
+                seed.energy = *energy;
+                seed.sampling_weight = *sampling_weight;
+                restored += 1;
+            }
+        }
+        restored
+    }
+
+    /// A table of each API's coverage, exec count, prompt count, energy, sampling weight and success rate, sorted
+    /// by the energy in descending order.
+    pub fn describe(&self) -> String {
+        let mut seeds: Vec<&Seed> = self.seeds.values().collect();
+        seeds.sort_by(|a, b| b.energy.total_cmp(&a.energy).then_with(|| a.name.cmp(&b.name)));
+        let width = seeds.iter().map(|seed| seed.name.len()).max().unwrap_or(0).max(3);
+        let mut table = format!(
+            "{:<width$} {:>8} {:>10} {:>12} {:>10} {:>10} {:>8}\n",
+            "API", "coverage", "exec_count", "prompt_count", "energy", "weight", "success"
+        );
+        for seed in seeds {
+            table.push_str(&format!(
+                "{:<width$} {:>8.4} {:>10} {:>12} {:>10.6} {:>10.6} {:>8.4}\n",
+                seed.name,
+                seed.coverage,
+                seed.exec_count,
+                seed.prompt_count,
+                seed.energy,
+                seed.sampling_weight,
+                seed.success_rate()
+            ));
+        }
+        table
+    }
+
     pub fn get_seed_by_name(&self, name: &str) -> Option<&Seed> {
         self.seeds.get(name)
     }
@@ -112,6 +303,12 @@ impl Schedule {
         for (key, value) in e_counter {
             set_exec_counter_value(key, value);
         }
+        for (key, value) in load_success_counter(deopt) {
+            set_success_counter_value(key, value);
+        }
+        for (key, value) in load_failure_counter(deopt) {
+            set_failure_counter_value(key, value);
+        }
         for (key, value) in p_counter {
             set_prompt_counter_value(key, value);
         }
@@ -119,21 +316,30 @@ impl Schedule {
     //initial the energies for API mode
     pub fn initialize_energies_for_api_mode(&mut self) {
         self.seeds.clear();
-        for gadget in get_func_gadgets() {
+        self.unexplored.clear();
+        for gadget in get_selectable_func_gadgets() {
             let api_name = gadget.get_func_name();
             let seed = Seed::new_for_api_mode(api_name);
             self.seeds.insert(api_name.to_string(), seed);
+            if get_prompt_counter_value(api_name).unwrap_or(0) == 0 {
+                self.unexplored.insert(api_name.to_string());
+            }
         }
     }
     // Compute the energy for each library API. The high energy means the high probablity to be choosed in prompt.
     pub fn update_energies(&mut self, api_coverage: &HashMap<String, f32>) {
         self.seeds.clear();
-        for gadget in get_func_gadgets() {
+        for gadget in get_selectable_func_gadgets() {
             let api_name = gadget.get_func_name();
             let coverage = api_coverage.get(api_name).unwrap();
             let prompt_count = get_prompt_counter_value(api_name).unwrap_or(0);
             let exec_count = get_exec_counter_value(api_name).unwrap_or(0);
-            let seed = Seed::new(api_name, *coverage, exec_count, prompt_count, self.exponent);
+            let mut seed = Seed::new(api_name, *coverage, exec_count, prompt_count, self.exponent);
+            seed.weight_by_success(
+                get_success_counter_value(api_name).unwrap_or(0),
+                get_failure_counter_value(api_name).unwrap_or(0),
+                self.success_weight,
+            );
             self.seeds.insert(api_name.to_string(), seed);
         }
         let energies_str: Vec<f32> = self.seeds.values().map(|x| x.energy).collect();
@@ -228,19 +434,23 @@ impl Schedule {
             serde_json::to_string(&sample_str).unwrap()
         );
     }
-    pub fn update_prompt(&self, prompt: &mut Prompt, deopt: &mut Deopt) -> eyre::Result<()> {
+    pub fn update_prompt(&mut self, prompt: &mut Prompt, deopt: &mut Deopt) -> eyre::Result<()> {
         if should_deterministic_mutate(deopt) {
             mutate_prompt(prompt, self, deopt);
         } else {
             let combination = self.assemble_high_energy_combiantion();
             prompt.set_combination(combination);
         }
+        self.record_combination(prompt.gadgets.iter().map(|gadget| gadget.get_func_name()));
         Ok(())
     }
 
-    pub fn update_prompt_for_api_mode(&self, prompt: &mut Prompt) -> eyre::Result<()> {
+    pub fn update_prompt_for_api_mode(&mut self, prompt: &mut Prompt) -> eyre::Result<()> {
         let combination = self.assemble_high_energy_combiantion();
+        self.mark_explored(combination.iter().map(|gadget| gadget.get_func_name()));
         prompt.set_combination(combination);
+        self.record_combination(prompt.gadgets.iter().map(|gadget| gadget.get_func_name()));
+        log::info!("{} APIs remain unexplored.", self.unexplored.len());
         Ok(())
     }
 
@@ -265,32 +475,75 @@ impl Schedule {
         false
     }
 
-    pub fn assemble_high_energy_combiantion(&self) -> Vec<&'static FuncGadget> {
+    pub fn assemble_high_energy_combiantion(&mut self) -> Vec<&'static FuncGadget> {
+        self.assemble_combination_by(get_func_gadget, is_banned_func)
+    }
+
+    /// Assemble a combination of the APIs chosen by energy, whose gadgets are found by `lookup`. The APIs without
+    /// gadgets, e.g., stale after a change of the ban list, are dropped from the schedule and others are chosen.
+    fn assemble_combination_by(
+        &mut self,
+        lookup: impl Fn(&str) -> Option<&'static FuncGadget>,
+        is_banned: impl Fn(&str) -> bool,
+    ) -> Vec<&'static FuncGadget> {
         log::info!("random assemble new prompt combination with their engies.");
-        let len = rand_comb_len();
-        let mut comb: Vec<&str> = Vec::new();
+        // the combination cannot be longer than the available APIs.
+        let available = |seeds: &HashMap<String, Seed>| seeds.keys().filter(|api| !is_banned(api)).count();
+        let mut len = rand_comb_len().min(available(&self.seeds));
+        let mut comb: Vec<String> = Vec::new();
         let mut gadgets = Vec::new();
         while comb.len() < len {
-            let api = self.choose_api_by_energy();
-            if comb.contains(&api) {
+            // once a seed API is chosen, prefer the APIs near to the chosen ones.
+            let api = match &self.embeddings {
+                Some(embeddings) if !comb.is_empty() => {
+                    let chosen: Vec<&str> = comb.iter().map(|api| api.as_str()).collect();
+                    self.choose_api_near(embeddings, &chosen)
+                }
+                _ => self.choose_api_by_energy(),
+            }
+            .to_string();
+            if comb.contains(&api) || is_banned(&api) {
                 continue;
             }
+            let Some(gadget) = lookup(&api) else {
+                log::warn!("Cannot find the gadget of {api}, drop it from the schedule.");
+                self.seeds.remove(&api);
+                self.unexplored.remove(&api);
+                len = len.min(available(&self.seeds));
+                continue;
+            };
             // 打印本次选中的API及其能量
-            if let Some(seed) = self.seeds.get(api) {
+            if let Some(seed) = self.seeds.get(&api) {
                 log::info!("choose api: {} energy: {}", api, seed.energy);
             }
             comb.push(api);
-            let gadget =
-                get_func_gadget(api).unwrap_or_else(|| panic!("cannot found api {api} in gadgets"));
             gadgets.push(gadget);
         }
 
         gadgets
     }
 
+    fn get_sampling_weight(&self, seed: &Seed) -> f32 {
+        if self.unexplored.contains(&seed.name) {
+            seed.sampling_weight + self.exploration_bonus
+        } else {
+            seed.sampling_weight
+        }
+    }
+
+    /// Choose an API by its energy, scaled by its similarity to the `chosen` APIs.
+    fn choose_api_near(&self, embeddings: &ApiEmbeddings, chosen: &[&str]) -> &str {
+        let values: Vec<&Seed> = self.seeds.values().collect();
+        let weights: Vec<f32> = values
+            .iter()
+            .map(|x| self.get_sampling_weight(x) * embeddings.bias(&x.name, chosen))
+            .collect();
+        &values[weighted_choose(weights)].name
+    }
+
     pub fn choose_api_by_energy(&self) -> &str {
         let values: Vec<&Seed> = self.seeds.values().collect();
-        let energies: Vec<f32> = values.iter().map(|x| x.sampling_weight).collect();
+        let energies: Vec<f32> = values.iter().map(|x| self.get_sampling_weight(x)).collect();
         let choose = weighted_choose(energies);
         let mut choose_seed = values[choose];
         
@@ -331,11 +584,19 @@ impl Schedule {
 }
 
 pub fn rand_choose_combination(len: usize) -> Vec<&'static FuncGadget> {
-    let mut combination: Vec<&'static FuncGadget> = Vec::new();
-    let func_gagdets = get_func_gadgets();
+    rand_choose_combination_from(get_selectable_func_gadgets(), len)
+}
+
+/// Randomly choose `len` distinct gadgets from `func_gagdets`.
+pub fn rand_choose_combination_from<'a>(
+    func_gagdets: &[&'a FuncGadget],
+    len: usize,
+) -> Vec<&'a FuncGadget> {
+    let mut combination: Vec<&FuncGadget> = Vec::new();
+    let len = len.min(func_gagdets.len());
     while combination.len() < len {
         let idx: usize = rand::random::<usize>() % func_gagdets.len();
-        let gadget = &func_gagdets[idx];
+        let gadget = func_gagdets[idx];
         if combination
             .iter()
             .any(|x| x.get_func_name() == gadget.get_func_name())
@@ -346,3 +607,319 @@ pub fn rand_choose_combination(len: usize) -> Vec<&'static FuncGadget> {
     }
     combination
 }
+
+/// Enumerate the `len`-combinations of `total` gadgets in the lexicographic order of their indices.
+/// It is saved after each round to be resumed by `--resume`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CombinationEnumerator {
+    len: usize,
+    total: usize,
+    /// The indices of the next combination, None once all are enumerated.
+    next: Option<Vec<usize>>,
+}
+
+impl CombinationEnumerator {
+    pub fn new(len: usize, total: usize) -> Self {
+        let len = len.min(total);
+        let next = (len > 0).then(|| (0..len).collect());
+        Self { len, total, next }
+    }
+
+    /// Resume the enumerator from `path` if it enumerates the same combinations, otherwise start a new one.
+    pub fn load_or_new(path: &Path, len: usize, total: usize) -> Self {
+        let new = Self::new(len, total);
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return new;
+        };
+        match serde_json::from_str::<Self>(&content) {
+            Ok(saved) if saved.len == new.len && saved.total == new.total => {
+                log::info!("Resume the combination enumeration from {path:?}.");
+                saved
+            }
+            _ => {
+                log::warn!("Cannot resume the combination enumeration from {path:?}, restart it.");
+                new
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> eyre::Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// The indices of the next combination, then advance to the one after it.
+    pub fn next_indices(&mut self) -> Option<Vec<usize>> {
+        let current = self.next.take()?;
+        let mut indices = current.clone();
+        // find the rightmost index that can be increased.
+        let mut i = self.len;
+        while i > 0 && indices[i - 1] == self.total - self.len + i - 1 {
+            i -= 1;
+        }
+        if i > 0 {
+            indices[i - 1] += 1;
+            for j in i..self.len {
+                indices[j] = indices[j - 1] + 1;
+            }
+            self.next = Some(indices);
+        }
+        Some(current)
+    }
+
+    /// The next combination of the selectable gadgets.
+    pub fn next_combination(&mut self) -> Option<Vec<&'static FuncGadget>> {
+        let gadgets = get_selectable_func_gadgets();
+        self.next_indices()
+            .map(|indices| indices.iter().map(|idx| gadgets[*idx]).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::gadget::ApiFilter;
+
+    #[test]
+    fn test_enumerate_combinations() -> eyre::Result<()> {
+        let mut enumerator = CombinationEnumerator::new(2, 4);
+        let mut combinations = Vec::new();
+        for _ in 0..3 {
+            combinations.push(enumerator.next_indices().unwrap());
+        }
+        let path = std::env::temp_dir().join("prompt_fuzz_comb_enumerator.json");
+        enumerator.save(&path)?;
+        // resume from the saved state.
+        let mut enumerator = CombinationEnumerator::load_or_new(&path, 2, 4);
+        while let Some(indices) = enumerator.next_indices() {
+            combinations.push(indices);
+        }
+        let expected: Vec<Vec<usize>> =
+            vec![vec![0, 1], vec![0, 2], vec![0, 3], vec![1, 2], vec![1, 3], vec![2, 3]];
+        assert_eq!(combinations, expected);
+        // a different enumeration restarts.
+        let mut enumerator = CombinationEnumerator::load_or_new(&path, 3, 4);
+        assert_eq!(enumerator.next_indices(), Some(vec![0, 1, 2]));
+        std::fs::remove_file(&path)?;
+
+        let mut enumerator = CombinationEnumerator::new(5, 2);
+        assert_eq!(enumerator.next_indices(), Some(vec![0, 1]));
+        assert_eq!(enumerator.next_indices(), None);
+        assert_eq!(CombinationEnumerator::new(2, 0).next_indices(), None);
+        Ok(())
+    }
+
+    fn void_gadgets(names: &[&str]) -> Vec<FuncGadget> {
+        names
+            .iter()
+            .map(|name| {
+                FuncGadget::new(
+                    name.to_string(),
+                    vec![],
+                    vec![],
+                    vec![],
+                    "void".to_string(),
+                    "void".to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_banned_api_never_selected() {
+        let gadgets = void_gadgets(&[
+            "cJSON_Parse",
+            "cJSON_Print",
+            "cJSON_Delete",
+            "cJSON_InitHooks",
+            "cJSON_free",
+            "cJSON_malloc",
+        ]);
+        let filter = ApiFilter::new(
+            &Some(vec![
+                "cJSON_InitHooks".to_string(),
+                "^cJSON_(malloc|free)$".to_string(),
+            ]),
+            None,
+            &[],
+        );
+        let selectable = filter.filter(&gadgets);
+        assert_eq!(selectable.len(), 3);
+        for _ in 0..100 {
+            let combination = rand_choose_combination_from(&selectable, 5);
+            assert_eq!(combination.len(), 3);
+            for gadget in combination {
+                assert!(filter.is_selectable(gadget.get_func_name()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_excluded_api_never_selected() {
+        let gadgets = void_gadgets(&["cJSON_Parse", "cJSON_Print", "cJSON_Delete", "cJSON_InitHooks"]);
+        let filter = ApiFilter::new(
+            &Some(vec!["cJSON_InitHooks".to_string()]),
+            None,
+            &["cJSON_Print".to_string(), "cJSON_Missing".to_string()],
+        );
+        assert_eq!(filter.unknown_apis(&gadgets), vec!["cJSON_Missing"]);
+        let selectable = filter.filter(&gadgets);
+        for _ in 0..100 {
+            let combination = rand_choose_combination_from(&selectable, 5);
+            let mut names: Vec<&str> = combination.iter().map(|gadget| gadget.get_func_name()).collect();
+            names.sort();
+            assert_eq!(names, vec!["cJSON_Delete", "cJSON_Parse"]);
+        }
+    }
+
+    #[test]
+    fn test_combination_from_allowlist() {
+        let gadgets = void_gadgets(&["cJSON_Parse", "cJSON_Print", "cJSON_Delete", "cJSON_Minify", "cJSON_free"]);
+        let include: Vec<String> = ["cJSON_Parse", "cJSON_Print", "cJSON_Delete", "cJSON_free", "cJSON_Missing"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let filter = ApiFilter::new(
+            &Some(vec!["^cJSON_free$".to_string()]),
+            Some(&include),
+            &["cJSON_Delete".to_string()],
+        );
+        assert_eq!(filter.unknown_included_apis(&gadgets), vec!["cJSON_Missing"]);
+        // the exclusion is applied after the inclusion.
+        let selectable = filter.filter(&gadgets);
+        for _ in 0..100 {
+            for gadget in rand_choose_combination_from(&selectable, 3) {
+                let name = gadget.get_func_name();
+                assert!(name == "cJSON_Parse" || name == "cJSON_Print", "{name} is not allowed");
+            }
+        }
+        assert!(ApiFilter::new(&None, None, &[]).unknown_included_apis(&gadgets).is_empty());
+    }
+
+    #[test]
+    fn test_assemble_skips_stale_api() {
+        let gadgets: &'static [FuncGadget] = Vec::leak(void_gadgets(&["compress", "crc32"]));
+        let lookup = |api: &str| gadgets.iter().find(|gadget| gadget.get_func_name() == api);
+        let mut schedule = Schedule::new();
+        for name in ["compress", "crc32", "stale_api"] {
+            schedule.seeds.insert(name.to_string(), Seed::new_for_api_mode(name));
+        }
+        schedule.unexplored.insert("stale_api".to_string());
+        for _ in 0..50 {
+            let combination = schedule.assemble_combination_by(lookup, |_| false);
+            assert!(!combination.is_empty() && combination.len() <= 2);
+            assert!(combination.iter().all(|gadget| gadget.get_func_name() != "stale_api"));
+        }
+        // the stale API is chosen sooner or later, and dropped.
+        assert!(schedule.get_seed_by_name("stale_api").is_none());
+        assert_eq!(schedule.get_unexplored_num(), 0);
+
+        // nothing left to choose from.
+        let mut schedule = Schedule::new();
+        schedule.seeds.insert("stale_api".to_string(), Seed::new_for_api_mode("stale_api"));
+        assert!(schedule.assemble_combination_by(lookup, |_| false).is_empty());
+    }
+
+    #[test]
+    fn test_assemble_with_too_few_apis() {
+        let gadgets: &'static [FuncGadget] = Vec::leak(void_gadgets(&["compress", "crc32", "inflate"]));
+        let lookup = |api: &str| gadgets.iter().find(|gadget| gadget.get_func_name() == api);
+        let mut schedule = Schedule::new();
+        for gadget in gadgets {
+            let name = gadget.get_func_name();
+            schedule.seeds.insert(name.to_string(), Seed::new_for_api_mode(name));
+        }
+        // a single API is left after the ban list, shorter than any combination length.
+        let is_banned = |api: &str| api != "crc32";
+        for _ in 0..20 {
+            let combination = schedule.assemble_combination_by(lookup, is_banned);
+            let names: Vec<&str> = combination.iter().map(|gadget| gadget.get_func_name()).collect();
+            assert_eq!(names, vec!["crc32"]);
+        }
+        assert!(schedule.assemble_combination_by(lookup, |_| true).is_empty());
+    }
+
+    #[test]
+    fn test_describe_schedule() {
+        let mut schedule = Schedule::new();
+        for (name, coverage, exec_count) in [("inflate", 0.5, 3), ("compress", 0.2, 0), ("crc32", 1.0, 9)] {
+            schedule
+                .seeds
+                .insert(name.to_string(), Seed::new(name, coverage, exec_count, 1, 1));
+        }
+        let table = schedule.describe();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("API "));
+        let names: Vec<&str> = lines[1..].iter().map(|line| line.split_whitespace().next().unwrap()).collect();
+        assert_eq!(names, ["compress", "inflate", "crc32"]);
+        assert_eq!(
+            lines[1].split_whitespace().collect::<Vec<_>>(),
+            ["compress", "0.2000", "0", "1", "0.400000", "0.000000", "0.5000"]
+        );
+    }
+
+    #[test]
+    fn test_success_weight() {
+        let mut seed = Seed::new("inflate", 0.5, 2, 1, 1);
+        let energy = seed.energy;
+        // the exec count is not a count of the validated programs.
+        assert_eq!(seed.success_rate(), 0.5);
+        // no weight, the outcomes are only recorded.
+        assert_eq!(seed.weight_by_success(2, 0, 0_f32), energy);
+        assert_eq!(seed.success_rate(), 0.75);
+        assert_eq!(seed.weight_by_success(2, 6, 0_f32), energy);
+        assert_eq!(seed.success_rate(), 0.3);
+
+        let mut reliable = Seed::new("inflate", 0.5, 2, 1, 1);
+        let mut unreliable = Seed::new("inflate", 0.5, 2, 1, 1);
+        reliable.weight_by_success(2, 0, 2_f32);
+        unreliable.weight_by_success(2, 6, 2_f32);
+        assert!((reliable.energy - energy * 0.5625).abs() < 1e-6);
+        assert!((unreliable.energy - energy * 0.09).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_exploration_bonus() {
+        let mut schedule = Schedule::new();
+        for name in ["explored", "unexplored"] {
+            schedule.seeds.insert(name.to_string(), Seed::new_for_api_mode(name));
+        }
+        schedule.unexplored.insert("unexplored".to_string());
+        schedule.set_exploration_bonus(9_f32);
+        let count_unexplored = |schedule: &Schedule| {
+            (0..1000).filter(|_| schedule.choose_api_by_energy() == "unexplored").count()
+        };
+        assert!(count_unexplored(&schedule) > 800);
+
+        // no bonus once it is selected.
+        schedule.mark_explored(["unexplored"]);
+        assert_eq!(schedule.get_unexplored_num(), 0);
+        let count = count_unexplored(&schedule);
+        assert!(count > 350 && count < 650, "{count}");
+    }
+
+    #[test]
+    fn test_combination_diversity() {
+        let set = |apis: &[&str]| apis.iter().map(|api| api.to_string()).collect();
+        assert_eq!(jaccard_distance(&set(&["a", "b"]), &set(&["b", "a"])), 0_f32);
+        assert_eq!(jaccard_distance(&set(&["a", "b"]), &set(&["c"])), 1_f32);
+        let distance = jaccard_distance(&set(&["a", "b"]), &set(&["b", "c"]));
+        assert!((distance - 2_f32 / 3_f32).abs() < 1e-6);
+
+        let mut schedule = Schedule::new();
+        schedule.set_diversity_threshold(Some(0.5));
+        assert_eq!(schedule.record_combination(["a", "b"]), 1_f32);
+        assert_eq!(schedule.record_combination(["c", "d"]), 1_f32);
+        assert_eq!(schedule.get_low_diversity_round(), 0);
+        // distance 0 from ["a", "b"] and 1 from ["c", "d"].
+        assert_eq!(schedule.record_combination(["a", "b"]), 0.5);
+        for _ in 0..DIVERSITY_WINDOW {
+            schedule.record_combination(["a", "b"]);
+        }
+        assert_eq!(schedule.get_diversity(), 0_f32);
+        assert_eq!(schedule.get_low_diversity_round(), DIVERSITY_WINDOW);
+        schedule.reset_diversity();
+        assert_eq!(schedule.get_low_diversity_round(), 0);
+    }
+}
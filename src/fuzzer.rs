@@ -1,26 +1,156 @@
 use crate::{
-    config::{self, get_config, get_handler_type, get_library_name, HandlerType},
+    config::{self, get_config, get_handler_type, get_library_name, CombStrategy, HandlerType},
     deopt::Deopt,
     execution::{
-        logger::{init_gtl, ProgramLogger},
+        logger::{init_gtl, ProgramError, ProgramLogger},
         Executor,
     },
     feedback::{
-        observer::Observer,
-        schedule::{rand_choose_combination, Schedule},
+        embedding::ApiEmbeddings,
+        observer::{pairs_of_triples, Observer},
+        schedule::{rand_choose_combination, CombinationEnumerator, Schedule},
     },
     minimize::minimize,
-    program::{libfuzzer::LibFuzzer, rand::rand_comb_len, serde::Deserializer, Program},
-    request::{self, prompt::Prompt},
+    program::{
+        gadget::{ctype, get_selectable_func_gadgets, is_type_compatible, ApiSignature},
+        judge::{HeuristicJudge, Judge},
+        libfuzzer::LibFuzzer,
+        rand::rand_comb_len,
+        serde::Deserializer, Program, record_program_outcome,
+    },
+    request::{
+        self,
+        prompt::{save_prompt_counter, set_prompt_counter_value, ProgramTask, Prompt},
+        transcript::TranscriptRecorder,
+        GenerationResult,
+    },
+    checkpoint::{should_checkpoint, Checkpoint},
     cntg_program::seed_metas::SeedMetas,
+    summary::FuzzSummary,
 };
-use tree_sitter::{Parser, TreeCursor};
 
 use eyre::Result;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
+use crate::progress::ProgressBar;
 use std::time::{Duration, Instant};
 use std::option::Option;
+use std::path::{Path, PathBuf};
+
+/// The states restored from the log of a previous execution.
+pub struct ResumeState {
+    pub seed_id: usize,
+    pub quiet_round: usize,
+    pub logger: ProgramLogger,
+}
+
+impl ResumeState {
+    pub fn from_log(log_str: &str) -> Result<Self> {
+        let logger = ProgramLogger::snyc_from_str(log_str)?;
+        let mut quiet_round = 0;
+        // the last loop record is the most recent state.
+        if let Some(idx) = log_str.rfind("[Mutate Loop]: loop:") {
+            let mut de = Deserializer::from_input(&log_str[idx..]);
+            de.consume_token_until("quiet_round:")?;
+            quiet_round = de.parse_number::<usize>()?;
+        }
+        Ok(Self {
+            seed_id: logger.get_gc_total(),
+            quiet_round,
+            logger,
+        })
+    }
+}
+
+/// Reject the program if the judge scores it below `--judge-threshold`.
+fn check_program_judged(judge: Option<&dyn Judge>, program: &Program) -> Option<ProgramError> {
+    let (judge, threshold) = (judge?, get_config().judge_threshold?);
+    let score = judge.score(program);
+    (score < threshold).then(|| {
+        ProgramError::Judged(format!(
+            "The judge scored {score:.2}, below --judge-threshold {threshold:.2}"
+        ))
+    })
+}
+
+/// Reject the program if it is longer than `--max-program-len`.
+fn check_program_len(program: &Program, limit: Option<usize>) -> Option<ProgramError> {
+    let limit = limit?;
+    let line_num = program.get_line_num();
+    if line_num > limit {
+        return Some(ProgramError::TooLong(format!(
+            "The program has {line_num} lines, exceeding the limit of {limit} lines."
+        )));
+    }
+    None
+}
+
+/// The backoff before retrying the `empty`-th consecutive generation without any program.
+fn empty_generation_backoff(empty: usize) -> Duration {
+    Duration::from_secs(1 << empty.saturating_sub(1).min(4))
+}
+
+/// Find the log of the most recent prior execution in `output_dir`. The newest log belongs to the current execution.
+pub fn find_previous_log(output_dir: &Path) -> Result<Option<PathBuf>> {
+    let mut logs = find_fuzzer_logs(output_dir)?;
+    logs.pop();
+    Ok(logs.pop())
+}
+
+/// The log of the current execution, i.e., the latest one.
+pub fn find_current_log(output_dir: &Path) -> Result<Option<PathBuf>> {
+    Ok(find_fuzzer_logs(output_dir)?.pop())
+}
+
+fn find_fuzzer_logs(output_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut logs: Vec<PathBuf> = std::fs::read_dir(output_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path.extension().is_some_and(|ext| ext == "log")
+                && path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with("fuzzer_"))
+        })
+        .collect();
+    // the log names are timestamped.
+    logs.sort();
+    Ok(logs)
+}
+
+/// The arguments of the execution for `entry_point`: `--entry-points` is replaced by `--entry-point <entry_point>`.
+pub fn entry_point_args(args: &[String], entry_point: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--entry-points" {
+            args.next();
+        } else if !arg.starts_with("--entry-points=") {
+            out.push(arg.clone());
+        }
+    }
+    out.push("--entry-point".to_string());
+    out.push(entry_point.to_string());
+    out
+}
+
+/// Run an execution of the fuzzer for each entry point of `--entry-points` in turn. They run in child processes,
+/// as the counters, the gadgets and the coverage are global to a process.
+pub fn fuzz_entry_points() -> Result<()> {
+    let exe = std::env::current_exe()?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let entry_points = get_config().entry_points.clone();
+    for entry_point in entry_points {
+        log::info!("Synthesize the drivers of the entry point `{entry_point}`.");
+        let status = std::process::Command::new(&exe)
+            .args(entry_point_args(&args, &entry_point))
+            .status()?;
+        if !status.success() {
+            eyre::bail!("The execution of the entry point `{entry_point}` failed: {status}");
+        }
+    }
+    Ok(())
+}
 
 pub struct Fuzzer {
     pub deopt: Deopt,
@@ -30,16 +160,40 @@ pub struct Fuzzer {
     /// LLM model handler
     handler: Box<dyn request::Handler>,
     pub quiet_round: usize,
+    /// sampling temperature used for the requests of current round.
+    temperature: f32,
+    /// consecutive rounds that no program was generated successfully.
+    pub stuck_round: usize,
+    /// consecutive checked batches that every program failed for an infrastructure error.
+    infra_batches: usize,
+    /// the success ratio of the rounds, which `--auto-n-sample` adjusts the sample count by.
+    succ_ratio: request::SuccRatioTracker,
+    /// the transcripts of `--save-transcripts`.
+    transcripts: Option<TranscriptRecorder>,
+    /// the judge of `--judge-threshold`, which rates the API sequences before the validation.
+    judge: Option<Box<dyn Judge>>,
+    /// the background generation of the HTTP handler, see `Fuzzer::pipelined_generate`.
+    #[cfg(feature = "async")]
+    pipeline: Option<request::async_handler::GenerationPipeline<request::http::HttpHandler>>,
 }
 
 impl Fuzzer {
     /// create a new fuzzer
     pub fn new() -> Result<Self> {
-        let deopt = Deopt::new(get_library_name())?;
-        let executor = Executor::new(&deopt)?;
-        let observer = Observer::new(&deopt);
+        #[allow(unused_mut)]
+        let mut fuzzer = Self::with_handler(Self::default_handler())?;
+        #[cfg(feature = "async")]
+        if get_handler_type() == HandlerType::Http {
+            let handler = request::http::HttpHandler::new()?;
+            fuzzer.pipeline = Some(request::async_handler::GenerationPipeline::new(handler)?);
+        }
+        Ok(fuzzer)
+    }
+
+    /// The LLM handler chosen by `--handler`.
+    pub fn default_handler() -> Box<dyn request::Handler> {
         // 根据配置选择handler类型
-        let handler: Box<dyn request::Handler> = match get_handler_type() {
+        match get_handler_type() {
             HandlerType::Openai => {
                 log::info!("Using OpenAI handler");
                 Box::<request::openai::OpenAIHanler>::default()
@@ -48,9 +202,28 @@ impl Fuzzer {
                 log::info!("Using HTTP handler");
                 Box::new(request::http::HttpHandler::new().unwrap())
             }
-        };
+        }
+    }
 
+    /// Create a fuzzer of the current config that requests the programs from `handler`.
+    pub fn with_handler(handler: Box<dyn request::Handler>) -> Result<Self> {
+        let deopt = Deopt::new(get_library_name())?;
+        config::get_fuzz_time_window(&deopt.config)?;
+        if get_config().include_only_apis.is_some() {
+            crate::program::gadget::check_api_filter()?;
+        }
+        let executor = Executor::new(&deopt)?;
+        crate::execution::toolchain::check_toolchain(&deopt, get_config().strict_toolchain)?;
+        let observer = Observer::new(&deopt);
+
+        let transcripts = match &get_config().save_transcripts {
+            Some(path) => Some(TranscriptRecorder::new(path)?),
+            None => None,
+        };
         init_gtl();
+        let judge = get_config()
+            .judge_threshold
+            .map(|_| Box::new(HeuristicJudge::new(&get_library_name())) as Box<dyn Judge>);
         let fuzzer = Self {
             deopt,
             executor,
@@ -58,53 +231,204 @@ impl Fuzzer {
             schedule: Schedule::new(),
             handler,
             quiet_round: 0,
+            temperature: get_config().temperature_schedule.map_or_else(
+                || get_config().mode_temperature(&get_config().generation_mode).0,
+                |s| s.start,
+            ),
+            stuck_round: 0,
+            infra_batches: 0,
+            succ_ratio: request::SuccRatioTracker::default(),
+            transcripts,
+            judge,
+            #[cfg(feature = "async")]
+            pipeline: None,
         };
         Ok(fuzzer)
     }
 
-    fn sync_quiet_round(&mut self, content: &str) -> Result<()> {
-        if let Some(idx) = content.find("[Mutate Loop]: loop:") {
-            let mut de = Deserializer::from_input(&content[idx..]);
-            de.consume_token_until("quiet_round:")?;
-            let quiet_round = de.parse_number::<usize>()?;
-            self.quiet_round = quiet_round;
+    // When the execution stopped or crashed by internal's bugs, sync from the previous state.
+    pub fn sync_from_previous_state(&mut self, logger: &mut ProgramLogger) -> Result<()> {
+        let Some(file) = find_previous_log(&self.deopt.get_library_output_dir()?)? else {
+            log::warn!("No previous execution is found, start from a clean state.");
+            return Ok(());
+        };
+        log::info!("Find a previous exection: {file:?}");
+        log::info!("Restore the states from the previous execution!");
+        let log_str = std::fs::read_to_string(&file)?;
+        let mut state = ResumeState::from_log(&log_str)?;
+        // the log may lag behind the checkpoint if the execution was killed before it is flushed.
+        if let Some(checkpoint) = self.load_checkpoint_of(&file)? {
+            let logger = checkpoint.restore_logger()?;
+            if logger.get_gc_total() > state.logger.get_gc_total() {
+                log::info!("Restore the states from the checkpoint at loop {}.", checkpoint.loop_cnt);
+                state = ResumeState {
+                    seed_id: checkpoint.seed_id,
+                    quiet_round: checkpoint.quiet_round,
+                    logger,
+                };
+            }
         }
+        self.deopt.seed_id = state.seed_id;
+        self.quiet_round = state.quiet_round;
+        _ = std::mem::replace(logger, state.logger);
+        let observer = match Observer::load_snapshot(&self.deopt) {
+            Ok(Some(observer)) => {
+                log::info!("Restore the observer from the snapshot.");
+                self.deopt.load_programs_from_seeds()?;
+                observer
+            }
+            Ok(None) => Observer::sync_from_previous(&mut self.deopt)?,
+            Err(err) => {
+                log::warn!("Failed to load the observer snapshot: {err}, recompute it from seeds.");
+                Observer::sync_from_previous(&mut self.deopt)?
+            }
+        };
+        _ = std::mem::replace(&mut self.observer, observer);
+        Schedule::snyc_from_str(&self.deopt);
         Ok(())
     }
 
-    // When the execution stopped or crashed by internal's bugs, sync from the previous state.
-    pub fn sync_from_previous_state(&mut self, logger: &mut ProgramLogger) -> Result<()> {
-        let mut is_the_last = false;
-        for file in crate::deopt::utils::read_sort_dir(&self.deopt.get_library_output_dir()?)?
-            .iter()
-            .rev()
-        {
-            if file.is_dir() {
-                continue;
+    /// The checkpoint written by the execution of `log`. A checkpoint of another execution is stale and ignored.
+    fn load_checkpoint_of(&self, log: &Path) -> Result<Option<Checkpoint>> {
+        let path = self.deopt.get_library_checkpoint_path()?;
+        match Checkpoint::load(&path) {
+            Ok(Some(checkpoint)) if checkpoint.is_of_run(log) => Ok(Some(checkpoint)),
+            Ok(Some(checkpoint)) => {
+                log::warn!(
+                    "Ignore the checkpoint {path:?} of the execution {:?}, which is not the previous one {log:?}.",
+                    checkpoint.run
+                );
+                Ok(None)
             }
-            if let Some(ext) = file.extension() {
-                if ext == "log" {
-                    if !is_the_last {
-                        is_the_last = true;
-                        continue;
-                    }
-                    log::info!("Find a previous exection: {file:?}");
-                    log::info!("Restore the states from the previous execution!");
-                    let log_str = std::fs::read_to_string(file)?;
-                    let sync_logger = ProgramLogger::snyc_from_str(&log_str)?;
-                    self.deopt.seed_id = sync_logger.get_gc_total();
-                    _ = std::mem::replace(logger, sync_logger);
-                    let observer = Observer::sync_from_previous(&mut self.deopt)?;
-                    _ = std::mem::replace(&mut self.observer, observer);
-                    Schedule::snyc_from_str(&self.deopt);
-                    self.sync_quiet_round(&log_str)?;
-                    return Ok(());
-                }
+            Ok(None) => Ok(None),
+            Err(err) => {
+                log::warn!("Failed to load the checkpoint {path:?}: {err}");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Restore the schedule energies from the checkpoint of the previous execution, if any.
+    fn restore_schedule_from_checkpoint(&mut self) -> Result<()> {
+        let Some(log) = find_previous_log(&self.deopt.get_library_output_dir()?)? else {
+            return Ok(());
+        };
+        if let Some(checkpoint) = self.load_checkpoint_of(&log)? {
+            let restored = self.schedule.restore_energies(&checkpoint.energies);
+            self.schedule.loop_count = checkpoint.schedule_loop_count;
+            log::info!("Restore the energies of {restored} APIs from the checkpoint.");
+        }
+        Ok(())
+    }
+
+    /// Remove the checkpoint and the checkpointed prompt of a previous execution, so that a later `--resume` never restores them.
+    fn discard_checkpoint(&self) -> Result<()> {
+        for path in [
+            self.deopt.get_library_checkpoint_path()?,
+            self.deopt.get_library_prompt_checkpoint_path()?,
+        ] {
+            if path.exists() {
+                std::fs::remove_file(&path)?;
+                log::info!("Remove the checkpoint {path:?} of a previous execution.");
+            }
+        }
+        Ok(())
+    }
+
+    /// Adjust the sample count of `--auto-n-sample` by the programs of this round.
+    fn tune_n_sample(&mut self, logger: &ProgramLogger) {
+        if !get_config().auto_n_sample {
+            return;
+        }
+        let Some(ratio) = self.succ_ratio.observe(logger.get_rc_succ(), logger.get_rc_total()) else {
+            return;
+        };
+        let cur = request::get_n_sample();
+        let next = request::next_n_sample(cur, ratio, config::get_n_sample_range());
+        if next != cur {
+            request::set_n_sample(next);
+            log::info!("Adjust the sample count from {cur} to {next}, the success ratio: {ratio:.2}");
+        }
+    }
+
+    /// The prompt to start from: the one of `--load-prompt`, or the checkpointed one on `--resume`.
+    fn load_initial_prompt(&self) -> Result<Option<Prompt>> {
+        if let Some(path) = &get_config().load_prompt {
+            let prompt = Prompt::load_from(path)?;
+            log::info!("Start from the prompt {path:?}");
+            return Ok(Some(prompt));
+        }
+        if !get_config().resume {
+            return Ok(None);
+        }
+        // the prompt is checkpointed alongside `misc/checkpoint.json`, so it is as stale as the checkpoint.
+        let Some(log) = find_previous_log(&self.deopt.get_library_output_dir()?)? else {
+            return Ok(None);
+        };
+        if self.load_checkpoint_of(&log)?.is_none() {
+            return Ok(None);
+        }
+        match Prompt::load(&self.deopt) {
+            Ok(Some(prompt)) => {
+                log::info!("Restore the prompt from the checkpoint.");
+                Ok(Some(prompt))
+            }
+            Ok(None) => Ok(None),
+            Err(err) => {
+                log::warn!("Failed to load the checkpointed prompt: {err}");
+                Ok(None)
             }
         }
+    }
+
+    /// Write the checkpoint of `--save-every` after `loop_cnt` loops.
+    fn checkpoint(&self, logger: &ProgramLogger, prompt: &Prompt, loop_cnt: usize) -> Result<()> {
+        if !should_checkpoint(loop_cnt, get_config().save_every) {
+            return Ok(());
+        }
+        let path = self.deopt.get_library_checkpoint_path()?;
+        let mut checkpoint = Checkpoint::collect(
+            loop_cnt,
+            self.deopt.seed_id,
+            self.quiet_round,
+            &self.schedule,
+            logger,
+        );
+        checkpoint.run = find_current_log(&self.deopt.get_library_output_dir()?)?
+            .and_then(|log| log.file_name().map(|name| name.to_string_lossy().into_owned()));
+        checkpoint.save(&path)?;
+        prompt.save(&self.deopt)?;
+        log::info!("Checkpoint at loop {loop_cnt} is written to {path:?}");
         Ok(())
     }
 
+    /// Pre-populate the energies of the schedule by the per-API coverage of the existing corpus,
+    /// so that the first rounds target the under-covered APIs instead of a zero-coverage baseline.
+    /// The observer is recomputed from the seeds unless it is restored already. Returns whether the schedule is warmed up.
+    pub fn warm_start_schedule(&mut self) -> Result<bool> {
+        let seed_dir = self.deopt.get_library_seed_dir()?;
+        if crate::deopt::utils::read_sort_dir(&seed_dir)?.is_empty() {
+            return Ok(false);
+        }
+        if self.observer.get_branch_coverage().0 == 0 {
+            match Observer::sync_from_previous(&mut self.deopt) {
+                Ok(observer) => self.observer = observer,
+                Err(err) => {
+                    log::warn!("Failed to collect the coverage of the existing corpus, skip the warm-up: {err}");
+                    return Ok(false);
+                }
+            }
+        }
+        let api_coverage = self.observer.compute_library_api_coverage()?;
+        self.schedule.update_energies(api_coverage);
+        log::info!(
+            "Warm up the schedule from {} seeds, branch coverage: {:.2}%",
+            self.deopt.seed_queue.len(),
+            self.observer.get_branch_cover_percent()
+        );
+        Ok(true)
+    }
+
     pub fn transform_seeds_to_fuzzers(&self) -> Result<()> {
         let programs = crate::deopt::utils::read_sort_dir(&self.deopt.get_library_seed_dir()?)?;
         let core = get_config().cores;
@@ -126,6 +450,181 @@ impl Fuzzer {
         Ok(())
     }
 
+    /// Count a generation that yields no program, and back off before the retry.
+    /// After `MAX_EMPTY_GENERATIONS` in a row, shuffle the prompt and return true to end the round.
+    fn handle_empty_generation(&self, empty: &mut usize, prompt: &mut Prompt) -> bool {
+        *empty += 1;
+        if *empty >= config::MAX_EMPTY_GENERATIONS {
+            log::warn!("LLM generated no program for {empty} times in a row, shuffle the prompt.");
+            crate::mutation::prompt_shuffle(prompt);
+            *empty = 0;
+            return true;
+        }
+        let backoff = empty_generation_backoff(*empty);
+        log::warn!(
+            "LLM generated no program ({empty}/{}), retry in {}s.",
+            config::MAX_EMPTY_GENERATIONS,
+            backoff.as_secs()
+        );
+        std::thread::sleep(backoff);
+        false
+    }
+
+    /// Request programs from the LLM, and record the request latency.
+    fn timed_generate(&mut self, prompt: &Prompt, logger: &mut ProgramLogger) -> Result<Vec<Program>> {
+        let prompts_per_round = get_config().prompts_per_round;
+        if prompts_per_round > 1 && matches!(prompt.task, ProgramTask::Generate) {
+            return self.generate_parallel(prompt, prompts_per_round, logger);
+        }
+        let start = Instant::now();
+        let result = generate_with_escalation(self.handler.as_ref(), prompt, self.temperature);
+        logger.log_request_latency(start.elapsed());
+        self.save_raw_completions(prompt, request::take_raw_completions(), logger)?;
+        let result = result?;
+        report_truncation(&result);
+        let programs = result.into_programs();
+        if let Some(transcripts) = &mut self.transcripts {
+            transcripts.record(prompt, &programs);
+        }
+        Ok(programs)
+    }
+
+    /// Generate a batch for `prompt`, and start generating the next batch of it in the background, so that the
+    /// next batch is generated while this one is validated. Without a pipeline, it is `timed_generate`.
+    /// The pipelined batches are not escalated on truncation, and their raw completions are not saved.
+    fn pipelined_generate(&mut self, prompt: &Prompt, logger: &mut ProgramLogger) -> Result<Vec<Program>> {
+        #[cfg(feature = "async")]
+        if let Some(pipeline) = &mut self.pipeline {
+            if matches!(prompt.task, ProgramTask::Generate) {
+                let start = Instant::now();
+                let result = match pipeline.take() {
+                    Some(result) => result,
+                    None => {
+                        pipeline.prefetch(prompt.clone(), self.temperature);
+                        pipeline.take().expect("the generation is just prefetched")
+                    }
+                };
+                pipeline.prefetch(prompt.clone(), self.temperature);
+                logger.log_request_latency(start.elapsed());
+                let result = result?;
+                report_truncation(&result);
+                let programs = result.into_programs();
+                if let Some(transcripts) = &mut self.transcripts {
+                    transcripts.record(prompt, &programs);
+                }
+                return Ok(programs);
+            }
+        }
+        self.timed_generate(prompt, logger)
+    }
+
+    /// Drop the batch generated in the background for a prompt that is done with.
+    fn cancel_pipelined_generation(&mut self) {
+        #[cfg(feature = "async")]
+        if let Some(pipeline) = &mut self.pipeline {
+            pipeline.cancel();
+        }
+    }
+
+    /// Generate for `prompt` and `k - 1` other high-energy combinations concurrently, and pool their programs.
+    /// The timed out generations are skipped, unless all of them are timed out.
+    fn generate_parallel(
+        &mut self,
+        prompt: &Prompt,
+        k: usize,
+        logger: &mut ProgramLogger,
+    ) -> Result<Vec<Program>> {
+        let mut prompts = vec![prompt.clone()];
+        for _ in 1..k {
+            let mut combination = self.schedule.assemble_high_energy_combiantion();
+            if combination.is_empty() {
+                // the energies are not initialized before the first mutation in FuzzDriver mode.
+                combination = rand_choose_combination(rand_comb_len());
+            }
+            self.schedule
+                .mark_explored(combination.iter().map(|gadget| gadget.get_func_name()));
+            let mut extra = prompt.clone();
+            extra.set_combination(combination);
+            prompts.push(extra);
+        }
+
+        let handler = &self.handler;
+        let temperature = self.temperature;
+        let results: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = prompts
+                .iter()
+                .map(|prompt| {
+                    scope.spawn(move || {
+                        let start = Instant::now();
+                        let programs = generate_with_escalation(handler.as_ref(), prompt, temperature);
+                        (start.elapsed(), programs, request::take_raw_completions())
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("the generation thread panicked"))
+                .collect()
+        });
+
+        let mut generations = Vec::new();
+        for (prompt, (latency, programs, completions)) in prompts.iter().zip(results) {
+            logger.log_request_latency(latency);
+            self.save_raw_completions(prompt, completions, logger)?;
+            generations.push(programs.map(|result| {
+                report_truncation(&result);
+                let programs = result.into_programs();
+                if let Some(transcripts) = &mut self.transcripts {
+                    transcripts.record(prompt, &programs);
+                }
+                programs
+            }));
+        }
+        pool_generations(generations)
+    }
+
+    /// Append the transcript of a successful program, with `--save-transcripts`.
+    fn save_transcript(&mut self, program: &Program, branch_coverage: Option<f32>) {
+        let Some(transcripts) = &mut self.transcripts else {
+            return;
+        };
+        match transcripts.write(program, branch_coverage) {
+            Ok(true) => {}
+            Ok(false) => log::warn!("The prompt of program {} is not recorded, skip its transcript.", program.id),
+            Err(err) => log::error!("Failed to save the transcript of program {}: {err}", program.id),
+        }
+    }
+
+    /// Append the raw completions received by the handler to the file of this round, with `--verbose-generation`.
+    fn save_raw_completions(
+        &self,
+        prompt: &Prompt,
+        completions: Vec<String>,
+        logger: &mut ProgramLogger,
+    ) -> Result<()> {
+        if !config::verbose_generation() {
+            return Ok(());
+        }
+        if completions.is_empty() {
+            return Ok(());
+        }
+        let combination: Vec<&str> = prompt.gadgets.iter().map(|gadget| gadget.get_func_name()).collect();
+        let (content, unparsable) = format_raw_completions(&combination, &completions);
+        logger.log_unparsable_completions(unparsable);
+
+        let dir = self.deopt.get_library_work_dir()?.join("raw_generations");
+        crate::deopt::utils::create_dir_if_nonexist(&dir)?;
+        let path = dir.join(format!("round_{:05}.txt", logger.get_round_num()));
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+        file.write_all(content.as_bytes())?;
+        log::info!(
+            "{unparsable} of {} raw completions failed to parse, {} in total. Saved to {path:?}",
+            completions.len(),
+            logger.get_unparsable_num()
+        );
+        Ok(())
+    }
+
     pub fn generate_until_n_success(
         &mut self,
         prompt: &mut Prompt,
@@ -136,12 +635,47 @@ impl Fuzzer {
             get_config().fuzz_round_succ
         );
         let mut succ_programs = Vec::new();
+        let mut empty_generations = 0;
+        let bar = ProgressBar::new("Generate programs", get_config().fuzz_round_succ);
+        if let Some(transcripts) = &mut self.transcripts {
+            transcripts.clear();
+        }
+        // a batch left in flight by a failed round is of an older prompt.
+        self.cancel_pipelined_generation();
 
         while succ_programs.len() < get_config().fuzz_round_succ {
-            let mut programs = self.handler.generate(prompt)?;
+            let mut programs = match self.pipelined_generate(prompt, logger) {
+                Ok(programs) => programs,
+                Err(err) if request::is_request_timeout(&err) => {
+                    log::warn!("LLM request timed out, count this round as failed: {err}");
+                    break;
+                }
+                Err(err) => return Err(err),
+            };
+            if programs.is_empty() {
+                if self.handle_empty_generation(&mut empty_generations, prompt) {
+                    break;
+                }
+                continue;
+            }
+            empty_generations = 0;
             for program in &mut programs {
                 program.id = self.deopt.inc_seed_id();
             }
+            // the runaway programs are not worth compiling.
+            let mut too_long = Vec::new();
+            let limit = get_config().max_program_len;
+            programs.retain(|program| match check_program_len(program, limit) {
+                Some(err_msg) => {
+                    too_long.push((program.clone(), err_msg));
+                    false
+                }
+                None => true,
+            });
+            for (program, err_msg) in too_long {
+                self.deopt.save_err_program(&program, &err_msg)?;
+                logger.log_err(&err_msg);
+            }
 
             log::debug!(
                 "LLM generated {} programs. Sanitize those programs!",
@@ -150,11 +684,13 @@ impl Fuzzer {
             let check_res = self
                 .executor
                 .check_programs_are_correct(&programs, &self.deopt)?;
+            self.check_infra_errors(&check_res)?;
             // Check each generated programs, and save thems according where they contains errors.
             for (i, program) in programs.iter().enumerate() {
                 let has_err = check_res
                     .get(i)
                     .unwrap_or_else(|| panic!("cannot obtain check_res at `{i}`"));
+                record_program_outcome(program, has_err.as_ref(), &self.deopt)?;
                 // save as error programs
                 if let Some(err_msg) = has_err {
                     self.deopt.save_err_program(program, err_msg)?;
@@ -165,6 +701,8 @@ impl Fuzzer {
                 }
             }
             logger.print_succ_round();
+            bar.set_position(succ_programs.len());
+            bar.set_message(&format!("validated: {}", logger.get_rc_total()));
             // if the combiantion continusely failed in a long time, shuffle the prompt to escape the bad combination;
             if self
                 .schedule
@@ -174,6 +712,7 @@ impl Fuzzer {
                 break;
             }
         }
+        self.cancel_pipelined_generation();
 
         Ok(succ_programs)
     }
@@ -187,20 +726,34 @@ impl Fuzzer {
             get_config().fuzz_round_succ
         );
         let mut succ_programs = Vec::new();
+        let mut empty_generations = 0;
+        let bar = ProgressBar::new("Generate programs", get_config().fuzz_round_succ);
+        if let Some(transcripts) = &mut self.transcripts {
+            transcripts.clear();
+        }
 
         while succ_programs.len() < get_config().fuzz_round_succ {
-            let mut programs = self.handler.generate(prompt)?;
-            for program in &mut programs {
-                program.id = self.deopt.inc_seed_id();
+            let required = get_config().fuzz_round_succ - succ_programs.len();
+            let validated = match self.generate_and_validate(prompt, required, logger) {
+                Ok(validated) => validated,
+                Err(err) if request::is_request_timeout(&err) => {
+                    log::warn!("LLM request timed out, count this round as failed: {err}");
+                    break;
+                }
+                Err(err) => return Err(err),
+            };
+            if validated.is_empty() {
+                if self.handle_empty_generation(&mut empty_generations, prompt) {
+                    break;
+                }
+                continue;
             }
-            log::debug!(
-                "LLM generated {} programs. Sanitize those programs!",
-                programs.len()
-            );
-            for program in programs {
-                let error = self.executor.validate_api_sequence(&program, &self.deopt)?;
-
-                if let Some(err) = error {
+            empty_generations = 0;
+            for (program, error) in validated {
+                if let Some(err @ (ProgramError::TooLong(_) | ProgramError::Judged(_))) = error {
+                    self.deopt.save_err_program(&program, &err)?;
+                    logger.log_err(&err);
+                } else if let Some(err) = error {
                     // --- 修复逻辑开始 ---
                     log::warn!(
                         "Program {} failed validation. Attempting to repair. Error: {}",
@@ -209,7 +762,14 @@ impl Fuzzer {
                     );
 
                     prompt.set_repair_task(program.statements.clone(), err.clone());
-                    let mut repaired_programs = self.handler.generate(prompt)?;
+                    let mut repaired_programs = match self.timed_generate(prompt, logger) {
+                        Ok(programs) => programs,
+                        Err(err) if request::is_request_timeout(&err) => {
+                            log::warn!("LLM repair request timed out: {err}");
+                            vec![]
+                        }
+                        Err(err) => return Err(err),
+                    };
                     if let Some(repaired_program) = repaired_programs.get_mut(0) {
                         repaired_program.id = program.id;
 
@@ -245,6 +805,8 @@ impl Fuzzer {
                 }
             }
             logger.print_succ_round();
+            bar.set_position(succ_programs.len());
+            bar.set_message(&format!("validated: {}", logger.get_rc_total()));
             if self
                 .schedule
                 .should_shuffle(logger.get_rc_succ(), logger.get_rc_total())
@@ -256,40 +818,109 @@ impl Fuzzer {
 
         Ok(succ_programs)
     }
-    fn extract_calls_recursive(source: &str, cursor: &mut TreeCursor, calls: &mut Vec<String>) {
-        let node = cursor.node();
 
-        if node.kind() == "call_expression" {
-            if let Some(function_node) = node.child_by_field_name("function") {
-                let func_name = function_node
-                    .utf8_text(source.as_bytes())
-                    .unwrap()
-                    .to_string();
-                calls.push(func_name);
+    /// Generate programs for the prompt and validate them.
+    /// In streaming mode, the remaining generations are cancelled once `required` programs pass the validation.
+    fn generate_and_validate(
+        &mut self,
+        prompt: &Prompt,
+        required: usize,
+        logger: &mut ProgramLogger,
+    ) -> Result<Vec<(Program, Option<ProgramError>)>> {
+        let mut validated = Vec::new();
+        if !get_config().stream {
+            let mut programs = self.timed_generate(prompt, logger)?;
+            for program in &mut programs {
+                program.id = self.deopt.inc_seed_id();
             }
-        }
-
-        if cursor.goto_first_child() {
-            Self::extract_calls_recursive(source, cursor, calls);
-            while cursor.goto_next_sibling() {
-                Self::extract_calls_recursive(source, cursor, calls);
+            log::debug!(
+                "LLM generated {} programs. Sanitize those programs!",
+                programs.len()
+            );
+            let (mut judged, mut quick_rejected) = (0, 0);
+            for program in programs {
+                let error = match check_program_len(&program, get_config().max_program_len) {
+                    Some(err) => Some(err),
+                    None => match check_program_judged(self.judge.as_deref(), &program) {
+                        Some(err) => {
+                            judged += 1;
+                            Some(err)
+                        }
+                        None => match self.quick_check_api_sequence(&program)? {
+                            Some(err) => {
+                                quick_rejected += 1;
+                                Some(err)
+                            }
+                            None => self.executor.validate_api_sequence(&program, &self.deopt)?,
+                        },
+                    },
+                };
+                validated.push((program, error));
             }
-            cursor.goto_parent();
+            if self.judge.is_some() {
+                log::info!("The judge filtered {judged} of {} programs.", validated.len());
+            }
+            if config::quick_validate() {
+                log::info!("Quick validation rejected {quick_rejected} of {} programs.", validated.len());
+            }
+            return Ok(validated);
         }
-    }
-    fn extract_function_calls(source: &str) -> Vec<String> {
-        let mut parser = Parser::new();
-        parser
-            .set_language(tree_sitter_cpp::language())
-            .expect("Failed to load C++ grammar");
 
-        let tree = parser.parse(source, None).expect("Failed to parse code");
-        let root_node = tree.root_node();
-
-        let mut calls = Vec::new();
-        let mut cursor = root_node.walk();
-        Self::extract_calls_recursive(source, &mut cursor, &mut calls);
-        calls
+        let mut validate_err = None;
+        let mut n_succ = 0;
+        let mut judged = 0;
+        let judge = self.judge.as_deref();
+        // the validation of streamed programs is not counted in the request latency.
+        let start = Instant::now();
+        let mut validate_time = Duration::ZERO;
+        let result = self
+            .handler
+            .generate_streaming(prompt, self.temperature, &mut |mut program| {
+                program.id = self.deopt.inc_seed_id();
+                if let Some(err) = check_program_len(&program, get_config().max_program_len) {
+                    validated.push((program, Some(err)));
+                    return true;
+                }
+                if let Some(err) = check_program_judged(judge, &program) {
+                    judged += 1;
+                    validated.push((program, Some(err)));
+                    return true;
+                }
+                let validate_start = Instant::now();
+                let res = self.executor.validate_api_sequence(&program, &self.deopt);
+                validate_time += validate_start.elapsed();
+                match res {
+                    Ok(error) => {
+                        if error.is_none() {
+                            n_succ += 1;
+                        }
+                        validated.push((program, error));
+                        n_succ < required
+                    }
+                    Err(err) => {
+                        validate_err = Some(err);
+                        false
+                    }
+                }
+            });
+        logger.log_request_latency(start.elapsed().saturating_sub(validate_time));
+        self.save_raw_completions(prompt, request::take_raw_completions(), logger)?;
+        result?;
+        if let Some(transcripts) = &mut self.transcripts {
+            let programs: Vec<Program> = validated.iter().map(|(program, _)| program.clone()).collect();
+            transcripts.record(prompt, &programs);
+        }
+        if let Some(err) = validate_err {
+            return Err(err);
+        }
+        log::debug!(
+            "LLM streamed {} programs, {n_succ} of them are valid.",
+            validated.len()
+        );
+        if judge.is_some() {
+            log::info!("The judge filtered {judged} of {} programs.", validated.len());
+        }
+        Ok(validated)
     }
 
     fn extract_3gram_triples(calls: &[String]) -> Vec<(String, String, String)> {
@@ -304,46 +935,197 @@ impl Fuzzer {
             })
             .collect()
     }
+
+    /// Keep the triples of library APIs where each call is fed by a former one, e.g., `b` and `c` both
+    /// consume the handle returned by `a`.
+    fn retain_type_compatible_triples(
+        triples: &mut Vec<(String, String, String)>,
+        signatures: &HashMap<String, ApiSignature>,
+    ) {
+        let compatible = |a: &String, b: &String| match (signatures.get(a), signatures.get(b)) {
+            (Some(a), Some(b)) => is_type_compatible(a, b),
+            _ => false,
+        };
+        triples.retain(|(a, b, c)| compatible(a, b) && (compatible(a, c) || compatible(b, c)));
+    }
+
+    /// Log the state of the schedule by `--dump-schedule`.
+    fn dump_schedule(&self) {
+        if get_config().dump_schedule {
+            log::info!("The schedule at loop {}:\n{}", self.schedule.loop_count, self.schedule.describe());
+        }
+    }
+
+    /// The syntax-only check of `--quick-validate` before the API sequence is built, None if the flag is off.
+    fn quick_check_api_sequence(&self, program: &Program) -> Result<Option<ProgramError>> {
+        if !config::quick_validate() {
+            return Ok(None);
+        }
+        self.executor.quick_check_api_sequence(program, &self.deopt)
+    }
+
+    /// Save the observer snapshot for `--resume`. A failed save is retried in the next round instead of aborting.
+    fn save_observer_snapshot(&self) {
+        if let Err(err) = self.observer.save_snapshot(&self.deopt) {
+            log::warn!("Failed to save the observer snapshot: {err}");
+        }
+    }
+
     fn mutate_prompt(&mut self, prompt: &mut Prompt) -> Result<()> {
         let api_coverage = self.observer.compute_library_api_coverage()?;
         self.schedule.update_energies(api_coverage);
+        self.dump_schedule();
         self.schedule.update_prompt(prompt, &mut self.deopt)?;
         Ok(())
     }
 
-    pub fn is_converge(&self) -> bool {
-        if self.quiet_round >= get_config().fuzz_converge_round {
-            return true;
+    /// Fail fast if the programs keep failing for our own setup instead of themselves, as no generation can fix it.
+    fn check_infra_errors(&mut self, check_res: &[Option<ProgramError>]) -> Result<()> {
+        if !crate::execution::logger::is_infra_failed_batch(check_res) {
+            self.infra_batches = 0;
+            return Ok(());
         }
-        false
+        self.infra_batches += 1;
+        let err = check_res[0].as_ref().unwrap();
+        log::warn!(
+            "All {} programs failed for an infrastructure error ({}/{}): {err}",
+            check_res.len(),
+            self.infra_batches,
+            get_config().infra_retry_cap
+        );
+        if self.infra_batches >= get_config().infra_retry_cap {
+            eyre::bail!(
+                "Abort: every program failed for an infrastructure error in {} consecutive batches. Please check the compiler (--cxx) and the build library of {}.\n{err}",
+                self.infra_batches,
+                self.deopt.project_name
+            );
+        }
+        Ok(())
+    }
+
+    /// Decay the temperature by the progress of fuzz loop if `--temperature-schedule` is set, otherwise use the
+    /// temperature of the current generation mode. The progress is the elapsed time if a timeout is given,
+    /// otherwise the quiet rounds.
+    fn update_temperature(&mut self, start: &Instant, timeout: Option<Duration>) {
+        let config = get_config();
+        let Some(schedule) = config.temperature_schedule else {
+            let (temperature, option) = config.mode_temperature(&config.generation_mode);
+            self.temperature = temperature;
+            log::info!("Sampling temperature of this round: {temperature:.2} (from {option})");
+            return;
+        };
+        let progress = if let Some(timeout) = timeout {
+            start.elapsed().as_secs_f32() / timeout.as_secs_f32()
+        } else {
+            self.quiet_round as f32 / converge_round().max(1) as f32
+        };
+        self.temperature = schedule.temperature_at(progress);
+        log::info!(
+            "Sampling temperature of this round: {:.2} (from --temperature-schedule)",
+            self.temperature
+        );
+    }
+
+    pub fn is_converge(&self) -> bool {
+        self.quiet_round >= converge_round()
     }
 
     pub fn is_stuck(&self, len: usize) -> bool {
         len == 0
     }
 
+    /// Count the consecutive stuck rounds, and return whether the prompt should be hard reset.
+    fn should_hard_reset(&mut self, is_stuck: bool) -> bool {
+        if !is_stuck {
+            self.stuck_round = 0;
+            return false;
+        }
+        self.stuck_round += 1;
+        if let Some(threshold) = get_config().stuck_threshold {
+            return self.stuck_round >= threshold;
+        }
+        false
+    }
+
+    /// The number of discovered API pairs to stop at, from `--target-pairs` and `--target-pair-fraction`.
+    /// The smaller one is taken if both are set.
+    fn get_pair_target(&self) -> Option<usize> {
+        let fraction_target = get_config().target_pair_fraction.map(|fraction| {
+            let total = self
+                .observer
+                .total_possible_pairs(get_config().type_compatible_pairs);
+            (fraction * total as f32).ceil() as usize
+        });
+        match (get_config().target_pairs, fraction_target) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Whether the combinations have stayed similar for `--stuck-threshold` rounds.
+    fn is_low_diversity(&self) -> bool {
+        if get_config().diversity_threshold.is_none() {
+            return false;
+        }
+        get_config()
+            .stuck_threshold
+            .is_some_and(|threshold| self.schedule.get_low_diversity_round() >= threshold)
+    }
+
+    /// Replace the prompt with a fresh random combination, and reset the prompt counters of the stuck APIs.
+    pub fn hard_reset_prompt(&mut self, prompt: &mut Prompt) {
+        log::warn!(
+            "Fuzzer has been stuck for {} rounds, hard reset the prompt.",
+            self.stuck_round
+        );
+        for gadget in prompt.gadgets.iter() {
+            set_prompt_counter_value(gadget.get_func_name().to_string(), 0);
+        }
+        save_prompt_counter();
+        *prompt = Prompt::from_combination(rand_choose_combination(rand_comb_len()));
+        self.schedule
+            .mark_explored(prompt.gadgets.iter().map(|gadget| gadget.get_func_name()));
+        self.schedule.reset_diversity();
+        self.stuck_round = 0;
+    }
+
     pub fn should_recheck(&self) -> bool {
         if self.quiet_round >= get_config().fuzz_converge_round / 2 && get_config().recheck {
             return true;
         }
         false
     }
+    /// Recheck the seeds and recompute the coverage in a pause phase, which blocks the other
+    /// accessors holding `Observer::pause_handle` until the seeds and coverage are consistent again.
+    fn recheck(&mut self) -> Result<()> {
+        let pause = self.observer.pause_handle();
+        let _paused = pause.write().unwrap();
+        log::info!("Pause the accessors of the observer to recheck the seeds.");
+        self.executor.recheck_seed(&mut self.deopt)?;
+        self.observer.recompute_global_coverage()?;
+        self.deopt.load_programs_from_seeds()?;
+        Ok(())
+    }
+
     // pub fn  extract_api_pairs(&self, program: &Program) -> Result<Vec<(String, String)>> {
     //     let string= program.statements.clone();
     // }
 
     pub fn fuzz_loop(&mut self) -> Result<()> {
         let mut logger = ProgramLogger::default();
-        let initial_combination = rand_choose_combination(rand_comb_len());
-        // let mut prompt = if let Some(prompt) = load_prompt(&self.deopt) {
-        //     prompt
-        // } else {
-        //     Prompt::from_combination(initial_combination)
-        // };
-        for a in initial_combination.iter() {
-            log::debug!("Initial combination: {}", a.name);
-        }
-        let mut prompt = Prompt::from_combination(initial_combination);
+        let loaded_prompt = self.load_initial_prompt()?;
+        // the loaded prompt is kept as is in the first round.
+        let mut keep_prompt = loaded_prompt.is_some();
+        let mut prompt = match loaded_prompt {
+            Some(prompt) => prompt,
+            None => {
+                let initial_combination = rand_choose_combination(rand_comb_len());
+                for a in initial_combination.iter() {
+                    log::debug!("Initial combination: {}", a.name);
+                }
+                Prompt::from_combination(initial_combination)
+            }
+        };
         let mut loop_cnt = 0;
         let mut has_checked = false;
 
@@ -355,187 +1137,370 @@ impl Fuzzer {
         }
         let start = Instant::now();
 
-        // self.sync_from_previous_state(&mut logger)?;
+        if get_config().only_minimize {
+            log::info!("--only-minimize is set, skip the generation loop.");
+            self.sync_from_previous_state(&mut logger)?;
+            return self.minimize_corpus();
+        }
+        if get_config().resume {
+            self.sync_from_previous_state(&mut logger)?;
+        } else {
+            self.discard_checkpoint()?;
+        }
+        self.schedule.set_diversity_threshold(get_config().diversity_threshold);
+        self.schedule.set_success_weight(get_config().success_weight);
 
         if get_config().generation_mode == config::GenerationModeP::FuzzDriver {
             log::info!("Using FuzzDriver mode, initial prompt: {prompt:?}");
+            let mut seed_metas = SeedMetas::new(&start);
+            let mut strategy = config::get_comb_strategy();
+            if strategy == CombStrategy::Semantic {
+                let path = self.deopt.get_library_api_embeddings_path()?;
+                match ApiEmbeddings::load(&path) {
+                    Ok(embeddings) => self.schedule.set_embeddings(embeddings),
+                    Err(err) => {
+                        log::warn!("Failed to load the API embeddings from {path:?}: {err}, fall back to the energy strategy.");
+                        strategy = CombStrategy::Energy;
+                    }
+                }
+            }
+            let enumerator_path = self.deopt.get_library_comb_enumerator_path()?;
+            if strategy != CombStrategy::Random
+                && strategy != CombStrategy::Enumerate
+                && self.warm_start_schedule()?
+                && !keep_prompt
+            {
+                prompt.set_combination(self.schedule.assemble_high_energy_combiantion());
+            }
+            if get_config().resume {
+                self.restore_schedule_from_checkpoint()?;
+            }
+            let mut enumerator = (strategy == CombStrategy::Enumerate).then(|| {
+                let len = config::get_comb_len_range().1;
+                let total = get_selectable_func_gadgets().len();
+                if get_config().resume {
+                    CombinationEnumerator::load_or_new(&enumerator_path, len, total)
+                } else {
+                    CombinationEnumerator::new(len, total)
+                }
+            });
             loop {
+                let round_start = Instant::now();
                 if self.is_converge() {
                     break;
                 }
+                if std::mem::take(&mut keep_prompt) {
+                    log::debug!("Generate from the loaded prompt in the first round.");
+                } else if let Some(enumerator) = enumerator.as_mut() {
+                    let Some(combination) = enumerator.next_combination() else {
+                        log::info!("All combinations are enumerated. Stopping seed generation.");
+                        break;
+                    };
+                    prompt = Prompt::from_combination(combination);
+                }
+                self.update_temperature(&start, timeout);
                 let programs = self.generate_until_n_success(&mut prompt, &mut logger)?;
                 let is_stuck = self.is_stuck(programs.len());
                 let mut has_new = false;
+                let min_new_branches = config::get_min_new_branches();
+                let mut below_threshold = 0;
                 for mut program in programs {
-                    self.deopt.save_succ_program(&program)?;
+                    let seed_path = self.deopt.save_succ_program(&program)?;
                     let coverage = self.deopt.get_seed_coverage(program.id)?;
+                    if get_config().keep_profiles {
+                        self.deopt.keep_seed_profile(program.id)?;
+                    }
                     let unique_branches = self.observer.has_unique_branch(&coverage);
                     has_new = !unique_branches.is_empty();
                     program.update_quality(unique_branches, &self.deopt)?;
-                    self.deopt.update_seed_queue(program, &coverage, has_new)?;
+                    let keep = program.get_quality().meets_min_new_branches(min_new_branches);
+                    if has_new && !keep {
+                        below_threshold += 1;
+                    }
                     self.observer.merge_coverage(&coverage);
+                    let cover_percent = self.observer.get_branch_cover_percent();
+                    self.save_transcript(&program, Some(cover_percent));
+                    self.deopt.update_seed_queue(program, &coverage, keep)?;
+                    seed_metas.add(&seed_path, Instant::now(), Some(cover_percent))?;
                 }
-                if !get_config().disable_power_schedule {
-                    self.mutate_prompt(&mut prompt)?;
-                } else {
-                    let new_comb = rand_choose_combination(config::DEFAULT_COMB_LEN);
-                    prompt = Prompt::from_combination(new_comb);
+                if below_threshold > 0 {
+                    log::info!(
+                        "Rejected {below_threshold} seeds with fewer unique branches than --min-new-branches {min_new_branches} in this round."
+                    );
                 }
-
-                if has_new {
-                    self.quiet_round = 0;
-                } else if !is_stuck {
-                    self.quiet_round += 1;
+                match strategy {
+                    CombStrategy::Energy | CombStrategy::Semantic => self.mutate_prompt(&mut prompt)?,
+                    CombStrategy::Random => {
+                        let new_comb = rand_choose_combination(config::DEFAULT_COMB_LEN);
+                        prompt = Prompt::from_combination(new_comb);
+                    }
+                    // the next combination is taken at the start of the next round.
+                    CombStrategy::Enumerate => {}
                 }
+                if let Some(enumerator) = &enumerator {
+                    enumerator.save(&enumerator_path)?;
+                } else if self.should_hard_reset(is_stuck) {
+                    self.hard_reset_prompt(&mut prompt);
+                } else if self.is_low_diversity() {
+                    log::warn!("The diversity of combinations is low, hard reset the prompt.");
+                    self.hard_reset_prompt(&mut prompt);
+                }
+
+                self.quiet_round = next_quiet_round(self.quiet_round, has_new, is_stuck);
                 // As the corpus is also evolved, we recheck the seeds on the evolved corpus to eliminate the error programs that was not catched before.
                 if self.should_recheck() && !has_checked {
-                    self.executor.recheck_seed(&mut self.deopt)?;
-                    self.observer.recompute_global_coverage()?;
-                    self.deopt.load_programs_from_seeds()?;
+                    self.recheck()?;
                     has_checked = true;
                     self.quiet_round = 0;
                     crate::mutation::prompt_shuffle(&mut prompt);
                 }
                 loop_cnt += 1;
+                logger.log_round_duration(round_start.elapsed());
+                self.tune_n_sample(&logger);
                 logger.reset_round();
+                self.save_observer_snapshot();
+                self.checkpoint(&logger, &prompt, loop_cnt)?;
                 log::info!(
-                    "[Mutate Loop]: loop: {loop_cnt}, quiet_round: {}, {}",
+                    "[Mutate Loop]: loop: {loop_cnt}, quiet_round: {}, diversity: {:.2}, {}",
                     self.quiet_round,
+                    self.schedule.get_diversity(),
                     self.observer.dump_global_states()
                 );
             }
+            let meta_path = self.deopt.get_work_seed_meta_path()?;
+            if let Err(err) = seed_metas.write_to(&meta_path) {
+                log::error!("Failed to write seed meta data to {meta_path:?}: {err}");
+            }
         } else if get_config().generation_mode == config::GenerationModeP::ApiCombination {
             let mut seed_metas = SeedMetas::new(&Instant::now());
             //    log::info!("Using api combination mode, initial prompt: {prompt:?}");
+            self.schedule.set_exploration_bonus(get_config().exploration_bonus);
             self.schedule.initialize_energies_for_api_mode();
+            if get_config().resume {
+                self.restore_schedule_from_checkpoint()?;
+            }
             // let mut file = std::fs::OpenOptions::new()
             //     .create(true)
             //     .append(true)
             //     .open("output111.txt")?;
-            loop {
-                if self.is_converge() {
-                    break;
-                }
-                if timeout.is_some() && start.elapsed() > timeout.unwrap() {
-                    log::info!("Time out is reached. Stopping seed generation.");
+            loop_cnt = self.api_combination_rounds(
+                &mut prompt,
+                &mut logger,
+                &mut seed_metas,
+                &start,
+                timeout,
+            )?;
+            let result = seed_metas.write_to(&self.deopt.get_seed_meta_path().unwrap());
+            if result.is_err() {
+                log::error!("Failed to write seed meta data!");
+            }
+
+        }
+        let summary = FuzzSummary::collect(
+            &logger,
+            &self.observer,
+            &self.schedule,
+            loop_cnt,
+            start.elapsed().as_secs(),
+            self.deopt.count_crash_artifacts()?,
+        );
+        summary.write_to(&self.deopt.get_library_work_dir()?)?;
+        if get_config().no_minimize {
+            log::info!("Fuzzing loop finished. Minimization is skipped by --no-minimize.");
+            return Ok(());
+        }
+        log::info!("Fuzzing loop finished. Starting minimization...");
+        self.minimize_corpus()
+    }
+
+    /// The rounds of ApiCombination mode until converged, timed out after `timeout` from `start`, or the pair
+    /// target is reached. Returns the number of rounds.
+    fn api_combination_rounds(
+        &mut self,
+        prompt: &mut Prompt,
+        logger: &mut ProgramLogger,
+        seed_metas: &mut SeedMetas,
+        start: &Instant,
+        timeout: Option<Duration>,
+    ) -> Result<usize> {
+        let mut loop_cnt = 0;
+        let pair_target = self.get_pair_target();
+        let signatures: Option<HashMap<String, ApiSignature>> =
+            get_config().type_compatible_triples.then(|| {
+                self.deopt
+                    .exported_apis()
+                    .into_iter()
+                    .map(|sig| (sig.name.clone(), sig.canonicalize(ctype::retrieve_canonical_type)))
+                    .collect()
+            });
+        loop {
+            let round_start = Instant::now();
+            if self.is_converge() {
+                break;
+            }
+            if timeout.is_some() && start.elapsed() > timeout.unwrap() {
+                log::info!("Time out is reached. Stopping seed generation.");
+                break;
+            }
+            if let Some(target) = pair_target {
+                let discovered = self.observer.get_discovered_pair_num();
+                if discovered >= target {
+                    log::info!("Discovered {discovered} API pairs, reached the target {target}.");
                     break;
                 }
-                let mut first_prompt=String::from("Hello");
-                if get_config().enable_cot{
-                    log::info!("Current prompt is in CoT mode.");
-                    // 生成执行计划
-                    prompt.set_cot_plan_task();
-                    match self.handler.generate_single(&prompt) {
-                        Ok(plan_program) => {
-                            first_prompt = plan_program.statements.clone();
-                            log::info!("Execution plan generated successfully");
-                            log::debug!("Plan:\n{}", first_prompt);
-                        }
-                        Err(e) => {
-                            log::error!("CoT Phase 1 error: {}, falling back", e);
-                        }
+            }
+            self.update_temperature(start, timeout);
+            let mut first_prompt=String::from("Hello");
+            if get_config().enable_cot{
+                log::info!("Current prompt is in CoT mode.");
+                // 生成执行计划
+                prompt.set_cot_plan_task();
+                let plan = self.handler.generate_single(prompt, self.temperature);
+                // the plans are not programs, so they are not saved as raw completions.
+                request::take_raw_completions();
+                match plan {
+                    Ok(plan_program) => {
+                        first_prompt = plan_program.statements.clone();
+                        log::info!("Execution plan generated successfully");
+                        log::debug!("Plan:\n{}", first_prompt);
+                    }
+                    Err(e) => {
+                        log::error!("CoT Phase 1 error: {}, falling back", e);
                     }
                 }
+            }
 
-                Prompt::set_generate_task(&mut prompt);
-                if get_config().enable_cot{
-                    prompt.set_cot_code_task(first_prompt);
-                    log::info!("Current prompt is in CoT code generation mode.");
+            Prompt::set_generate_task(prompt);
+            if get_config().enable_cot{
+                prompt.set_cot_code_task(first_prompt);
+                log::info!("Current prompt is in CoT code generation mode.");
+            }
+            
+            let programs =
+                self.generate_and_validate_api_sequences(prompt, logger)?;
+            self.schedule.increment_loop();
+            println!(
+                "Current loop count: {}",
+                self.schedule.loop_count
+            );
+            if programs.is_empty() {
+                log::debug!("No programs generated successfully, continue to next round.");
+                self.schedule.update_prompt_for_api_mode(prompt)?;
+                if self.should_hard_reset(true) {
+                    self.hard_reset_prompt(prompt);
                 }
-                
-                let programs =
-                    self.generate_and_validate_api_sequences(&mut prompt, &mut logger)?;
-                self.schedule.increment_loop();
-                println!(
-                    "Current loop count: {}",
-                    self.schedule.loop_count
-                );
-                if programs.is_empty() {
-                    log::debug!("No programs generated successfully, continue to next round.");
-                    self.schedule.update_prompt_for_api_mode(&mut prompt)?;
-                    loop_cnt += 1;
-                    continue;
+                logger.log_round_duration(round_start.elapsed());
+                loop_cnt += 1;
+                continue;
+            }
+            let program_len = programs.len();
+            log::debug!(
+                "LLM generated {} successful programs. Sanitize those programs!",
+                program_len
+            );
+            //  下面都是跑的
+            let is_stuck = self.is_stuck(programs.len());
+            self.stuck_round = 0;
+            let mut round_newly_discovered_pairs: HashSet<(String, String,String)> = HashSet::new();
+            let mut successful_programs_this_round: Vec<Program> = Vec::new();
+            for program in programs {
+                let seed_path = self.deopt.save_succ_program(&program)?;
+                seed_metas.add(&seed_path, Instant::now(), None);
+                self.save_transcript(&program, None);
+                let cpp_code = &program.statements;
+                let calls = crate::program::extract_function_calls(cpp_code);
+                let mut pairs = Self::extract_3gram_triples(&calls);
+                if let Some(signatures) = &signatures {
+                    let extracted = pairs.len();
+                    Self::retain_type_compatible_triples(&mut pairs, signatures);
+                    log::debug!(
+                        "Dropped {} of {extracted} API triples without data flows in program {}.",
+                        extracted - pairs.len(),
+                        program.id
+                    );
                 }
-                let program_len = programs.len();
-                log::debug!(
-                    "LLM generated {} successful programs. Sanitize those programs!",
-                    program_len
-                );
-                //  下面都是跑的
-                let is_stuck = self.is_stuck(programs.len());
-                let mut round_newly_discovered_pairs: HashSet<(String, String,String)> = HashSet::new();
-                let mut successful_programs_this_round: Vec<Program> = Vec::new();
-                for program in programs {
-                    let seed_path = self.deopt.save_succ_program(&program)?;
-                    seed_metas.add(&seed_path, Instant::now(), None);
-                    let cpp_code = &program.statements;
-                    let calls = Self::extract_function_calls(cpp_code);
-                    let pairs = Self::extract_3gram_triples(&calls);
-
-                    // 保存API pairs到新文件
-                    let pairs_dir = self.deopt.get_library_succ_seed_dir()?.join("pairs");
-                    if !pairs_dir.exists() {
-                        std::fs::create_dir_all(&pairs_dir)?;
-                    }
-                    let pairs_path = pairs_dir.join(format!("{}.pairs", program.id));
-                    let mut pairs_file = std::fs::File::create(pairs_path)?;
-                    for pair in &pairs {
-                        writeln!(pairs_file, "(\"{}\", \"{}\",\"{}\")", pair.0, pair.1,pair.2)?;
-                    }
 
-                    successful_programs_this_round.push(program.clone());
-                    let mut discovered_pairs_guard =
-                        self.observer.discovered_api_triples.write().unwrap();
-                    for pair in pairs {
-                        // log::debug!("Discovered API pair: {:?}", pair);
-                        if discovered_pairs_guard.insert(pair.clone()) {
-                          //  writeln!(file, "{:?}", pair)?;
-                            round_newly_discovered_pairs.insert(pair);
-                        }
-                    }
+                // 保存API pairs到新文件
+                let pairs_dir = self.deopt.get_library_succ_seed_dir()?.join("pairs");
+                if !pairs_dir.exists() {
+                    std::fs::create_dir_all(&pairs_dir)?;
                 }
-                if !successful_programs_this_round.is_empty() {
-                    if let Some(example_program) = successful_programs_this_round.last() {
-                        log::info!(
-                            "Adding successful program {} as an example for the next prompt.",
-                            example_program.id
-                        );
-                        prompt.add_successful_example(example_program.statements.clone());
-                    }
+                let pairs_path = pairs_dir.join(format!("{}.pairs", program.id));
+                let mut pairs_file = std::fs::File::create(pairs_path)?;
+                for pair in &pairs {
+                    writeln!(pairs_file, "(\"{}\", \"{}\",\"{}\")", pair.0, pair.1,pair.2)?;
                 }
-                let has_new_in_round = round_newly_discovered_pairs.capacity() >= get_config().num_new_pairs;
 
-                if has_new_in_round {
-                    self.quiet_round = 0;
-                    log::debug!(
-                        "Discovered {} new API pairs in this round.",
-                        round_newly_discovered_pairs.len()
+                successful_programs_this_round.push(program.clone());
+                let mut discovered_pairs_guard =
+                    self.observer.discovered_api_triples.write().unwrap();
+                for pair in pairs {
+                    // log::debug!("Discovered API pair: {:?}", pair);
+                    if discovered_pairs_guard.insert(pair.clone()) {
+                      //  writeln!(file, "{:?}", pair)?;
+                        round_newly_discovered_pairs.insert(pair);
+                    }
+                }
+            }
+            if !successful_programs_this_round.is_empty() {
+                if let Some(example_program) = successful_programs_this_round.last() {
+                    log::info!(
+                        "Adding successful program {} as an example for the next prompt.",
+                        example_program.id
                     );
-                    self.schedule
-                        .update_energies_from_api_pairs(&round_newly_discovered_pairs);
-                } else if !is_stuck {
-                    self.quiet_round += 1;
+                    prompt.add_successful_example(example_program.statements.clone());
                 }
-                self.schedule.update_prompt_for_api_mode(&mut prompt)?;
-                loop_cnt += 1;
-                logger.reset_round();
-                log::info!(
-                    "[Mutate Loop]: loop: {loop_cnt}, quiet_round: {}, discovered_api_pairs: {}",
-                    self.quiet_round,
-                    self.observer.discovered_api_triples.read().unwrap().len()
+            }
+            let has_new_in_round = round_newly_discovered_pairs.len() >= get_config().num_new_pairs;
+            self.quiet_round = next_quiet_round(self.quiet_round, has_new_in_round, is_stuck);
+            if has_new_in_round {
+                log::debug!(
+                    "Discovered {} new API pairs in this round.",
+                    round_newly_discovered_pairs.len()
                 );
-                if self.quiet_round == get_config().quiet_round && program_len != 0 {
-                    break;
-                }
+                self.schedule
+                    .update_energies_from_api_pairs(&round_newly_discovered_pairs);
             }
-            let result = seed_metas.write_to(&self.deopt.get_seed_meta_path().unwrap());
-            if result.is_err() {
-                log::error!("Failed to write seed meta data!");
+            self.dump_schedule();
+            // steer the next generations away from the pairs just found.
+            prompt.add_avoid_pairs(&pairs_of_triples(round_newly_discovered_pairs.iter()));
+            self.schedule.update_prompt_for_api_mode(prompt)?;
+            if self.is_low_diversity() {
+                log::warn!("The diversity of combinations is low, hard reset the prompt.");
+                self.hard_reset_prompt(prompt);
+            }
+            loop_cnt += 1;
+            logger.log_round_duration(round_start.elapsed());
+            self.tune_n_sample(logger);
+            logger.reset_round();
+            self.save_observer_snapshot();
+            self.checkpoint(logger, prompt, loop_cnt)?;
+            log::info!(
+                "[Mutate Loop]: loop: {loop_cnt}, quiet_round: {}, diversity: {:.2}, discovered_api_pairs: {}",
+                self.quiet_round,
+                self.schedule.get_diversity(),
+                self.observer.discovered_api_triples.read().unwrap().len()
+            );
+            let discovered = self.observer.get_discovered_pair_num();
+            let total = self
+                .observer
+                .total_possible_pairs(get_config().type_compatible_pairs);
+            log::info!(
+                "Discovered API pairs: {discovered}/{total} ({:.2}%)",
+                100.0 * discovered as f32 / total.max(1) as f32
+            );
+            if let Some(target) = pair_target {
+                log::info!(
+                    "Progress toward the pair target: {discovered}/{target} ({:.1}%)",
+                    100.0 * discovered as f32 / target.max(1) as f32
+                );
             }
-
         }
-        log::info!("Fuzzing loop finished. Starting minimization...");
+        Ok(loop_cnt)
+    }
 
+    /// Minimize the corpus by the minimizer of the generation mode.
+    fn minimize_corpus(&self) -> Result<()> {
         match get_config().generation_mode {
             config::GenerationModeP::FuzzDriver => {
                 log::info!("Minimizing corpus by branch coverage...");
@@ -555,14 +1520,363 @@ impl Fuzzer {
 
     pub fn fuzzer_run(&self) -> Result<()> {
         self.transform_seeds_to_fuzzers()?;
-        self.executor.run_libfuzzer(false, None, None)?;
-        self.executor.run_libfuzzer(true, None, None)?;
+        let time_limit = config::get_fuzzer_run_time_limit(&self.deopt.config);
+        self.executor.run_libfuzzer(false, time_limit, None)?;
+        self.executor.run_libfuzzer(true, time_limit, None)?;
         Ok(())
     }
 }
 
+/// Generate for `prompt`, and request the programs truncated by the token limit again with raised max tokens.
+/// The raised max tokens are kept for the following requests.
+fn generate_with_escalation(
+    handler: &dyn request::Handler,
+    prompt: &Prompt,
+    temperature: f32,
+) -> Result<GenerationResult> {
+    let mut result = handler.generate(prompt, temperature)?;
+    loop {
+        let truncated = result.truncated_num();
+        if truncated == 0 {
+            return Ok(result);
+        }
+        let Some(cur) = request::get_max_tokens() else {
+            return Ok(result);
+        };
+        let Some(next) = request::escalate_max_tokens() else {
+            return Ok(result);
+        };
+        log::info!(
+            "{truncated} of {} programs were truncated, raise max tokens from {cur} to {next} and request them again.",
+            result.finish_reasons.len()
+        );
+        result.drop_truncated();
+        let retried = handler.generate_n(prompt, temperature, truncated)?;
+        result.merge(retried);
+    }
+}
+
+/// Warn about the completions cut off by the token limit, which rarely compile.
+fn report_truncation(result: &GenerationResult) {
+    let truncated = result.truncated_num();
+    if truncated > 0 {
+        log::warn!(
+            "{truncated} of {} programs from `{}` were truncated by the token limit (finish_reason: length). Consider setting or raising --max-tokens and --max-tokens-cap.",
+            result.finish_reasons.len(),
+            result.model,
+        );
+    }
+}
+
+/// Pool the programs of the concurrent generations. The timed out generations are skipped,
+/// unless all of them are timed out. Other errors are returned.
+fn pool_generations(generations: Vec<Result<Vec<Program>>>) -> Result<Vec<Program>> {
+    let mut pooled = Vec::new();
+    let mut timeout = None;
+    for programs in generations {
+        match programs {
+            Ok(programs) => pooled.extend(programs),
+            Err(err) if request::is_request_timeout(&err) => {
+                log::warn!("LLM request of a parallel prompt timed out: {err}");
+                timeout.get_or_insert(err);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    match timeout {
+        Some(err) if pooled.is_empty() => Err(err),
+        _ => Ok(pooled),
+    }
+}
+
+/// Format the raw completions of a prompt combination, and count those that fail to parse into a program.
+fn format_raw_completions(combination: &[&str], completions: &[String]) -> (String, usize) {
+    let mut content = String::new();
+    let mut unparsable = 0;
+    for (i, completion) in completions.iter().enumerate() {
+        let parsable = crate::program::serde::is_parsable_completion(completion);
+        if !parsable {
+            unparsable += 1;
+        }
+        content.push_str(&format!(
+            "===== completion {}/{}, combination: {}, parsed: {parsable} =====\n{completion}\n",
+            i + 1,
+            completions.len(),
+            combination.join(", ")
+        ));
+    }
+    (content, unparsable)
+}
+
+/// The quiet rounds after which the fuzz loop is converged: `--fc` in FuzzDriver mode, and
+/// `--quiet-round` in ApiCombination mode.
+fn converge_round() -> usize {
+    match get_config().generation_mode {
+        config::GenerationModeP::FuzzDriver => get_config().fuzz_converge_round,
+        config::GenerationModeP::ApiCombination => get_config().quiet_round,
+    }
+}
+
+/// A round without new coverage (or new API pairs) is quiet, unless the generation is stuck.
+fn next_quiet_round(quiet_round: usize, has_new: bool, is_stuck: bool) -> usize {
+    if has_new {
+        0
+    } else if is_stuck {
+        quiet_round
+    } else {
+        quiet_round + 1
+    }
+}
+
 impl Drop for Fuzzer {
     fn drop(&mut self) {
         log::info!("Config: {:#?}", get_config());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_point_args() {
+        let args: Vec<String> = ["zlib", "--entry-points", "decode,encode", "-c", "4"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        assert_eq!(
+            entry_point_args(&args, "decode"),
+            ["zlib", "-c", "4", "--entry-point", "decode"]
+        );
+        let args = vec!["zlib".to_string(), "--entry-points=decode,encode".to_string()];
+        assert_eq!(entry_point_args(&args, "encode"), ["zlib", "--entry-point", "encode"]);
+    }
+
+    #[test]
+    fn test_retain_type_compatible_triples() {
+        let sig = |name: &str, params: &[&str], ret: &str| {
+            let sig = ApiSignature {
+                name: name.to_string(),
+                ret_type: ret.to_string(),
+                param_types: params.iter().map(|ty| ty.to_string()).collect(),
+            };
+            (name.to_string(), sig)
+        };
+        let signatures = HashMap::from([
+            sig("gzopen", &["const char *", "const char *"], "gzFile"),
+            sig("gzdopen", &["int", "const char *"], "gzFile"),
+            sig("gzbuffer", &["gzFile", "unsigned int"], "int"),
+            sig("gzclose", &["gzFile"], "int"),
+        ]);
+        let triple = |a: &str, b: &str, c: &str| (a.to_string(), b.to_string(), c.to_string());
+        let mut triples = vec![
+            triple("gzopen", "gzbuffer", "gzclose"),
+            triple("gzopen", "gzdopen", "gzclose"),
+            // memset is not a library API.
+            triple("memset", "gzopen", "gzclose"),
+        ];
+        Fuzzer::retain_type_compatible_triples(&mut triples, &signatures);
+        assert_eq!(triples, vec![triple("gzopen", "gzbuffer", "gzclose")]);
+    }
+
+    #[test]
+    fn test_resume_from_previous_log() -> Result<()> {
+        let output_dir = std::env::temp_dir().join("prompt_fuzz_resume_test");
+        let _ = std::fs::remove_dir_all(&output_dir);
+        std::fs::create_dir_all(&output_dir)?;
+        let prior_log = "\
+[INFO] [Mutate Loop]: loop: 1, quiet_round: 1, discovered_api_pairs: 3
+[INFO] [round] total: 10, succ: 2, syntax: 3, link: 1, execute: 2, hang: 0, fuzzer: 1, coverage: 1
+[INFO] [global] total: 10, succ: 2, syntax: 3, link: 1, execute: 2, hang: 0, fuzzer: 1, coverage: 1
+[INFO] [Mutate Loop]: loop: 2, quiet_round: 4, discovered_api_pairs: 3
+[INFO] [round] total: 10, succ: 1, syntax: 5, link: 1, execute: 1, hang: 1, fuzzer: 1, coverage: 0
+[INFO] [global] total: 20, succ: 3, syntax: 8, link: 2, execute: 3, hang: 1, fuzzer: 2, coverage: 1
+";
+        std::fs::write(output_dir.join("fuzzer_2024-01-01_10-00-00.log"), "")?;
+        std::fs::write(output_dir.join("fuzzer_2024-01-02_10-00-00.log"), prior_log)?;
+        std::fs::write(output_dir.join("llm_server.log"), "")?;
+        // the log of the current execution.
+        std::fs::write(output_dir.join("fuzzer_2024-01-03_10-00-00.log"), "")?;
+
+        let log_file = find_previous_log(&output_dir)?.unwrap();
+        assert_eq!(log_file, output_dir.join("fuzzer_2024-01-02_10-00-00.log"));
+        assert_eq!(
+            find_current_log(&output_dir)?.unwrap(),
+            output_dir.join("fuzzer_2024-01-03_10-00-00.log")
+        );
+        let state = ResumeState::from_log(&std::fs::read_to_string(log_file)?)?;
+        assert_eq!(state.quiet_round, 4);
+        assert_eq!(state.seed_id, 20);
+        assert_eq!(state.logger.get_gc_total(), 20);
+
+        std::fs::remove_dir_all(&output_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_generation_backoff() {
+        assert_eq!(empty_generation_backoff(1), Duration::from_secs(1));
+        assert_eq!(empty_generation_backoff(2), Duration::from_secs(2));
+        assert_eq!(empty_generation_backoff(100), Duration::from_secs(16));
+    }
+
+    #[test]
+    fn test_pool_generations() {
+        let timeout = || -> Result<Vec<Program>> {
+            Err(request::http::HttpClientError::TimeoutError("request".to_string()).into())
+        };
+        let pooled = pool_generations(vec![
+            Ok(vec![Program::new("int a;")]),
+            timeout(),
+            Ok(vec![Program::new("int b;"), Program::new("int c;")]),
+        ])
+        .unwrap();
+        assert_eq!(pooled.len(), 3);
+        assert!(pool_generations(vec![timeout(), timeout()]).is_err());
+        assert!(pool_generations(vec![Ok(vec![]), Err(eyre::eyre!("bad request"))]).is_err());
+    }
+
+    #[test]
+    fn test_format_raw_completions() {
+        let completions = vec![
+            "Here it is:\n```cpp\nint LLVMFuzzerTestOneInput(const uint8_t *data, size_t size) {\n  return 0;\n}\n```".to_string(),
+            "I cannot help with that.".to_string(),
+        ];
+        let (content, unparsable) = format_raw_completions(&["compress", "uncompress"], &completions);
+        assert_eq!(unparsable, 1);
+        assert!(content.starts_with(
+            "===== completion 1/2, combination: compress, uncompress, parsed: true =====\nHere it is:\n"
+        ));
+        assert!(content.contains(
+            "===== completion 2/2, combination: compress, uncompress, parsed: false =====\nI cannot help with that.\n"
+        ));
+    }
+
+    #[test]
+    fn test_persist_through_barren_rounds() {
+        // (has_new, is_stuck) of each round, with a converge limit of 3 quiet rounds.
+        let rounds = [
+            (true, false),
+            (false, false),
+            (false, true),
+            (false, false),
+            (true, false),
+            (false, false),
+            (false, false),
+            (false, false),
+        ];
+        let mut quiet_round = 0;
+        let mut converged_at = None;
+        for (i, (has_new, is_stuck)) in rounds.into_iter().enumerate() {
+            quiet_round = next_quiet_round(quiet_round, has_new, is_stuck);
+            if quiet_round >= 3 {
+                converged_at = Some(i);
+                break;
+            }
+        }
+        // the barren and stuck rounds before the last new round do not terminate the loop.
+        assert_eq!(converged_at, Some(7));
+    }
+
+    /// A handler whose requests all time out, so that every round generates no program.
+    struct TimedOutHandler(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+    impl request::Handler for TimedOutHandler {
+        fn generate(&self, _prompt: &Prompt, _temperature: f32) -> Result<GenerationResult> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            Err(request::http::HttpClientError::TimeoutError("stub".to_string()).into())
+        }
+
+        fn generate_single(&self, _prompt: &Prompt, _temperature: f32) -> Result<Program> {
+            eyre::bail!("no plan in the stub handler")
+        }
+    }
+
+    #[test]
+    fn test_api_rounds_persist_through_failed_rounds() -> Result<()> {
+        // the config and the logger may be initialized by another test in the process already.
+        if config::CONFIG_INSTANCE.get().is_none() {
+            crate::config::Config::init_test("cJSON");
+        }
+        // the observer snapshot and the checkpoints of the rounds go to a work dir of this test only.
+        let work_dir = std::env::temp_dir().join(format!("prompt_fuzz_api_rounds_{}", std::process::id()));
+        let deopt = Deopt::new("cJSON".to_string())?.with_dir_overrides(Some(work_dir.clone()), None);
+        let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut fuzzer = Fuzzer {
+            executor: Executor {
+                header_cmd: String::new(),
+                deopt: deopt.clone(),
+            },
+            observer: Observer::new(&deopt),
+            deopt,
+            schedule: Schedule::new(),
+            handler: Box::new(TimedOutHandler(calls.clone())),
+            quiet_round: 0,
+            temperature: 0.6,
+            stuck_round: 0,
+            infra_batches: 0,
+            succ_ratio: request::SuccRatioTracker::default(),
+            transcripts: None,
+            judge: None,
+            #[cfg(feature = "async")]
+            pipeline: None,
+        };
+        // the rounds save the combination of the prompt to the library of the global config, which is removed at the end unless it existed.
+        let prompt_path = Deopt::new(get_library_name())?.get_library_misc_dir()?.join("prompt.json");
+        let keep_prompt_file = prompt_path.exists();
+        let mut prompt = Prompt::new(Vec::new());
+        let mut logger = ProgramLogger::default();
+        let mut seed_metas = SeedMetas::new(&Instant::now());
+
+        // the failed rounds are neither quiet nor the end of the loop, which runs on until the timeout.
+        let start = Instant::now();
+        let timeout = Duration::from_millis(100);
+        let rounds = fuzzer.api_combination_rounds(
+            &mut prompt,
+            &mut logger,
+            &mut seed_metas,
+            &start,
+            Some(timeout),
+        )?;
+        assert!(start.elapsed() >= timeout);
+        assert!(rounds > converge_round(), "stopped after {rounds} rounds");
+        assert_eq!(calls.load(std::sync::atomic::Ordering::Relaxed), rounds);
+        assert_eq!(fuzzer.quiet_round, 0);
+
+        // a converged loop stops before any request.
+        let rounds_before = rounds;
+        fuzzer.quiet_round = converge_round();
+        let rounds = fuzzer.api_combination_rounds(
+            &mut prompt,
+            &mut logger,
+            &mut seed_metas,
+            &start,
+            None,
+        )?;
+        assert_eq!(rounds, 0);
+        assert_eq!(
+            calls.load(std::sync::atomic::Ordering::Relaxed),
+            rounds_before
+        );
+
+        if !keep_prompt_file && prompt_path.exists() {
+            std::fs::remove_file(&prompt_path)?;
+        }
+        if work_dir.exists() {
+            std::fs::remove_dir_all(&work_dir)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_too_long_program() {
+        let program = Program::new("int a = 0;\n\n  \nint b = a;\nint c = b;");
+        assert_eq!(program.get_line_num(), 3);
+        assert!(check_program_len(&program, None).is_none());
+        assert!(check_program_len(&program, Some(3)).is_none());
+        assert!(matches!(
+            check_program_len(&program, Some(2)),
+            Some(ProgramError::TooLong(_))
+        ));
+    }
+}
@@ -1,5 +1,6 @@
 pub mod analysis;
 pub mod ast;
+pub mod checkpoint;
 pub mod config;
 pub mod deopt;
 pub mod execution;
@@ -9,7 +10,9 @@ pub mod minimize;
 pub mod mutation;
 pub mod program;
 pub mod cntg_program;
+pub mod progress;
 pub mod request;
+pub mod summary;
 use async_openai::error::OpenAIError;
 use config::get_library_name;
 use deopt::Deopt;
@@ -27,6 +30,7 @@ pub fn init_logger() -> Result<()> {
         .format_for_files(opt_format)
         .log_to_file(log_file)
         .duplicate_to_stdout(flexi_logger::Duplicate::Debug)
+        .format_for_stdout(progress::bridge_format)
         .rotate(
             flexi_logger::Criterion::Size(1 << 30),
             Naming::Timestamps,
@@ -39,6 +43,7 @@ pub fn init_logger() -> Result<()> {
 pub fn init_debug_logger() -> Result<()> {
     flexi_logger::Logger::try_with_env_or_str("debug")?
         .log_to_stdout()
+        .format_for_stdout(progress::bridge_format)
         .start()?;
     Ok(())
 }
@@ -55,6 +60,11 @@ pub enum FuzzerError {
     RetryError(String, u8),
     #[error("Cannot find `input_data.size();`")]
     FuzzerInputError,
+    #[error("The compilation of `{0}` timed out after {1}s and was killed.")]
+    CompileTimeout(String, u64),
+    /// A failure of our own setup that no program could fix, e.g., a missing build library or compiler.
+    #[error("{0}")]
+    Infra(String),
 }
 
 pub enum Critical {
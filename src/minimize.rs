@@ -1,47 +1,154 @@
-use crate::{deopt::Deopt, feedback::observer::Observer, program::Program};
+use crate::{
+    deopt::Deopt,
+    feedback::{branches::Branch, observer::Observer},
+    program::Program,
+};
 use eyre::Result;
-use std::collections::HashSet;
-use std::path::PathBuf;
-fn extract_api_pairs_from_program(program: &Program) -> HashSet<(String, String)> {
-    // This logic is borrowed from fuzzer.rs
-    let mut parser = tree_sitter::Parser::new();
-    parser
-        .set_language(tree_sitter_cpp::language())
-        .expect("Failed to load C++ grammar");
-    let tree = parser.parse(&program.statements, None).unwrap();
-    let mut calls = Vec::new();
-    extract_calls_recursive(tree.root_node(), &program.statements, &mut calls);
-    calls
-        .windows(2)
-        .map(|w| (w[0].clone(), w[1].clone()))
-        .collect()
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// The branches covered by a corpus.
+#[derive(Debug, Default)]
+pub struct CoverageSet {
+    branches: HashSet<Branch>,
 }
 
-// Helper for the helper
-fn extract_calls_recursive(node: tree_sitter::Node, source: &str, calls: &mut Vec<String>) {
-    if node.kind() == "call_expression" {
-        if let Some(function_node) = node.child_by_field_name("function") {
-            if let Ok(func_name) = function_node.utf8_text(source.as_bytes()) {
-                calls.push(func_name.to_string());
+impl CoverageSet {
+    pub fn from_observer(observer: &Observer) -> Self {
+        observer.get_covered_branch().into_iter().collect()
+    }
+
+    /// The branches covered by the seeds, None if the coverage of any seed is unavailable.
+    pub fn from_seeds(seeds: &[PathBuf], deopt: &Deopt) -> Option<Self> {
+        let mut observer = Observer::new(deopt);
+        for seed in seeds {
+            let coverage = Program::load_from_path(seed)
+                .and_then(|program| deopt.get_seed_coverage(program.id));
+            match coverage {
+                Ok(coverage) => observer.merge_coverage(&coverage),
+                Err(err) => {
+                    log::warn!("The coverage of {seed:?} is unavailable: {err}");
+                    return None;
+                }
             }
         }
+        Some(Self::from_observer(&observer))
+    }
+
+    /// The branches covered by a single seed.
+    pub fn from_seed(seed: &Path, deopt: &Deopt) -> Result<Self> {
+        let coverage = deopt.get_seed_coverage(Program::load_from_path(seed)?.id)?;
+        let mut observer = Observer::new(deopt);
+        observer.merge_coverage(&coverage);
+        Ok(Self::from_observer(&observer))
+    }
+
+    pub fn len(&self) -> usize {
+        self.branches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.branches.is_empty()
+    }
+}
+
+impl FromIterator<Branch> for CoverageSet {
+    fn from_iter<T: IntoIterator<Item = Branch>>(iter: T) -> Self {
+        Self {
+            branches: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Fail if the minimized corpus lost more than `epsilon` (a fraction) of the branches covered before minimization.
+pub fn verify_no_regression(before: &CoverageSet, after: &CoverageSet, epsilon: f32) -> Result<()> {
+    let lost = before.branches.difference(&after.branches).count();
+    log::info!(
+        "Branch coverage before minimization: {}, after: {}, lost: {lost}",
+        before.len(),
+        after.len()
+    );
+    if before.is_empty() {
+        return Ok(());
     }
-    for child in node.children(&mut node.walk()) {
-        extract_calls_recursive(child, source, calls);
+    let lost_ratio = lost as f32 / before.len() as f32;
+    if lost_ratio > epsilon {
+        eyre::bail!(
+            "The minimization dropped {lost} of {} covered branches ({:.2}%), more than the epsilon {:.2}%.",
+            before.len(),
+            lost_ratio * 100.0,
+            epsilon * 100.0
+        );
     }
+    Ok(())
+}
+
+/// The indices of the seeds that can be removed together without reducing the branches covered by all the seeds.
+/// The seeds covering fewer branches are tried first, so the richer seeds are kept.
+pub fn find_redundant(sets: &[CoverageSet]) -> Vec<usize> {
+    let mut counts: HashMap<&Branch, usize> = HashMap::new();
+    for set in sets {
+        for branch in &set.branches {
+            *counts.entry(branch).or_default() += 1;
+        }
+    }
+    let mut order: Vec<usize> = (0..sets.len()).collect();
+    order.sort_by_key(|&i| sets[i].len());
+    let mut redundant = Vec::new();
+    for i in order {
+        if sets[i].branches.iter().all(|branch| counts[branch] > 1) {
+            for branch in &sets[i].branches {
+                *counts.get_mut(branch).unwrap() -= 1;
+            }
+            redundant.push(i);
+        }
+    }
+    redundant.sort();
+    redundant
+}
+
+/// Check the coverage of the kept seeds against all the succ seeds, skipped if the coverage is unavailable,
+/// e.g., not collected in API mode.
+fn verify_seeds_no_regression(all_seeds: &[PathBuf], kept_seeds: &[PathBuf], deopt: &Deopt) -> Result<()> {
+    let (Some(before), Some(after)) = (
+        CoverageSet::from_seeds(all_seeds, deopt),
+        CoverageSet::from_seeds(kept_seeds, deopt),
+    ) else {
+        log::warn!("Skip the coverage regression check of the minimization.");
+        return Ok(());
+    };
+    verify_no_regression(&before, &after, crate::config::get_minimize_epsilon())
+}
+fn extract_api_pairs_from_program(program: &Program) -> HashSet<(String, String)> {
+    crate::program::extract_function_calls(&program.statements)
+        .windows(2)
+        .map(|w| (w[0].clone(), w[1].clone()))
+        .collect()
+}
+
+/// The unique adjacent call pairs over all the programs.
+pub fn unique_api_pairs<'a>(programs: impl IntoIterator<Item = &'a Program>) -> HashSet<(String, String)> {
+    programs
+        .into_iter()
+        .flat_map(extract_api_pairs_from_program)
+        .collect()
 }
 
 /// Minimize seed programs by unique API pairs
+/// The seeds are selected without comparing coverage, and the kept ones are only checked against `--minimize-epsilon`
+/// if the coverage of the succ seeds was collected.
 pub fn minimize_by_api_pairs(deopt: &Deopt) -> Result<()> {
     let succ_seeds_dir = deopt.get_library_succ_seed_dir()?;
     let final_seeds_dir = deopt.get_library_seed_dir()?;
 
     // 1. Get all successful programs and the API pairs they contain.
     let mut programs_with_pairs: Vec<(PathBuf, HashSet<(String, String)>)> = Vec::new();
+    let mut all_seeds = Vec::new();
     for file in crate::deopt::utils::read_sort_dir(&succ_seeds_dir)? {
         if file.is_dir() {
             continue;
         }
+        all_seeds.push(file.clone());
         let program = Program::load_from_path(&file)?;
         let pairs = extract_api_pairs_from_program(&program);
         if !pairs.is_empty() {
@@ -72,9 +179,9 @@ pub fn minimize_by_api_pairs(deopt: &Deopt) -> Result<()> {
     }
     std::fs::create_dir_all(&final_seeds_dir)?;
     let num = final_seeds.len();
-    for seed_path in final_seeds {
+    for seed_path in &final_seeds {
         let dest_path = final_seeds_dir.join(seed_path.file_name().unwrap());
-        std::fs::copy(&seed_path, &dest_path)?;
+        std::fs::copy(seed_path, &dest_path)?;
         log::info!("Kept unique API seed: {:?}", dest_path);
     }
 
@@ -84,7 +191,7 @@ pub fn minimize_by_api_pairs(deopt: &Deopt) -> Result<()> {
         covered_pairs.len()
     );
 
-    Ok(())
+    verify_seeds_no_regression(&all_seeds, &final_seeds, deopt)
 }
 
 /// Minimize seed programs by unique branches
@@ -101,10 +208,12 @@ pub fn minimize(deopt: &Deopt) -> Result<()> {
 
     // iterate the sorted seeds, only the seeds still triger unique branch(s) are retained.
     let mut observer = Observer::new(deopt);
+    let mut before = Observer::new(deopt);
     for (program_path, _) in program_coverage {
         let program = Program::load_from_path(&program_path)?;
         let seed = deopt.get_seed_path_by_id(program.id)?;
         let coverage = deopt.get_seed_coverage(program.id)?;
+        before.merge_coverage(&coverage);
         let unique_branches = observer.has_unique_branch(&coverage);
         if unique_branches.is_empty() {
             if seed.exists() {
@@ -124,5 +233,71 @@ pub fn minimize(deopt: &Deopt) -> Result<()> {
     }
 
     log::info!("{}", observer.dump_global_states());
-    Ok(())
+    verify_no_regression(
+        &CoverageSet::from_observer(&before),
+        &CoverageSet::from_observer(&observer),
+        crate::config::get_minimize_epsilon(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn branch(line: usize, taken: usize) -> Branch {
+        [line, 1, line, 8, 0, 0, 4, taken]
+    }
+
+    #[test]
+    fn test_verify_no_regression() {
+        let before: CoverageSet = (1..=10).map(|line| branch(line, 1)).collect();
+        let same: CoverageSet = (1..=10).map(|line| branch(line, 1)).collect();
+        assert!(verify_no_regression(&before, &same, 0.0).is_ok());
+
+        // a new branch does not make up for a lost one.
+        let lost_one: CoverageSet =
+            (2..=10).map(|line| branch(line, 1)).chain([branch(1, 0)]).collect();
+        assert!(verify_no_regression(&before, &lost_one, 0.0).is_err());
+        assert!(verify_no_regression(&before, &lost_one, 0.1).is_ok());
+        assert!(verify_no_regression(&before, &lost_one, 0.05).is_err());
+
+        assert!(verify_no_regression(&CoverageSet::default(), &CoverageSet::default(), 0.0).is_ok());
+    }
+
+    #[test]
+    fn test_unique_api_pairs() {
+        let programs = [
+            Program::new("void f() { a(); b(); c(); }"),
+            Program::new("void g() { b(); c(); b(); }"),
+        ];
+        let pairs = unique_api_pairs(&programs);
+        assert_eq!(pairs.len(), 3);
+        assert!(pairs.contains(&("c".to_string(), "b".to_string())));
+        assert!(unique_api_pairs(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_find_redundant() {
+        let sets: Vec<CoverageSet> = vec![
+            (1..=5).map(|line| branch(line, 1)).collect(),
+            (1..=3).map(|line| branch(line, 1)).collect(),
+            (4..=6).map(|line| branch(line, 1)).collect(),
+            CoverageSet::default(),
+            // the same as the first one, only one of them is redundant.
+            (1..=5).map(|line| branch(line, 1)).collect(),
+        ];
+        let redundant = find_redundant(&sets);
+        assert_eq!(redundant, vec![0, 1, 3]);
+
+        // removing the redundant seeds together keeps the coverage.
+        let all: CoverageSet = sets.iter().flat_map(|set| set.branches.iter().copied()).collect();
+        let kept: CoverageSet = sets
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !redundant.contains(i))
+            .flat_map(|(_, set)| set.branches.iter().copied())
+            .collect();
+        assert!(verify_no_regression(&all, &kept, 0.0).is_ok());
+        assert!(find_redundant(&[]).is_empty());
+    }
 }
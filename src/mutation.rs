@@ -118,7 +118,7 @@ fn prompt_crossover(prompt1: Vec<String>, prompt2: Vec<String>) -> Vec<String> {
     }
 }
 
-pub fn mutate_prompt(prompt: &mut Prompt, schedule: &Schedule, deopt: &mut Deopt) {
+pub fn mutate_prompt(prompt: &mut Prompt, schedule: &mut Schedule, deopt: &mut Deopt) {
     let mutators = vec![
         MutationOperator::Add,
         MutationOperator::Replace,
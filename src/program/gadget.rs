@@ -10,7 +10,7 @@ use ctype::*;
 use eyre::{Context, Result};
 use once_cell::sync::OnceCell;
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use super::{rand::random_sample, Deserialize, Deserializer};
 
@@ -32,18 +32,8 @@ pub fn get_func_gadgets() -> &'static Vec<FuncGadget> {
             return gadgets;
         }
         let mut gadgets = parse_func_gadgets(&deopt).unwrap();
-        if let Some(ban_funcs) = &deopt.config.ban {
-            gadgets.retain(|x| {
-                for ban_func in ban_funcs {
-                    let re = Regex::new(ban_func)
-                        .unwrap_or_else(|_| panic!("Error regex in config.yaml: {ban_func}"));
-                    if re.is_match(x.get_func_name()) {
-                        return false;
-                    }
-                }
-                true
-            })
-        }
+        let ban_list = compile_ban_list(&deopt.config.ban);
+        gadgets.retain(|x| !is_banned(x.get_func_name(), &ban_list));
         func_gadget::dump_func_gadgets(&gadgets, &deopt).unwrap();
         log::debug!("Parsed {} function gadgets.", gadgets.len());
         if gadgets.is_empty() {
@@ -53,6 +43,174 @@ pub fn get_func_gadgets() -> &'static Vec<FuncGadget> {
     })
 }
 
+/// The signature of an exported API, in the types written in the library headers.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ApiSignature {
+    pub name: String,
+    pub ret_type: String,
+    pub param_types: Vec<String>,
+}
+
+impl From<&FuncGadget> for ApiSignature {
+    fn from(gadget: &FuncGadget) -> Self {
+        Self {
+            name: gadget.name.clone(),
+            ret_type: gadget.alias_ret_type.trim().to_string(),
+            param_types: gadget
+                .alias_arg_types
+                .iter()
+                .map(|ty| ty.trim().to_string())
+                .collect(),
+        }
+    }
+}
+
+impl ApiSignature {
+    /// The non-primitive types this API produces: the return type and the types of mutable pointer params.
+    pub fn get_output_types(&self) -> HashSet<String> {
+        let mut outputs: HashSet<String> = get_base_type(&self.ret_type).into_iter().collect();
+        for ty in &self.param_types {
+            let is_mut_pointer = ty.contains('*') && !ty.trim_start().starts_with("const");
+            if is_mut_pointer {
+                outputs.extend(get_base_type(ty));
+            }
+        }
+        outputs
+    }
+
+    /// The non-primitive types this API consumes.
+    pub fn get_input_types(&self) -> HashSet<String> {
+        self.param_types.iter().filter_map(|ty| get_base_type(ty)).collect()
+    }
+
+    /// The signature in the types given by `canonical`, e.g., `ctype::retrieve_canonical_type`, so that a pointer
+    /// typedef like `z_streamp` is a pointer to the same type as the `z_stream *` written elsewhere.
+    pub fn canonicalize(&self, canonical: impl Fn(&str) -> String) -> Self {
+        Self {
+            name: self.name.clone(),
+            ret_type: canonical(&self.ret_type),
+            param_types: self.param_types.iter().map(|ty| canonical(ty)).collect(),
+        }
+    }
+}
+
+/// Whether data can flow from `a` to `b`, i.e., the return type or out-params of `a` can feed the params of `b`.
+/// The pointer typedefs are only known as pointers in the canonical signatures, see `ApiSignature::canonicalize`.
+pub fn is_type_compatible(a: &ApiSignature, b: &ApiSignature) -> bool {
+    !a.get_output_types().is_disjoint(&b.get_input_types())
+}
+
+/// Compile the regexes of functions banned in config.yaml.
+pub fn compile_ban_list(ban: &Option<Vec<String>>) -> Vec<Regex> {
+    let mut ban_list = Vec::new();
+    for ban_func in ban.iter().flatten() {
+        let re = Regex::new(ban_func)
+            .unwrap_or_else(|_| panic!("Error regex in config.yaml: {ban_func}"));
+        ban_list.push(re);
+    }
+    ban_list
+}
+
+pub fn is_banned(func: &str, ban_list: &[Regex]) -> bool {
+    ban_list.iter().any(|re| re.is_match(func))
+}
+
+/// Which functions can be selected into combinations and prompts: those in `--include-only-apis` if given,
+/// and neither banned by config.yaml nor excluded by `--exclude-apis`.
+#[derive(Debug, Default)]
+pub struct ApiFilter {
+    ban_list: Vec<Regex>,
+    include: Option<HashSet<String>>,
+    exclude: HashSet<String>,
+}
+
+/// The names of `apis` that are not any of `gadgets`, sorted.
+fn unknown_names<'a>(apis: &'a HashSet<String>, gadgets: &[FuncGadget]) -> Vec<&'a str> {
+    let mut unknown: Vec<&str> = apis
+        .iter()
+        .filter(|api| !gadgets.iter().any(|gadget| &gadget.name == *api))
+        .map(|api| api.as_str())
+        .collect();
+    unknown.sort();
+    unknown
+}
+
+impl ApiFilter {
+    pub fn new(ban: &Option<Vec<String>>, include: Option<&[String]>, exclude: &[String]) -> Self {
+        Self {
+            ban_list: compile_ban_list(ban),
+            include: include.map(|include| include.iter().cloned().collect()),
+            exclude: exclude.iter().cloned().collect(),
+        }
+    }
+
+    /// The exclusion is applied after the inclusion.
+    pub fn is_selectable(&self, func: &str) -> bool {
+        self.include.as_ref().is_none_or(|include| include.contains(func))
+            && !is_banned(func, &self.ban_list)
+            && !self.exclude.contains(func)
+    }
+
+    /// The excluded names that are not any of `gadgets`, sorted.
+    pub fn unknown_apis(&self, gadgets: &[FuncGadget]) -> Vec<&str> {
+        unknown_names(&self.exclude, gadgets)
+    }
+
+    /// The included names that are not any of `gadgets`, sorted.
+    pub fn unknown_included_apis(&self, gadgets: &[FuncGadget]) -> Vec<&str> {
+        self.include
+            .as_ref()
+            .map_or_else(Vec::new, |include| unknown_names(include, gadgets))
+    }
+
+    pub fn filter<'a>(&self, gadgets: &'a [FuncGadget]) -> Vec<&'a FuncGadget> {
+        gadgets
+            .iter()
+            .filter(|x| self.is_selectable(x.get_func_name()))
+            .collect()
+    }
+}
+
+/// The filter of the library's config.yaml, `--include-only-apis` and `--exclude-apis`.
+pub fn get_api_filter() -> &'static ApiFilter {
+    static FILTER: OnceCell<ApiFilter> = OnceCell::new();
+    FILTER.get_or_init(|| {
+        let deopt = Deopt::new(config::get_library_name()).unwrap();
+        let include = config::get_include_only_apis();
+        ApiFilter::new(&deopt.config.ban, include.as_deref(), &config::get_exclude_apis())
+    })
+}
+
+/// Check the APIs of `--include-only-apis` against the library, and that some APIs are selectable.
+pub fn check_api_filter() -> Result<()> {
+    let unknown = get_api_filter().unknown_included_apis(get_func_gadgets());
+    if !unknown.is_empty() {
+        eyre::bail!("--include-only-apis: {} are not APIs of the library", unknown.join(", "));
+    }
+    if get_selectable_func_gadgets().is_empty() {
+        eyre::bail!("No API of the library is selectable, check --include-only-apis and --exclude-apis");
+    }
+    Ok(())
+}
+
+/// Whether this function is banned by the library's config.yaml, or filtered out by `--include-only-apis` or `--exclude-apis`.
+pub fn is_banned_func(func: &str) -> bool {
+    !get_api_filter().is_selectable(func)
+}
+
+/// The function gadgets that can be selected into combinations and prompts.
+/// The cached gadgets may be dumped before the ban list is updated, so the banned ones are filtered again.
+pub fn get_selectable_func_gadgets() -> &'static Vec<&'static FuncGadget> {
+    static GADGETS: OnceCell<Vec<&'static FuncGadget>> = OnceCell::new();
+    GADGETS.get_or_init(|| {
+        let filter = get_api_filter();
+        for api in filter.unknown_apis(get_func_gadgets()) {
+            log::warn!("--exclude-apis: {api} is not an API of the library, ignore it.");
+        }
+        filter.filter(get_func_gadgets())
+    })
+}
+
 #[derive(Eq, PartialEq, Hash, Clone, serde::Deserialize, serde::Serialize)]
 pub struct TypeGadget {
     /// type name
@@ -569,6 +727,11 @@ impl FuncGadget {
         sig
     }
 
+    /// Whether the outputs of this API can feed the inputs of `other`, as `is_type_compatible` of their signatures.
+    pub fn can_feed(&self, other: &FuncGadget) -> bool {
+        is_type_compatible(&ApiSignature::from(self), &ApiSignature::from(other))
+    }
+
     pub fn get_alias_arg_types(&self) -> &Vec<String> {
         &self.alias_arg_types
     }
@@ -942,18 +1105,21 @@ pub mod ctype {
 
     /// Recover the canonical type for a human wrappered type.
     pub fn retrieve_canonical_type(ty: &str) -> String {
+        resolve_typedefs(ty, |ty_name| {
+            get_type_gadget(ty_name)
+                .filter(|ty_gadget| matches!(&ty_gadget.class, TypeClass::Typedef))
+                .and_then(|ty_gadget| ty_gadget.underly_ty.clone())
+        })
+    }
+
+    /// Replace the typedef names in `ty` by their underlying types given by `underlying`, until it is not a typedef.
+    pub fn resolve_typedefs(ty: &str, underlying: impl Fn(&str) -> Option<String>) -> String {
         let mut full_type = String::from(ty);
         let mut ty_name = get_unsugared_unqualified_type(ty);
-        while let Some(ty_gadget) = get_type_gadget(&ty_name) {
-            if matches!(&ty_gadget.class, TypeClass::Typedef) {
-                if let Some(underly_ty) = &ty_gadget.underly_ty {
-                    let re = Regex::new(&format!(r"\b{ty_name}\b")).unwrap();
-                    full_type = re.replace(&full_type, underly_ty).to_string();
-                    ty_name = get_unsugared_unqualified_type(underly_ty);
-                    continue;
-                }
-            }
-            break;
+        while let Some(underly_ty) = underlying(&ty_name) {
+            let re = Regex::new(&format!(r"\b{ty_name}\b")).unwrap();
+            full_type = re.replace(&full_type, underly_ty.as_str()).to_string();
+            ty_name = get_unsugared_unqualified_type(&underly_ty);
         }
         full_type
     }
@@ -986,6 +1152,29 @@ pub mod ctype {
     }
 }
 
+/// The unqualified type without pointers, None if it is primitive.
+fn get_base_type(ty: &str) -> Option<String> {
+    let base = get_unsugared_unqualified_type(ty);
+    if base.is_empty() || is_primitive_type(&base) {
+        return None;
+    }
+    Some(base)
+}
+
+/// Count the ordered pairs of the APIs of `signatures`.
+/// If `type_compatible`, only the pairs whose first API can feed the second, as `is_type_compatible`, are counted.
+pub fn count_possible_pairs(signatures: &[ApiSignature], type_compatible: bool) -> usize {
+    if !type_compatible {
+        return signatures.len() * signatures.len();
+    }
+    let outputs: Vec<HashSet<String>> = signatures.iter().map(|sig| sig.get_output_types()).collect();
+    let inputs: Vec<HashSet<String>> = signatures.iter().map(|sig| sig.get_input_types()).collect();
+    outputs
+        .iter()
+        .map(|output| inputs.iter().filter(|input| !output.is_disjoint(input)).count())
+        .sum()
+}
+
 pub fn is_library_api(func: &str) -> bool {
     get_func_gadget(func).is_some()
 }
@@ -995,7 +1184,7 @@ pub fn get_func_gadget(func: &str) -> Option<&'static FuncGadget> {
 }
 
 pub fn dump_func_gadgets_tostr() -> String {
-    let gadgets = get_func_gadgets();
+    let gadgets = get_selectable_func_gadgets();
     let gadgets = random_sample(gadgets, crate::config::MAX_CONTEXT_APIS);
     let mut dump_str = vec![];
     for gadget in gadgets {
@@ -1024,6 +1213,128 @@ mod tests {
     use super::{typed_gadget::parse_type_gadgets, *};
     use crate::config::Config;
 
+    #[test]
+    fn test_count_possible_pairs() {
+        // the gadget types of the parsed gadgets differ from the header types.
+        let gadget = |name: &str, args: &[(&str, &str)], ret: (&str, &str)| {
+            FuncGadget::new(
+                name.to_string(),
+                args.iter().map(|_| "arg".to_string()).collect(),
+                args.iter().map(|(ty, _)| ty.to_string()).collect(),
+                args.iter().map(|(_, alias)| alias.to_string()).collect(),
+                ret.0.to_string(),
+                ret.1.to_string(),
+            )
+        };
+        let create = gadget("cJSON_CreateObject", &[], ("FuzzMutPointer<cJSON>", "cJSON *"));
+        let print = gadget(
+            "cJSON_Print",
+            &[("FuzzConstPointer<cJSON>", "const cJSON *")],
+            ("FuzzMutPointer<i8>", "char *"),
+        );
+        let version = gadget("cJSON_Version", &[], ("FuzzConstPointer<i8>", "const char *"));
+        let minify = gadget("cJSON_Minify", &[("FuzzMutPointer<i8>", "char *")], ("()", "void"));
+        let compare = gadget(
+            "cJSON_Compare",
+            &[("FuzzConstPointer<cJSON>", "const cJSON *const"), ("i32", "cJSON_bool")],
+            ("i32", "cJSON_bool"),
+        );
+        assert!(create.can_feed(&print));
+        assert!(!print.can_feed(&create));
+        // primitive types do not make data flows.
+        assert!(!print.can_feed(&minify));
+        assert!(!version.can_feed(&minify));
+
+        let typedefs: HashMap<&str, &str> = HashMap::from([("cJSON_bool", "int")]);
+        let signatures: Vec<ApiSignature> = [&create, &print, &version, &minify, &compare]
+            .into_iter()
+            .map(|gadget| {
+                ApiSignature::from(gadget).canonicalize(|ty| {
+                    ctype::resolve_typedefs(ty, |name| typedefs.get(name).map(|underlying| underlying.to_string()))
+                })
+            })
+            .collect();
+        assert_eq!(count_possible_pairs(&signatures, false), 25);
+        // only (create, print) and (create, compare), the typedef of int makes no data flow.
+        assert_eq!(count_possible_pairs(&signatures, true), 2);
+    }
+
+    #[test]
+    fn test_api_signature_from_gadget() -> Result<()> {
+        Config::init_test("cJSON");
+        let deopt = Deopt::new("cJSON".to_string())?;
+        let apis = deopt.exported_apis();
+        let parse = apis
+            .iter()
+            .find(|sig| sig.name == "cJSON_Parse")
+            .expect("cJSON_Parse is exported");
+        assert_eq!(parse.ret_type, "cJSON *");
+        assert_eq!(parse.param_types, vec!["const char *"]);
+        assert!(apis.iter().all(|sig| !sig.ret_type.ends_with(' ')));
+        Ok(())
+    }
+
+    #[test]
+    fn test_api_signature_from_literal_gadget() {
+        // the zlib `compress` declared in zlib.h.
+        let gadget = FuncGadget::new(
+            "compress".to_string(),
+            vec!["dest".into(), "destLen".into(), "source".into(), "sourceLen".into()],
+            vec![
+                "FuzzMutPointer<u8>".into(),
+                "FuzzMutPointer<u64>".into(),
+                "FuzzConstPointer<u8>".into(),
+                "u64".into(),
+            ],
+            vec!["Bytef *".into(), "uLongf *".into(), "const Bytef *".into(), "uLong".into()],
+            "i32".to_string(),
+            "int ".to_string(),
+        );
+        let sig = ApiSignature::from(&gadget);
+        assert_eq!(sig.name, "compress");
+        assert_eq!(sig.ret_type, "int");
+        assert_eq!(sig.param_types, vec!["Bytef *", "uLongf *", "const Bytef *", "uLong"]);
+    }
+
+    #[test]
+    fn test_is_type_compatible() {
+        let sig = |name: &str, params: &[&str], ret: &str| ApiSignature {
+            name: name.to_string(),
+            ret_type: ret.to_string(),
+            param_types: params.iter().map(|ty| ty.to_string()).collect(),
+        };
+        let init = sig("deflateInit_", &["z_streamp", "int", "const char *", "int"], "int");
+        let deflate = sig("deflate", &["z_streamp", "int"], "int");
+        let open = sig("gzopen", &["const char *", "const char *"], "gzFile");
+        let write = sig("gzwrite", &["gzFile", "voidpc", "unsigned int"], "int");
+        let version = sig("zlibVersion", &[], "const char *");
+        // a pointer typedef like z_streamp is an out-param once resolved.
+        let typedefs: HashMap<&str, &str> = HashMap::from([
+            ("z_streamp", "z_stream *"),
+            ("z_stream", "struct z_stream_s"),
+            ("gzFile", "struct gzFile_s *"),
+        ]);
+        let canonical = |ty: &str| {
+            ctype::resolve_typedefs(ty, |name| typedefs.get(name).map(|underlying| underlying.to_string()))
+        };
+        assert_eq!(canonical("z_streamp"), "struct z_stream_s *");
+        assert_eq!(canonical("const char *"), "const char *");
+        assert!(!is_type_compatible(&init, &deflate));
+        assert!(is_type_compatible(&init.canonicalize(canonical), &deflate.canonicalize(canonical)));
+        assert!(is_type_compatible(&open.canonicalize(canonical), &write.canonicalize(canonical)));
+        assert!(!is_type_compatible(&write.canonicalize(canonical), &open.canonicalize(canonical)));
+        assert!(is_type_compatible(&open, &write));
+        assert!(!is_type_compatible(&write, &open));
+        // primitive types do not make data flows.
+        assert!(!is_type_compatible(&version, &open));
+
+        let stream_init = sig("deflateInit_", &["z_stream *", "int"], "int");
+        let stream_end = sig("deflateEnd", &["z_stream *"], "int");
+        assert!(is_type_compatible(&stream_init, &stream_end));
+        // the typedef and the pointer written out are the same type.
+        assert!(is_type_compatible(&init.canonicalize(canonical), &stream_end.canonicalize(canonical)));
+    }
+
     #[test]
     fn test_fuzzable_funcs() {
         Config::init_test("c-ares");
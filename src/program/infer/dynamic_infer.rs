@@ -81,7 +81,7 @@ impl Executor {
             corpus.as_os_str().to_os_string(),
             OsString::from(max_total_time),
         ];
-        let dict = self.deopt.get_library_build_dict_path().unwrap();
+        let dict = self.deopt.get_library_build_dict_path()?;
         if dict.exists() {
             let dict_arg = format!("-dict={}", dict.to_string_lossy());
             extra_args.push(OsString::from(dict_arg));
@@ -0,0 +1,157 @@
+//! The judge rates the plausibility (0-1) of a generated API sequence from its source, so that the hopeless
+//! programs are skipped before the expensive `validate_api_sequence`.
+
+use super::{extract_function_calls, Program};
+
+pub trait Judge {
+    /// The plausibility of the program, in [0, 1].
+    fn score(&self, program: &Program) -> f32;
+}
+
+/// The words in the API names that acquire and release the resources of a library.
+const INIT_WORDS: [&str; 9] = [
+    "create", "new", "init", "open", "alloc", "begin", "start", "parse", "load",
+];
+const CLEANUP_WORDS: [&str; 11] = [
+    "free", "destroy", "delete", "close", "end", "cleanup", "release", "finish", "dispose", "deinit",
+    "clear",
+];
+
+/// The libc calls that acquire a resource, and the calls that release it.
+const LIBC_RESOURCES: [(&[&str], &[&str]); 2] = [
+    (&["malloc", "calloc", "strdup"], &["free"]),
+    (&["fopen", "fmemopen", "tmpfile"], &["fclose"]),
+];
+
+/// A judge by the shape of the source:
+/// - the program must define the required `test_<lib>_api_sequence`, otherwise it scores 0;
+/// - the library resources created or initialized are cleaned up afterwards;
+/// - the libc resources are balanced, e.g., the mallocs are freed and the fopened files are closed;
+/// - the program returns 66, which the validation expects on success.
+///
+/// The last three weigh equally.
+pub struct HeuristicJudge {
+    required_func: String,
+}
+
+impl HeuristicJudge {
+    pub fn new(lib: &str) -> Self {
+        Self {
+            required_func: format!("test_{lib}_api_sequence"),
+        }
+    }
+}
+
+impl Judge for HeuristicJudge {
+    fn score(&self, program: &Program) -> f32 {
+        let source = &program.statements;
+        if !source.contains(&self.required_func) {
+            return 0_f32;
+        }
+        let calls = extract_function_calls(source);
+        let returns_66 = regex::Regex::new(r"return\s*\(?\s*66\b").unwrap().is_match(source);
+        (cleanup_score(&calls) + balance_score(&calls) + if returns_66 { 1_f32 } else { 0_f32 }) / 3_f32
+    }
+}
+
+/// Split an API name into its lowercase words by `_`, digits and the camel case, e.g., `inflateEnd` to
+/// `inflate end`, and `cJSON_Delete` to `c json delete`.
+fn split_words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut prev_lower = false;
+    for c in name.chars() {
+        let boundary = !c.is_ascii_alphabetic() || (c.is_ascii_uppercase() && prev_lower);
+        if boundary && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        if c.is_ascii_alphabetic() {
+            word.push(c.to_ascii_lowercase());
+        }
+        prev_lower = c.is_ascii_lowercase();
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn has_any_word(name: &str, dict: &[&str]) -> bool {
+    split_words(name).iter().any(|word| dict.contains(&word.as_str()))
+}
+
+/// 1 if nothing is created or a cleanup API follows the first created one, otherwise 0.
+fn cleanup_score(calls: &[String]) -> f32 {
+    let Some(first_init) = calls.iter().position(|call| has_any_word(call, &INIT_WORDS)) else {
+        return 1_f32;
+    };
+    if calls[first_init + 1..]
+        .iter()
+        .any(|call| has_any_word(call, &CLEANUP_WORDS))
+    {
+        1_f32
+    } else {
+        0_f32
+    }
+}
+
+/// The fraction of the libc resources used in the program that are released at least as many times as acquired.
+fn balance_score(calls: &[String]) -> f32 {
+    let count = |names: &[&str]| calls.iter().filter(|call| names.contains(&call.as_str())).count();
+    let mut used = 0;
+    let mut balanced = 0;
+    for (acquire, release) in LIBC_RESOURCES {
+        let acquired = count(acquire);
+        if acquired == 0 {
+            continue;
+        }
+        used += 1;
+        if count(release) >= acquired {
+            balanced += 1;
+        }
+    }
+    if used == 0 {
+        return 1_f32;
+    }
+    balanced as f32 / used as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(split_words("inflateEnd"), vec!["inflate", "end"]);
+        assert_eq!(split_words("cJSON_Delete"), vec!["c", "json", "delete"]);
+        assert_eq!(split_words("deflateInit2_"), vec!["deflate", "init"]);
+        assert_eq!(split_words("png_create_read_struct"), vec!["png", "create", "read", "struct"]);
+    }
+
+    #[test]
+    fn test_heuristic_judge() {
+        let judge = HeuristicJudge::new("cJSON");
+        let good = Program::new(
+            "int test_cJSON_api_sequence() {\n\
+             cJSON *root = cJSON_CreateObject();\n\
+             char *buf = (char *)malloc(16);\n\
+             cJSON_AddNumberToObject(root, \"n\", 1);\n\
+             free(buf);\n\
+             cJSON_Delete(root);\n\
+             return 66;\n}",
+        );
+        assert_eq!(judge.score(&good), 1_f32);
+
+        let leaky = Program::new(
+            "int test_cJSON_api_sequence() {\n\
+             cJSON *root = cJSON_CreateObject();\n\
+             char *buf = (char *)malloc(16);\n\
+             cJSON_AddNumberToObject(root, \"n\", 1);\n\
+             return 66;\n}",
+        );
+        assert!((judge.score(&leaky) - 1_f32 / 3_f32).abs() < 1e-6);
+
+        let unnamed = Program::new("int main() { cJSON_Delete(cJSON_CreateObject()); return 66; }");
+        assert_eq!(judge.score(&unnamed), 0_f32);
+    }
+}
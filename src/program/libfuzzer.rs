@@ -78,6 +78,10 @@ impl LibFuzzer {
     }
 
     fn init(&self) -> Result<()> {
+        if crate::config::keep_artifacts() {
+            log::info!("--keep-artifacts is set, the old fuzzer and driver dirs are kept.");
+            return Ok(());
+        }
         let fuzzer_dir = self.deopt.get_library_fuzzer_dir(self.use_constraint)?;
         if fuzzer_dir.exists() {
             std::fs::remove_dir_all(fuzzer_dir)?;
@@ -362,7 +366,7 @@ impl LibFuzzer {
                     }
                 }
                 let fuzzer_binary = get_fuzzer_path(&fuzzer_dir);
-                executor.compile_lib_fuzzers(
+                executor.compile_core_fuzzers(
                     &fuzzer_dir,
                     &fuzzer_binary,
                     crate::execution::Compile::FUZZER,
@@ -463,8 +467,8 @@ pub fn fix_driver_naming_conflict(program: &Path) -> Result<()> {
     Ok(())
 }
 
-fn parse_artifact_from_log(fuzz_log: &Path) -> Result<Vec<u8>> {
-    let fuzzer_dir = get_file_dirname(fuzz_log);
+/// the crash report is the tail of fuzz log after the last status line.
+fn get_crash_report_from_log(fuzz_log: &Path) -> String {
     let log_vec = std::fs::read(fuzz_log).expect("cannot open fuzz log");
     let log_str = String::from_utf8_lossy(&log_vec).to_string();
 
@@ -478,7 +482,12 @@ fn parse_artifact_from_log(fuzz_log: &Path) -> Result<Vec<u8>> {
         err_buf.push(line);
     }
     err_buf.reverse();
-    let err_buf = err_buf.join("\n");
+    err_buf.join("\n")
+}
+
+fn parse_artifact_from_log(fuzz_log: &Path) -> Result<Vec<u8>> {
+    let fuzzer_dir = get_file_dirname(fuzz_log);
+    let err_buf = get_crash_report_from_log(fuzz_log);
 
     for line in err_buf.lines().rev() {
         if let Some(crash_input) = line.strip_prefix("Base64: ") {
@@ -605,6 +614,11 @@ pub fn respawn_libfuzzer_process(fuzzer_dir: &Path, executor: &Executor) -> Resu
 
     let artifact =
         parse_artifact_from_log(&fuzz_log).context(format!("parse artifact fail: {fuzz_log:?}"))?;
+    let fuzzer_name = fuzzer_dir.file_name().unwrap().to_string_lossy().to_string();
+    let report = get_crash_report_from_log(&fuzz_log);
+    executor
+        .deopt
+        .save_crash_artifact(&fuzzer_name, Some(&artifact), &report)?;
 
     if let Some(driver_id) = parse_driver_id(&artifact) {
         log::info!("Found an error happened in driver: {driver_id}");
@@ -806,10 +820,11 @@ pub mod sanitize_crash {
         for corpora in corpora_files {
             log::info!("test UBSan on corpora: {corpora:?}");
             let extra_args = vec![corpora.as_os_str()];
-            let extra_envs = vec![(
-                OsStr::new("UBSAN_OPTIONS"),
-                OsStr::new("symbolize=1:print_stacktrace=1:halt_on_error=1"),
-            )];
+            let ubsan_options = crate::config::merge_sanitizer_options(&[
+                "symbolize=1:print_stacktrace=1:halt_on_error=1",
+                &executor.deopt.get_ubsan_options(),
+            ]);
+            let extra_envs = vec![(OsStr::new("UBSAN_OPTIONS"), OsStr::new(&ubsan_options))];
             let child = executor.spawn(&fuzzer, extra_args, extra_envs, None, None, true);
             let output = child.wait_with_output()?;
             if output.status.success() {
@@ -1,13 +1,14 @@
 pub mod array;
 pub mod gadget;
 pub mod infer;
+pub mod judge;
 pub mod libfuzzer;
 pub mod rand;
 pub mod serde;
 pub mod shim;
 pub mod transform;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::RwLock;
 
@@ -15,6 +16,7 @@ use self::gadget::FuncGadget;
 use self::serde::{Deserialize, Deserializer};
 use crate::ast::Visitor;
 use crate::deopt::Deopt;
+use crate::execution::logger::ProgramError;
 use crate::execution::Executor;
 use crate::feedback::branches::Branch;
 use eyre::Context;
@@ -70,6 +72,151 @@ pub fn load_exec_counter(deopt: &Deopt) -> HashMap<String, u32> {
     counter
 }
 
+static FAILURE_COUNTER: OnceCell<RwLock<HashMap<String, u32>>> = OnceCell::new();
+
+/// The number of the programs calling this API that failed the validation.
+pub fn get_failure_counter_value(key: &str) -> Option<u32> {
+    let guard = FAILURE_COUNTER
+        .get_or_init(|| RwLock::new(HashMap::new()))
+        .read()
+        .unwrap();
+    guard.get(key).copied()
+}
+
+pub fn set_failure_counter_value(key: String, value: u32) {
+    let mut guard = FAILURE_COUNTER
+        .get_or_init(|| RwLock::new(HashMap::new()))
+        .write()
+        .unwrap();
+    guard.insert(key, value);
+}
+
+pub fn save_failure_counter(deopt: &Deopt) -> Result<()> {
+    save_outcome_counter(&FAILURE_COUNTER, deopt, "failure_counter.json")
+}
+
+/// The failure counter of the last execution. Empty if it is absent, e.g., written by an older version.
+pub fn load_failure_counter(deopt: &Deopt) -> HashMap<String, u32> {
+    load_outcome_counter(deopt, "failure_counter.json")
+}
+
+static SUCCESS_COUNTER: OnceCell<RwLock<HashMap<String, u32>>> = OnceCell::new();
+
+/// The number of the programs calling this API that passed the validation.
+pub fn get_success_counter_value(key: &str) -> Option<u32> {
+    let guard = SUCCESS_COUNTER
+        .get_or_init(|| RwLock::new(HashMap::new()))
+        .read()
+        .unwrap();
+    guard.get(key).copied()
+}
+
+pub fn set_success_counter_value(key: String, value: u32) {
+    let mut guard = SUCCESS_COUNTER
+        .get_or_init(|| RwLock::new(HashMap::new()))
+        .write()
+        .unwrap();
+    guard.insert(key, value);
+}
+
+pub fn save_success_counter(deopt: &Deopt) -> Result<()> {
+    save_outcome_counter(&SUCCESS_COUNTER, deopt, "success_counter.json")
+}
+
+/// The success counter of the last execution. Empty if it is absent, e.g., written by an older version.
+pub fn load_success_counter(deopt: &Deopt) -> HashMap<String, u32> {
+    load_outcome_counter(deopt, "success_counter.json")
+}
+
+fn save_outcome_counter(
+    counter: &OnceCell<RwLock<HashMap<String, u32>>>,
+    deopt: &Deopt,
+    file_name: &str,
+) -> Result<()> {
+    let counter_path = deopt.get_library_misc_dir()?.join(file_name);
+    let guard = counter
+        .get_or_init(|| RwLock::new(HashMap::new()))
+        .read()
+        .unwrap();
+    std::fs::write(counter_path, serde_json::to_string(&*guard)?)?;
+    Ok(())
+}
+
+fn load_outcome_counter(deopt: &Deopt, file_name: &str) -> HashMap<String, u32> {
+    deopt
+        .get_library_misc_dir()
+        .ok()
+        .and_then(|dir| std::fs::read_to_string(dir.join(file_name)).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// The library APIs called by the program, each counted once.
+fn called_library_apis(program: &Program) -> HashSet<String> {
+    extract_function_calls(&program.statements)
+        .into_iter()
+        .filter(|call| gadget::get_func_gadget(call).is_some())
+        .collect()
+}
+
+/// Count a validated program, or a program failed by `err`, once against each library API it calls.
+/// The errors that are not the program's own fault, see `ProgramError::is_validation_failure`, are not counted.
+pub fn record_program_outcome(program: &Program, err: Option<&ProgramError>, deopt: &Deopt) -> Result<()> {
+    match err {
+        None => {
+            for call in called_library_apis(program) {
+                let count = get_success_counter_value(&call).unwrap_or(0);
+                set_success_counter_value(call, count + 1);
+            }
+            save_success_counter(deopt)
+        }
+        Some(err) if err.is_validation_failure() => {
+            for call in called_library_apis(program) {
+                let count = get_failure_counter_value(&call).unwrap_or(0);
+                set_failure_counter_value(call, count + 1);
+            }
+            save_failure_counter(deopt)
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+/// The callees of all the call expressions in `source`, in the order they are called.
+/// The names are normalized: the whitespaces, a leading `::` and the parentheses around the callee are stripped.
+pub fn extract_function_calls(source: &str) -> Vec<String> {
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(tree_sitter_cpp::language())
+        .expect("Failed to load C++ grammar");
+    let Some(tree) = parser.parse(source, None) else {
+        return Vec::new();
+    };
+    let mut calls = Vec::new();
+    extract_calls_recursive(tree.root_node(), source, &mut calls);
+    calls
+}
+
+fn extract_calls_recursive(node: tree_sitter::Node, source: &str, calls: &mut Vec<String>) {
+    if node.kind() == "call_expression" {
+        if let Some(function_node) = node.child_by_field_name("function") {
+            if let Ok(func_name) = function_node.utf8_text(source.as_bytes()) {
+                calls.push(normalize_callee(func_name));
+            }
+        }
+    }
+    for child in node.children(&mut node.walk()) {
+        extract_calls_recursive(child, source, calls);
+    }
+}
+
+fn normalize_callee(callee: &str) -> String {
+    let mut callee: String = callee.chars().filter(|c| !c.is_whitespace()).collect();
+    while callee.starts_with('(') && callee.ends_with(')') {
+        callee = callee[1..callee.len() - 1].to_string();
+    }
+    callee.trim_start_matches("::").to_string()
+}
+
 #[derive(Default, Debug, Clone, ::serde::Deserialize, ::serde::Serialize)]
 pub struct Quality {
     pub density: usize,
@@ -104,11 +251,65 @@ impl Quality {
         nr_branches
     }
 
+    /// Whether the program triggers at least `min` (and at least one) unique branches, to be kept as a seed.
+    pub fn meets_min_new_branches(&self, min: usize) -> bool {
+        let nr_branches = self.get_nr_unique_branches();
+        nr_branches > 0 && nr_branches >= min
+    }
+
     pub fn compute_quality_score(&self) -> f32 {
         let top = self.density * (1 + self.unique_branches.len());
         let bottom = 1 + self.visited;
         top as f32 / bottom as f32
     }
+
+    /// number of distinct library APIs called in the program.
+    pub fn get_api_diversity(&self) -> usize {
+        let mut calls: Vec<&String> = self.library_calls.iter().collect();
+        calls.sort();
+        calls.dedup();
+        calls.len()
+    }
+
+    /// the ratio of library calls that repeat an already called API.
+    pub fn get_length_penalty(&self) -> f32 {
+        if self.library_calls.is_empty() {
+            return 0_f32;
+        }
+        let repeated = self.library_calls.len() - self.get_api_diversity();
+        repeated as f32 / self.library_calls.len() as f32
+    }
+
+    pub fn breakdown(&self) -> QualityBreakdown {
+        QualityBreakdown {
+            density: self.density,
+            unique_branch_funcs: self.unique_branches.len(),
+            unique_branches: self.get_nr_unique_branches(),
+            api_diversity: self.get_api_diversity(),
+            length_penalty: self.get_length_penalty(),
+            visited: self.visited,
+            score: self.compute_quality_score(),
+        }
+    }
+}
+
+/// The component scores of a program's quality, used to analyze why a seed ranked where it did.
+#[derive(Default, Debug, Clone, PartialEq, ::serde::Serialize, ::serde::Deserialize)]
+pub struct QualityBreakdown {
+    /// number of correlated API calls.
+    pub density: usize,
+    /// number of functions that have unique branches.
+    pub unique_branch_funcs: usize,
+    /// total number of unique branches.
+    pub unique_branches: usize,
+    /// number of distinct library APIs called.
+    pub api_diversity: usize,
+    /// the ratio of repeated library calls.
+    pub length_penalty: f32,
+    /// times this program has been selected.
+    pub visited: usize,
+    /// the overall quality score, same as `Quality::compute_quality_score`.
+    pub score: f32,
 }
 
 #[derive(Default, Debug, Clone)]
@@ -139,11 +340,36 @@ impl Program {
     /// load and instantiate program from path.
     pub fn load_from_path(entry: &Path) -> eyre::Result<Program> {
         log::trace!("load program from {entry:?}");
-        let program = std::fs::read_to_string(entry).context(format!("{entry:#?}"))?;
+        let program = crate::deopt::utils::read_to_string_maybe_gz(entry)
+            .context(format!("{entry:#?}"))?;
         let program = Program::load_from_str(&program)?;
         Ok(program)
     }
 
+    /// The library APIs this program calls, in the order they are called. The library APIs are the
+    /// function gadgets saved of `deopt`, or the gadgets of the fuzzed library if they are not saved yet.
+    pub fn called_apis(&self, deopt: &Deopt) -> Vec<String> {
+        let apis: HashSet<String> = match gadget::func_gadget::load_func_gadgets(deopt) {
+            Ok(gadgets) => gadgets.into_iter().map(|gadget| gadget.name).collect(),
+            Err(_) => gadget::get_func_gadgets()
+                .iter()
+                .map(|gadget| gadget.name.clone())
+                .collect(),
+        };
+        extract_function_calls(&self.statements)
+            .into_iter()
+            .filter(|call| apis.contains(call))
+            .collect()
+    }
+
+    /// Count the non-empty lines of statements.
+    pub fn get_line_num(&self) -> usize {
+        self.statements
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .count()
+    }
+
     fn strip_include_headers(&mut self) {
         let mut stmts = vec![];
         for line in self.statements.lines() {
@@ -243,6 +469,10 @@ impl Program {
         self.quality.compute_quality_score()
     }
 
+    pub fn quality_breakdown(&self) -> QualityBreakdown {
+        self.quality.breakdown()
+    }
+
     pub fn convert_to_prompt(&self) -> Vec<String> {
         let calls: &Vec<String> = &self.get_quality().critical_calls;
         let mut prompt = Vec::new();
@@ -259,3 +489,81 @@ impl Program {
         self.quality.visited += 1
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quality_breakdown() {
+        let calls = ["cJSON_Parse", "cJSON_Print", "cJSON_Parse", "cJSON_Delete"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let mut quality = Quality::new(3, calls, vec![]);
+        quality.set_unique_branches(HashMap::from([("cJSON_Parse".to_string(), vec![])]));
+        let mut program = Program::default();
+        program.set_quality(quality);
+        program.increase_visited();
+
+        let breakdown = program.quality_breakdown();
+        assert_eq!(breakdown.api_diversity, 3);
+        assert_eq!(breakdown.unique_branch_funcs, 1);
+        assert_eq!(breakdown.length_penalty, 0.25);
+        assert_eq!(breakdown.visited, 1);
+        assert_eq!(breakdown.score, program.get_weight());
+    }
+
+    #[test]
+    fn test_min_new_branches() {
+        let mut quality = Quality::default();
+        assert!(!quality.meets_min_new_branches(0));
+        assert!(!quality.meets_min_new_branches(1));
+        quality.set_unique_branches(HashMap::from([
+            ("inflate".to_string(), vec![[1; 8], [2; 8]]),
+            ("deflate".to_string(), vec![[3; 8]]),
+        ]));
+        assert!(quality.meets_min_new_branches(1));
+        assert!(quality.meets_min_new_branches(3));
+        assert!(!quality.meets_min_new_branches(4));
+    }
+
+    #[test]
+    fn test_called_apis() -> Result<()> {
+        let work_dir = std::env::temp_dir().join("prompt_fuzz_called_apis");
+        let _ = std::fs::remove_dir_all(&work_dir);
+        let deopt = Deopt {
+            project_name: "zlib".to_string(),
+            ..Default::default()
+        }
+        .with_dir_overrides(Some(work_dir.clone()), None);
+        let gadget = |name: &str| {
+            format!(
+                r#"{{"name": "{name}", "arg_idents": [], "arg_types": [], "alias_arg_types": [], "ret_type": "int", "alias_ret_type": "int"}}"#
+            )
+        };
+        std::fs::write(
+            deopt.get_library_func_gadget_path()?.with_extension("json"),
+            format!("[{}, {}]", gadget("compress"), gadget("crc32")),
+        )?;
+
+        let program = Program::new(
+            "#include <string.h>\n\
+             int LLVMFuzzerTestOneInput(const uint8_t *data, size_t size) {\n\
+                 unsigned char buf[64];\n\
+                 memcpy(buf, data, size < 64 ? size : 64);\n\
+                 if (compress(buf, &len, data, size) != 0) { printf(\"error\"); }\n\
+                 ::crc32(0, buf, len);\n\
+                 (compress)(buf, &len, data, size);\n\
+                 return 0;\n\
+             }",
+        );
+        assert_eq!(
+            extract_function_calls(&program.statements),
+            ["memcpy", "compress", "printf", "crc32", "compress"]
+        );
+        assert_eq!(program.called_apis(&deopt), ["compress", "crc32", "compress"]);
+        std::fs::remove_dir_all(&work_dir)?;
+        Ok(())
+    }
+}
@@ -1,9 +1,6 @@
-use eyre::Context;
 use once_cell::sync::Lazy;
 use rand::rngs::StdRng;
-use rand::{
-    distributions::WeightedIndex, prelude::Distribution, seq::SliceRandom, Rng, SeedableRng,
-};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
 use std::sync::Mutex;
 
 // Initialize the global random number generator (RNG)
@@ -34,14 +31,35 @@ pub fn random_sample<T: Clone>(seq: &[T], n: usize) -> Vec<&T> {
 }
 
 pub fn weighted_choose(weights: Vec<f32>) -> usize {
-    if weights.iter().all(|x| *x == 0.0_f32) {
+    let mut rng = get_global_rng();
+    weighted_choose_with(&weights, &mut *rng)
+}
+
+/// Choose an index with the probability proportional to its weight, by `rng`.
+///
+/// The weights are scanned in index order by their cumulative sum, and the first index whose cumulative
+/// mass exceeds the sampled point is chosen. So the choice only depends on the state of `rng`, and the ties
+/// resolve to the lowest index given equal cumulative mass. Returns 0 if all the weights are 0.
+/// The weights are normalized by the largest one, so that the huge weights do not sum to infinity.
+pub fn weighted_choose_with<R: Rng + ?Sized>(weights: &[f32], rng: &mut R) -> usize {
+    if weights.iter().any(|x| !x.is_finite() || *x < 0.0_f32) {
+        panic!("InvalidWeight: {weights:?}");
+    }
+    let max = weights.iter().cloned().fold(0.0_f32, f32::max);
+    if max == 0.0_f32 {
         return 0;
     }
-    let dist = WeightedIndex::new(&weights)
-        .context(format!("InvalidWeight: {weights:?}"))
-        .unwrap();
-    let mut rng = get_global_rng();
-    dist.sample(&mut *rng)
+    let total: f32 = weights.iter().map(|x| x / max).sum();
+    let point = rng.gen_range(0.0..total);
+    let mut cumulative = 0.0_f32;
+    for (index, weight) in weights.iter().enumerate() {
+        cumulative += weight / max;
+        if point < cumulative {
+            return index;
+        }
+    }
+    // the rounding error of the cumulative sum, fall back to the last index of positive weight.
+    weights.iter().rposition(|x| *x > 0.0_f32).unwrap()
 }
 
 pub fn prob_coin(prob: f32) -> bool {
@@ -54,8 +72,53 @@ pub fn prob_coin(prob: f32) -> bool {
     random < prob
 }
 
+/// A random combination length in `--comb-len-min..=--comb-len-max`.
 pub fn rand_comb_len() -> usize {
-    let lens: Vec<usize> = (4..6).collect();
+    let (min, max) = crate::config::get_comb_len_range();
+    rand_comb_len_between(min, max)
+}
+
+pub fn rand_comb_len_between(min: usize, max: usize) -> usize {
+    let lens: Vec<usize> = (min..=max).collect();
     let select_len = random_select(&lens);
     *select_len
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted_choose_reproducible() {
+        let weights = vec![0.5_f32; 8];
+        let choose = |seed: u64| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..200)
+                .map(|_| weighted_choose_with(&weights, &mut rng))
+                .collect::<Vec<usize>>()
+        };
+        let choices = choose(42);
+        assert_eq!(choices, choose(42));
+        assert!((0..8).all(|index| choices.contains(&index)));
+
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..200 {
+            assert_ne!(weighted_choose_with(&[1.0, 0.0, 1.0], &mut rng), 1);
+        }
+        assert_eq!(weighted_choose_with(&[0.0, 0.0], &mut rng), 0);
+        assert_eq!(weighted_choose_with(&[0.0, 2.0, 0.0], &mut rng), 1);
+        // the weights summing to infinity.
+        for _ in 0..200 {
+            assert_ne!(weighted_choose_with(&[f32::MAX, 0.0, f32::MAX], &mut rng), 1);
+        }
+    }
+
+    #[test]
+    fn test_rand_comb_len_between() {
+        for _ in 0..100 {
+            let len = rand_comb_len_between(2, 7);
+            assert!((2..=7).contains(&len));
+        }
+        assert_eq!(rand_comb_len_between(3, 3), 3);
+    }
+}
@@ -3,6 +3,8 @@ use std::path::PathBuf;
 
 use crate::request::prompt::combination_to_str;
 use eyre::Result;
+use once_cell::sync::Lazy;
+use regex::Regex;
 
 use super::{
     gadget::{get_func_gadget, FuncGadget},
@@ -219,4 +221,188 @@ impl<'de> Deserializer<'de> {
     pub fn remain(&self) -> &str {
         self.input
     }
+
+    /// Narrow the input to the code of a LLM completion and return it.
+    /// Fenced code blocks are preferred, then the code around the first function definition.
+    /// The leading and trailing prose is stripped.
+    pub fn extract_code_block(&mut self) -> &'de str {
+        let code = match extract_fenced_block(self.input) {
+            Some(block) => block,
+            None => extract_unfenced_code(self.input),
+        };
+        self.input = code;
+        code
+    }
+
+    /// Split a completion holding several programs at the lines of `separator`, and extract the code of each part.
+    /// The parts without code are dropped. Without the separator, or if no part holds a function definition,
+    /// the completion is parsed as a single program.
+    pub fn split_programs(&self, separator: &str) -> Vec<&'de str> {
+        let input = self.input;
+        let mut parts = Vec::new();
+        let mut start = 0;
+        let mut offset = 0;
+        for line in input.split_inclusive('\n') {
+            if line.trim() == separator.trim() {
+                parts.push(&input[start..offset]);
+                start = offset + line.len();
+            }
+            offset += line.len();
+        }
+        if parts.is_empty() {
+            return vec![Deserializer::from_input(input).extract_code_block()];
+        }
+        parts.push(&input[start..]);
+        let programs: Vec<&str> = parts
+            .into_iter()
+            .map(|part| Deserializer::from_input(part).extract_code_block())
+            .filter(|code| FUNC_DEF.is_match(code))
+            .collect();
+        if programs.is_empty() {
+            return vec![Deserializer::from_input(input).extract_code_block()];
+        }
+        programs
+    }
+}
+
+static FUNC_DEF: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?m)^[A-Za-z_][\w \t\*&:<>,"]*\([^;{}]*\)\s*(const\s*)?\{"#).unwrap()
+});
+
+static CODE_START: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?m)^[ \t]*(#[ \t]*(include|define|if|ifdef|ifndef|pragma)\b|(typedef|struct|extern|static|using|namespace)\b)",
+    )
+    .unwrap()
+});
+
+/// Whether a raw completion can be parsed into a program, i.e., its code defines a function.
+pub fn is_parsable_completion(completion: &str) -> bool {
+    FUNC_DEF.is_match(Deserializer::from_input(completion).extract_code_block())
+}
+
+/// Extract the fenced code blocks, and choose the first one that defines a function.
+/// Otherwise, the longest one is chosen. An unclosed fence (truncated output) lasts to the end.
+fn extract_fenced_block(input: &str) -> Option<&str> {
+    let mut blocks = Vec::new();
+    let mut rest = input;
+    while let Some(open) = rest.find("```") {
+        // skip the language tag, e.g., ```cpp
+        let after_open = &rest[open + 3..];
+        let body = match after_open.find('\n') {
+            Some(idx) => &after_open[idx + 1..],
+            None => "",
+        };
+        match body.find("```") {
+            Some(close) => {
+                blocks.push(&body[..close]);
+                rest = &body[close + 3..];
+            }
+            None => {
+                blocks.push(body);
+                break;
+            }
+        }
+    }
+    let blocks: Vec<&str> = blocks
+        .into_iter()
+        .map(|block| block.trim_matches('\n'))
+        .filter(|block| !block.trim().is_empty())
+        .collect();
+    if let Some(block) = blocks.iter().find(|block| FUNC_DEF.is_match(block)) {
+        return Some(block);
+    }
+    blocks.into_iter().max_by_key(|block| block.len())
+}
+
+/// Take the code from the first declaration or function definition to the last closing brace.
+fn extract_unfenced_code(input: &str) -> &str {
+    let Some(func) = FUNC_DEF.find(input) else {
+        return input.trim();
+    };
+    let start = match CODE_START.find(&input[..func.start()]) {
+        Some(decl) => decl.start(),
+        None => func.start(),
+    };
+    let end = match input.rfind('}') {
+        Some(end) if end >= func.end() => end + 1,
+        _ => input.len(),
+    };
+    &input[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "#include <stdio.h>
+
+int LLVMFuzzerTestOneInput(const uint8_t *data, size_t size) {
+    if (size < 1) {
+        return 0;
+    }
+    return 0;
+}";
+
+    fn extract(completion: &str) -> String {
+        Deserializer::from_input(completion)
+            .extract_code_block()
+            .to_string()
+    }
+
+    #[test]
+    fn test_extract_code_block() {
+        // the plain code is kept.
+        assert_eq!(extract(CODE), CODE);
+        // a fenced block with the explanations around.
+        let completion = format!(
+            "Sure! Here is a fuzz driver:\n\n```cpp\n{CODE}\n```\n\nIt checks the size first.\n"
+        );
+        assert_eq!(extract(&completion), CODE);
+        // a fenced block without the language tag.
+        assert_eq!(extract(&format!("```\n{CODE}\n```")), CODE);
+        // the first block that defines a function is preferred to the shell commands.
+        let completion = format!(
+            "Compile:\n```sh\nclang -fsanitize=fuzzer driver.cc\n```\nThe driver:\n```c++\n{CODE}\n```"
+        );
+        assert_eq!(extract(&completion), CODE);
+        // the output is truncated before the closing fence.
+        assert_eq!(extract(&format!("Here you go:\n```c\n{CODE}\n")), CODE);
+        // no fence: the prose before the includes and after the last brace is stripped.
+        let completion = format!("Below is the implementation.\n{CODE}\nHope this helps!");
+        assert_eq!(extract(&completion), CODE);
+        // no code at all.
+        assert_eq!(extract("  I cannot help with that.\n"), "I cannot help with that.");
+    }
+
+    #[test]
+    fn test_extract_code_block_api_sequence() {
+        let code = "void test_cJSON_api_sequence() {
+    cJSON *root = cJSON_CreateObject();
+    cJSON_Delete(root);
+}";
+        let completion = format!("1. Create an object.\n2. Delete it.\n\n{code}\n\nThat is all.");
+        assert_eq!(extract(&completion), code);
+    }
+
+    #[test]
+    fn test_split_programs() {
+        let separator = "// ----- NEXT PROGRAM -----";
+        let other = CODE.replace("size < 1", "size < 2");
+        // the separator between two fenced blocks.
+        let completion = format!("Two drivers:\n```cpp\n{CODE}\n```\n{separator}\n```cpp\n{other}\n```\nDone.");
+        let de = Deserializer::from_input(&completion);
+        assert_eq!(de.split_programs(separator), vec![CODE, other.as_str()]);
+        // the separator inside a single fenced block, and a trailing separator.
+        let completion = format!("```cpp\n{CODE}\n  {separator}\n{other}\n```\n{separator}\n");
+        let de = Deserializer::from_input(&completion);
+        assert_eq!(de.split_programs(separator), vec![CODE, other.as_str()]);
+        // fall back to a single program.
+        let completion = format!("```cpp\n{CODE}\n```");
+        assert_eq!(Deserializer::from_input(&completion).split_programs(separator), vec![CODE]);
+        // the separator is mentioned, but no part is a program.
+        let completion = format!("I will put {separator} between programs.\n{separator}\nint x;\n");
+        let single = Deserializer::from_input(&completion).extract_code_block();
+        assert_eq!(Deserializer::from_input(&completion).split_programs(separator), vec![single]);
+    }
 }
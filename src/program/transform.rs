@@ -219,15 +219,17 @@ impl<'a> Transformer<'a> {
     /// change the data input read from file to read from fuzzer.
     fn change_input_data_fuzzable(&mut self) -> Result<()> {
         let visitor = self.get_new_visitor()?;
+        // whether append a null terminator at the end of bytes, `--null-term` overrides the library config.
+        let null_term =
+            crate::config::get_null_term().resolve(self.deopt.config.should_terminate_with_null());
         // whether needs to cast the type of bytes.
         let init_stmt = if let Some(ty_name) = visitor.find_ty_with_arg_name("data") {
-            // whether append a null terminator at the end of bytes.
-            if self.deopt.config.should_terminate_with_null() {
+            if null_term {
                 format!("FDPConsumeRawBytesWithNullTerm({ty_name}, data, size, fdp)")
             } else {
                 format!("FDPConsumeRawBytes({ty_name}, data, size, fdp)")
             }
-        } else if self.deopt.config.should_terminate_with_null() {
+        } else if null_term {
             "FDPConsumeRawBytesWithNullTerm(const uint8_t *, data, size, fdp)".to_string()
         } else {
             "FDPConsumeRawBytes(const uint8_t *, data, size, fdp)".to_string()
@@ -0,0 +1,185 @@
+//! Progress bars of the long running phases, which are shown only with `--progress` on a terminal.
+//!
+//! The bars are drawn on stderr. To coexist with the log output, the logger formats the stdout
+//! records by `bridge_format`, which clears the visible bar before each record. The bar is
+//! redrawn at its next update.
+
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use flexi_logger::DeferredNow;
+use log::Record;
+use once_cell::sync::OnceCell;
+
+const BAR_WIDTH: usize = 30;
+
+/// Whether a bar is drawn on the current line of stderr.
+static BAR_VISIBLE: Mutex<bool> = Mutex::new(false);
+
+struct BarState {
+    label: String,
+    pos: usize,
+    total: usize,
+    message: String,
+    start: Instant,
+}
+
+impl BarState {
+    fn render(&self) -> String {
+        let filled = (self.pos.min(self.total) * BAR_WIDTH)
+            .checked_div(self.total)
+            .unwrap_or(BAR_WIDTH);
+        let mut bar = "=".repeat(filled);
+        if filled < BAR_WIDTH {
+            bar.push('>');
+            bar.push_str(&" ".repeat(BAR_WIDTH - filled - 1));
+        }
+        let mut line = format!(
+            "{} [{bar}] {}/{} ({}s)",
+            self.label,
+            self.pos,
+            self.total,
+            self.start.elapsed().as_secs()
+        );
+        if !self.message.is_empty() {
+            line.push(' ');
+            line.push_str(&self.message);
+        }
+        line
+    }
+}
+
+/// A progress bar, which does nothing if it is hidden.
+pub struct ProgressBar {
+    state: Option<Mutex<BarState>>,
+}
+
+impl ProgressBar {
+    /// Create a bar of `total` steps, which is hidden unless the progress is enabled.
+    pub fn new(label: &str, total: usize) -> Self {
+        if !is_enabled() {
+            return Self::hidden();
+        }
+        let bar = Self {
+            state: Some(Mutex::new(BarState {
+                label: label.to_string(),
+                pos: 0,
+                total,
+                message: String::new(),
+                start: Instant::now(),
+            })),
+        };
+        bar.draw();
+        bar
+    }
+
+    pub fn hidden() -> Self {
+        Self { state: None }
+    }
+
+    pub fn inc(&self, delta: usize) {
+        self.update(|state| state.pos += delta);
+    }
+
+    pub fn set_position(&self, pos: usize) {
+        self.update(|state| state.pos = pos);
+    }
+
+    pub fn set_message(&self, message: &str) {
+        self.update(|state| state.message = message.to_string());
+    }
+
+    /// Clear the bar from the terminal.
+    pub fn finish(&self) {
+        if self.state.is_some() {
+            clear_visible_bar();
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut BarState)) {
+        if let Some(state) = &self.state {
+            f(&mut state.lock().unwrap());
+            self.draw();
+        }
+    }
+
+    fn draw(&self) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        let line = state.lock().unwrap().render();
+        let mut visible = BAR_VISIBLE.lock().unwrap();
+        let mut stderr = std::io::stderr();
+        let _ = write!(stderr, "\r\x1b[2K{line}");
+        let _ = stderr.flush();
+        *visible = true;
+    }
+}
+
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Whether `--progress` is set and stderr is a terminal.
+pub fn is_enabled() -> bool {
+    crate::config::show_progress() && std::io::stderr().is_terminal()
+}
+
+fn clear_visible_bar() {
+    let mut visible = BAR_VISIBLE.lock().unwrap();
+    if *visible {
+        let mut stderr = std::io::stderr();
+        let _ = write!(stderr, "\r\x1b[2K");
+        let _ = stderr.flush();
+        *visible = false;
+    }
+}
+
+/// The stdout format of the logger, which clears the visible bar before the record.
+/// Otherwise, it is the same as the default format of flexi_logger.
+pub fn bridge_format(
+    w: &mut dyn Write,
+    now: &mut DeferredNow,
+    record: &Record,
+) -> std::io::Result<()> {
+    static STDOUT_IS_TERMINAL: OnceCell<bool> = OnceCell::new();
+    clear_visible_bar();
+    if *STDOUT_IS_TERMINAL.get_or_init(|| std::io::stdout().is_terminal()) {
+        flexi_logger::colored_default_format(w, now, record)
+    } else {
+        flexi_logger::default_format(w, now, record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_progress_bar() {
+        let mut state = BarState {
+            label: "Compile cores".to_string(),
+            pos: 0,
+            total: 3,
+            message: String::new(),
+            start: Instant::now(),
+        };
+        assert_eq!(
+            state.render(),
+            format!("Compile cores [>{}] 0/3 (0s)", " ".repeat(BAR_WIDTH - 1))
+        );
+        state.pos = 3;
+        state.message = "Core_002".to_string();
+        assert_eq!(
+            state.render(),
+            format!("Compile cores [{}] 3/3 (0s) Core_002", "=".repeat(BAR_WIDTH))
+        );
+        // hidden bars are no-op.
+        let bar = ProgressBar::hidden();
+        bar.inc(1);
+        bar.finish();
+    }
+}
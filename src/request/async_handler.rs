@@ -0,0 +1,239 @@
+//! The async variant of `Handler`, enabled by the `async` feature.
+//!
+//! `BlockingHandler` adapts an `AsyncHandler` to the sync `Handler` used by `Fuzzer`, and
+//! `GenerationPipeline` runs a generation in the background, so that the generation of the next
+//! batch overlaps with the validation of the current one, see `Fuzzer::pipelined_generate`.
+//! The raw completions of `--verbose-generation` are recorded on the runtime threads, so they are
+//! not saved for the pipelined generations.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use eyre::{eyre, Result};
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+
+use super::{prompt::Prompt, GenerationResult, Handler};
+use crate::program::Program;
+
+pub trait AsyncHandler: Send + Sync {
+    /// generate programs via a formatted prompt, sampled with `temperature`.
+    fn generate(
+        &self,
+        prompt: &Prompt,
+        temperature: f32,
+    ) -> impl Future<Output = Result<GenerationResult>> + Send;
+
+    /// generate a single program (used for CoT Phase 1: plan generation)
+    fn generate_single(
+        &self,
+        prompt: &Prompt,
+        temperature: f32,
+    ) -> impl Future<Output = Result<Program>> + Send;
+}
+
+/// Run an `AsyncHandler` as a sync `Handler` by blocking on its own runtime.
+pub struct BlockingHandler<H> {
+    handler: H,
+    rt: Runtime,
+}
+
+impl<H: AsyncHandler> BlockingHandler<H> {
+    pub fn new(handler: H) -> Result<Self> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| eyre!("Failed to create tokio runtime: {}", e))?;
+        Ok(Self { handler, rt })
+    }
+}
+
+impl<H: AsyncHandler> Handler for BlockingHandler<H> {
+    fn generate(&self, prompt: &Prompt, temperature: f32) -> Result<GenerationResult> {
+        self.rt.block_on(self.handler.generate(prompt, temperature))
+    }
+
+    fn generate_single(&self, prompt: &Prompt, temperature: f32) -> Result<Program> {
+        self.rt.block_on(self.handler.generate_single(prompt, temperature))
+    }
+}
+
+/// Prefetch a generation in the background while the caller does other work, e.g., validates the
+/// programs of the current round. At most one generation is in flight.
+pub struct GenerationPipeline<H> {
+    handler: Arc<H>,
+    rt: Runtime,
+    pending: Option<JoinHandle<Result<GenerationResult>>>,
+}
+
+impl<H: AsyncHandler + 'static> GenerationPipeline<H> {
+    pub fn new(handler: H) -> Result<Self> {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| eyre!("Failed to create tokio runtime: {}", e))?;
+        Ok(Self {
+            handler: Arc::new(handler),
+            rt,
+            pending: None,
+        })
+    }
+
+    /// Start generating for `prompt` in the background. The generation in flight, if any, is awaited first
+    /// and returned to the caller.
+    pub fn prefetch(&mut self, prompt: Prompt, temperature: f32) -> Option<Result<GenerationResult>> {
+        let previous = self.take();
+        let handler = self.handler.clone();
+        self.pending = Some(
+            self.rt
+                .spawn(async move { handler.generate(&prompt, temperature).await }),
+        );
+        previous
+    }
+
+    /// Whether a generation is in flight.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Drop the generation in flight, e.g., once the prompt it is generated for is changed.
+    pub fn cancel(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            pending.abort();
+        }
+    }
+
+    /// Wait for the generation in flight, None if nothing is prefetched.
+    pub fn take(&mut self) -> Option<Result<GenerationResult>> {
+        let pending = self.pending.take()?;
+        Some(
+            self.rt
+                .block_on(pending)
+                .unwrap_or_else(|err| Err(eyre!("The prefetched generation panicked: {err}"))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::prompt::ProgramTask;
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tokio::sync::Semaphore;
+
+    fn empty_prompt() -> Prompt {
+        Prompt {
+            gadgets: Vec::new(),
+            successful_examples: VecDeque::new(),
+            avoid_pairs: VecDeque::new(),
+            task: ProgramTask::Generate,
+        }
+    }
+
+    /// Take 100ms to return a program of the number of prompted gadgets.
+    struct SleepyHandler;
+
+    impl AsyncHandler for SleepyHandler {
+        fn generate(
+            &self,
+            prompt: &Prompt,
+            _temperature: f32,
+        ) -> impl Future<Output = Result<GenerationResult>> + Send {
+            let statements = format!("// {}", prompt.gadgets.len());
+            async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                Ok(GenerationResult {
+                    programs: vec![Program::new(&statements)],
+                    ..Default::default()
+                })
+            }
+        }
+
+        async fn generate_single(&self, _prompt: &Prompt, _temperature: f32) -> Result<Program> {
+            Ok(Program::new("// plan"))
+        }
+    }
+
+    #[test]
+    fn test_blocking_handler() -> Result<()> {
+        let handler = BlockingHandler::new(SleepyHandler)?;
+        let prompt = empty_prompt();
+        let programs = handler.generate_programs(&prompt, 1.0)?;
+        assert_eq!(programs[0].statements, "// 0");
+        assert_eq!(handler.generate_single(&prompt, 1.0)?.statements, "// plan");
+        Ok(())
+    }
+
+    /// Count the started generations, and finish each one only once the test releases a permit.
+    struct GatedHandler {
+        started: Arc<AtomicUsize>,
+        release: Arc<Semaphore>,
+    }
+
+    impl AsyncHandler for GatedHandler {
+        fn generate(
+            &self,
+            _prompt: &Prompt,
+            _temperature: f32,
+        ) -> impl Future<Output = Result<GenerationResult>> + Send {
+            let started = self.started.clone();
+            let release = self.release.clone();
+            async move {
+                let id = started.fetch_add(1, Ordering::SeqCst);
+                release.acquire().await?.forget();
+                Ok(GenerationResult {
+                    programs: vec![Program::new(&format!("// {id}"))],
+                    ..Default::default()
+                })
+            }
+        }
+
+        async fn generate_single(&self, _prompt: &Prompt, _temperature: f32) -> Result<Program> {
+            Ok(Program::new("// plan"))
+        }
+    }
+
+    fn wait_started(started: &AtomicUsize, n: usize) {
+        for _ in 0..1000 {
+            if started.load(Ordering::SeqCst) >= n {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        panic!("the generation {n} is not started in the background");
+    }
+
+    #[test]
+    fn test_pipeline_overlaps_generation() -> Result<()> {
+        let started = Arc::new(AtomicUsize::new(0));
+        let release = Arc::new(Semaphore::new(0));
+        let mut pipeline = GenerationPipeline::new(GatedHandler {
+            started: started.clone(),
+            release: release.clone(),
+        })?;
+        assert!(pipeline.take().is_none());
+
+        assert!(pipeline.prefetch(empty_prompt(), 1.0).is_none());
+        assert!(pipeline.is_pending());
+        // the generation runs before it is taken, i.e., while the caller validates the current batch.
+        wait_started(&started, 1);
+        release.add_permits(1);
+        let result = pipeline.take().unwrap()?;
+        assert_eq!(result.programs[0].statements, "// 0");
+        assert!(!pipeline.is_pending());
+
+        // prefetching the next batch hands over the one in flight.
+        assert!(pipeline.prefetch(empty_prompt(), 1.0).is_none());
+        wait_started(&started, 2);
+        release.add_permits(1);
+        let previous = pipeline.prefetch(empty_prompt(), 1.0).unwrap()?;
+        assert_eq!(previous.programs[0].statements, "// 1");
+        wait_started(&started, 3);
+        pipeline.cancel();
+        assert!(!pipeline.is_pending());
+        assert!(pipeline.take().is_none());
+        Ok(())
+    }
+}
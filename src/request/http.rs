@@ -1,6 +1,5 @@
 use async_openai::types::ChatCompletionRequestMessage;
 use eyre::{eyre, Result};
-use futures::future::join_all;
 use reqwest::{Client, Error as ReqwestError, Method, Response};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -8,25 +7,10 @@ use std::collections::HashMap;
 use std::time::Duration;
 use tokio::time::timeout;
 
-use crate::program::Program;
-
-/// Token使用统计结构
-#[derive(Debug, Clone, Default)]
-pub struct TokenUsage {
-    pub prompt_tokens: u32,
-    pub completion_tokens: u32,
-    pub total_tokens: u32,
-}
+use super::{GenerationResult, ResponseMeta, TokenUsage};
+use crate::program::{serde::Deserializer, Program};
 
 impl TokenUsage {
-    pub fn new(prompt_tokens: u32, completion_tokens: u32, total_tokens: u32) -> Self {
-        Self {
-            prompt_tokens,
-            completion_tokens,
-            total_tokens,
-        }
-    }
-
     pub fn from_openai_usage(usage: &OpenAIUsage) -> Self {
         Self {
             prompt_tokens: usage.prompt_tokens,
@@ -34,11 +18,19 @@ impl TokenUsage {
             total_tokens: usage.total_tokens,
         }
     }
+}
 
-    pub fn add(&mut self, other: &TokenUsage) {
-        self.prompt_tokens += other.prompt_tokens;
-        self.completion_tokens += other.completion_tokens;
-        self.total_tokens += other.total_tokens;
+impl ResponseMeta {
+    pub fn from_openai_response(response: &OpenAIResponse) -> Self {
+        Self {
+            usage: TokenUsage::from_openai_usage(&response.usage),
+            finish_reasons: response
+                .choices
+                .iter()
+                .filter_map(|choice| choice.finish_reason.clone())
+                .collect(),
+            model: response.model.clone(),
+        }
     }
 }
 
@@ -51,6 +43,8 @@ pub struct HttpClientConfig {
     pub default_headers: HashMap<String, String>,
     pub retry_attempts: u32,
     pub retry_delay: Duration,
+    /// 请求经过的代理地址
+    pub proxy: Option<String>,
 }
 
 impl Default for HttpClientConfig {
@@ -64,11 +58,12 @@ impl Default for HttpClientConfig {
 
         Self {
             base_url: "https://api.openai.com".to_string(),
-            timeout: Duration::from_secs(180),
+            timeout: Duration::from_secs(crate::config::REQUEST_TIMEOUT),
             connect_timeout: Duration::from_secs(10),
             default_headers: headers,
             retry_attempts: 3,
             retry_delay: Duration::from_secs(2),
+            proxy: None,
         }
     }
 }
@@ -81,6 +76,64 @@ impl HttpClientConfig {
     pub fn set_timeout(&mut self, timeout: Duration) {
         self.timeout = timeout;
     }
+
+    pub fn set_proxy(&mut self, proxy: &str) {
+        self.proxy = Some(proxy.to_string());
+    }
+
+    pub fn add_header(&mut self, key: &str, value: &str) {
+        self.default_headers
+            .insert(key.to_string(), value.to_string());
+    }
+
+    pub fn set_bearer_token(&mut self, token: &str) {
+        self.add_header("Authorization", &format!("Bearer {token}"));
+    }
+
+    /// 从环境变量读取自托管推理网关的配置:
+    ///   - `HTTP_LLM_BASE`: 服务地址, 默认使用 `OPENAI_PROXY_BASE`.
+    ///   - `HTTP_LLM_TOKEN`: bearer token, 默认使用 `OPENAI_API_KEY`.
+    ///   - `HTTP_LLM_HEADERS`: 额外的请求头, 格式为 `Name: value; Name2: value2`.
+    ///   - `HTTP_LLM_PROXY`: 请求经过的代理地址.
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+        config.set_timeout(crate::config::get_request_timeout());
+        let base_url = std::env::var("HTTP_LLM_BASE")
+            .ok()
+            .or_else(|| crate::config::get_openai_proxy().clone())
+            .unwrap_or_else(|| "https://api.openai.com/v1".to_string());
+        config.set_base_url(&base_url);
+
+        let token = std::env::var("HTTP_LLM_TOKEN")
+            .or_else(|_| std::env::var("OPENAI_API_KEY"))
+            .map_err(|_| eyre!("Neither HTTP_LLM_TOKEN nor OPENAI_API_KEY environment variable is set"))?;
+        config.set_bearer_token(&token);
+
+        if let Ok(headers) = std::env::var("HTTP_LLM_HEADERS") {
+            for (key, value) in parse_header_list(&headers)? {
+                config.add_header(&key, &value);
+            }
+        }
+        if let Ok(proxy) = std::env::var("HTTP_LLM_PROXY") {
+            config.set_proxy(&proxy);
+        }
+        Ok(config)
+    }
+}
+
+/// 解析 `Name: value; Name2: value2` 格式的请求头列表
+pub fn parse_header_list(headers: &str) -> Result<Vec<(String, String)>> {
+    let mut list = Vec::new();
+    for header in headers.split(';') {
+        if header.trim().is_empty() {
+            continue;
+        }
+        let (key, value) = header
+            .split_once(':')
+            .ok_or_else(|| eyre!("Invalid header `{header}`, expect `Name: value`"))?;
+        list.push((key.trim().to_string(), value.trim().to_string()));
+    }
+    Ok(list)
 }
 
 /// OpenAI API请求结构
@@ -102,6 +155,9 @@ pub struct OpenAIRequest {
     pub stop: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    /// 每次请求的completion数量
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u8>,
 }
 
 /// OpenAI消息结构
@@ -159,6 +215,8 @@ pub struct OpenAIUsage {
 pub enum HttpClientError {
     #[error("Request failed: {0}")]
     RequestError(#[from] ReqwestError),
+    #[error("Failed to connect to {url}: {source}")]
+    ConnectionError { url: String, source: ReqwestError },
     #[error("Timeout error: {0}")]
     TimeoutError(String),
     #[error("Parse error: {0}")]
@@ -187,11 +245,16 @@ impl HttpClient {
             headers.insert(header_name, header_value);
         }
 
-        let client = Client::builder()
+        let mut builder = Client::builder()
             .timeout(config.timeout)
             .connect_timeout(config.connect_timeout)
-            .default_headers(headers)
-            .build()?;
+            .default_headers(headers);
+        if let Some(proxy) = &config.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| eyre!("Invalid proxy address {}: {}", proxy, e))?;
+            builder = builder.proxy(proxy);
+        }
+        let client = builder.build()?;
 
         Ok(Self { client, config })
     }
@@ -253,7 +316,13 @@ impl HttpClient {
             request_builder = request_builder.json(body);
         }
 
-        let response = request_builder.send().await?;
+        let response = request_builder
+            .send()
+            .await
+            .map_err(|source| HttpClientError::ConnectionError {
+                url: url.to_string(),
+                source,
+            })?;
 
         if !response.status().is_success() {
             return Err(HttpClientError::ApiError {
@@ -453,6 +522,7 @@ impl HttpClient {
             presence_penalty: None,
             stop: None,
             stream: Some(false),
+            n: None,
         }
     }
 
@@ -580,17 +650,11 @@ pub struct HttpHandler {
 }
 
 impl HttpHandler {
-    /// 创建新的HttpHandler实例
+    /// 创建新的HttpHandler实例, 配置见 `HttpClientConfig::from_env`
     pub fn new() -> Result<Self> {
-        let api_key = std::env::var("OPENAI_API_KEY")
-            .map_err(|_| eyre!("OPENAI_API_KEY environment variable not set"))?;
-        let base_url = crate::config::get_openai_proxy()
-            .clone()
-            .unwrap_or_else(|| "https://api.openai.com/v1".to_string());
-
-        let client = HttpClient::with_default_config()?
-            .with_api_key(&api_key)
-            .with_base_url(&base_url);
+        let config = HttpClientConfig::from_env()?;
+        log::debug!("Using HTTP LLM endpoint: {}", config.base_url);
+        let client = HttpClient::new(config)?;
 
         let rt = tokio::runtime::Builder::new_current_thread()
             .enable_all()
@@ -618,8 +682,14 @@ impl HttpHandler {
         messages: Vec<OpenAIMessage>,
         model: String,
         strip_wrapper: bool,
+        temperature: f32,
     ) -> Result<(Program, TokenUsage)> {
-        let request = HttpClient::build_openai_request(&model, messages, Some(0.7), None);
+        let request = HttpClient::build_openai_request(
+            &model,
+            messages,
+            Some(temperature),
+            super::get_max_tokens(),
+        );
 
         let response = self.client.chat_completion(&request).await?;
 
@@ -628,6 +698,7 @@ impl HttpHandler {
         }
 
         let content = &response.choices[0].message.content;
+        super::record_raw_completion(content);
 
         // 根据参数决定是否剥离代码包装器
         let final_content = if strip_wrapper {
@@ -641,45 +712,71 @@ impl HttpHandler {
         Ok((Program::new(&final_content), usage))
     }
 
-  
-
-
-    /// 剥离代码包装器（复制自openai.rs）
-    fn strip_code_wrapper(&self, input: &str) -> String {
-        let mut input = input.trim();
-        let mut event = "";
-        if let Some(idx) = input.find("```") {
-            event = &input[..idx];
-            input = &input[idx..];
+    /// 单次请求生成`n`个程序
+    async fn generate_programs(
+        &self,
+        messages: Vec<OpenAIMessage>,
+        model: String,
+        strip_wrapper: bool,
+        temperature: f32,
+        n: usize,
+    ) -> Result<(Vec<Program>, ResponseMeta)> {
+        let mut request = HttpClient::build_openai_request(
+            &model,
+            messages,
+            Some(temperature),
+            super::get_max_tokens(),
+        );
+        // the programs are written in a single completion with the separator.
+        let separator = crate::config::get_program_separator();
+        if n > 1 && separator.is_none() {
+            request.n = Some(n as u8);
         }
-        let input = self.strip_code_prefix(input, "cpp");
-        let input = self.strip_code_prefix(input, "CPP");
-        let input = self.strip_code_prefix(input, "C++");
-        let input = self.strip_code_prefix(input, "c++");
-        let input = self.strip_code_prefix(input, "c");
-        let input = self.strip_code_prefix(input, "C");
-        let input = self.strip_code_prefix(input, "\n");
-        if let Some(idx) = input.rfind("```") {
-            let input = &input[..idx];
-            let input = ["/*", event, "*/\n", input].concat();
-            return input;
+
+        let response = self.client.chat_completion(&request).await?;
+        let mut meta = ResponseMeta::from_openai_response(&response);
+        // the finish reasons are kept per program to tell the truncated ones.
+        meta.finish_reasons.clear();
+        let mut programs = Vec::new();
+        for choice in &response.choices {
+            let content = &choice.message.content;
+            super::record_raw_completion(content);
+            let split: Vec<Program> = match (&separator, strip_wrapper) {
+                (Some(separator), true) => Deserializer::from_input(content)
+                    .split_programs(separator)
+                    .into_iter()
+                    .map(Program::new)
+                    .collect(),
+                (None, true) => vec![Program::new(&self.strip_code_wrapper(content))],
+                (_, false) => vec![Program::new(content)],
+            };
+            meta.finish_reasons.extend(super::split_finish_reasons(
+                choice.finish_reason.clone(),
+                split.len(),
+            ));
+            programs.extend(split);
         }
-        ["/*", event, "*/\n", input].concat()
+        Ok((programs, meta))
     }
 
-    fn strip_code_prefix<'a>(&self, input: &'a str, pat: &str) -> &'a str {
-        let pat = String::from_iter(["```", pat]);
-        if input.starts_with(&pat) {
-            if let Some(p) = input.strip_prefix(&pat) {
-                return p;
-            }
-        }
-        input
+  
+
+
+    /// 剥离代码包装器及其前后的说明文字
+    fn strip_code_wrapper(&self, input: &str) -> String {
+        Deserializer::from_input(input).extract_code_block().to_string()
     }
 }
 
-impl super::Handler for HttpHandler {
-    fn generate(&self, prompt: &super::prompt::Prompt) -> eyre::Result<Vec<Program>> {
+impl HttpHandler {
+    /// The async body of `Handler::generate`, also used by `AsyncHandler` with the `async` feature.
+    /// `n_sample` programs are generated, or a single plan for the CoT plan phase.
+    pub async fn generate_async(
+        &self,
+        prompt: &super::prompt::Prompt,
+        temperature: f32,
+        n_sample: usize,
+    ) -> eyre::Result<GenerationResult> {
         let start = std::time::Instant::now();
 
         // 将prompt转换为ChatGPT消息
@@ -692,50 +789,42 @@ impl super::Handler for HttpHandler {
             .collect();
 
         // 获取配置
-        let config = crate::config::get_config();
         let model = crate::config::get_openai_model_name().clone();
-        let mut num=config.n_sample;
-        if config.enable_cot {
-            match &prompt.task {
-                crate::request::prompt::ProgramTask::CotPlan => {
-                    // CoT阶段1: 生成执行计划，只生成1个
-                    num = 1;
-                    log::debug!("CoT Phase 1: Generating single execution plan");
-                }
-                crate::request::prompt::ProgramTask::CotCode { .. } => {
-                    // CoT阶段2: 根据计划生成代码，并行生成多个
-                    num = config.n_sample;
-                    log::debug!("CoT Phase 2: Generating {} programs based on plan", num);
-                }
-                _ => {
-                    // 其他情况使用默认值
+        // the config guard is not `Send`, so it should be released before awaiting.
+        let (num, per_call) = {
+            let config = crate::config::get_config();
+            let mut num = n_sample;
+            if config.enable_cot {
+                match &prompt.task {
+                    crate::request::prompt::ProgramTask::CotPlan => {
+                        // CoT阶段1: 生成执行计划，只生成1个
+                        num = 1;
+                        log::debug!("CoT Phase 1: Generating single execution plan");
+                    }
+                    crate::request::prompt::ProgramTask::CotCode { .. } => {
+                        // CoT阶段2: 根据计划生成代码，并行生成多个
+                        num = n_sample;
+                        log::debug!("CoT Phase 2: Generating {} programs based on plan", num);
+                    }
+                    _ => {
+                        // 其他情况使用默认值
+                    }
                 }
             }
-        }
-        // 创建异步任务，并行执行
-        let mut futures = Vec::new();
+            (num, config.programs_per_call as usize)
+        };
         
         // 判断是否为CoT Plan阶段（不需要strip）
         let strip_wrapper = !matches!(&prompt.task, crate::request::prompt::ProgramTask::CotPlan);
 
-        for _ in 0..num {
-            let messages_clone = messages.clone();
-            let model_clone = model.clone();
-            let future = self.generate_single_program(messages_clone, model_clone, strip_wrapper);
-            futures.push(future);
-        }
+        // 并行执行所有任务，后端返回的completion不足时继续请求
+        let responses = super::collect_completions(num, per_call, |n| {
+            self.generate_programs(messages.clone(), model.clone(), strip_wrapper, temperature, n)
+        })
+        .await?;
 
-        // 并行执行所有任务
-        let results = self.rt.block_on(join_all(futures));
-
-        let mut programs = Vec::new();
-        let mut total_usage = TokenUsage::default();
-
-        for result in results {
-            let (program, usage) = result?;
-            programs.push(program);
-            total_usage.add(&usage);
-        }
+        let result = GenerationResult::from_responses(responses);
+        let total_usage = result.usage.clone().unwrap_or_default();
 
         let elapsed = start.elapsed();
         log::info!("HTTP Client Generate time: {}s", elapsed.as_secs());
@@ -746,9 +835,14 @@ impl super::Handler for HttpHandler {
             total_usage.total_tokens
         );
 
-        Ok(programs)
+        Ok(result)
     }
-    fn generate_single(&self, prompt: &super::prompt::Prompt) -> eyre::Result<Program> {
+    /// The async body of `Handler::generate_single`.
+    pub async fn generate_single_async(
+        &self,
+        prompt: &super::prompt::Prompt,
+        temperature: f32,
+    ) -> eyre::Result<Program> {
         let start = std::time::Instant::now();
 
         // 将prompt转换为ChatGPT消息
@@ -767,7 +861,9 @@ impl super::Handler for HttpHandler {
         let strip_wrapper = !matches!(&prompt.task, crate::request::prompt::ProgramTask::CotPlan);
 
         // 生成单个程序
-        let (program, usage) = self.rt.block_on(self.generate_single_program(messages, model, strip_wrapper))?;
+        let (program, usage) = self
+            .generate_single_program(messages, model, strip_wrapper, temperature)
+            .await?;
 
         let elapsed = start.elapsed();
         log::info!("HTTP Client Generate Single time: {}s", elapsed.as_secs());
@@ -782,6 +878,53 @@ impl super::Handler for HttpHandler {
     }
 }
 
+impl super::Handler for HttpHandler {
+    fn generate(
+        &self,
+        prompt: &super::prompt::Prompt,
+        temperature: f32,
+    ) -> eyre::Result<GenerationResult> {
+        let n_sample = super::get_n_sample() as usize;
+        self.rt.block_on(self.generate_async(prompt, temperature, n_sample))
+    }
+
+    fn generate_n(
+        &self,
+        prompt: &super::prompt::Prompt,
+        temperature: f32,
+        n: usize,
+    ) -> eyre::Result<GenerationResult> {
+        self.rt.block_on(self.generate_async(prompt, temperature, n))
+    }
+
+    fn generate_single(
+        &self,
+        prompt: &super::prompt::Prompt,
+        temperature: f32,
+    ) -> eyre::Result<Program> {
+        self.rt.block_on(self.generate_single_async(prompt, temperature))
+    }
+}
+
+#[cfg(feature = "async")]
+impl super::async_handler::AsyncHandler for HttpHandler {
+    fn generate(
+        &self,
+        prompt: &super::prompt::Prompt,
+        temperature: f32,
+    ) -> impl std::future::Future<Output = eyre::Result<GenerationResult>> + Send {
+        self.generate_async(prompt, temperature, super::get_n_sample() as usize)
+    }
+
+    fn generate_single(
+        &self,
+        prompt: &super::prompt::Prompt,
+        temperature: f32,
+    ) -> impl std::future::Future<Output = eyre::Result<Program>> + Send {
+        self.generate_single_async(prompt, temperature)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -809,6 +952,7 @@ mod tests {
             presence_penalty: None,
             stop: None,
             stream: Some(false),
+            n: None,
         };
 
         assert!(client.validate_openai_request(&valid_request).is_ok());
@@ -824,6 +968,7 @@ mod tests {
             presence_penalty: None,
             stop: None,
             stream: Some(false),
+            n: None,
         };
 
         assert!(client.validate_openai_request(&invalid_request).is_err());
@@ -895,4 +1040,68 @@ mod tests {
         env::remove_var("OPENAI_API_KEY");
         env::remove_var("OPENAI_MODEL_NAME");
     }
+
+    #[test]
+    fn test_parse_header_list() {
+        let headers = parse_header_list("X-Gateway-Key: abc; X-Team:fuzz;").unwrap();
+        assert_eq!(
+            headers,
+            vec![
+                ("X-Gateway-Key".to_string(), "abc".to_string()),
+                ("X-Team".to_string(), "fuzz".to_string())
+            ]
+        );
+        assert!(parse_header_list("X-Gateway-Key").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connection_error_contains_url() {
+        let mut config = HttpClientConfig::default();
+        config.set_base_url("http://127.0.0.1:1/v1");
+        config.retry_attempts = 1;
+        let client = HttpClient::new(config).unwrap();
+        let request = HttpClient::build_openai_request(
+            "test-model",
+            vec![OpenAIMessageBuilder::user("Hello")],
+            None,
+            None,
+        );
+        let err = client.chat_completion(&request).await.unwrap_err();
+        assert!(err.to_string().contains("http://127.0.0.1:1/v1/chat/completions"));
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout_with_stub_server() {
+        // a stub server that accepts connections but never responds.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            let mut conns = Vec::new();
+            while let Ok((conn, _)) = listener.accept().await {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                conns.push(conn);
+            }
+        });
+
+        let mut config = HttpClientConfig::default();
+        config.set_base_url(&format!("http://{addr}/v1"));
+        config.set_timeout(Duration::from_millis(300));
+        config.retry_attempts = 2;
+        config.retry_delay = Duration::from_millis(10);
+        let client = HttpClient::new(config).unwrap();
+        let request = HttpClient::build_openai_request(
+            "test-model",
+            vec![OpenAIMessageBuilder::user("Hello")],
+            None,
+            None,
+        );
+
+        let start = std::time::Instant::now();
+        let err = client.chat_completion(&request).await.unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(crate::request::is_request_timeout(&err));
+        assert_eq!(accepted.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
 }
@@ -1,15 +1,465 @@
+use std::{
+    cell::RefCell,
+    future::Future,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering},
+};
+
 use crate::program::Program;
+use futures::future::join_all;
 
 use self::prompt::Prompt;
 
+#[cfg(feature = "async")]
+pub mod async_handler;
 pub mod http;
 pub mod openai;
 pub mod prompt;
+pub mod transcript;
+
+/// Token使用统计结构
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+}
+
+impl TokenUsage {
+    pub fn new(prompt_tokens: u32, completion_tokens: u32, total_tokens: u32) -> Self {
+        Self {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens,
+        }
+    }
+
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+    }
+}
+
+/// The finish reason of a completion that was cut off by the token limit.
+pub const FINISH_REASON_LENGTH: &str = "length";
+
+/// The metadata of a chat response.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseMeta {
+    pub usage: TokenUsage,
+    pub finish_reasons: Vec<String>,
+    pub model: String,
+}
+
+/// The programs generated for a prompt, with the metadata of the responses.
+#[derive(Debug, Default)]
+pub struct GenerationResult {
+    pub programs: Vec<Program>,
+    pub usage: Option<TokenUsage>,
+    /// The finish reason of the completion each program comes from, e.g., `stop` or `length`.
+    pub finish_reasons: Vec<String>,
+    pub model: String,
+}
+
+/// The finish reasons of the `n` programs split from a completion. Only the last program is
+/// cut off if the completion is truncated.
+pub fn split_finish_reasons(reason: Option<String>, n: usize) -> Vec<String> {
+    let reason = reason.unwrap_or_default();
+    let mut reasons = vec!["stop".to_string(); n.saturating_sub(1)];
+    if n > 0 {
+        reasons.push(reason);
+    }
+    reasons
+}
+
+impl GenerationResult {
+    /// Merge the responses of the calls issued for a prompt.
+    pub fn from_responses(responses: Vec<(Vec<Program>, ResponseMeta)>) -> Self {
+        let mut result = Self::default();
+        for (programs, meta) in responses {
+            result.programs.extend(programs);
+            result.usage.get_or_insert_with(TokenUsage::default).add(&meta.usage);
+            result.finish_reasons.extend(meta.finish_reasons);
+            if result.model.is_empty() {
+                result.model = meta.model;
+            }
+        }
+        result
+    }
+
+    /// Drop the programs truncated by the token limit.
+    pub fn drop_truncated(&mut self) {
+        let programs = std::mem::take(&mut self.programs);
+        let reasons = std::mem::take(&mut self.finish_reasons);
+        (self.programs, self.finish_reasons) = programs
+            .into_iter()
+            .zip(reasons)
+            .filter(|(_, reason)| reason.as_str() != FINISH_REASON_LENGTH)
+            .unzip();
+    }
+
+    /// Append the programs and the usage of another generation for the same prompt.
+    pub fn merge(&mut self, other: GenerationResult) {
+        self.programs.extend(other.programs);
+        self.finish_reasons.extend(other.finish_reasons);
+        if let Some(usage) = other.usage {
+            self.usage.get_or_insert_with(TokenUsage::default).add(&usage);
+        }
+        if self.model.is_empty() {
+            self.model = other.model;
+        }
+    }
+
+    pub fn into_programs(self) -> Vec<Program> {
+        self.programs
+    }
+
+    /// The number of programs truncated by the token limit.
+    pub fn truncated_num(&self) -> usize {
+        self.finish_reasons
+            .iter()
+            .filter(|reason| reason.as_str() == FINISH_REASON_LENGTH)
+            .count()
+    }
+}
+
+/// The max tokens of the requests, 0 before the first request. It is raised by `escalate_max_tokens`
+/// and kept raised for the rest of the run.
+static MAX_TOKENS_NOW: AtomicU32 = AtomicU32::new(0);
+
+/// The max tokens of the next request, None if `--max-tokens` is not given and the limit is left to the backend.
+pub fn get_max_tokens() -> Option<u32> {
+    let (initial, _) = crate::config::get_max_tokens_range()?;
+    match MAX_TOKENS_NOW.load(Ordering::Relaxed) {
+        0 => Some(initial),
+        max_tokens => Some(max_tokens),
+    }
+}
+
+/// Double `cur` up to `cap`, None if `cur` already reaches `cap`.
+pub fn next_max_tokens(cur: u32, cap: u32) -> Option<u32> {
+    if cur >= cap {
+        return None;
+    }
+    Some(cur.saturating_mul(2).min(cap))
+}
+
+/// Raise the max tokens of the following requests after a truncated completion.
+/// Returns the raised max tokens, or None if it reaches `--max-tokens-cap` already or `--max-tokens` is not given.
+pub fn escalate_max_tokens() -> Option<u32> {
+    let (_, cap) = crate::config::get_max_tokens_range()?;
+    let cur = get_max_tokens()?;
+    let next = next_max_tokens(cur, cap)?;
+    // the concurrent generations may escalate at the same time, keep the larger one.
+    MAX_TOKENS_NOW.fetch_max(next, Ordering::Relaxed);
+    Some(next)
+}
+
+/// The sample count of the requests, 0 before it is adjusted by `--auto-n-sample`.
+static N_SAMPLE_NOW: AtomicU8 = AtomicU8::new(0);
+
+/// Above this smoothed success ratio, fewer samples are requested per round.
+pub const HIGH_SUCC_RATIO: f32 = 0.8;
+/// Below this smoothed success ratio, more samples are requested per round.
+pub const LOW_SUCC_RATIO: f32 = 0.2;
+/// The weight of the latest round in the smoothed success ratio.
+const SUCC_RATIO_SMOOTHING: f32 = 0.3;
+
+/// The number of programs sampled in the next request, `--n-sample` unless adjusted.
+pub fn get_n_sample() -> u8 {
+    match N_SAMPLE_NOW.load(Ordering::Relaxed) {
+        0 => crate::config::get_sample_num(),
+        n_sample => n_sample,
+    }
+}
+
+pub fn set_n_sample(n_sample: u8) {
+    N_SAMPLE_NOW.store(n_sample, Ordering::Relaxed);
+}
+
+/// The sample count after a round of the smoothed success `ratio`: a quarter (at least one) down if
+/// the ratio is above `HIGH_SUCC_RATIO`, or up if below `LOW_SUCC_RATIO`, clamped into `[min, max]`.
+pub fn next_n_sample(cur: u8, ratio: f32, (min, max): (u8, u8)) -> u8 {
+    let step = (cur / 4).max(1);
+    let next = if ratio > HIGH_SUCC_RATIO {
+        cur.saturating_sub(step)
+    } else if ratio < LOW_SUCC_RATIO {
+        cur.saturating_add(step)
+    } else {
+        cur
+    };
+    next.clamp(min, max)
+}
 
-pub trait Handler {
-    /// generate programs via a formatted prompt
-    fn generate(&self, prompt: &Prompt) -> eyre::Result<Vec<Program>>;
+/// Track the success ratio of the generated programs across rounds, smoothed exponentially so that
+/// a single lucky or unlucky round does not flip the sample count.
+#[derive(Debug, Default)]
+pub struct SuccRatioTracker {
+    ratio: Option<f32>,
+}
+
+impl SuccRatioTracker {
+    /// Add a round of `succ` successful of `total` programs. Returns the smoothed ratio, None before any program.
+    pub fn observe(&mut self, succ: usize, total: usize) -> Option<f32> {
+        if total == 0 {
+            return self.ratio;
+        }
+        let ratio = succ as f32 / total as f32;
+        let smoothed = match self.ratio {
+            Some(prev) => prev + SUCC_RATIO_SMOOTHING * (ratio - prev),
+            None => ratio,
+        };
+        self.ratio = Some(smoothed);
+        self.ratio
+    }
+}
+
+thread_local! {
+    /// The raw completions received by the handlers on this thread since the last `take_raw_completions`.
+    /// The handlers block on their requests, so the completions are received on the calling thread.
+    static RAW_COMPLETIONS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Record a raw completion before it is deserialized, if `--verbose-generation` is set.
+/// Every handler should call it on each received completion.
+pub fn record_raw_completion(content: &str) {
+    if crate::config::verbose_generation() {
+        RAW_COMPLETIONS.with(|completions| completions.borrow_mut().push(content.to_string()));
+    }
+}
+
+/// Take the raw completions recorded so far on this thread.
+pub fn take_raw_completions() -> Vec<String> {
+    RAW_COMPLETIONS.with(|completions| completions.take())
+}
+
+/// Whether the request failed as it was timed out. The exhausted retries count only if the last attempt was timed out.
+pub fn is_request_timeout(err: &eyre::Report) -> bool {
+    if err.downcast_ref::<tokio::time::error::Elapsed>().is_some() {
+        return true;
+    }
+    if let Some(source) = err.downcast_ref::<reqwest::Error>() {
+        return source.is_timeout();
+    }
+    if let Some(async_openai::error::OpenAIError::Reqwest(source)) =
+        err.downcast_ref::<async_openai::error::OpenAIError>()
+    {
+        return source.is_timeout();
+    }
+    match err.downcast_ref::<http::HttpClientError>() {
+        Some(http::HttpClientError::TimeoutError(_)) => true,
+        Some(http::HttpClientError::ConnectionError { source, .. }) => source.is_timeout(),
+        Some(http::HttpClientError::RequestError(source)) => source.is_timeout(),
+        _ => false,
+    }
+}
+
+/// Split `total` completions into calls that each ask for at most `per_call` completions.
+pub fn plan_completion_calls(total: usize, per_call: usize) -> Vec<usize> {
+    let per_call = per_call.max(1);
+    let mut calls = vec![per_call; total / per_call];
+    if !total.is_multiple_of(per_call) {
+        calls.push(total % per_call);
+    }
+    calls
+}
+
+/// Issue concurrent calls of `call(n)` until `total` programs are received.
+///
+/// Some backends ignore `n` and return fewer completions than asked, the missing ones are requested again.
+pub async fn collect_completions<U, F, Fut>(
+    total: usize,
+    per_call: usize,
+    mut call: F,
+) -> eyre::Result<Vec<(Vec<Program>, U)>>
+where
+    F: FnMut(usize) -> Fut,
+    Fut: Future<Output = eyre::Result<(Vec<Program>, U)>>,
+{
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    let mut responses = Vec::new();
+    let mut received = 0;
+    while received < total {
+        let calls = plan_completion_calls(total - received, per_call);
+        let results = join_all(calls.iter().map(|n| call(*n))).await;
+        let before = received;
+        for (n, result) in calls.into_iter().zip(results) {
+            let (programs, usage) = result?;
+            if programs.len() < n && !WARNED.swap(true, Ordering::Relaxed) {
+                log::warn!(
+                    "The LLM backend returned {} of {n} requested completions, request the rest in additional calls.",
+                    programs.len()
+                );
+            }
+            received += programs.len();
+            responses.push((programs, usage));
+        }
+        if received == before {
+            eyre::bail!("The LLM backend returned no completions.");
+        }
+    }
+    Ok(responses)
+}
+
+/// The handlers are shared by the concurrent generations of `--prompts-per-round`, so they must be `Sync`.
+pub trait Handler: Sync {
+    /// generate programs via a formatted prompt, sampled with `temperature`.
+    fn generate(&self, prompt: &Prompt, temperature: f32) -> eyre::Result<GenerationResult>;
+
+    /// generate `n` programs instead of the `--n-sample` ones, e.g., to replace the truncated programs.
+    /// Handlers that cannot set the sample count fall back to `generate` and keep the first `n` programs.
+    fn generate_n(&self, prompt: &Prompt, temperature: f32, n: usize) -> eyre::Result<GenerationResult> {
+        let mut result = self.generate(prompt, temperature)?;
+        result.programs.truncate(n);
+        result.finish_reasons.truncate(n);
+        Ok(result)
+    }
+
+    /// generate programs, without the metadata of the responses.
+    fn generate_programs(&self, prompt: &Prompt, temperature: f32) -> eyre::Result<Vec<Program>> {
+        Ok(self.generate(prompt, temperature)?.into_programs())
+    }
     
     /// generate a single program (used for CoT Phase 1: plan generation)
-    fn generate_single(&self, prompt: &Prompt) -> eyre::Result<Program>;
+    fn generate_single(&self, prompt: &Prompt, temperature: f32) -> eyre::Result<Program>;
+
+    /// generate programs by streaming the responses, `on_program` is called once a program is completely received.
+    /// The remaining generations are cancelled once `on_program` returns false.
+    /// Handlers that do not support streaming fall back to `generate`.
+    fn generate_streaming(
+        &self,
+        prompt: &Prompt,
+        temperature: f32,
+        on_program: &mut dyn FnMut(Program) -> bool,
+    ) -> eyre::Result<()> {
+        for program in self.generate_programs(prompt, temperature)? {
+            if !on_program(program) {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_max_tokens() {
+        assert_eq!(next_max_tokens(2048, 16384), Some(4096));
+        assert_eq!(next_max_tokens(12000, 16384), Some(16384));
+        assert_eq!(next_max_tokens(16384, 16384), None);
+        assert_eq!(next_max_tokens(u32::MAX / 2 + 1, u32::MAX), Some(u32::MAX));
+    }
+
+    #[test]
+    fn test_is_request_timeout() {
+        let retry = || crate::FuzzerError::RetryError("request".to_string(), 3);
+        assert!(!is_request_timeout(&retry().into()));
+        assert!(!is_request_timeout(
+            &http::HttpClientError::RetryExhausted { attempts: 3 }.into()
+        ));
+        assert!(!is_request_timeout(&eyre::eyre!("bad request").wrap_err(retry())));
+
+        let timeout = eyre::Report::new(http::HttpClientError::TimeoutError("slow".to_string()));
+        assert!(is_request_timeout(&timeout));
+        assert!(is_request_timeout(&timeout.wrap_err(retry())));
+
+        let rt = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        let elapsed = rt
+            .block_on(async {
+                tokio::time::timeout(std::time::Duration::from_millis(1), std::future::pending::<()>())
+                    .await
+            })
+            .unwrap_err();
+        assert!(is_request_timeout(&elapsed.into()));
+    }
+
+    #[test]
+    fn test_next_n_sample() {
+        assert_eq!(next_n_sample(10, 0.9, (2, 32)), 8);
+        assert_eq!(next_n_sample(10, 0.1, (2, 32)), 12);
+        assert_eq!(next_n_sample(10, 0.5, (2, 32)), 10);
+        assert_eq!(next_n_sample(2, 1.0, (2, 32)), 2);
+        assert_eq!(next_n_sample(3, 1.0, (1, 32)), 2);
+        assert_eq!(next_n_sample(30, 0.0, (2, 32)), 32);
+
+        let mut tracker = SuccRatioTracker::default();
+        assert_eq!(tracker.observe(0, 0), None);
+        assert_eq!(tracker.observe(10, 10), Some(1.0));
+        // a single failed round does not drop the ratio below the band.
+        assert!((tracker.observe(0, 10).unwrap() - 0.7).abs() < 1e-6);
+        assert!((tracker.observe(0, 0).unwrap() - 0.7).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_merge_generation_result() {
+        let meta = |reasons: &[&str]| ResponseMeta {
+            usage: TokenUsage::new(10, 20, 30),
+            finish_reasons: reasons.iter().map(|reason| reason.to_string()).collect(),
+            model: "gpt-4o-mini".to_string(),
+        };
+        let result = GenerationResult::from_responses(vec![
+            (vec![Program::new("int a;"), Program::new("int b;")], meta(&["stop", "length"])),
+            (vec![Program::new("int c;")], meta(&["length"])),
+        ]);
+        assert_eq!(result.usage, Some(TokenUsage::new(20, 40, 60)));
+        assert_eq!(result.model, "gpt-4o-mini");
+        assert_eq!(result.truncated_num(), 2);
+        assert_eq!(result.into_programs().len(), 3);
+        assert!(GenerationResult::from_responses(Vec::new()).usage.is_none());
+    }
+
+    #[test]
+    fn test_drop_truncated_programs() {
+        assert_eq!(split_finish_reasons(Some("length".to_string()), 3), ["stop", "stop", "length"]);
+        assert_eq!(split_finish_reasons(None, 1), [""]);
+        assert!(split_finish_reasons(Some("stop".to_string()), 0).is_empty());
+
+        let meta = |reasons: &[&str]| ResponseMeta {
+            usage: TokenUsage::new(10, 20, 30),
+            finish_reasons: reasons.iter().map(|reason| reason.to_string()).collect(),
+            model: "gpt-4o-mini".to_string(),
+        };
+        let mut result = GenerationResult::from_responses(vec![(
+            vec![Program::new("int a;"), Program::new("int b;"), Program::new("int c;")],
+            meta(&["length", "stop", "length"]),
+        )]);
+        result.drop_truncated();
+        assert_eq!(result.truncated_num(), 0);
+        assert_eq!(result.programs.len(), 1);
+        assert_eq!(result.programs[0].statements, "int b;");
+
+        result.merge(GenerationResult::from_responses(vec![(
+            vec![Program::new("int d;"), Program::new("int e;")],
+            meta(&["stop", "length"]),
+        )]));
+        assert_eq!(result.programs.len(), 3);
+        assert_eq!(result.truncated_num(), 1);
+        assert_eq!(result.usage, Some(TokenUsage::new(20, 40, 60)));
+    }
+
+    #[test]
+    fn test_collect_completions_on_under_delivery() -> eyre::Result<()> {
+        assert_eq!(plan_completion_calls(10, 1), vec![1; 10]);
+        assert_eq!(plan_completion_calls(10, 4), vec![4, 4, 2]);
+        assert_eq!(plan_completion_calls(3, 0), vec![1, 1, 1]);
+
+        // a backend that ignores `n` and always returns a single completion.
+        let mut asked = Vec::new();
+        let responses = futures::executor::block_on(collect_completions(5, 3, |n| {
+            asked.push(n);
+            async { Ok((vec![Program::new("int main() {}")], ())) }
+        }))?;
+        let programs: Vec<Program> = responses.into_iter().flat_map(|(p, _)| p).collect();
+        assert_eq!(programs.len(), 5);
+        assert_eq!(asked, vec![3, 2, 3, 2, 1]);
+
+        let empty = futures::executor::block_on(collect_completions(2, 1, |_| async {
+            Ok((Vec::new(), ()))
+        }));
+        assert!(empty.is_err());
+        Ok(())
+    }
 }
@@ -3,40 +3,25 @@ use std::{process::Child, time::Duration};
 use crate::{
     config::{self, get_config, get_openai_proxy},
     is_critical_err,
-    program::Program,
+    program::{serde::Deserializer, Program},
     FuzzerError,
 };
 use async_openai::{
     config::OpenAIConfig,
     types::{
-        ChatCompletionRequestMessage, CreateChatCompletionRequest, CreateChatCompletionRequestArgs,
-        CreateChatCompletionResponse,
+        ChatChoice, ChatCompletionRequestMessage, CreateChatCompletionRequest,
+        CreateChatCompletionRequestArgs, CreateChatCompletionResponse, ResponseFormat,
+        ResponseFormatJsonSchema,
     },
     Client,
 };
 use eyre::Result;
-use futures::future::join_all;
+use futures::{stream::FuturesUnordered, StreamExt};
 use once_cell::sync::OnceCell;
 
-use super::Handler;
-
-/// Token使用统计结构
-#[derive(Debug, Clone, Default)]
-pub struct TokenUsage {
-    pub prompt_tokens: u32,
-    pub completion_tokens: u32,
-    pub total_tokens: u32,
-}
+use super::{collect_completions, GenerationResult, Handler, ResponseMeta, TokenUsage};
 
 impl TokenUsage {
-    pub fn new(prompt_tokens: u32, completion_tokens: u32, total_tokens: u32) -> Self {
-        Self {
-            prompt_tokens,
-            completion_tokens,
-            total_tokens,
-        }
-    }
-
     pub fn from_response(response: &CreateChatCompletionResponse) -> Self {
         if let Some(usage) = &response.usage {
             Self {
@@ -48,14 +33,24 @@ impl TokenUsage {
             Self::default()
         }
     }
+}
 
-    pub fn add(&mut self, other: &TokenUsage) {
-        self.prompt_tokens += other.prompt_tokens;
-        self.completion_tokens += other.completion_tokens;
-        self.total_tokens += other.total_tokens;
+impl ResponseMeta {
+    pub fn from_response(response: &CreateChatCompletionResponse) -> Self {
+        Self {
+            usage: TokenUsage::from_response(response),
+            finish_reasons: response.choices.iter().filter_map(finish_reason).collect(),
+            model: response.model.clone(),
+        }
     }
 }
 
+/// The finish reason of a choice as the string of the API, e.g., `length`.
+fn finish_reason(choice: &ChatChoice) -> Option<String> {
+    let reason = serde_json::to_value(choice.finish_reason.as_ref()?).ok()?;
+    reason.as_str().map(|reason| reason.to_string())
+}
+
 pub struct OpenAIHanler {
     _child: Option<Child>,
     rt: tokio::runtime::Runtime,
@@ -73,24 +68,28 @@ impl Default for OpenAIHanler {
 
 impl Handler for OpenAIHanler {
     /// Generate `SAMPLE_N` programs by chatting with instructions.
-    fn generate(&self, prompt: &super::prompt::Prompt) -> eyre::Result<Vec<Program>> {
+    fn generate(
+        &self,
+        prompt: &super::prompt::Prompt,
+        temperature: f32,
+    ) -> eyre::Result<GenerationResult> {
+        self.generate_n(prompt, temperature, super::get_n_sample() as usize)
+    }
+
+    fn generate_n(
+        &self,
+        prompt: &super::prompt::Prompt,
+        temperature: f32,
+        n_sample: usize,
+    ) -> eyre::Result<GenerationResult> {
         let start = std::time::Instant::now();
         let chat_msgs = prompt.to_chatgpt_message();
-        let mut futures = Vec::new();
-        for _ in 0..get_config().n_sample {
-            let future = generate_program_by_chat(chat_msgs.clone());
-            futures.push(future);
-        }
-        let results = self.rt.block_on(join_all(futures));
-
-        let mut programs = Vec::new();
-        let mut total_usage = TokenUsage::default();
-
-        for result in results {
-            let (program, usage) = result?;
-            programs.push(program);
-            total_usage.add(&usage);
-        }
+        let per_call = get_config().programs_per_call as usize;
+        let responses = self.rt.block_on(collect_completions(n_sample, per_call, |n| {
+            generate_programs_by_chat(chat_msgs.clone(), temperature, n)
+        }))?;
+        let result = GenerationResult::from_responses(responses);
+        let total_usage = result.usage.clone().unwrap_or_default();
 
         let elapsed = start.elapsed();
         log::info!("OpenAI Generate time: {}s", elapsed.as_secs());
@@ -101,14 +100,18 @@ impl Handler for OpenAIHanler {
             total_usage.total_tokens
         );
 
-        Ok(programs)
+        Ok(result)
     }
 
     /// Generate a single program (used for CoT Phase 1: plan generation)
-    fn generate_single(&self, prompt: &super::prompt::Prompt) -> eyre::Result<Program> {
+    fn generate_single(
+        &self,
+        prompt: &super::prompt::Prompt,
+        temperature: f32,
+    ) -> eyre::Result<Program> {
         let start = std::time::Instant::now();
         let chat_msgs = prompt.to_chatgpt_message();
-        let result = self.rt.block_on(generate_program_by_chat(chat_msgs));
+        let result = self.rt.block_on(generate_program_by_chat(chat_msgs, temperature));
         
         let (program, usage) = result?;
         
@@ -123,6 +126,44 @@ impl Handler for OpenAIHanler {
 
         Ok(program)
     }
+
+    /// Consume the SSE chunks of `SAMPLE_N` completions concurrently, and hand over each program once its stream is finished.
+    fn generate_streaming(
+        &self,
+        prompt: &super::prompt::Prompt,
+        temperature: f32,
+        on_program: &mut dyn FnMut(Program) -> bool,
+    ) -> eyre::Result<()> {
+        let start = std::time::Instant::now();
+        let chat_msgs = prompt.to_chatgpt_message();
+        let mut streams = FuturesUnordered::new();
+        for _ in 0..super::get_n_sample() {
+            streams.push(generate_program_by_stream(chat_msgs.clone(), temperature));
+        }
+
+        let mut total_usage = TokenUsage::default();
+        self.rt.block_on(async {
+            while let Some(result) = streams.next().await {
+                let (program, usage) = result?;
+                total_usage.add(&usage);
+                if !on_program(program) {
+                    log::debug!("Cancel the remaining {} streaming requests.", streams.len());
+                    break;
+                }
+            }
+            Ok::<(), eyre::Report>(())
+        })?;
+
+        let elapsed = start.elapsed();
+        log::info!("OpenAI Generate Streaming time: {}s", elapsed.as_secs());
+        log::info!(
+            "OpenAI Token Usage - Prompt: {}, Completion: {}, Total: {}",
+            total_usage.prompt_tokens,
+            total_usage.completion_tokens,
+            total_usage.total_tokens
+        );
+        Ok(())
+    }
 }
 
 /// Get the OpenAI interface client.
@@ -132,7 +173,7 @@ fn get_client() -> Result<&'static Client<OpenAIConfig>> {
     let client = CLIENT.get_or_init(|| {
         let http_client = reqwest::ClientBuilder::new()
             .connect_timeout(Duration::from_secs(10))
-            .timeout(Duration::from_secs(180))
+            .timeout(config::get_request_timeout())
             .build()
             .unwrap();
         let openai_config = if let Some(proxy) = get_openai_proxy() {
@@ -152,13 +193,15 @@ fn get_client() -> Result<&'static Client<OpenAIConfig>> {
 fn create_chat_request(
     msgs: Vec<ChatCompletionRequestMessage>,
     stop: Option<String>,
+    temperature: f32,
 ) -> Result<CreateChatCompletionRequest> {
     let mut binding = CreateChatCompletionRequestArgs::default();
     let binding = binding.model(config::get_openai_model_name());
 
-    let mut request = binding
-        .messages(msgs)
-        .temperature(config::get_config().temperature);
+    let mut request = binding.messages(msgs).temperature(temperature);
+    if let Some(max_tokens) = super::get_max_tokens() {
+        request = request.max_completion_tokens(max_tokens);
+    }
     if let Some(stop) = stop {
         request = request.stop(stop);
     }
@@ -166,11 +209,75 @@ fn create_chat_request(
     Ok(request)
 }
 
+/// The fixed schema of structured responses: the program is held in the `code` field.
+fn program_response_format() -> ResponseFormat {
+    ResponseFormat::JsonSchema {
+        json_schema: ResponseFormatJsonSchema {
+            description: Some("The generated program.".to_string()),
+            name: "program".to_string(),
+            schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "code": {
+                        "type": "string",
+                        "description": "The complete source code of the program, without markdown fences."
+                    }
+                },
+                "required": ["code"],
+                "additionalProperties": false
+            })),
+            strict: Some(true),
+        },
+    }
+}
+
+/// Create a request for a chat prompt whose response is a program.
+fn create_program_request(
+    msgs: Vec<ChatCompletionRequestMessage>,
+    temperature: f32,
+) -> Result<CreateChatCompletionRequest> {
+    let mut request = create_chat_request(msgs, None, temperature)?;
+    if get_config().structured_output {
+        request.response_format = Some(program_response_format());
+    }
+    Ok(request)
+}
+
+/// Extract the program code from the response content.
+/// The structured response is preferred, and the plain text is the fallback.
+fn extract_program_content(content: &str) -> String {
+    strip_code_wrapper(&structured_code(content))
+}
+
+/// The `code` field of a structured response, or the content itself.
+fn structured_code(content: &str) -> String {
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(content.trim()) {
+        if let Some(code) = value.get("code").and_then(|code| code.as_str()) {
+            return code.to_string();
+        }
+    }
+    content.to_string()
+}
+
+/// Extract the programs of the response content, which are split at the lines of `separator` if given.
+fn extract_program_contents(content: &str, separator: Option<&str>) -> Vec<String> {
+    let Some(separator) = separator else {
+        return vec![extract_program_content(content)];
+    };
+    let code = structured_code(content);
+    Deserializer::from_input(&code)
+        .split_programs(separator)
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+}
+
 /// Get a response for a chat request
 async fn get_chat_response(
     request: CreateChatCompletionRequest,
 ) -> Result<CreateChatCompletionResponse> {
     let client = get_client().unwrap();
+    let mut last_err = None;
     for _retry in 0..config::RETRY_N {
         let response = client
             .chat()
@@ -183,28 +290,99 @@ async fn get_chat_response(
                 return Ok(response);
             }
             crate::Critical::NonCritical => {
+                last_err = response.err();
                 continue;
             }
             crate::Critical::Critical => return Err(response.err().unwrap()),
         }
     }
-    Err(FuzzerError::RetryError(format!("{request:?}"), config::RETRY_N).into())
+    // keep the last error, so that a timed out request is still told by `is_request_timeout`.
+    let err = FuzzerError::RetryError(format!("{request:?}"), config::RETRY_N);
+    Err(match last_err {
+        Some(last_err) => last_err.wrap_err(err),
+        None => err.into(),
+    })
 }
 
 pub async fn generate_program_by_chat(
     chat_msgs: Vec<ChatCompletionRequestMessage>,
+    temperature: f32,
 ) -> Result<(Program, TokenUsage)> {
-    let request = create_chat_request(chat_msgs, None)?;
+    let request = create_chat_request(chat_msgs, None, temperature)?;
     let respond = get_chat_response(request).await?;
 
     let usage = TokenUsage::from_response(&respond);
     let choice = respond.choices.first().unwrap();
     let content = choice.message.content.as_ref().unwrap();
+    super::record_raw_completion(content);
     let content = strip_code_wrapper(&content);
     let program = Program::new(&content);
     Ok((program, usage))
 }
 
+/// Generate `n` programs from the choices of a single chat request.
+pub async fn generate_programs_by_chat(
+    chat_msgs: Vec<ChatCompletionRequestMessage>,
+    temperature: f32,
+    n: usize,
+) -> Result<(Vec<Program>, ResponseMeta)> {
+    let mut request = create_program_request(chat_msgs, temperature)?;
+    // the programs are written in a single completion with the separator.
+    let separator = config::get_program_separator();
+    if n > 1 && separator.is_none() {
+        request.n = Some(n as u8);
+    }
+    let respond = get_chat_response(request).await?;
+
+    let mut meta = ResponseMeta::from_response(&respond);
+    // the finish reasons are kept per program to tell the truncated ones.
+    meta.finish_reasons.clear();
+    let mut programs = Vec::new();
+    for choice in &respond.choices {
+        let Some(content) = choice.message.content.as_ref() else {
+            continue;
+        };
+        super::record_raw_completion(content);
+        let contents = extract_program_contents(content, separator.as_deref());
+        meta.finish_reasons
+            .extend(super::split_finish_reasons(finish_reason(choice), contents.len()));
+        programs.extend(contents.iter().map(|content| Program::new(content)));
+    }
+    Ok((programs, meta))
+}
+
+/// Assemble a program from the streamed chunks of a chat completion.
+pub async fn generate_program_by_stream(
+    chat_msgs: Vec<ChatCompletionRequestMessage>,
+    temperature: f32,
+) -> Result<(Program, TokenUsage)> {
+    let request = create_program_request(chat_msgs, temperature)?;
+    let client = get_client()?;
+    let mut stream = client.chat().create_stream(request).await?;
+
+    let mut content = String::new();
+    let mut usage = TokenUsage::default();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        if let Some(choice) = chunk.choices.first() {
+            if let Some(delta) = &choice.delta.content {
+                content.push_str(delta);
+            }
+        }
+        if let Some(chunk_usage) = &chunk.usage {
+            usage = TokenUsage::new(
+                chunk_usage.prompt_tokens,
+                chunk_usage.completion_tokens,
+                chunk_usage.total_tokens,
+            );
+        }
+    }
+    super::record_raw_completion(&content);
+    let content = extract_program_content(&content);
+    let program = Program::new(&content);
+    Ok((program, usage))
+}
+
 fn strip_code_prefix<'a>(input: &'a str, pat: &str) -> &'a str {
     let pat = String::from_iter(["```", pat]);
     if input.starts_with(&pat) {
@@ -246,6 +424,25 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_extract_program_content() {
+        let code = "int LLVMFuzzerTestOneInput(const uint8_t *data, size_t size) {\n  return 0;\n}";
+        let structured = serde_json::json!({ "code": code }).to_string();
+        assert_eq!(extract_program_content(&structured), format!("/**/\n{code}"));
+        // fallback to the plain text response.
+        let plain = ["Here is the driver:\n```cpp\n", code, "\n```"].concat();
+        let content = extract_program_content(&plain);
+        assert!(content.starts_with("/*Here is the driver:\n*/") && content.contains(code));
+        assert!(!content.contains("```"));
+        assert_eq!(extract_program_content("{\"plan\": 1}"), "/**/\n{\"plan\": 1}");
+
+        let separator = "// NEXT";
+        let joined = [code, separator, code].join("\n");
+        let structured = serde_json::json!({ "code": joined }).to_string();
+        assert_eq!(extract_program_contents(&structured, Some(separator)), vec![code, code]);
+        assert_eq!(extract_program_contents(&plain, None), vec![extract_program_content(&plain)]);
+    }
+
     #[test]
     fn test_get_client() -> Result<()> {
         dotenv::dotenv().ok();
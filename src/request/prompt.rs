@@ -9,10 +9,10 @@ use std::collections::VecDeque;
 use std::{
     collections::{HashMap, HashSet},
     fmt::Display,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::RwLock,
 };
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub enum ProgramTask {
     Generate,
     /// Chain of Thought Phase 1: Generate execution plan
@@ -30,9 +30,14 @@ pub enum ProgramTask {
 pub struct Prompt {
     pub gadgets: Vec<&'static FuncGadget>,
     pub successful_examples: VecDeque<String>,
+    /// The recently discovered API pairs the model should avoid reusing, the most recent at the back.
+    pub avoid_pairs: VecDeque<(String, String)>,
     pub task: ProgramTask,
 }
 
+/// The maximum number of pairs to avoid listed in a prompt, which bounds the prompt length.
+pub const MAX_AVOID_PAIRS: usize = 30;
+
 impl Prompt {
     pub fn new(gadgets: Vec<&'static FuncGadget>) -> Self {
         // Check if CoT mode is enabled
@@ -46,6 +51,7 @@ impl Prompt {
         Self {
             gadgets,
             successful_examples: VecDeque::new(),
+            avoid_pairs: VecDeque::new(),
             task,
         }
     }
@@ -68,6 +74,75 @@ impl Prompt {
     pub fn set_cot_code_task(&mut self, execution_plan: String) {
         self.task = ProgramTask::CotCode { execution_plan };
     }
+
+    /// Checkpoint the prompt to `misc/prompt_checkpoint.json` of the library, see `Prompt::load`.
+    pub fn save(&self, deopt: &Deopt) -> eyre::Result<()> {
+        self.save_to(&deopt.get_library_prompt_checkpoint_path()?)
+    }
+
+    /// Load the prompt checkpointed by `Prompt::save`, None if there is none.
+    pub fn load(deopt: &Deopt) -> eyre::Result<Option<Self>> {
+        let path = deopt.get_library_prompt_checkpoint_path()?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        Self::load_from(&path).map(Some)
+    }
+
+    pub fn save_to(&self, path: &Path) -> eyre::Result<()> {
+        let saved = SavedPrompt {
+            combination: self
+                .gadgets
+                .iter()
+                .map(|gadget| gadget.get_func_name().to_string())
+                .collect(),
+            successful_examples: self.successful_examples.clone(),
+            avoid_pairs: self.avoid_pairs.clone(),
+            task: self.task.clone(),
+        };
+        std::fs::write(path, serde_json::to_string_pretty(&saved)?)?;
+        Ok(())
+    }
+
+    /// Load a prompt saved by `Prompt::save_to`, or the bare list of API names in `misc/prompt.json`.
+    pub fn load_from(path: &Path) -> eyre::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| eyre::eyre!("Cannot read the prompt {path:?}: {err}"))?;
+        let saved = match serde_json::from_str::<SavedPrompt>(&content) {
+            Ok(saved) => saved,
+            Err(err) => match serde_json::from_str::<Vec<String>>(&content) {
+                Ok(combination) => SavedPrompt {
+                    combination,
+                    successful_examples: VecDeque::new(),
+                    avoid_pairs: VecDeque::new(),
+                    task: ProgramTask::Generate,
+                },
+                Err(_) => eyre::bail!("Cannot parse the prompt {path:?}: {err}"),
+            },
+        };
+        let mut gadgets = Vec::new();
+        for func in saved.combination.iter() {
+            let Some(gadget) = get_func_gadget(func) else {
+                eyre::bail!("The API `{func}` of the prompt {path:?} is not found in the library");
+            };
+            gadgets.push(gadget);
+        }
+        Ok(Self {
+            gadgets,
+            successful_examples: saved.successful_examples,
+            avoid_pairs: saved.avoid_pairs,
+            task: saved.task,
+        })
+    }
+}
+
+/// The persisted form of a prompt, whose APIs are referred by names.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct SavedPrompt {
+    combination: Vec<String>,
+    successful_examples: VecDeque<String>,
+    avoid_pairs: VecDeque<(String, String)>,
+    task: ProgramTask,
 }
 
 impl Display for Prompt {
@@ -157,6 +232,34 @@ impl Prompt {
         self.successful_examples.push_back(example_code);
     }
 
+    /// Ask the model to avoid reusing these discovered pairs. Only the `MAX_AVOID_PAIRS` most recently added pairs are kept.
+    pub fn add_avoid_pairs(&mut self, pairs: &HashSet<(String, String)>) {
+        let mut pairs: Vec<&(String, String)> = pairs.iter().collect();
+        pairs.sort();
+        for pair in pairs {
+            // a re-found pair is moved to the back as the most recent one.
+            self.avoid_pairs.retain(|avoid| avoid != pair);
+            self.avoid_pairs.push_back(pair.clone());
+        }
+        while self.avoid_pairs.len() > MAX_AVOID_PAIRS {
+            self.avoid_pairs.pop_front();
+        }
+    }
+
+    /// The instruction that lists the pairs to avoid, the most recent first. Empty if there is none.
+    fn avoid_pairs_to_str(&self) -> String {
+        if self.avoid_pairs.is_empty() {
+            return String::new();
+        }
+        let mut instruction = String::from(
+            "The following API call pairs (caller -> callee) have been covered already. Avoid reusing them and prefer new combinations of calls:\n",
+        );
+        for (caller, callee) in self.avoid_pairs.iter().rev() {
+            instruction.push_str(&format!("- {caller} -> {callee}\n"));
+        }
+        instruction
+    }
+
     /// from generative prompt to API combination vec.
     pub fn get_combination(&self) -> eyre::Result<Vec<&'static FuncGadget>> {
         Ok(self.gadgets.clone())
@@ -175,8 +278,10 @@ impl Prompt {
             log::debug!("Using FuzzDriver generation mode");
             let sys_msg = get_sys_gen_message(ctx, &config);
             log::trace!("System role: {sys_msg}");
-            let user_msg = config::get_user_chat_template()
+            let mut user_msg = config::get_user_chat_template()
                 .replace("{combinations}", &combination_to_str(&self.gadgets));
+            user_msg.push_str(entry_point_instruction());
+            user_msg.push_str(&program_separator_instruction(&config));
             let sys_msg = ChatCompletionRequestSystemMessageArgs::default()
                 .content(sys_msg)
                 .build()
@@ -209,9 +314,12 @@ impl Prompt {
                         String::new()
                     };
 
-                    config::get_user_gen_template()
-                        .replace("{combinations}", &combination_to_str(&self.gadgets))
+                    let user_msg = config::get_user_gen_template()
+                        .replace("{combinations}", &combination_to_str(&self.gadgets));
+                    fill_avoid_pairs(&user_msg, &self.avoid_pairs_to_str())
                         .replace("{successful_examples}", &successful_examples)
+                        + entry_point_instruction()
+                        + &program_separator_instruction(&config)
                 }
                 
                 ProgramTask::CotPlan => {
@@ -245,6 +353,8 @@ impl Prompt {
                         .replace("{execution_plan}", execution_plan)
                         .replace("{project_rules}", &project_rules)
                         .replace("{successful_examples}", &successful_examples)
+                        + entry_point_instruction()
+                        + &program_separator_instruction(&config)
                     
                     
                     
@@ -286,6 +396,38 @@ impl Prompt {
     }
 }
 
+static ENTRY_POINT_INSTRUCTION: OnceCell<String> = OnceCell::new();
+
+/// The instruction of the entry point of `--entry-points`, empty for the whole library.
+/// The entry point is fixed during an execution, so its suffix is read once.
+fn entry_point_instruction() -> &'static str {
+    ENTRY_POINT_INSTRUCTION.get_or_init(|| {
+        let deopt = Deopt::new(get_library_name()).unwrap();
+        match deopt.get_entry_point_suffix() {
+            "" => String::new(),
+            suffix => format!("\n{suffix}\n"),
+        }
+    })
+}
+
+/// Fill `{avoid_pairs}` of `template` with the `avoid_pairs` instruction, which ends with a newline.
+/// The line of the placeholder is dropped if the instruction is empty, rather than left blank.
+fn fill_avoid_pairs(template: &str, avoid_pairs: &str) -> String {
+    template
+        .replace("{avoid_pairs}\n", avoid_pairs)
+        .replace("{avoid_pairs}", avoid_pairs.trim_end())
+}
+
+/// The instruction to write several programs in one completion, empty without `--program-separator`.
+fn program_separator_instruction(config: &Config) -> String {
+    match &config.program_separator {
+        Some(separator) => config::PROGRAM_SEPARATOR_TEMPLATE
+            .replace("{n}", &config.programs_per_call.to_string())
+            .replace("{separator}", separator),
+        None => String::new(),
+    }
+}
+
 /// get the message of the system role for generative tasks.
 pub fn get_sys_gen_message(ctx: String, config: &Config) -> String {
     let deopt = Deopt::new(get_library_name()).unwrap();
@@ -299,7 +441,12 @@ pub fn get_sys_gen_message(ctx: String, config: &Config) -> String {
     if let Some(desc) = deopt.config.desc {
         ctx_template.insert_str(0, &desc);
     }
-    let ctx_template = ctx_template.replace("{headers}", &get_include_sys_headers_str());
+    let mut headers = get_include_sys_headers_str();
+    for include in config::get_extra_include_lines() {
+        headers.push('\n');
+        headers.push_str(include);
+    }
+    let ctx_template = ctx_template.replace("{headers}", &headers);
     let ctx_template = ctx_template.replace("{APIs}", &dump_func_gadgets_tostr());
     let ctx_template = ctx_template.replace("{context}", &ctx);
     template.push_str("\n\n");
@@ -309,7 +456,6 @@ pub fn get_sys_gen_message(ctx: String, config: &Config) -> String {
 
 /// get the type definitions in args of the apis of the combination.
 fn get_combination_definitions(combination: &Vec<&FuncGadget>) -> String {
-    let mut context = Vec::new();
     let mut unique_tys = HashSet::new();
     for func in combination {
         for arg in func.get_alias_arg_types() {
@@ -317,17 +463,40 @@ fn get_combination_definitions(combination: &Vec<&FuncGadget>) -> String {
         }
         unique_tys.insert(get_unsugared_unqualified_type(func.get_alias_ret_type()));
     }
-    // insert the force types
     let deopt = crate::deopt::Deopt::new(get_library_name()).unwrap();
-    if let Some(force_types) = &deopt.config.force_types {
-        for ty in force_types {
-            unique_tys.insert(ty.to_string());
-        }
-    }
+    let force_types = deopt.config.force_types.unwrap_or_default();
+    assemble_type_definitions(unique_tys, &force_types, get_type_definition)
+}
 
+/// Assemble the definitions of types into context.
+/// The force types are resolved before the others, so that they are always included rather than being pruned as visited.
+fn assemble_type_definitions<F>(
+    tys: HashSet<String>,
+    force_types: &[String],
+    mut get_def: F,
+) -> String
+where
+    F: FnMut(&str, &mut HashSet<String>) -> Option<String>,
+{
+    let mut context = Vec::new();
     let mut visited: HashSet<String> = HashSet::new();
-    for ty in unique_tys {
-        if let Some(def) = get_type_definition(&ty, &mut visited) {
+    let mut forced: HashSet<&String> = HashSet::new();
+    for ty in force_types {
+        if !forced.insert(ty) {
+            continue;
+        }
+        match get_def(ty, &mut visited) {
+            Some(def) => context.push(def),
+            None => log::warn!(
+                "Cannot find the declaration of force type `{ty}` in library, please check your config.yaml."
+            ),
+        }
+    }
+    for ty in tys {
+        if forced.contains(&ty) {
+            continue;
+        }
+        if let Some(def) = get_def(&ty, &mut visited) {
             context.push(def);
         }
     }
@@ -354,22 +523,6 @@ pub fn save_prompt(combination: &[&FuncGadget]) {
     std::fs::write(counter_path, serde_json::to_string(&funcs).unwrap()).unwrap();
 }
 
-pub fn load_prompt(deopt: &Deopt) -> Option<Prompt> {
-    let counter_path: PathBuf = [deopt.get_library_misc_dir().unwrap(), "prompt.json".into()]
-        .iter()
-        .collect();
-    if counter_path.exists() {
-        log::debug!("Loading prompt from the previous execution");
-        let content = std::fs::read_to_string(counter_path).unwrap();
-        let funcs: Vec<String> = serde_json::from_str(&content).unwrap();
-        let combination: Vec<&FuncGadget> =
-            funcs.iter().map(|x| get_func_gadget(x).unwrap()).collect();
-        let prompt = Prompt::from_combination(combination);
-        return Some(prompt);
-    }
-    None
-}
-
 use crate::{
     analysis::header::get_include_sys_headers_str,
     config::{self, get_library_name},
@@ -387,3 +540,107 @@ impl Serialize for Prompt {
         combination_to_str(&self.gadgets)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_avoid_pairs() {
+        let pair = |a: &str, b: &str| (a.to_string(), b.to_string());
+        let mut prompt = Prompt {
+            gadgets: Vec::new(),
+            successful_examples: VecDeque::new(),
+            avoid_pairs: VecDeque::new(),
+            task: ProgramTask::Generate,
+        };
+        assert_eq!(prompt.avoid_pairs_to_str(), "");
+        prompt.add_avoid_pairs(&[pair("a", "b"), pair("b", "c")].into());
+        prompt.add_avoid_pairs(&[pair("c", "d"), pair("a", "b")].into());
+        assert_eq!(prompt.avoid_pairs, [pair("b", "c"), pair("a", "b"), pair("c", "d")]);
+        let instruction = prompt.avoid_pairs_to_str();
+        assert!(instruction.ends_with("- c -> d\n- a -> b\n- b -> c\n"));
+
+        let many: HashSet<(String, String)> =
+            (0..MAX_AVOID_PAIRS).map(|i| pair("x", &format!("f{i:02}"))).collect();
+        prompt.add_avoid_pairs(&many);
+        assert_eq!(prompt.avoid_pairs.len(), MAX_AVOID_PAIRS);
+        assert!(!prompt.avoid_pairs.contains(&pair("c", "d")));
+    }
+
+    #[test]
+    fn test_fill_avoid_pairs() {
+        let template = "APIs:\n{combinations}\n{avoid_pairs}\nExamples:";
+        assert_eq!(fill_avoid_pairs(template, ""), "APIs:\n{combinations}\nExamples:");
+        assert_eq!(
+            fill_avoid_pairs(template, "Avoid:\n- a -> b\n"),
+            "APIs:\n{combinations}\nAvoid:\n- a -> b\nExamples:"
+        );
+        assert_eq!(fill_avoid_pairs("{avoid_pairs}", "Avoid:\n"), "Avoid:");
+    }
+
+    #[test]
+    fn test_force_types_in_context() {
+        let defs: HashMap<&str, &str> = HashMap::from([
+            ("cJSON", "typedef struct cJSON cJSON;"),
+            ("cJSON_Hooks", "typedef struct cJSON_Hooks cJSON_Hooks;"),
+        ]);
+        // the stub lookup that marks the visited types like `get_type_definition`.
+        let get_def = |ty: &str, visited: &mut HashSet<String>| {
+            if !visited.insert(ty.to_string()) {
+                return None;
+            }
+            defs.get(ty).map(|def| def.to_string())
+        };
+        let tys = HashSet::from(["cJSON".to_string(), "cJSON_Hooks".to_string()]);
+        let force_types = vec!["cJSON_Hooks".to_string(), "cJSON_Missing".to_string()];
+        let context = assemble_type_definitions(tys, &force_types, get_def);
+        assert!(context.contains("typedef struct cJSON_Hooks cJSON_Hooks;"));
+        assert!(context.contains("typedef struct cJSON cJSON;"));
+        assert_eq!(context.matches("cJSON_Hooks;").count(), 1);
+
+        let context = assemble_type_definitions(HashSet::new(), &force_types, get_def);
+        assert_eq!(context, "typedef struct cJSON_Hooks cJSON_Hooks;");
+    }
+
+    #[test]
+    fn test_prompt_round_trip() -> eyre::Result<()> {
+        if config::CONFIG_INSTANCE.get().is_none() {
+            Config::init_test("cJSON");
+        }
+        let gadgets: Vec<&'static FuncGadget> = ["cJSON_Parse", "cJSON_Delete"]
+            .iter()
+            .map(|name| get_func_gadget(name).expect("the API is in cJSON"))
+            .collect();
+        let mut prompt = Prompt {
+            gadgets,
+            successful_examples: VecDeque::from(["int main() { return 0; }".to_string()]),
+            avoid_pairs: VecDeque::from([("a".to_string(), "b".to_string())]),
+            task: ProgramTask::Generate,
+        };
+        prompt.set_cot_code_task("1. create\n2. delete".to_string());
+        let path = std::env::temp_dir().join("prompt_fuzz_prompt_round_trip.json");
+        prompt.save_to(&path)?;
+        let loaded = Prompt::load_from(&path)?;
+        let names: Vec<&str> = loaded.gadgets.iter().map(|gadget| gadget.get_func_name()).collect();
+        assert_eq!(names, ["cJSON_Parse", "cJSON_Delete"]);
+        assert_eq!(loaded.successful_examples, prompt.successful_examples);
+        assert_eq!(loaded.avoid_pairs, prompt.avoid_pairs);
+        assert!(matches!(
+            &loaded.task,
+            ProgramTask::CotCode { execution_plan } if execution_plan == "1. create\n2. delete"
+        ));
+
+        // the bare list of API names in `misc/prompt.json`.
+        std::fs::write(&path, r#"["cJSON_Parse"]"#)?;
+        let loaded = Prompt::load_from(&path)?;
+        assert_eq!(loaded.gadgets.len(), 1);
+        assert!(matches!(loaded.task, ProgramTask::Generate));
+        std::fs::write(&path, r#"["cJSON_Missing"]"#)?;
+        assert!(Prompt::load_from(&path).is_err());
+        std::fs::write(&path, "{")?;
+        assert!(Prompt::load_from(&path).is_err());
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+//! The prompt+response transcripts of the successful programs, saved by `--save-transcripts`.
+//!
+//! Each line of the transcript file is a JSON record of a successful program:
+//! `{"messages": [{"role": "system", ...}, {"role": "user", ...}], "completion": "...", "metadata": {...}}`,
+//! where `messages` is the exact prompt that the program is generated from.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_openai::types::ChatCompletionRequestMessage;
+use eyre::Result;
+use serde::Serialize;
+
+use super::prompt::Prompt;
+use crate::program::Program;
+
+#[derive(Debug, Serialize)]
+pub struct TranscriptMeta {
+    pub id: usize,
+    /// The APIs of the prompt combination.
+    pub combination: Vec<String>,
+    /// The branch coverage of the corpus after the program is merged, None in API mode.
+    pub branch_coverage: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Transcript<'a> {
+    pub messages: &'a [ChatCompletionRequestMessage],
+    pub completion: &'a str,
+    pub metadata: TranscriptMeta,
+}
+
+struct PromptRecord {
+    combination: Vec<String>,
+    messages: Vec<ChatCompletionRequestMessage>,
+}
+
+pub struct TranscriptRecorder {
+    path: PathBuf,
+    /// The prompt of each generated program of this round, keyed by the program statements.
+    prompts: HashMap<String, Arc<PromptRecord>>,
+}
+
+impl TranscriptRecorder {
+    pub fn new(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            prompts: HashMap::new(),
+        })
+    }
+
+    /// Remember the prompt that `programs` are generated from.
+    pub fn record(&mut self, prompt: &Prompt, programs: &[Program]) {
+        if programs.is_empty() {
+            return;
+        }
+        self.record_messages(
+            prompt.gadgets.iter().map(|gadget| gadget.get_func_name().to_string()).collect(),
+            prompt.to_chatgpt_message(),
+            programs,
+        );
+    }
+
+    fn record_messages(
+        &mut self,
+        combination: Vec<String>,
+        messages: Vec<ChatCompletionRequestMessage>,
+        programs: &[Program],
+    ) {
+        let record = Arc::new(PromptRecord {
+            combination,
+            messages,
+        });
+        for program in programs {
+            self.prompts.insert(program.statements.clone(), record.clone());
+        }
+    }
+
+    /// Forget the prompts of the programs of the last round.
+    pub fn clear(&mut self) {
+        self.prompts.clear();
+    }
+
+    /// Append the transcript of a successful program. Returns false if its prompt is not recorded.
+    pub fn write(&mut self, program: &Program, branch_coverage: Option<f32>) -> Result<bool> {
+        let Some(record) = self.prompts.remove(&program.statements) else {
+            return Ok(false);
+        };
+        let transcript = Transcript {
+            messages: &record.messages,
+            completion: &program.statements,
+            metadata: TranscriptMeta {
+                id: program.id,
+                combination: record.combination.clone(),
+                branch_coverage,
+            },
+        };
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(&transcript)?)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_openai::types::{ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs};
+
+    #[test]
+    fn test_write_transcripts() -> Result<()> {
+        let path = std::env::temp_dir().join("prompt_fuzz_transcripts.jsonl");
+        _ = std::fs::remove_file(&path);
+        let mut recorder = TranscriptRecorder::new(&path)?;
+        let messages = vec![
+            ChatCompletionRequestSystemMessageArgs::default().content("You are a fuzzer.").build()?.into(),
+            ChatCompletionRequestUserMessageArgs::default().content("Use compress.").build()?.into(),
+        ];
+        let mut program = Program::new("int main() { return 0; }");
+        program.id = 3;
+        let other = Program::new("int main() { return 1; }");
+        recorder.record_messages(vec!["compress".to_string()], messages, &[program.clone(), other]);
+        assert!(recorder.write(&program, Some(12.5))?);
+        // a transcript is written once.
+        assert!(!recorder.write(&program, Some(12.5))?);
+        recorder.clear();
+        assert!(!recorder.write(&Program::new("int main() { return 1; }"), None)?);
+
+        let content = std::fs::read_to_string(&path)?;
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let record: serde_json::Value = serde_json::from_str(lines[0])?;
+        assert_eq!(record["messages"][0]["role"], "system");
+        assert_eq!(record["messages"][1]["content"], "Use compress.");
+        assert_eq!(record["completion"], program.statements.as_str());
+        assert_eq!(record["metadata"]["id"], 3);
+        assert_eq!(record["metadata"]["combination"][0], "compress");
+        assert_eq!(record["metadata"]["branch_coverage"], 12.5);
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
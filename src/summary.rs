@@ -0,0 +1,143 @@
+//! The summary of a fuzz loop, written to `summary.md` and `summary.json` in the work dir at loop exit.
+
+use std::path::Path;
+
+use eyre::Result;
+use serde::Serialize;
+
+use crate::execution::logger::ProgramLogger;
+use crate::feedback::{observer::Observer, schedule::Schedule};
+
+/// The number of APIs listed in `top_apis`.
+pub const SUMMARY_TOP_APIS: usize = 10;
+
+#[derive(Debug, Serialize)]
+pub struct ApiEnergy {
+    pub name: String,
+    pub energy: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FuzzSummary {
+    pub library: String,
+    pub generation_mode: String,
+    pub loops: usize,
+    pub elapsed_secs: u64,
+    pub generated: usize,
+    pub succ: usize,
+    pub err: usize,
+    /// The error programs of each kind.
+    pub errors: Vec<(String, usize)>,
+    pub covered_branches: usize,
+    pub total_branches: usize,
+    pub branch_coverage: f32,
+    pub unique_crashes: usize,
+    pub discovered_api_pairs: usize,
+    pub top_apis: Vec<ApiEnergy>,
+}
+
+impl FuzzSummary {
+    pub fn collect(
+        logger: &ProgramLogger,
+        observer: &Observer,
+        schedule: &Schedule,
+        loops: usize,
+        elapsed_secs: u64,
+        unique_crashes: usize,
+    ) -> Self {
+        let config = crate::config::get_config();
+        let (covered_branches, total_branches) = observer.get_branch_coverage();
+        Self {
+            library: config.target.clone(),
+            generation_mode: format!("{:?}", config.generation_mode),
+            loops,
+            elapsed_secs,
+            generated: logger.get_gc_total(),
+            succ: logger.get_gc_succ(),
+            err: logger.get_gc_total() - logger.get_gc_succ(),
+            errors: logger
+                .get_gc_errors()
+                .into_iter()
+                .map(|(kind, num)| (kind.to_string(), num))
+                .collect(),
+            covered_branches,
+            total_branches,
+            branch_coverage: observer.get_branch_cover_percent(),
+            unique_crashes,
+            discovered_api_pairs: observer.get_discovered_pair_num(),
+            top_apis: schedule
+                .top_energy_apis(SUMMARY_TOP_APIS)
+                .into_iter()
+                .map(|(name, energy)| ApiEnergy { name, energy })
+                .collect(),
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut md = format!("# Fuzzing summary of {}\n\n", self.library);
+        md.push_str(&format!("- Generation mode: {}\n", self.generation_mode));
+        md.push_str(&format!("- Loops: {}\n", self.loops));
+        md.push_str(&format!("- Elapsed: {}s\n", self.elapsed_secs));
+        md.push_str(&format!(
+            "- Programs: {} generated, {} succ, {} err\n",
+            self.generated, self.succ, self.err
+        ));
+        md.push_str(&format!(
+            "- Branch coverage: {}/{} ({:.2}%)\n",
+            self.covered_branches, self.total_branches, self.branch_coverage
+        ));
+        md.push_str(&format!("- Unique crashes: {}\n", self.unique_crashes));
+        md.push_str(&format!("- Discovered API pairs: {}\n", self.discovered_api_pairs));
+
+        md.push_str("\n## Errors\n\n| kind | programs |\n| --- | --- |\n");
+        for (kind, num) in &self.errors {
+            md.push_str(&format!("| {kind} | {num} |\n"));
+        }
+        md.push_str("\n## Top APIs by energy\n\n| API | energy |\n| --- | --- |\n");
+        for api in &self.top_apis {
+            md.push_str(&format!("| {} | {:.4} |\n", api.name, api.energy));
+        }
+        md
+    }
+
+    /// Write `summary.md` and `summary.json` into `dir`.
+    pub fn write_to(&self, dir: &Path) -> Result<()> {
+        std::fs::write(dir.join("summary.md"), self.to_markdown())?;
+        std::fs::write(dir.join("summary.json"), serde_json::to_string_pretty(self)?)?;
+        log::info!("The fuzzing summary is written to {dir:?}");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_markdown() {
+        let summary = FuzzSummary {
+            library: "zlib".to_string(),
+            generation_mode: "FuzzDriver".to_string(),
+            loops: 3,
+            elapsed_secs: 120,
+            generated: 10,
+            succ: 4,
+            err: 6,
+            errors: vec![("syntax".to_string(), 5), ("link".to_string(), 1)],
+            covered_branches: 50,
+            total_branches: 200,
+            branch_coverage: 25.0,
+            unique_crashes: 1,
+            discovered_api_pairs: 7,
+            top_apis: vec![ApiEnergy { name: "compress".to_string(), energy: 0.5 }],
+        };
+        let md = summary.to_markdown();
+        assert!(md.starts_with("# Fuzzing summary of zlib\n"));
+        assert!(md.contains("- Programs: 10 generated, 4 succ, 6 err\n"));
+        assert!(md.contains("- Branch coverage: 50/200 (25.00%)\n"));
+        assert!(md.contains("| syntax | 5 |\n| link | 1 |\n"));
+        assert!(md.contains("| compress | 0.5000 |\n"));
+        let json: serde_json::Value = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["top_apis"][0]["name"], "compress");
+    }
+}